@@ -9,6 +9,7 @@ use tracing::error;
 use near_primitives::block::BlockValidityError;
 use near_primitives::challenge::{ChunkProofs, ChunkState};
 use near_primitives::errors::{EpochError, StorageError};
+use near_primitives::light_client::LightClientBlockError;
 use near_primitives::serialize::to_base;
 use near_primitives::shard_layout::ShardLayoutError;
 use near_primitives::sharding::{ChunkHash, ShardChunkHeader};
@@ -186,6 +187,9 @@ pub enum ErrorKind {
     /// Invalid shard id
     #[error("Invalid state request: {0}")]
     InvalidStateRequest(String),
+    /// Epoch Sync response is inconsistent with the verified light client blocks
+    #[error("Invalid epoch sync response: {0}")]
+    InvalidEpochSyncResponse(String),
     /// Invalid VRF proof, or incorrect random_output in the header
     #[error("Invalid Randomness Beacon Output")]
     InvalidRandomnessBeaconOutput,
@@ -312,6 +316,7 @@ impl Error {
             | ErrorKind::InvalidBalanceBurnt
             | ErrorKind::InvalidShardId(_)
             | ErrorKind::InvalidStateRequest(_)
+            | ErrorKind::InvalidEpochSyncResponse(_)
             | ErrorKind::InvalidRandomnessBeaconOutput
             | ErrorKind::InvalidBlockMerkleRoot
             | ErrorKind::NotAValidator
@@ -383,6 +388,18 @@ impl From<BlockValidityError> for Error {
     }
 }
 
+impl From<LightClientBlockError> for Error {
+    fn from(error: LightClientBlockError) -> Self {
+        match error {
+            LightClientBlockError::InvalidApprovals => ErrorKind::InvalidApprovals,
+            LightClientBlockError::InvalidSignature(_) => ErrorKind::InvalidSignature,
+            LightClientBlockError::NotEnoughApprovals => ErrorKind::NotEnoughApprovals,
+            LightClientBlockError::InvalidNextBlockProducers => ErrorKind::InvalidNextBPHash,
+        }
+        .into()
+    }
+}
+
 impl From<StorageError> for Error {
     fn from(error: StorageError) -> Self {
         ErrorKind::StorageError(error).into()
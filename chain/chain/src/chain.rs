@@ -12,13 +12,14 @@ use rand::SeedableRng;
 use tracing::{debug, error, info, warn};
 
 use near_chain_primitives::error::{BlockKnownError, Error, ErrorKind, LogTransientStorageError};
-use near_primitives::block::{genesis_chunks, Tip};
+use near_primitives::block::{genesis_chunks, Approval, ApprovalInner, Tip};
 use near_primitives::challenge::{
     BlockDoubleSign, Challenge, ChallengeBody, ChallengesResult, ChunkProofs, ChunkState,
     MaybeEncodedShardChunk, SlashedValidator,
 };
 use near_primitives::checked_feature;
 use near_primitives::hash::{hash, CryptoHash};
+use near_primitives::light_client::verify_approvals;
 use near_primitives::merkle::{
     combine_hash, merklize, verify_path, Direction, MerklePath, MerklePathItem,
 };
@@ -28,17 +29,20 @@ use near_primitives::sharding::{
     ShardProof, StateSyncInfo,
 };
 use near_primitives::syncing::{
-    get_num_state_parts, ReceiptProofResponse, RootProof, ShardStateSyncResponseHeader,
-    ShardStateSyncResponseHeaderV1, ShardStateSyncResponseHeaderV2, StateHeaderKey, StatePartKey,
+    get_num_state_parts, EpochSyncFinalizationResponse, ReceiptProofResponse, RootProof,
+    ShardStateSyncResponseHeader, ShardStateSyncResponseHeaderV1, ShardStateSyncResponseHeaderV2,
+    StateHeaderKey, StatePartKey,
 };
 use near_primitives::transaction::ExecutionOutcomeWithIdAndProof;
 use near_primitives::types::chunk_extra::ChunkExtra;
+use near_primitives::types::validator_stake::ValidatorStake;
 use near_primitives::types::{
     AccountId, Balance, BlockExtra, BlockHeight, BlockHeightDelta, EpochId, Gas, MerkleHash,
     NumBlocks, NumShards, ShardId, StateChangesForSplitStates, StateRoot,
 };
 use near_primitives::unwrap_or_return;
 use near_primitives::utils::MaybeValidated;
+use near_primitives::version::ProtocolVersion;
 use near_primitives::views::{
     ExecutionOutcomeWithIdView, ExecutionStatusView, FinalExecutionOutcomeView,
     FinalExecutionOutcomeWithReceiptView, FinalExecutionStatus, LightClientBlockView,
@@ -405,6 +409,19 @@ pub struct Chain {
     pub block_economics_config: BlockEconomicsConfig,
     pub doomslug_threshold_mode: DoomslugThresholdMode,
     pending_states_to_patch: Option<Vec<StateRecord>>,
+    /// Snapshots of the chain head that can be reverted to, by snapshot id.
+    #[cfg(feature = "sandbox")]
+    sandbox_snapshots: std::collections::BTreeMap<u64, SandboxSnapshot>,
+    /// Id of the next snapshot, so that ids of deleted snapshots are never reused.
+    #[cfg(feature = "sandbox")]
+    next_sandbox_snapshot_id: u64,
+}
+
+/// Head and state roots captured by `Chain::sandbox_snapshot`.
+#[cfg(feature = "sandbox")]
+struct SandboxSnapshot {
+    head: Tip,
+    state_roots: Vec<(ShardUId, StateRoot)>,
 }
 
 impl ChainAccess for Chain {
@@ -461,6 +478,10 @@ impl Chain {
             block_economics_config: BlockEconomicsConfig::from(chain_genesis),
             doomslug_threshold_mode,
             pending_states_to_patch: None,
+            #[cfg(feature = "sandbox")]
+            sandbox_snapshots: std::collections::BTreeMap::new(),
+            #[cfg(feature = "sandbox")]
+            next_sandbox_snapshot_id: 0,
         })
     }
 
@@ -580,6 +601,10 @@ impl Chain {
             block_economics_config: BlockEconomicsConfig::from(chain_genesis),
             doomslug_threshold_mode,
             pending_states_to_patch: None,
+            #[cfg(feature = "sandbox")]
+            sandbox_snapshots: std::collections::BTreeMap::new(),
+            #[cfg(feature = "sandbox")]
+            next_sandbox_snapshot_id: 0,
         })
     }
 
@@ -777,6 +802,13 @@ impl Chain {
         let head = self.store.head()?;
         let tail = self.store.tail()?;
         let gc_stop_height = self.runtime_adapter.get_gc_stop_height(&head.last_block_hash);
+        // Keep the state of all snapshots around so that they can be reverted to.
+        #[cfg(feature = "sandbox")]
+        let gc_stop_height = self
+            .sandbox_snapshots
+            .values()
+            .map(|snapshot| snapshot.head.height)
+            .fold(gc_stop_height, std::cmp::min);
 
         if gc_stop_height > head.height {
             return Err(ErrorKind::GCError(
@@ -1074,6 +1106,31 @@ impl Chain {
         chain_update.commit()
     }
 
+    /// Saves the light client blocks of the epochs which end right before one of `headers`, the
+    /// same way as it's done when the first block of an epoch becomes the head. Used by nodes
+    /// which only follow the header chain, so the headers must already be saved and on the
+    /// canonical header chain.
+    pub fn save_epoch_light_client_blocks(&mut self, headers: &[BlockHeader]) -> Result<(), Error> {
+        let mut chain_update = self.chain_update();
+        for header in headers {
+            match chain_update.chain_store_update.get_block_hash_by_height(header.height()) {
+                Ok(hash) if &hash == header.hash() => {}
+                _ => continue,
+            }
+            let prev = chain_update.get_previous_header(header)?.clone();
+            if prev.epoch_id() == header.epoch_id()
+                || prev.last_final_block() == &CryptoHash::default()
+            {
+                continue;
+            }
+            let light_client_block = chain_update.create_light_client_block(&prev)?;
+            chain_update
+                .chain_store_update
+                .save_epoch_light_client_block(&prev.epoch_id().0, light_client_block);
+        }
+        chain_update.commit()
+    }
+
     /// Returns if given block header is on the current chain.
     pub fn is_on_current_chain(&mut self, header: &BlockHeader) -> Result<(), Error> {
         let chain_header = self.get_header_by_height(header.height())?;
@@ -1696,6 +1753,229 @@ impl Chain {
         )
     }
 
+    /// Builds the response to Epoch Sync finalization for `epoch_id`. It contains the first
+    /// block of the epoch, the headers at the end of the previous epoch which are needed to
+    /// state sync to it, and the epoch manager data committed to by the first block.
+    pub fn get_epoch_sync_finalization_response(
+        &mut self,
+        epoch_id: &EpochId,
+    ) -> Result<EpochSyncFinalizationResponse, Error> {
+        // Find any block of the epoch on the canonical chain.
+        let head = self.head()?;
+        let block_hash = if &head.epoch_id == epoch_id {
+            head.last_block_hash
+        } else {
+            let height = self.store.get_epoch_light_client_block(&epoch_id.0)?.inner_lite.height;
+            *self.get_header_by_height(height)?.hash()
+        };
+        let epoch_start_height = self.runtime_adapter.get_epoch_start_height(&block_hash)?;
+        let cur_epoch_header = self.get_header_by_height(epoch_start_height)?.clone();
+        if cur_epoch_header.epoch_id() != epoch_id {
+            return Err(ErrorKind::InvalidEpochHash.into());
+        }
+
+        // State sync to the first block of the epoch proves the last two chunks of every shard,
+        // and header sync from it needs the last final block, so send all the headers back to
+        // the earliest of them.
+        let last_final_height = self.store.get_block_height(cur_epoch_header.last_final_block())?;
+        let mut chunks_seen = vec![0; cur_epoch_header.chunk_mask().len()];
+        let mut prev_epoch_headers = vec![];
+        let mut header = self.get_previous_header(&cur_epoch_header)?.clone();
+        loop {
+            for (shard_id, &included) in header.chunk_mask().iter().enumerate() {
+                match chunks_seen.get_mut(shard_id) {
+                    Some(seen) if included => *seen += 1,
+                    _ => {}
+                }
+            }
+            let done =
+                header.height() <= last_final_height && chunks_seen.iter().all(|&seen| seen >= 2);
+            let prev_hash = *header.prev_hash();
+            prev_epoch_headers.push(header);
+            if done || prev_hash == CryptoHash::default() {
+                break;
+            }
+            header = self.get_block_header(&prev_hash)?.clone();
+        }
+        prev_epoch_headers.reverse();
+
+        // Approvals of a block endorse its parent only if it is produced at the next height.
+        let mut cur_epoch_next_headers = vec![];
+        let mut parent_height = cur_epoch_header.height();
+        let mut endorsed = false;
+        for height in cur_epoch_header.height() + 1..=head.height {
+            let header = match self.get_header_by_height(height) {
+                Ok(header) => header.clone(),
+                Err(err) => match err.kind() {
+                    ErrorKind::DBNotFoundErr(_) => continue,
+                    _ => return Err(err),
+                },
+            };
+            endorsed = header.height() == parent_height + 1;
+            parent_height = header.height();
+            cur_epoch_next_headers.push(header);
+            if endorsed {
+                break;
+            }
+        }
+        if !endorsed {
+            return Err(ErrorKind::Other(format!(
+                "First block of epoch {:?} is not endorsed yet",
+                epoch_id
+            ))
+            .into());
+        }
+
+        let header_sync_init_header_tree =
+            self.store.get_block_merkle_tree(cur_epoch_header.hash())?.clone();
+        let (
+            prev_epoch_first_block_info,
+            prev_epoch_prev_last_block_info,
+            prev_epoch_last_block_info,
+            prev_epoch_info,
+            cur_epoch_info,
+            next_epoch_info,
+        ) = self.runtime_adapter.get_epoch_sync_data(
+            cur_epoch_header.prev_hash(),
+            epoch_id,
+            cur_epoch_header.next_epoch_id(),
+        )?;
+        Ok(EpochSyncFinalizationResponse {
+            cur_epoch_header: cur_epoch_header.clone(),
+            prev_epoch_headers,
+            header_sync_init_header: cur_epoch_header,
+            header_sync_init_header_tree,
+            prev_epoch_first_block_info,
+            prev_epoch_prev_last_block_info,
+            prev_epoch_last_block_info,
+            prev_epoch_info,
+            cur_epoch_info,
+            next_epoch_info,
+            cur_epoch_next_headers,
+        })
+    }
+
+    /// Finishes Epoch Sync: verifies the finalization response for `epoch_id`, whose block
+    /// producers were verified using light client blocks, and saves its headers and epoch data.
+    /// Returns the hash of the first block of the epoch, from which header sync continues.
+    pub fn init_after_epoch_sync(
+        &mut self,
+        epoch_id: &EpochId,
+        block_producers: &[ValidatorStake],
+        response: EpochSyncFinalizationResponse,
+    ) -> Result<CryptoHash, Error> {
+        let invalid =
+            |reason: &str| Error::from(ErrorKind::InvalidEpochSyncResponse(reason.to_string()));
+        let cur_epoch_header = &response.cur_epoch_header;
+        // The first block of epoch N has the last block of epoch N-1 as the id of epoch N+1.
+        if cur_epoch_header.epoch_id() != epoch_id
+            || cur_epoch_header.next_epoch_id() != &EpochId(*cur_epoch_header.prev_hash())
+        {
+            return Err(invalid("not the first block of the epoch"));
+        }
+        if &response.header_sync_init_header != cur_epoch_header
+            || response.header_sync_init_header_tree.root() != *cur_epoch_header.block_merkle_root()
+        {
+            return Err(invalid("invalid header to start header sync from"));
+        }
+        if cur_epoch_header.epoch_sync_data_hash() != Some(response.epoch_sync_data_hash()) {
+            return Err(invalid("epoch data doesn't match the header"));
+        }
+
+        // The epoch data is committed to by the header, so the header must be signed by the
+        // verified block producers of the epoch to be trusted.
+        let epoch_info = &response.cur_epoch_info;
+        let stake_key = |stake: &ValidatorStake| {
+            (stake.account_id().clone(), stake.public_key().clone(), stake.stake())
+        };
+        let epoch_block_producers = epoch_info
+            .block_producers_settlement()
+            .iter()
+            .unique()
+            .map(|id| stake_key(&epoch_info.get_validator(*id)))
+            .collect::<Vec<_>>();
+        if epoch_block_producers != block_producers.iter().map(stake_key).collect::<Vec<_>>() {
+            return Err(invalid("block producers don't match the light client blocks"));
+        }
+        let block_producer =
+            epoch_info.get_validator(epoch_info.sample_block_producer(cur_epoch_header.height()));
+        if !cur_epoch_header.verify_block_producer(block_producer.public_key()) {
+            return Err(ErrorKind::InvalidSignature.into());
+        }
+        // A single block producer could still sign a header with made up epoch data, so the
+        // header must be followed by a block whose approvals endorse it or its descendant.
+        let mut parent = cur_epoch_header;
+        let mut endorsed = false;
+        for header in response.cur_epoch_next_headers.iter() {
+            if header.prev_hash() != parent.hash() || header.epoch_id() != epoch_id {
+                return Err(invalid("headers following the epoch start are not linked"));
+            }
+            if header.height() == parent.height() + 1 {
+                let approval_data = Approval::get_data_for_sig(
+                    &ApprovalInner::Endorsement(*parent.hash()),
+                    header.height(),
+                );
+                verify_approvals(header.approvals(), &approval_data, block_producers)?;
+                endorsed = true;
+                break;
+            }
+            parent = header;
+        }
+        if !endorsed {
+            return Err(invalid("the first block of the epoch is not endorsed"));
+        }
+
+        // The headers of the previous epoch must lead to the first block of the epoch.
+        let mut prev_hash = None;
+        for header in response.prev_epoch_headers.iter() {
+            if prev_hash.map_or(false, |prev_hash| &prev_hash != header.prev_hash()) {
+                return Err(invalid("headers of the previous epoch are not linked"));
+            }
+            prev_hash = Some(*header.hash());
+        }
+        if prev_hash.as_ref() != Some(cur_epoch_header.prev_hash())
+            || response.prev_epoch_last_block_info.hash() != cur_epoch_header.prev_hash()
+        {
+            return Err(invalid("headers of the previous epoch don't lead to the epoch"));
+        }
+
+        let cur_epoch_header = cur_epoch_header.clone();
+        let prev_epoch_id = response.prev_epoch_last_block_info.epoch_id().clone();
+        // The epoch manager data and the headers are committed together, so that a failure
+        // doesn't leave the node with epoch data but without the headers it refers to.
+        let epoch_manager_update = self.runtime_adapter.epoch_sync_init_epoch_manager(
+            response.prev_epoch_first_block_info,
+            response.prev_epoch_prev_last_block_info,
+            response.prev_epoch_last_block_info,
+            &prev_epoch_id,
+            response.prev_epoch_info,
+            epoch_id,
+            response.cur_epoch_info,
+            cur_epoch_header.next_epoch_id(),
+            response.next_epoch_info,
+        )?;
+
+        let mut chain_store_update = self.store.store_update();
+        chain_store_update.merge(epoch_manager_update);
+        for header in response.prev_epoch_headers {
+            chain_store_update.save_block_header_no_update_tree(header)?;
+        }
+        let last_finalized_height =
+            chain_store_update.get_block_height(cur_epoch_header.last_final_block())?;
+        let epoch_manager_update = self.runtime_adapter.add_validator_proposals(
+            BlockHeaderInfo::new(&cur_epoch_header, last_finalized_height),
+        )?;
+        chain_store_update.merge(epoch_manager_update);
+        chain_store_update.save_block_merkle_tree(
+            *cur_epoch_header.hash(),
+            response.header_sync_init_header_tree,
+        );
+        chain_store_update.save_block_header_no_update_tree(cur_epoch_header.clone())?;
+        chain_store_update.force_save_header_head(&Tip::from_header(&cur_epoch_header))?;
+        chain_store_update.commit()?;
+        Ok(*cur_epoch_header.hash())
+    }
+
     pub fn get_state_response_header(
         &mut self,
         shard_id: ShardId,
@@ -2216,6 +2496,8 @@ impl Chain {
         let shard_uid = ShardUId::from_shard_id_and_layout(shard_id, &shard_layout);
         let prev_hash = *self.get_block_header(sync_hash)?.prev_hash();
         let state_root = *self.get_chunk_extra(&prev_hash, &shard_uid)?.state_root();
+        let prev_epoch_id = self.get_block_header(&prev_hash)?.epoch_id().clone();
+        let protocol_version = self.runtime_adapter.get_epoch_protocol_version(&prev_epoch_id)?;
         assert_ne!(shard_layout, next_epoch_shard_layout);
 
         state_split_scheduler(StateSplitRequest {
@@ -2225,6 +2507,7 @@ impl Chain {
             shard_uid,
             state_root: state_root,
             next_epoch_shard_layout,
+            protocol_version,
         });
 
         Ok(())
@@ -3143,6 +3426,77 @@ impl Chain {
     pub fn patch_state_in_progress(&self) -> bool {
         self.pending_states_to_patch.is_some()
     }
+
+    /// Captures the current head and its state roots, returning the id of the snapshot.
+    ///
+    /// Garbage collection doesn't go past the oldest snapshot, so its state stays available
+    /// until the snapshot is deleted with `sandbox_delete_snapshot`. Snapshots are only kept in
+    /// memory and don't survive a restart of the node.
+    pub fn sandbox_snapshot(&mut self) -> Result<u64, Error> {
+        let head = self.head()?;
+        let shard_layout = self.runtime_adapter.get_shard_layout(&head.epoch_id)?;
+        let state_roots = (0..shard_layout.num_shards())
+            .map(|shard_id| {
+                let shard_uid = ShardUId::from_shard_id_and_layout(shard_id, &shard_layout);
+                let chunk_extra = self.get_chunk_extra(&head.last_block_hash, &shard_uid)?;
+                Ok((shard_uid, *chunk_extra.state_root()))
+            })
+            .collect::<Result<Vec<_>, Error>>()?;
+        let snapshot_id = self.next_sandbox_snapshot_id;
+        self.next_sandbox_snapshot_id += 1;
+        self.sandbox_snapshots.insert(snapshot_id, SandboxSnapshot { head, state_roots });
+        Ok(snapshot_id)
+    }
+
+    /// Forgets the given snapshot, letting garbage collection remove the state it was keeping.
+    pub fn sandbox_delete_snapshot(&mut self, snapshot_id: u64) -> Result<(), Error> {
+        self.sandbox_snapshots
+            .remove(&snapshot_id)
+            .map(|_| ())
+            .ok_or_else(|| ErrorKind::Other(format!("Unknown snapshot {}", snapshot_id)).into())
+    }
+
+    /// Moves the head back to the block captured by the given snapshot.
+    ///
+    /// Blocks produced since then are left on an abandoned fork, and new blocks are built on top
+    /// of the snapshot head. Block heights keep increasing, so the latest known height is kept.
+    pub fn sandbox_revert(&mut self, snapshot_id: u64) -> Result<(), Error> {
+        let snapshot = self
+            .sandbox_snapshots
+            .get(&snapshot_id)
+            .ok_or_else(|| ErrorKind::Other(format!("Unknown snapshot {}", snapshot_id)))?;
+        let head = snapshot.head.clone();
+        let state_roots = snapshot.state_roots.clone();
+        // Make sure the snapshot block and its state are still in storage before moving the head.
+        self.get_block(&head.last_block_hash)?;
+        let tries = self.runtime_adapter.get_tries();
+        for (shard_uid, state_root) in state_roots.iter() {
+            tries.get_trie_for_shard(*shard_uid).retrieve_root_node(state_root).map_err(|err| {
+                ErrorKind::Other(format!(
+                    "State of shard {:?} at snapshot {} is not available: {}",
+                    shard_uid, snapshot_id, err
+                ))
+            })?;
+        }
+
+        let last_final_hash = *self.get_block_header(&head.last_block_hash)?.last_final_block();
+        let final_head = if last_final_hash == CryptoHash::default() {
+            Tip::from_header(self.genesis.header())
+        } else {
+            Tip::from_header(self.get_block_header(&last_final_hash)?)
+        };
+        let mut chain_store_update = self.store.store_update();
+        // Moves the header head back as well, so that header sync doesn't pull the chain forward.
+        chain_store_update.save_head(&head)?;
+        chain_store_update.save_final_head(&final_head)?;
+        chain_store_update.commit()?;
+        self.pending_states_to_patch = None;
+        // Blocks waiting for their parent or chunks may belong to the abandoned fork.
+        self.orphans = OrphanBlockPool::new();
+        self.blocks_with_missing_chunks = MissingChunksPool::new();
+        info!(target: "chain", "Reverted to snapshot {}: head @ {} [{}]", snapshot_id, head.height, head.last_block_hash);
+        Ok(())
+    }
 }
 
 /// Chain update helper, contains information that is needed to process block
@@ -3856,6 +4210,8 @@ impl<'a> ChainUpdate<'a> {
                     .get_state_changes_for_split_states(block.hash(), shard_id)?;
                 self.chain_store_update
                     .remove_state_changes_for_split_states(*block.hash(), shard_id);
+                let protocol_version =
+                    self.runtime_adapter.get_epoch_protocol_version(block.header().epoch_id())?;
                 let runtime_adapter = self.runtime_adapter.clone();
                 let block_hash = *block.hash();
                 result.push(Box::new(move || -> Result<ApplyChunkResult, Error> {
@@ -3866,6 +4222,7 @@ impl<'a> ChainUpdate<'a> {
                             split_state_roots,
                             &next_epoch_shard_layout,
                             state_changes,
+                            protocol_version,
                         )?,
                     }))
                 }));
@@ -3899,11 +4256,15 @@ impl<'a> ChainUpdate<'a> {
         };
         // split states are ready, apply update to them now
         if let Some(state_roots) = split_state_roots {
+            let protocol_version = runtime_adapter.get_epoch_protocol_version(
+                &runtime_adapter.get_epoch_id_from_prev_block(prev_block_hash)?,
+            )?;
             let split_state_results = runtime_adapter.apply_update_to_split_states(
                 block_hash,
                 state_roots,
                 &next_epoch_shard_layout,
                 state_changes,
+                protocol_version,
             )?;
             Ok(ApplySplitStateResultOrStateChanges::ApplySplitStateResults(split_state_results))
         } else {
@@ -4934,6 +5295,8 @@ pub struct StateSplitRequest {
     pub shard_uid: ShardUId,
     pub state_root: StateRoot,
     pub next_epoch_shard_layout: ShardLayout,
+    /// Protocol version of the epoch `state_root` belongs to.
+    pub protocol_version: ProtocolVersion,
 }
 
 #[derive(Message)]
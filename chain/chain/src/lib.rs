@@ -1,6 +1,8 @@
 pub use chain::{check_known, collect_receipts, Chain, MAX_ORPHAN_SIZE};
 pub use doomslug::{Doomslug, DoomslugBlockProductionReadiness, DoomslugThresholdMode};
-pub use lightclient::{create_light_client_block_view, get_epoch_block_producers_view};
+pub use lightclient::{
+    create_light_client_block_view, get_epoch_block_producers_view, validate_light_client_block,
+};
 pub use near_chain_primitives::{self, Error, ErrorKind};
 pub use near_primitives::receipt::ReceiptResult;
 pub use store::{ChainStore, ChainStoreAccess, ChainStoreUpdate};
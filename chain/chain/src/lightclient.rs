@@ -1,6 +1,8 @@
-use near_chain_primitives::Error;
+use near_chain_primitives::{Error, ErrorKind};
 use near_primitives::block::BlockHeader;
 use near_primitives::hash::{hash, CryptoHash};
+use near_primitives::light_client::{validate_next_block_producers, verify_approvals};
+use near_primitives::types::validator_stake::ValidatorStake;
 use near_primitives::types::EpochId;
 use near_primitives::views::validator_stake_view::ValidatorStakeView;
 use near_primitives::views::{BlockHeaderInnerLiteView, LightClientBlockView};
@@ -70,3 +72,34 @@ pub fn create_light_client_block_view(
         approvals_after_next,
     })
 }
+
+/// Validates a light client block received from an untrusted peer.
+///
+/// # Arguments
+///  * `light_client_block` - the block to validate
+///  * `epoch_id` - the epoch the block is expected to be in
+///  * `block_producers` - the ordered list of block producers of `epoch_id`
+///
+/// Checks that the block is in the expected epoch, that it is endorsed by more than 2/3 of the
+/// stake of `block_producers`, and that `next_bps` match `next_bp_hash` of the block.
+/// Returns the hash of the block.
+pub fn validate_light_client_block(
+    light_client_block: &LightClientBlockView,
+    epoch_id: &EpochId,
+    block_producers: &[ValidatorStake],
+) -> Result<CryptoHash, Error> {
+    let inner_lite = &light_client_block.inner_lite;
+    if &EpochId(inner_lite.epoch_id) != epoch_id {
+        return Err(ErrorKind::InvalidEpochHash.into());
+    }
+
+    verify_approvals(
+        &light_client_block.approvals_after_next,
+        &light_client_block.approval_message(),
+        block_producers,
+    )?;
+    if validate_next_block_producers(light_client_block)?.is_none() {
+        return Err(ErrorKind::InvalidNextBPHash.into());
+    }
+    Ok(light_client_block.current_block_hash())
+}
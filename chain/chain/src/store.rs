@@ -1635,17 +1635,21 @@ impl<'a> ChainStoreUpdate<'a> {
     }
 
     /// Save header head in Epoch Sync
-    /// Checking validity of header head is delegated to Epoch Sync methods
+    /// Checking validity of header head is delegated to Epoch Sync methods.
+    /// The block merkle tree for the header must be saved beforehand.
     pub fn force_save_header_head(&mut self, t: &Tip) -> Result<(), Error> {
         self.try_save_latest_known(t.height)?;
 
-        // TODO #3488
-        // Bowen: It seems that height_to_hashes is used to update ColBlockHeight, which stores blocks,
-        // not block headers, by height. Therefore I wonder whether this line here breaks some invariant
-        // since now we potentially don't have the corresponding block in storage.
-
-        //self.chain_tore_cache_update.height_to_hashes.insert(t.height, Some(t.last_block_hash));
-        //self.chain_store_cache_update.next_block_hashes.insert(t.prev_block_hash, t.last_block_hash);
+        // The height index is shared with header sync, which walks it back from the header head
+        // until it finds a matching hash, so the header head must be indexed.
+        let block_ordinal = self.get_block_merkle_tree(&t.last_block_hash)?.size();
+        self.chain_store_cache_update
+            .block_ordinal_to_hash
+            .insert(block_ordinal, t.last_block_hash);
+        self.chain_store_cache_update.height_to_hashes.insert(t.height, Some(t.last_block_hash));
+        self.chain_store_cache_update
+            .next_block_hashes
+            .insert(t.prev_block_hash, t.last_block_hash);
         self.header_head = Some(t.clone());
         Ok(())
     }
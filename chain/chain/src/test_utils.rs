@@ -37,7 +37,7 @@ use near_primitives::validator_signer::InMemoryValidatorSigner;
 use near_primitives::version::{ProtocolVersion, PROTOCOL_VERSION};
 use near_primitives::views::{
     AccessKeyInfoView, AccessKeyList, CallResult, ContractCodeView, EpochValidatorInfo,
-    QueryRequest, QueryResponse, QueryResponseKind, ViewStateResult,
+    QueryRequest, QueryResponse, QueryResponseKind, ValidatorEpochPerformanceView, ViewStateResult,
 };
 use near_store::test_utils::create_test_store;
 use near_store::{
@@ -629,8 +629,8 @@ impl RuntimeAdapter for KeyValueRuntime {
         _epoch_info: EpochInfo,
         _next_epoch_id: &EpochId,
         _next_epoch_info: EpochInfo,
-    ) -> Result<(), Error> {
-        Ok(())
+    ) -> Result<StoreUpdate, Error> {
+        Ok(self.store.store_update())
     }
 
     fn add_validator_proposals(
@@ -1102,6 +1102,16 @@ impl RuntimeAdapter for KeyValueRuntime {
         })
     }
 
+    fn get_validator_performance(
+        &self,
+        _account_id: &AccountId,
+        _block_hash: &CryptoHash,
+        _from_epoch_height: EpochHeight,
+        _to_epoch_height: EpochHeight,
+    ) -> Result<Vec<ValidatorEpochPerformanceView>, Error> {
+        Ok(vec![])
+    }
+
     fn compare_epoch_id(
         &self,
         epoch_id: &EpochId,
@@ -1192,6 +1202,7 @@ impl RuntimeAdapter for KeyValueRuntime {
         _state_roots: HashMap<ShardUId, StateRoot>,
         _next_shard_layout: &ShardLayout,
         _state_changes: StateChangesForSplitStates,
+        _protocol_version: ProtocolVersion,
     ) -> Result<Vec<ApplySplitStateResult>, Error> {
         Ok(vec![])
     }
@@ -1201,6 +1212,7 @@ impl RuntimeAdapter for KeyValueRuntime {
         _shard_uid: ShardUId,
         _state_root: &StateRoot,
         _next_epoch_shard_layout: &ShardLayout,
+        _protocol_version: ProtocolVersion,
     ) -> Result<HashMap<ShardUId, StateRoot>, Error> {
         Ok(HashMap::new())
     }
@@ -30,7 +30,9 @@ use near_primitives::version::{
     ProtocolVersion, MIN_GAS_PRICE_NEP_92, MIN_GAS_PRICE_NEP_92_FIX, MIN_PROTOCOL_VERSION_NEP_92,
     MIN_PROTOCOL_VERSION_NEP_92_FIX,
 };
-use near_primitives::views::{EpochValidatorInfo, QueryRequest, QueryResponse};
+use near_primitives::views::{
+    EpochValidatorInfo, QueryRequest, QueryResponse, ValidatorEpochPerformanceView,
+};
 use near_store::{PartialStorage, ShardTries, Store, StoreUpdate, Trie, WrappedTrieChanges};
 
 use crate::DoomslugThresholdMode;
@@ -300,6 +302,12 @@ pub trait RuntimeAdapter: Send + Sync {
         current_protocol_version: ProtocolVersion,
     ) -> Result<Option<InvalidTxError>, Error>;
 
+    /// Lets the transaction with the given hash act on behalf of its signer without a signature,
+    /// an access key or a nonce, until it is included in a chunk. Only called by the sandbox
+    /// impersonation RPC.
+    #[cfg(feature = "sandbox")]
+    fn sandbox_impersonate_transaction(&self, _tx_hash: CryptoHash) {}
+
     /// Returns an ordered list of valid transactions from the pool up the given limits.
     /// Pulls transactions from the given pool iterators one by one. Validates each transaction
     /// against the given `chain_validate` closure and runtime's transaction verifier.
@@ -537,7 +545,6 @@ pub trait RuntimeAdapter: Send + Sync {
     /// Amount of tokens minted in given epoch.
     fn get_epoch_minted_amount(&self, epoch_id: &EpochId) -> Result<Balance, Error>;
 
-    // TODO #3488 this likely to be updated
     /// Data that is necessary for prove Epochs in Epoch Sync.
     fn get_epoch_sync_data(
         &self,
@@ -546,7 +553,6 @@ pub trait RuntimeAdapter: Send + Sync {
         next_epoch_id: &EpochId,
     ) -> Result<(BlockInfo, BlockInfo, BlockInfo, EpochInfo, EpochInfo, EpochInfo), Error>;
 
-    // TODO #3488 this likely to be updated
     /// Hash that is necessary for prove Epochs in Epoch Sync.
     fn get_epoch_sync_data_hash(
         &self,
@@ -570,7 +576,7 @@ pub trait RuntimeAdapter: Send + Sync {
         epoch_info: EpochInfo,
         next_epoch_id: &EpochId,
         next_epoch_info: EpochInfo,
-    ) -> Result<(), Error>;
+    ) -> Result<StoreUpdate, Error>;
 
     /// Add proposals for validators.
     fn add_validator_proposals(
@@ -679,6 +685,16 @@ pub trait RuntimeAdapter: Send + Sync {
         epoch_id: ValidatorInfoIdentifier,
     ) -> Result<EpochValidatorInfo, Error>;
 
+    /// Performance of `account_id` in the finished epochs before the epoch of `block_hash` with
+    /// heights between `from_epoch_height` and `to_epoch_height` inclusive.
+    fn get_validator_performance(
+        &self,
+        account_id: &AccountId,
+        block_hash: &CryptoHash,
+        from_epoch_height: EpochHeight,
+        to_epoch_height: EpochHeight,
+    ) -> Result<Vec<ValidatorEpochPerformanceView>, Error>;
+
     /// Get the part of the state from given state root.
     /// `block_hash` is a block whose `prev_state_root` is `state_root`
     fn obtain_state_part(
@@ -700,19 +716,23 @@ pub trait RuntimeAdapter: Send + Sync {
         data: &Vec<u8>,
     ) -> bool;
 
+    /// `protocol_version` is the protocol version of the epoch of the block at `block_hash`.
     fn apply_update_to_split_states(
         &self,
         block_hash: &CryptoHash,
         state_roots: HashMap<ShardUId, StateRoot>,
         next_shard_layout: &ShardLayout,
         state_changes: StateChangesForSplitStates,
+        protocol_version: ProtocolVersion,
     ) -> Result<Vec<ApplySplitStateResult>, Error>;
 
+    /// `protocol_version` is the protocol version of the epoch `state_root` belongs to.
     fn build_state_for_split_shards(
         &self,
         shard_uid: ShardUId,
         state_root: &StateRoot,
         next_epoch_shard_layout: &ShardLayout,
+        protocol_version: ProtocolVersion,
     ) -> Result<HashMap<ShardUId, StateRoot>, Error>;
 
     /// Should be executed after accepting all the parts to set up a new state.
@@ -825,7 +845,8 @@ mod tests {
             KeyType::ED25519,
             "other2",
         );
-        let approvals = vec![Some(Approval::new(*b1.hash(), 1, 2, &other_signer).signature)];
+        let approvals =
+            vec![Some(Approval::new(*b1.hash(), 1, 2, &other_signer).unwrap().signature)];
         let b2 = Block::empty_with_approvals(
             &b1,
             2,
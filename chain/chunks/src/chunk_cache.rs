@@ -275,6 +275,14 @@ impl EncodedChunksCache {
             .map(|x| x.len() as ShardId)
             .unwrap_or_else(|| 0)
     }
+
+    /// Returns the shards whose chunks are ready to be included in the next block
+    pub fn shards_ready_for_block(&self, prev_block_hash: &CryptoHash) -> Vec<ShardId> {
+        self.block_hash_to_chunk_headers
+            .peek(prev_block_hash)
+            .map(|x| x.keys().cloned().collect())
+            .unwrap_or_default()
+    }
 }
 
 #[cfg(test)]
@@ -292,22 +300,25 @@ mod tests {
     fn create_chunk_header(height: u64, shard_id: u64) -> ShardChunkHeader {
         let signer =
             InMemoryValidatorSigner::from_random("test".parse().unwrap(), KeyType::ED25519);
-        ShardChunkHeader::V2(ShardChunkHeaderV2::new(
-            CryptoHash::default(),
-            CryptoHash::default(),
-            CryptoHash::default(),
-            CryptoHash::default(),
-            1,
-            height,
-            shard_id,
-            0,
-            0,
-            0,
-            CryptoHash::default(),
-            CryptoHash::default(),
-            vec![],
-            &signer,
-        ))
+        ShardChunkHeader::V2(
+            ShardChunkHeaderV2::new(
+                CryptoHash::default(),
+                CryptoHash::default(),
+                CryptoHash::default(),
+                CryptoHash::default(),
+                1,
+                height,
+                shard_id,
+                0,
+                0,
+                0,
+                CryptoHash::default(),
+                CryptoHash::default(),
+                vec![],
+                &signer,
+            )
+            .unwrap(),
+        )
     }
 
     #[test]
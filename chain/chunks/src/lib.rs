@@ -99,6 +99,7 @@ use near_pool::{PoolIteratorWrapper, TransactionPool};
 use near_primitives::block::Tip;
 use near_primitives::hash::{hash, CryptoHash};
 use near_primitives::merkle::{merklize, verify_path, MerklePath};
+use near_primitives::network::PeerId;
 use near_primitives::receipt::Receipt;
 use near_primitives::sharding::{
     ChunkHash, EncodedShardChunk, PartialEncodedChunk, PartialEncodedChunkPart,
@@ -121,7 +122,7 @@ use near_chain::near_chain_primitives::error::ErrorKind::DBNotFoundErr;
 pub use near_chunks_primitives::Error;
 use near_network_primitives::types::{
     AccountIdOrPeerTrackingShard, PartialEncodedChunkForwardMsg, PartialEncodedChunkRequestMsg,
-    PartialEncodedChunkResponseMsg,
+    PartialEncodedChunkResponseMsg, PeerScoreEvent,
 };
 use near_primitives::epoch_manager::RngSeed;
 use rand::Rng;
@@ -218,14 +219,15 @@ impl RequestPool {
     pub fn fetch(&mut self) -> Vec<(ChunkHash, ChunkRequestInfo)> {
         let mut removed_requests = HashSet::<ChunkHash>::default();
         let mut requests = Vec::new();
+        let now = Clock::instant();
         for (chunk_hash, mut chunk_request) in self.requests.iter_mut() {
-            if chunk_request.added.elapsed() > self.max_duration {
+            if now.saturating_duration_since(chunk_request.added) > self.max_duration {
                 debug!(target: "chunks", "Evicted chunk requested that was never fetched {} (shard_id: {})", chunk_hash.0, chunk_request.shard_id);
                 removed_requests.insert(chunk_hash.clone());
                 continue;
             }
-            if chunk_request.last_requested.elapsed() > self.retry_duration {
-                chunk_request.last_requested = Clock::instant();
+            if now.saturating_duration_since(chunk_request.last_requested) > self.retry_duration {
+                chunk_request.last_requested = now;
                 requests.push((chunk_hash.clone(), chunk_request.clone()));
             }
         }
@@ -507,6 +509,12 @@ impl ShardsManager {
         }
     }
 
+    /// Changes the account on whose behalf chunk parts are requested and forwarded.
+    pub fn set_me(&mut self, me: Option<AccountId>) {
+        self.seals_mgr.me = me.clone();
+        self.me = me;
+    }
+
     pub fn update_largest_seen_height(&mut self, new_height: BlockHeight) {
         self.encoded_chunks.update_largest_seen_height(
             new_height,
@@ -879,10 +887,48 @@ impl ShardsManager {
         }
     }
 
+    /// Returns when we last requested parts of the chunk, if we are still waiting for it.
+    pub fn chunk_last_requested(&self, chunk_hash: &ChunkHash) -> Option<Instant> {
+        self.requested_partial_encoded_chunks
+            .get_request_info(chunk_hash)
+            .map(|request_info| request_info.last_requested)
+    }
+
+    /// Updates the score of a peer which answered our request for parts of a chunk, given when
+    /// we last requested the chunk and the error processing the response failed with, if any.
+    /// Responses with invalid parts or receipts are penalized, and timely responses to chunks we
+    /// were still waiting for are rewarded.
+    pub fn update_chunk_responder_score(
+        &self,
+        peer_id: PeerId,
+        last_requested: Option<Instant>,
+        error: Option<&Error>,
+    ) {
+        let event = match (error, last_requested) {
+            (
+                Some(
+                    Error::InvalidPartMessage
+                    | Error::InvalidChunkPartId
+                    | Error::InvalidMerkleProof
+                    | Error::InvalidChunk,
+                ),
+                _,
+            ) => PeerScoreEvent::InvalidResponse,
+            (Some(_), _) | (None, None) => return,
+            (None, Some(last_requested)) => PeerScoreEvent::ResponseReceived {
+                latency: Clock::instant().saturating_duration_since(last_requested),
+            },
+        };
+        self.peer_manager_adapter.do_send(PeerManagerMessageRequest::NetworkRequests(
+            NetworkRequests::UpdatePeerScore { peer_id, event },
+        ));
+    }
+
     /// Resends chunk requests if haven't received it within expected time.
     pub fn resend_chunk_requests(&mut self, header_head: &Tip) {
         // Process chunk one part requests.
         let requests = self.requested_partial_encoded_chunks.fetch();
+        let now = Clock::instant();
         for (chunk_hash, chunk_request) in requests {
             let fetch_from_archival = self.runtime_adapter
                 .chunk_needs_to_be_fetched_from_archival(&chunk_request.ancestor_hash, &header_head.last_block_hash).unwrap_or_else(|err| {
@@ -898,10 +944,10 @@ impl ShardsManager {
                 &chunk_request.ancestor_hash,
                 chunk_request.shard_id,
                 &chunk_hash,
-                chunk_request.added.elapsed()
+                now.saturating_duration_since(chunk_request.added)
                     > self.requested_partial_encoded_chunks.switch_to_full_fetch_duration,
                 old_block
-                    || chunk_request.added.elapsed()
+                    || now.saturating_duration_since(chunk_request.added)
                         > self.requested_partial_encoded_chunks.switch_to_others_duration,
                 fetch_from_archival,
             ) {
@@ -918,6 +964,10 @@ impl ShardsManager {
         self.encoded_chunks.num_chunks_for_block(prev_block_hash)
     }
 
+    pub fn shards_ready_for_block(&self, prev_block_hash: &CryptoHash) -> Vec<ShardId> {
+        self.encoded_chunks.shards_ready_for_block(prev_block_hash)
+    }
+
     pub fn prepare_chunks(
         &mut self,
         prev_block_hash: &CryptoHash,
@@ -940,6 +990,21 @@ impl ShardsManager {
         }
     }
 
+    /// Drops the transaction pools and all chunks being received or requested, which may have been
+    /// built on blocks that are no longer part of the chain, e.g. after a sandbox revert.
+    pub fn clear_pools_and_caches(&mut self) {
+        self.tx_pools.clear();
+        self.encoded_chunks = EncodedChunksCache::new();
+        self.requested_partial_encoded_chunks = RequestPool::new(
+            Duration::from_millis(CHUNK_REQUEST_RETRY_MS),
+            Duration::from_millis(CHUNK_REQUEST_SWITCH_TO_OTHERS_MS),
+            Duration::from_millis(CHUNK_REQUEST_SWITCH_TO_FULL_FETCH_MS),
+            Duration::from_millis(CHUNK_REQUEST_RETRY_MAX_MS),
+        );
+        self.chunk_forwards_cache.clear();
+        self.seals_mgr = SealsManager::new(self.me.clone(), self.runtime_adapter.clone());
+    }
+
     /// Computes a deterministic random seed for given `shard_id`.
     /// This seed is used to randomize the transaction pool.
     /// For better security we want the seed to different in each shard.
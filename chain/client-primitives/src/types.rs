@@ -7,22 +7,24 @@ use chrono::DateTime;
 use near_primitives::time::Utc;
 
 use near_chain_configs::ProtocolConfigView;
+use near_crypto::PublicKey;
 use near_network_primitives::types::{AccountOrPeerIdOrHash, KnownProducer, PeerInfo};
 use near_primitives::errors::InvalidTxError;
 use near_primitives::hash::CryptoHash;
 use near_primitives::merkle::{MerklePath, PartialMerkleTree};
 use near_primitives::sharding::ChunkHash;
 use near_primitives::types::{
-    AccountId, BlockHeight, BlockReference, EpochId, EpochReference, MaybeBlockId, ShardId,
-    TransactionOrReceiptId,
+    AccountId, BlockHeight, BlockReference, EpochHeight, EpochId, EpochReference, MaybeBlockId,
+    ShardId, TransactionOrReceiptId,
 };
 use near_primitives::utils::generate_random_string;
 use near_primitives::views::validator_stake_view::ValidatorStakeView;
 use near_primitives::views::{
-    BlockView, ChunkView, EpochValidatorInfo, ExecutionOutcomeWithIdView,
-    FinalExecutionOutcomeViewEnum, GasPriceView, LightClientBlockLiteView, LightClientBlockView,
-    QueryRequest, QueryResponse, ReceiptView, StateChangesKindsView, StateChangesRequestView,
-    StateChangesView,
+    BlockProductionDebugInfoView, BlockView, ChunkView, EpochValidatorInfo,
+    ExecutionOutcomeWithIdView, FinalExecutionOutcomeViewEnum, GasPriceView,
+    LightClientBlockLiteView, LightClientBlockView, QueryRequest, QueryResponse, ReceiptView,
+    StateChangesKindsView, StateChangesRequestView, StateChangesView,
+    ValidatorEpochPerformanceView,
 };
 pub use near_primitives::views::{StatusResponse, StatusSyncInfo};
 
@@ -570,6 +572,90 @@ impl From<near_chain_primitives::Error> for GetValidatorInfoError {
     }
 }
 
+/// Performance of a validator in the finished epochs with heights in the given range.
+pub struct GetValidatorPerformance {
+    pub account_id: AccountId,
+    pub from_epoch_height: EpochHeight,
+    /// Defaults to the last finished epoch.
+    pub to_epoch_height: Option<EpochHeight>,
+}
+
+impl Message for GetValidatorPerformance {
+    type Result = Result<Vec<ValidatorEpochPerformanceView>, GetValidatorInfoError>;
+}
+
+/// Reloads the validator key from `ClientConfig::validator_key_file` and starts signing with it.
+/// The new key has to match the key staked for the current epoch unless `force` is set.
+pub struct ReloadValidatorKey {
+    pub force: bool,
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum ReloadValidatorKeyError {
+    #[error("Validator key file is not configured")]
+    KeyFileNotConfigured,
+    #[error("Failed to read validator key file: {0}")]
+    KeyFileError(String),
+    #[error("Key {new_key} of {account_id} does not match the key {staked_key} staked for the current epoch")]
+    KeyMismatch { account_id: AccountId, staked_key: PublicKey, new_key: PublicKey },
+    #[error("IO Error: {0}")]
+    IOError(String),
+    // NOTE: Currently, the underlying errors are too broad, and while we tried to handle
+    // expected cases, we cannot statically guarantee that no other errors will be returned
+    // in the future.
+    // TODO #3851: Remove this variant once we can exhaustively match all the underlying errors
+    #[error("It is a bug if you receive this error type, please, report this incident: https://github.com/near/nearcore/issues/new/choose. Details: {0}")]
+    Unreachable(String),
+}
+
+impl From<near_chain_primitives::Error> for ReloadValidatorKeyError {
+    fn from(error: near_chain_primitives::Error) -> Self {
+        match error.kind() {
+            near_chain_primitives::ErrorKind::IOErr(s) => Self::IOError(s),
+            _ => Self::Unreachable(error.to_string()),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct ReloadValidatorKeyResponse {
+    pub account_id: AccountId,
+    pub public_key: PublicKey,
+}
+
+impl Message for ReloadValidatorKey {
+    type Result = Result<ReloadValidatorKeyResponse, ReloadValidatorKeyError>;
+}
+
+/// State of doomslug and of the block production at the recent heights the node was the block
+/// producer for.
+pub struct GetBlockProductionDebugInfo {}
+
+#[derive(thiserror::Error, Debug)]
+pub enum GetBlockProductionDebugInfoError {
+    #[error("IO Error: {0}")]
+    IOError(String),
+    // NOTE: Currently, the underlying errors are too broad, and while we tried to handle
+    // expected cases, we cannot statically guarantee that no other errors will be returned
+    // in the future.
+    // TODO #3851: Remove this variant once we can exhaustively match all the underlying errors
+    #[error("It is a bug if you receive this error type, please, report this incident: https://github.com/near/nearcore/issues/new/choose. Details: {0}")]
+    Unreachable(String),
+}
+
+impl From<near_chain_primitives::Error> for GetBlockProductionDebugInfoError {
+    fn from(error: near_chain_primitives::Error) -> Self {
+        match error.kind() {
+            near_chain_primitives::ErrorKind::IOErr(s) => Self::IOError(s),
+            _ => Self::Unreachable(error.to_string()),
+        }
+    }
+}
+
+impl Message for GetBlockProductionDebugInfo {
+    type Result = Result<BlockProductionDebugInfoView, GetBlockProductionDebugInfoError>;
+}
+
 pub struct GetValidatorOrdered {
     pub block_id: MaybeBlockId,
 }
@@ -664,6 +750,8 @@ pub enum GetExecutionOutcomeError {
         transaction_or_receipt_id: near_primitives::hash::CryptoHash,
         shard_id: near_primitives::types::ShardId,
     },
+    #[error("Light nodes don't store execution outcomes, query a full node instead")]
+    LightNode,
     #[error("Internal error: {error_message}")]
     InternalError { error_message: String },
     // NOTE: Currently, the underlying errors are too broad, and while we tried to handle
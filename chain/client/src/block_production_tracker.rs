@@ -0,0 +1,179 @@
+use std::collections::{BTreeMap, HashMap};
+use std::time::Instant;
+
+use chrono::{DateTime, Utc};
+
+use near_primitives::types::{AccountId, BlockHeight, NumShards, ShardId};
+use near_primitives::views::{
+    ApprovalDebugView, BlockProductionDebugView, BlockProductionStatusView, ChunkReadyDebugView,
+};
+
+/// Keeps track of how the block production went at the recent heights this node was the block
+/// producer for: when approvals arrived, when chunks became ready, when the doomslug threshold was
+/// crossed and what happened to the block in the end.
+/// Only heights within a limited horizon from the latest recorded one are kept.
+#[derive(Debug, Default)]
+pub(crate) struct BlockProductionTracker {
+    heights: BTreeMap<BlockHeight, HeightInfo>,
+}
+
+const BLOCK_PRODUCTION_TRACKER_HORIZON: u64 = 50;
+
+#[derive(Debug, Default)]
+struct HeightInfo {
+    /// Last approval per account: whether it's an endorsement, and when it was received.
+    approvals: HashMap<AccountId, (bool, Instant)>,
+    chunks_ready: HashMap<ShardId, Instant>,
+    num_shards: Option<NumShards>,
+    threshold_crossed: Option<Instant>,
+    last_checked: Option<Instant>,
+    status: Option<BlockProductionStatusView>,
+}
+
+/// Converts a monotonic timestamp into wall clock time, given the current value of both clocks.
+pub(crate) fn to_utc(instant: Instant, now: Instant, now_utc: DateTime<Utc>) -> DateTime<Utc> {
+    now_utc
+        - chrono::Duration::from_std(now.saturating_duration_since(instant))
+            .unwrap_or_else(|_| chrono::Duration::zero())
+}
+
+impl BlockProductionTracker {
+    fn height_info(&mut self, height: BlockHeight) -> &mut HeightInfo {
+        let lowest_height = height.saturating_sub(BLOCK_PRODUCTION_TRACKER_HORIZON);
+        if self.heights.keys().next().map_or(false, |&h| h < lowest_height) {
+            self.heights = self.heights.split_off(&lowest_height);
+        }
+        self.heights.entry(height).or_default()
+    }
+
+    /// Records an approval for the block at `target_height`. Approvals too far ahead of the
+    /// doomslug tip are ignored, so that they don't push the recent heights out of the horizon.
+    pub fn record_approval(
+        &mut self,
+        target_height: BlockHeight,
+        tip_height: BlockHeight,
+        account_id: AccountId,
+        is_endorsement: bool,
+        timestamp: Instant,
+    ) {
+        if target_height > tip_height + BLOCK_PRODUCTION_TRACKER_HORIZON {
+            return;
+        }
+        self.height_info(target_height).approvals.insert(account_id, (is_endorsement, timestamp));
+    }
+
+    /// Records a check whether the block at `height` can be produced. Only the first time each
+    /// shard was seen ready is kept.
+    pub fn record_check(
+        &mut self,
+        height: BlockHeight,
+        shards_ready: Vec<ShardId>,
+        num_shards: NumShards,
+        threshold_crossed: Option<Instant>,
+        timestamp: Instant,
+    ) {
+        let info = self.height_info(height);
+        for shard_id in shards_ready {
+            info.chunks_ready.entry(shard_id).or_insert(timestamp);
+        }
+        info.num_shards = Some(num_shards);
+        if info.threshold_crossed.is_none() {
+            info.threshold_crossed = threshold_crossed;
+        }
+        info.last_checked = Some(timestamp);
+    }
+
+    pub fn record_status(&mut self, height: BlockHeight, status: BlockProductionStatusView) {
+        self.height_info(height).status = Some(status);
+    }
+
+    /// Returns the recorded heights, latest first.
+    pub fn get_debug_info(
+        &self,
+        now: Instant,
+        now_utc: DateTime<Utc>,
+    ) -> Vec<BlockProductionDebugView> {
+        let to_utc = |instant: Instant| to_utc(instant, now, now_utc);
+        self.heights
+            .iter()
+            .rev()
+            .map(|(&height, info)| {
+                let mut approvals: Vec<_> = info
+                    .approvals
+                    .iter()
+                    .map(|(account_id, &(is_endorsement, received))| ApprovalDebugView {
+                        account_id: account_id.clone(),
+                        is_endorsement,
+                        received_at: to_utc(received),
+                    })
+                    .collect();
+                approvals.sort_by_key(|approval| approval.received_at);
+                let mut chunks_ready: Vec<_> = info
+                    .chunks_ready
+                    .iter()
+                    .map(|(&shard_id, &ready)| ChunkReadyDebugView {
+                        shard_id,
+                        ready_at: to_utc(ready),
+                    })
+                    .collect();
+                chunks_ready.sort_by_key(|chunk| chunk.shard_id);
+                BlockProductionDebugView {
+                    height,
+                    approvals,
+                    chunks_ready,
+                    num_shards: info.num_shards,
+                    threshold_crossed_at: info.threshold_crossed.map(to_utc),
+                    last_checked_at: info.last_checked.map(to_utc),
+                    status: info.status.clone(),
+                }
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn test_block_production_tracker() {
+        let start = Instant::now();
+        let start_utc = Utc::now();
+        let mut tracker = BlockProductionTracker::default();
+
+        tracker.record_approval(3, 2, "test1".parse().unwrap(), true, start);
+        tracker.record_approval(3, 2, "test2".parse().unwrap(), false, start);
+        // Too far ahead of the tip.
+        tracker.record_approval(1000, 2, "test1".parse().unwrap(), true, start);
+        tracker.record_check(3, vec![1], 2, None, start + Duration::from_secs(1));
+        tracker.record_check(3, vec![0, 1], 2, Some(start), start + Duration::from_secs(2));
+        tracker.record_status(3, BlockProductionStatusView::WaitingForChunks);
+
+        let now = start + Duration::from_secs(10);
+        let now_utc = start_utc + chrono::Duration::seconds(10);
+        let info = tracker.get_debug_info(now, now_utc);
+        assert_eq!(info.len(), 1);
+        assert_eq!(info[0].height, 3);
+        assert_eq!(info[0].approvals.len(), 2);
+        assert_eq!(info[0].threshold_crossed_at, Some(start_utc));
+        assert_eq!(
+            info[0].chunks_ready.iter().map(|chunk| chunk.ready_at).collect::<Vec<_>>(),
+            vec![
+                start_utc + chrono::Duration::seconds(2),
+                start_utc + chrono::Duration::seconds(1)
+            ]
+        );
+        assert_eq!(info[0].last_checked_at, Some(start_utc + chrono::Duration::seconds(2)));
+        assert_eq!(info[0].status, Some(BlockProductionStatusView::WaitingForChunks));
+
+        // Heights beyond the horizon are forgotten.
+        tracker.record_status(
+            3 + BLOCK_PRODUCTION_TRACKER_HORIZON + 1,
+            BlockProductionStatusView::WaitingForApprovals,
+        );
+        let info = tracker.get_debug_info(now, now_utc);
+        assert_eq!(info.len(), 1);
+        assert_eq!(info[0].height, 3 + BLOCK_PRODUCTION_TRACKER_HORIZON + 1);
+    }
+}
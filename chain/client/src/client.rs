@@ -18,28 +18,34 @@ use near_chain::{
     BlockStatus, Chain, ChainGenesis, ChainStoreAccess, Doomslug, DoomslugThresholdMode, ErrorKind,
     Provenance, RuntimeAdapter,
 };
-use near_chain_configs::ClientConfig;
+use near_chain_configs::{ClientConfig, SigningJournalConfig};
 use near_chunks::{ProcessPartialEncodedChunkResult, ShardsManager};
 use near_network::types::{
     FullPeerInfo, NetworkClientResponses, NetworkRequests, PeerManagerAdapter,
 };
 use near_primitives::block::{Approval, ApprovalInner, ApprovalMessage, Block, BlockHeader, Tip};
 use near_primitives::challenge::{Challenge, ChallengeBody};
-use near_primitives::hash::CryptoHash;
+use near_primitives::hash::{hash, CryptoHash};
 use near_primitives::merkle::{merklize, MerklePath};
+use near_primitives::network::PeerId;
 use near_primitives::receipt::Receipt;
 use near_primitives::sharding::{
     EncodedShardChunk, PartialEncodedChunk, PartialEncodedChunkV2, ReedSolomonWrapper,
     ShardChunkHeader, ShardInfo,
 };
+use near_primitives::syncing::get_num_state_parts;
 use near_primitives::transaction::SignedTransaction;
 use near_primitives::types::chunk_extra::ChunkExtra;
 use near_primitives::types::{AccountId, ApprovalStake, BlockHeight, EpochId, NumBlocks, ShardId};
 use near_primitives::unwrap_or_return;
 use near_primitives::utils::{to_timestamp, MaybeValidated};
 use near_primitives::validator_signer::ValidatorSigner;
+use near_primitives::views::{BlockProductionDebugInfoView, BlockProductionStatusView};
+use near_store::signing_journal::{SignedMessageKind, SigningJournal, SigningJournalMode};
 
+use crate::block_production_tracker::{to_utc, BlockProductionTracker};
 use crate::chunks_delay_tracker::ChunksDelayTracker;
+use crate::state_parts_storage::{spawn_dump_state_parts, StatePartsStorage};
 use crate::sync::{BlockSync, EpochSync, HeaderSync, StateSync, StateSyncResult};
 use crate::{metrics, SyncStatus};
 use near_client_primitives::types::{Error, ShardSyncDownload, ShardSyncStatus};
@@ -53,6 +59,10 @@ use near_primitives::version::PROTOCOL_VERSION;
 
 const NUM_REBROADCAST_BLOCKS: usize = 30;
 
+/// Number of recent sync hashes to remember the state parts were dumped for, so that they are
+/// dumped once even if the first block of an epoch becomes the head more than once.
+const NUM_DUMPED_STATE_PARTS_SYNC_HASHES: usize = 10;
+
 /// The time we wait for the response to a Epoch Sync request before retrying
 // TODO #3488 set 30_000
 pub const EPOCH_SYNC_REQUEST_TIMEOUT: Duration = Duration::from_millis(1_000);
@@ -103,6 +113,16 @@ pub struct Client {
     last_time_head_progress_made: Instant,
     /// Keeps track of when the latest blocks and chunks were received.
     chunks_delay_tracker: ChunksDelayTracker,
+    /// Keeps track of the recent heights we were the block producer for.
+    pub(crate) block_production_tracker: BlockProductionTracker,
+    /// Journal of the signed blocks, chunks and approvals, checked before releasing them.
+    signing_journal: Option<SigningJournal>,
+    /// Where to fetch state parts from during state sync, before requesting them from peers.
+    state_parts_fetch: Option<Arc<StatePartsStorage>>,
+    /// Where to dump the state parts of the tracked shards at the start of every epoch.
+    state_parts_dump: Option<Arc<StatePartsStorage>>,
+    /// Sync hashes the state parts were dumped for recently.
+    dumped_state_parts: lru::LruCache<CryptoHash, ()>,
 }
 
 impl Client {
@@ -153,11 +173,37 @@ impl Client {
         );
         let block_sync =
             BlockSync::new(network_adapter.clone(), config.block_fetch_horizon, config.archive);
-        let state_sync = StateSync::new(network_adapter.clone(), config.state_sync_timeout);
+        let state_parts_fetch = config
+            .state_parts
+            .fetch
+            .as_ref()
+            .map(|location| Arc::new(StatePartsStorage::new(&config.chain_id, location)));
+        let state_parts_dump = config
+            .state_parts
+            .dump
+            .as_ref()
+            .map(|location| Arc::new(StatePartsStorage::new(&config.chain_id, location)));
+        let state_sync = StateSync::new(
+            network_adapter.clone(),
+            config.state_sync_timeout,
+            state_parts_fetch.clone(),
+        );
         let num_block_producer_seats = config.num_block_producer_seats as usize;
         let data_parts = runtime_adapter.num_data_parts();
         let parity_parts = runtime_adapter.num_total_parts() - data_parts;
 
+        let signing_journal = config.signing_journal.as_ref().map(|signing_journal| {
+            let mode = match signing_journal {
+                SigningJournalConfig::Local => SigningJournalMode::Local,
+                SigningJournalConfig::SharedFile { path } => {
+                    SigningJournalMode::SharedFile(path.clone())
+                }
+                SigningJournalConfig::LocalSocket { path } => {
+                    SigningJournalMode::LocalSocket(path.clone())
+                }
+            };
+            SigningJournal::new(chain.store().store().clone(), mode)
+        });
         let doomslug = Doomslug::new(
             chain.store().largest_target_height()?,
             config.min_block_production_delay,
@@ -191,6 +237,59 @@ impl Client {
             rebroadcasted_blocks: lru::LruCache::new(NUM_REBROADCAST_BLOCKS),
             last_time_head_progress_made: Clock::instant(),
             chunks_delay_tracker: Default::default(),
+            block_production_tracker: Default::default(),
+            signing_journal,
+            state_parts_fetch,
+            state_parts_dump,
+            dumped_state_parts: lru::LruCache::new(NUM_DUMPED_STATE_PARTS_SYNC_HASHES),
+        })
+    }
+
+    /// Records a signed message in the signing journal before it is released. Returns false if
+    /// the message conflicts with one signed before and must not be released.
+    fn check_signing_journal(
+        &self,
+        kind: SignedMessageKind,
+        height: BlockHeight,
+        hash: CryptoHash,
+    ) -> bool {
+        let signing_journal = match &self.signing_journal {
+            Some(signing_journal) => signing_journal,
+            None => return true,
+        };
+        match signing_journal.check_and_record(kind, height, hash) {
+            Ok(()) => true,
+            Err(err) => {
+                error!(target: "client", "Not releasing signed {} at height {}: {}", kind, height, err);
+                false
+            }
+        }
+    }
+
+    /// Replaces the validator signer used for producing blocks, chunks and approvals.
+    pub fn set_validator_signer(&mut self, validator_signer: Option<Arc<dyn ValidatorSigner>>) {
+        self.shards_mgr.set_me(validator_signer.as_ref().map(|x| x.validator_id().clone()));
+        self.doomslug.set_signer(validator_signer.clone());
+        self.validator_signer = validator_signer;
+    }
+
+    /// Returns the state of doomslug and of the block production at the recent heights we were
+    /// the block producer for.
+    pub fn get_block_production_debug_info(
+        &self,
+    ) -> Result<BlockProductionDebugInfoView, near_chain::Error> {
+        let head = self.chain.head()?;
+        let now = Clock::instant();
+        let now_utc = Clock::utc();
+        Ok(BlockProductionDebugInfoView {
+            head_height: head.height,
+            largest_final_height: self.doomslug.get_largest_final_height(),
+            largest_threshold_height: self.doomslug.get_largest_height_crossing_threshold(),
+            largest_target_height: self.doomslug.get_largest_target_height(),
+            timer_height: self.doomslug.get_timer_height(),
+            timer_started_at: to_utc(self.doomslug.get_timer_start(), now, now_utc),
+            timer_delay_ms: self.doomslug.get_timer_delay().as_millis() as u64,
+            heights: self.block_production_tracker.get_debug_info(now, now_utc),
         })
     }
 
@@ -371,6 +470,12 @@ impl Client {
             validator_signer.validator_id(),
             &next_block_proposer,
         )? {
+            self.block_production_tracker.record_status(
+                next_height,
+                BlockProductionStatusView::Skipped {
+                    reason: "a block at the height or a greater one is known".to_string(),
+                },
+            );
             return Ok(None);
         }
         let (validator_stake, _) = self.runtime_adapter.get_validator_by_account_id(
@@ -382,6 +487,16 @@ impl Client {
         let validator_pk = validator_stake.take_public_key();
         if validator_pk != validator_signer.public_key() {
             debug!(target: "client", "Local validator key {} does not match expected validator key {}, skipping block production", validator_signer.public_key(), validator_pk);
+            self.block_production_tracker.record_status(
+                next_height,
+                BlockProductionStatusView::Skipped {
+                    reason: format!(
+                        "local validator key {} does not match expected validator key {}",
+                        validator_signer.public_key(),
+                        validator_pk
+                    ),
+                },
+            );
             #[cfg(not(feature = "test_features"))]
             return Ok(None);
             #[cfg(feature = "test_features")]
@@ -397,9 +512,14 @@ impl Client {
         // If we are producing empty blocks and there are no transactions.
         if !self.config.produce_empty_blocks && new_chunks.is_empty() {
             debug!(target: "client", "Empty blocks, skipping block production");
+            self.block_production_tracker.record_status(
+                next_height,
+                BlockProductionStatusView::Skipped { reason: "no new chunks".to_string() },
+            );
             return Ok(None);
         }
 
+        let num_new_chunks = new_chunks.len();
         let mut approvals_map = self.doomslug.remove_witness(&prev_hash, prev_height, next_height);
 
         // At this point, the previous epoch hash must be available
@@ -494,7 +614,7 @@ impl Client {
         let next_epoch_protocol_version =
             self.runtime_adapter.get_epoch_protocol_version(&next_epoch_id)?;
 
-        let block = Block::produce(
+        let block = match Block::produce(
             this_epoch_protocol_version,
             next_epoch_protocol_version,
             prev_header,
@@ -514,7 +634,27 @@ impl Client {
             &*validator_signer,
             next_bp_hash,
             block_merkle_root,
-        );
+        ) {
+            Ok(block) => block,
+            Err(err) => {
+                warn!(target: "client", "Not producing block at height {}: {}", next_height, err);
+                self.block_production_tracker.record_status(
+                    next_height,
+                    BlockProductionStatusView::Skipped { reason: err.to_string() },
+                );
+                return Ok(None);
+            }
+        };
+
+        if !self.check_signing_journal(SignedMessageKind::Block, next_height, *block.hash()) {
+            self.block_production_tracker.record_status(
+                next_height,
+                BlockProductionStatusView::Skipped {
+                    reason: "refused by the signing journal".to_string(),
+                },
+            );
+            return Ok(None);
+        }
 
         // Update latest known even before returning block out, to prevent race conditions.
         self.chain.mut_store().save_latest_known(LatestKnown {
@@ -523,6 +663,14 @@ impl Client {
         })?;
 
         metrics::BLOCK_PRODUCED_TOTAL.inc();
+        self.block_production_tracker.record_status(
+            next_height,
+            BlockProductionStatusView::Produced {
+                block_hash: *block.hash(),
+                num_chunks: num_new_chunks,
+                produced_at: Clock::utc(),
+            },
+        );
 
         Ok(Some(block))
     }
@@ -622,6 +770,14 @@ impl Client {
             protocol_version,
         )?;
 
+        if !self.check_signing_journal(
+            SignedMessageKind::Chunk(shard_id),
+            next_height,
+            encoded_chunk.chunk_hash().0,
+        ) {
+            return Ok(None);
+        }
+
         debug!(
             target: "client",
             "Produced chunk at height {} for shard {} with {} txs and {} receipts, I'm {}, chunk_hash: {}",
@@ -687,7 +843,13 @@ impl Client {
     pub fn send_challenges(&mut self, challenges: Vec<ChallengeBody>) {
         if let Some(validator_signer) = &self.validator_signer {
             for body in challenges {
-                let challenge = Challenge::produce(body, &**validator_signer);
+                let challenge = match Challenge::produce(body, &**validator_signer) {
+                    Ok(challenge) => challenge,
+                    Err(err) => {
+                        warn!(target: "client", "Not sending challenge: {}", err);
+                        continue;
+                    }
+                };
                 self.challenges.insert(challenge.hash, challenge.clone());
                 self.network_adapter.do_send(PeerManagerMessageRequest::NetworkRequests(
                     NetworkRequests::Challenge(challenge),
@@ -751,27 +913,26 @@ impl Client {
 
         // Send out challenge if the block was found to be invalid.
         if let Some(validator_signer) = self.validator_signer.as_ref() {
-            match &result {
+            let body = match &result {
                 Err(e) => match e.kind() {
                     near_chain::ErrorKind::InvalidChunkProofs(chunk_proofs) => {
-                        self.network_adapter.do_send(PeerManagerMessageRequest::NetworkRequests(
-                            NetworkRequests::Challenge(Challenge::produce(
-                                ChallengeBody::ChunkProofs(*chunk_proofs),
-                                &**validator_signer,
-                            )),
-                        ));
+                        Some(ChallengeBody::ChunkProofs(*chunk_proofs))
                     }
                     near_chain::ErrorKind::InvalidChunkState(chunk_state) => {
-                        self.network_adapter.do_send(PeerManagerMessageRequest::NetworkRequests(
-                            NetworkRequests::Challenge(Challenge::produce(
-                                ChallengeBody::ChunkState(*chunk_state),
-                                &**validator_signer,
-                            )),
-                        ));
+                        Some(ChallengeBody::ChunkState(*chunk_state))
                     }
-                    _ => {}
+                    _ => None,
                 },
-                _ => {}
+                _ => None,
+            };
+            match body.map(|body| Challenge::produce(body, &**validator_signer)) {
+                Some(Ok(challenge)) => {
+                    self.network_adapter.do_send(PeerManagerMessageRequest::NetworkRequests(
+                        NetworkRequests::Challenge(challenge),
+                    ));
+                }
+                Some(Err(err)) => warn!(target: "client", "Not sending challenge: {}", err),
+                None => {}
             }
         }
 
@@ -794,15 +955,28 @@ impl Client {
         }
     }
 
+    /// Processes a response from `peer_id` to our request for parts of a chunk, and updates the
+    /// score of the peer accordingly.
     pub fn process_partial_encoded_chunk_response(
         &mut self,
         response: PartialEncodedChunkResponseMsg,
+        peer_id: PeerId,
     ) -> Result<Vec<AcceptedBlock>, Error> {
+        let last_requested = self.shards_mgr.chunk_last_requested(&response.chunk_hash);
         let header = self.shards_mgr.get_partial_encoded_chunk_header(&response.chunk_hash)?;
         let partial_chunk = PartialEncodedChunk::new(header, response.parts, response.receipts);
         // We already know the header signature is valid because we read it from the
         // shard manager.
-        self.process_partial_encoded_chunk(MaybeValidated::from_validated(partial_chunk))
+        let result =
+            self.process_partial_encoded_chunk(MaybeValidated::from_validated(partial_chunk));
+        match &result {
+            Ok(_) => self.shards_mgr.update_chunk_responder_score(peer_id, last_requested, None),
+            Err(Error::Chunk(err)) => {
+                self.shards_mgr.update_chunk_responder_score(peer_id, last_requested, Some(err))
+            }
+            Err(_) => {}
+        }
+        result
     }
 
     pub fn process_partial_encoded_chunk_forward(
@@ -915,9 +1089,11 @@ impl Client {
         headers: Vec<BlockHeader>,
     ) -> Result<(), near_chain::Error> {
         let mut challenges = vec![];
+        // Light nodes never process blocks, so the light client blocks are saved from headers.
+        let light_node_headers = if self.config.light_node { headers.clone() } else { vec![] };
         self.chain.sync_block_headers(headers, &mut |challenge| challenges.push(challenge))?;
         self.send_challenges(challenges);
-        Ok(())
+        self.chain.save_epoch_light_client_blocks(&light_node_headers)
     }
 
     /// Checks if the latest hash known to Doomslug matches the current head, and updates it if not.
@@ -960,11 +1136,70 @@ impl Client {
         Ok(())
     }
 
+    /// Submits a transaction acting on behalf of `signer_id` without its keys.
+    ///
+    /// The transaction uses the empty public key and is registered with the runtime, which then
+    /// skips its signature, access key and nonce checks. The nonce is only used to make the
+    /// transaction hash unique.
+    #[cfg(feature = "sandbox")]
+    pub fn sandbox_impersonate(
+        &mut self,
+        signer_id: AccountId,
+        receiver_id: AccountId,
+        actions: Vec<near_primitives::transaction::Action>,
+    ) -> Result<(CryptoHash, NetworkClientResponses), Error> {
+        let head = self.chain.head()?;
+        let transaction = near_primitives::transaction::Transaction {
+            signer_id,
+            public_key: near_crypto::PublicKey::empty(near_crypto::KeyType::ED25519),
+            nonce: to_timestamp(Clock::utc()),
+            receiver_id,
+            block_hash: head.last_block_hash,
+            actions,
+        };
+        let tx = SignedTransaction::new(
+            near_crypto::Signature::empty(near_crypto::KeyType::ED25519),
+            transaction,
+        );
+        let tx_hash = tx.get_hash();
+        self.runtime_adapter.sandbox_impersonate_transaction(tx_hash);
+        Ok((tx_hash, self.process_tx(tx, false, false)))
+    }
+
+    /// Reverts the chain to the given snapshot and makes the next block build on top of it.
+    #[cfg(feature = "sandbox")]
+    pub fn sandbox_revert(&mut self, snapshot_id: u64) -> Result<(), Error> {
+        self.chain.sandbox_revert(snapshot_id)?;
+        // Transactions, chunks and approvals received since the snapshot were validated against
+        // the abandoned fork, so they are dropped along with the approvals tracked by doomslug.
+        self.shards_mgr.clear_pools_and_caches();
+        self.pending_approvals.clear();
+        self.doomslug = Doomslug::new(
+            self.chain.store().largest_target_height()?,
+            self.config.min_block_production_delay,
+            self.config.max_block_production_delay,
+            self.config.max_block_production_delay / 10,
+            self.config.max_block_wait_delay,
+            self.validator_signer.clone(),
+            self.chain.doomslug_threshold_mode,
+        );
+        // Heights produced on the abandoned fork can't be reused.
+        let latest_known = self.chain.mut_store().get_latest_known()?;
+        self.sandbox_update_tip(latest_known.height)
+    }
+
     pub fn send_approval(
         &mut self,
         parent_hash: &CryptoHash,
         approval: Approval,
     ) -> Result<(), Error> {
+        if !self.check_signing_journal(
+            SignedMessageKind::Approval,
+            approval.target_height,
+            hash(&Approval::get_data_for_sig(&approval.inner, approval.target_height)),
+        ) {
+            return Ok(());
+        }
         let next_epoch_id = self.runtime_adapter.get_epoch_id_from_prev_block(parent_hash)?;
         let next_block_producer =
             self.runtime_adapter.get_block_producer(&next_epoch_id, approval.target_height)?;
@@ -981,6 +1216,64 @@ impl Client {
         Ok(())
     }
 
+    /// If `block` is the first block of an epoch, i.e. the sync hash for state sync to the epoch,
+    /// dumps the state parts of the tracked shards to the configured storage.
+    fn dump_state_parts(&mut self, block: &Block) {
+        let storage = match &self.state_parts_dump {
+            Some(storage) => storage.clone(),
+            None => return,
+        };
+        let sync_hash = *block.hash();
+        let sync_prev_hash = *block.header().prev_hash();
+        let is_epoch_start = match self.chain.get_block_header(&sync_prev_hash) {
+            Ok(header) => header.epoch_id() != block.header().epoch_id(),
+            // The genesis block has no state to sync.
+            Err(_) => return,
+        };
+        if !is_epoch_start || self.dumped_state_parts.put(sync_hash, ()).is_some() {
+            return;
+        }
+        let sync_prev_block = match self.chain.get_block(&sync_prev_hash) {
+            Ok(block) => block.clone(),
+            Err(err) => {
+                error!(target: "client", "Can't dump state parts at {}: {}", sync_hash, err);
+                return;
+            }
+        };
+
+        let me =
+            self.validator_signer.as_ref().map(|validator_signer| validator_signer.validator_id());
+        for (shard_id, chunk) in sync_prev_block.chunks().iter().enumerate() {
+            let shard_id = shard_id as ShardId;
+            if !self.runtime_adapter.cares_about_shard(me, &sync_prev_hash, shard_id, true) {
+                continue;
+            }
+            let state_root = chunk.prev_state_root();
+            let state_root_node = match self.runtime_adapter.get_state_root_node(
+                shard_id,
+                &sync_prev_hash,
+                &state_root,
+            ) {
+                Ok(state_root_node) => state_root_node,
+                Err(err) => {
+                    error!(target: "client", "Can't dump state parts of shard {} at {}: {}", shard_id, sync_hash, err);
+                    continue;
+                }
+            };
+            let num_parts = get_num_state_parts(state_root_node.memory_usage);
+            info!(target: "client", "Dumping {} state parts of shard {} at {}", num_parts, shard_id, sync_hash);
+            spawn_dump_state_parts(
+                storage.clone(),
+                self.runtime_adapter.clone(),
+                shard_id,
+                sync_hash,
+                sync_prev_hash,
+                state_root,
+                num_parts,
+            );
+        }
+    }
+
     /// Gets called when block got accepted.
     /// Send updates over network, update tx pool and notify ourselves if it's time to produce next block.
     /// Blocks are passed in no particular order.
@@ -1063,6 +1356,8 @@ impl Client {
                     panic!("The client protocol version is older than the protocol version of the network. Please update nearcore");
                 }
             }
+
+            self.dump_state_parts(&block);
         }
 
         if let Some(validator_signer) = self.validator_signer.clone() {
@@ -1405,7 +1700,15 @@ impl Client {
                     return;
                 }
             };
-        self.doomslug.on_approval_message(Clock::instant(), approval, &block_producer_stakes);
+        let now = Clock::instant();
+        self.block_production_tracker.record_approval(
+            *target_height,
+            self.doomslug.get_tip().1,
+            account_id.clone(),
+            matches!(inner, ApprovalInner::Endorsement(_)),
+            now,
+        );
+        self.doomslug.on_approval_message(now, approval, &block_producer_stakes);
     }
 
     /// Forwards given transaction to upcoming validators.
@@ -1683,11 +1986,12 @@ impl Client {
                 }
             };
             let state_sync_timeout = self.config.state_sync_timeout;
+            let state_parts_fetch = self.state_parts_fetch.clone();
             let epoch_id = self.chain.get_block(&sync_hash)?.header().epoch_id().clone();
             let (state_sync, new_shard_sync, blocks_catch_up_state) =
                 self.catchup_state_syncs.entry(sync_hash).or_insert_with(|| {
                     (
-                        StateSync::new(network_adapter1, state_sync_timeout),
+                        StateSync::new(network_adapter1, state_sync_timeout, state_parts_fetch),
                         new_shard_sync,
                         BlocksCatchUpState::new(sync_hash, epoch_id),
                     )
@@ -21,8 +21,9 @@ use near_chain::{
 };
 use near_chain_configs::ClientConfig;
 use near_client_primitives::types::{
-    Error, GetNetworkInfo, NetworkInfoResponse, ShardSyncDownload, ShardSyncStatus, Status,
-    StatusError, StatusSyncInfo, SyncStatus,
+    Error, GetBlockProductionDebugInfo, GetBlockProductionDebugInfoError, GetNetworkInfo,
+    NetworkInfoResponse, ReloadValidatorKey, ReloadValidatorKeyError, ReloadValidatorKeyResponse,
+    ShardSyncDownload, ShardSyncStatus, Status, StatusError, StatusSyncInfo, SyncStatus,
 };
 use near_network::types::{
     NetworkClientMessages, NetworkClientResponses, NetworkInfo, NetworkRequests,
@@ -37,12 +38,14 @@ use near_primitives::hash::CryptoHash;
 use near_primitives::network::{AnnounceAccount, PeerId};
 use near_primitives::syncing::StatePartKey;
 use near_primitives::time::{Clock, Utc};
-use near_primitives::types::BlockHeight;
+use near_primitives::types::{AccountId, BlockHeight, EpochId, NumShards};
 use near_primitives::unwrap_or_return;
 use near_primitives::utils::{from_timestamp, MaybeValidated};
-use near_primitives::validator_signer::ValidatorSigner;
+use near_primitives::validator_signer::{InMemoryValidatorSigner, ValidatorSigner};
 use near_primitives::version::PROTOCOL_VERSION;
-use near_primitives::views::ValidatorInfo;
+use near_primitives::views::{
+    BlockProductionDebugInfoView, BlockProductionStatusView, ValidatorInfo,
+};
 use near_store::db::DBCol::ColStateParts;
 use near_telemetry::TelemetryActor;
 use rand::seq::SliceRandom;
@@ -75,6 +78,8 @@ pub struct ClientActor {
     node_id: PeerId,
     /// Last time we announced our accounts as validators.
     last_validator_announce_time: Option<Instant>,
+    /// Validators we last asked the network to connect to directly, and the epoch we did it in.
+    validator_peers: Option<(EpochId, Vec<AccountId>)>,
     /// Info helper.
     info_helper: InfoHelper,
 
@@ -178,6 +183,7 @@ impl ClientActor {
                 peer_counter: 0,
             },
             last_validator_announce_time: None,
+            validator_peers: None,
             info_helper,
             block_production_next_attempt: now,
             log_summary_timer_next_attempt: now,
@@ -368,6 +374,37 @@ impl Handler<NetworkClientMessages> for ClientActor {
                         self.fastforward_delta = Some(delta_height);
                         NetworkClientResponses::NoResponse
                     }
+                    near_network_primitives::types::NetworkSandboxMessage::SandboxSnapshot => {
+                        NetworkClientResponses::SandboxResult(
+                            near_network_primitives::types::SandboxResponse::SandboxSnapshotCreated(
+                                self.client.chain.sandbox_snapshot().map_err(|err| err.to_string()),
+                            ),
+                        )
+                    }
+                    near_network_primitives::types::NetworkSandboxMessage::SandboxDeleteSnapshot(snapshot_id) => {
+                        NetworkClientResponses::SandboxResult(
+                            near_network_primitives::types::SandboxResponse::SandboxSnapshotDeleted(
+                                self.client.chain.sandbox_delete_snapshot(snapshot_id).map_err(|err| err.to_string()),
+                            ),
+                        )
+                    }
+                    near_network_primitives::types::NetworkSandboxMessage::SandboxRevert(snapshot_id) => {
+                        NetworkClientResponses::SandboxResult(
+                            near_network_primitives::types::SandboxResponse::SandboxReverted(
+                                self.client.sandbox_revert(snapshot_id).map_err(|err| err.to_string()),
+                            ),
+                        )
+                    }
+                    near_network_primitives::types::NetworkSandboxMessage::SandboxImpersonate { signer_id, receiver_id, actions } => {
+                        let result = match self.client.sandbox_impersonate(signer_id, receiver_id, actions) {
+                            Ok((_, NetworkClientResponses::InvalidTx(err))) => Err(err.to_string()),
+                            Ok((tx_hash, _)) => Ok(tx_hash),
+                            Err(err) => Err(err.to_string()),
+                        };
+                        NetworkClientResponses::SandboxResult(
+                            near_network_primitives::types::SandboxResponse::SandboxImpersonated(result),
+                        )
+                    }
                 };
             }
             NetworkClientMessages::Transaction { transaction, is_forwarded, check_only } => {
@@ -547,13 +584,35 @@ impl Handler<NetworkClientMessages> for ClientActor {
 
                 NetworkClientResponses::NoResponse
             }
-            NetworkClientMessages::EpochSyncResponse(_peer_id, _response) => {
-                // TODO #3488
-                NetworkClientResponses::NoResponse
+            NetworkClientMessages::EpochSyncResponse(peer_id, response) => {
+                match self.client.epoch_sync.on_response(peer_id, *response) {
+                    Ok(()) => NetworkClientResponses::NoResponse,
+                    Err(err) => {
+                        warn!(target: "sync", "Banning node for sending invalid epoch sync response: {}", err);
+                        NetworkClientResponses::Ban {
+                            ban_reason: ReasonForBan::EpochSyncInvalidResponse,
+                        }
+                    }
+                }
             }
-            NetworkClientMessages::EpochSyncFinalizationResponse(_peer_id, _response) => {
-                // TODO #3488
-                NetworkClientResponses::NoResponse
+            NetworkClientMessages::EpochSyncFinalizationResponse(peer_id, response) => {
+                match self.client.epoch_sync.on_finalization_response(
+                    &mut self.client.chain,
+                    peer_id,
+                    *response,
+                ) {
+                    Ok(()) => NetworkClientResponses::NoResponse,
+                    Err(err) if err.is_bad_data() => {
+                        warn!(target: "sync", "Banning node for sending invalid epoch sync finalization response: {}", err);
+                        NetworkClientResponses::Ban {
+                            ban_reason: ReasonForBan::EpochSyncInvalidFinalizationResponse,
+                        }
+                    }
+                    Err(err) => {
+                        error!(target: "sync", "Failed to finalize epoch sync: {}", err);
+                        NetworkClientResponses::NoResponse
+                    }
+                }
             }
             NetworkClientMessages::PartialEncodedChunkRequest(part_request_msg, route_back) => {
                 let _ = self.client.shards_mgr.process_partial_encoded_chunk_request(
@@ -563,9 +622,9 @@ impl Handler<NetworkClientMessages> for ClientActor {
                 );
                 NetworkClientResponses::NoResponse
             }
-            NetworkClientMessages::PartialEncodedChunkResponse(response) => {
+            NetworkClientMessages::PartialEncodedChunkResponse(response, peer_id) => {
                 if let Ok(accepted_blocks) =
-                    self.client.process_partial_encoded_chunk_response(response)
+                    self.client.process_partial_encoded_chunk_response(response, peer_id)
                 {
                     self.process_accepted_blocks(accepted_blocks);
                 }
@@ -616,7 +675,12 @@ impl Handler<Status> for ClientActor {
         let _d = delay_detector::DelayDetector::new(|| "client status".into());
         self.check_triggers(ctx);
 
-        let head = self.client.chain.head()?;
+        // Light nodes report the progress of the header chain.
+        let head = if self.client.config.light_node {
+            self.client.chain.header_head()?
+        } else {
+            self.client.chain.head()?
+        };
         let head_header = self.client.chain.get_block_header(&head.last_block_hash)?;
         let latest_block_time = head_header.raw_timestamp();
         let latest_state_root = (*head_header.prev_state_root()).into();
@@ -695,6 +759,76 @@ impl Handler<Status> for ClientActor {
     }
 }
 
+impl Handler<GetBlockProductionDebugInfo> for ClientActor {
+    type Result = Result<BlockProductionDebugInfoView, GetBlockProductionDebugInfoError>;
+
+    #[perf]
+    fn handle(
+        &mut self,
+        _msg: GetBlockProductionDebugInfo,
+        ctx: &mut Context<Self>,
+    ) -> Self::Result {
+        let _d =
+            delay_detector::DelayDetector::new(|| "client get block production debug info".into());
+        self.check_triggers(ctx);
+
+        Ok(self.client.get_block_production_debug_info()?)
+    }
+}
+
+impl Handler<ReloadValidatorKey> for ClientActor {
+    type Result = Result<ReloadValidatorKeyResponse, ReloadValidatorKeyError>;
+
+    #[perf]
+    fn handle(&mut self, msg: ReloadValidatorKey, ctx: &mut Context<Self>) -> Self::Result {
+        let _d = delay_detector::DelayDetector::new(|| "client reload validator key".into());
+        self.check_triggers(ctx);
+
+        let path = self
+            .client
+            .config
+            .validator_key_file
+            .as_ref()
+            .ok_or(ReloadValidatorKeyError::KeyFileNotConfigured)?;
+        let signer = InMemoryValidatorSigner::try_from_file(path)
+            .map_err(|err| ReloadValidatorKeyError::KeyFileError(err.to_string()))?;
+        let account_id = signer.validator_id().clone();
+        let public_key = signer.public_key();
+
+        let head = self.client.chain.head()?;
+        match self.client.runtime_adapter.get_validator_by_account_id(
+            &head.epoch_id,
+            &head.last_block_hash,
+            &account_id,
+        ) {
+            Ok((validator_stake, _)) => {
+                let staked_key = validator_stake.take_public_key();
+                if staked_key != public_key {
+                    if !msg.force {
+                        return Err(ReloadValidatorKeyError::KeyMismatch {
+                            account_id,
+                            staked_key,
+                            new_key: public_key,
+                        });
+                    }
+                    warn!(target: "client", "Forcing validator key {} for {}, which does not match the staked key {}", public_key, account_id, staked_key);
+                }
+            }
+            Err(err) if err.kind() == near_chain_primitives::ErrorKind::NotAValidator => {}
+            Err(err) => return Err(err.into()),
+        }
+
+        info!(target: "client", "Switching validator key to {} for {}", public_key, account_id);
+        let signer = Some(Arc::new(signer) as Arc<dyn ValidatorSigner>);
+        self.client.set_validator_signer(signer.clone());
+        self.info_helper.set_validator_signer(signer);
+        // Announce the account again, signed with the new key.
+        self.last_validator_announce_time = None;
+        self.validator_peers = None;
+        Ok(ReloadValidatorKeyResponse { account_id, public_key })
+    }
+}
+
 impl Handler<GetNetworkInfo> for ClientActor {
     type Result = Result<NetworkInfoResponse, String>;
 
@@ -755,11 +889,17 @@ impl ClientActor {
             debug!(target: "client", "Sending announce account for {}", validator_signer.validator_id());
             self.last_validator_announce_time = Some(now);
 
-            let signature = validator_signer.sign_account_announce(
+            let signature = match validator_signer.sign_account_announce(
                 validator_signer.validator_id(),
                 &self.node_id,
                 &next_epoch_id,
-            );
+            ) {
+                Ok(signature) => signature,
+                Err(err) => {
+                    warn!(target: "client", "Not announcing account: {}", err);
+                    return;
+                }
+            };
             self.network_adapter.do_send(PeerManagerMessageRequest::NetworkRequests(
                 NetworkRequests::AnnounceAccount(AnnounceAccount {
                     account_id: validator_signer.validator_id().clone(),
@@ -771,6 +911,45 @@ impl ClientActor {
         }
     }
 
+    /// Once per epoch, asks the network to keep direct connections with the other validators of
+    /// the current and next epoch, if we are one of them.
+    fn update_validator_peers(&mut self, epoch_id: EpochId, block_hash: CryptoHash) {
+        let validator_signer = match self.client.validator_signer.as_ref() {
+            None => return,
+            Some(signer) => signer.clone(),
+        };
+        if matches!(&self.validator_peers, Some((last_epoch_id, _)) if last_epoch_id == &epoch_id) {
+            return;
+        }
+
+        let validator_info = unwrap_or_return!(self
+            .client
+            .runtime_adapter
+            .get_validator_info(ValidatorInfoIdentifier::BlockHash(block_hash)));
+        let mut account_ids: Vec<AccountId> = (validator_info.current_validators.into_iter())
+            .map(|validator| validator.account_id)
+            .chain(validator_info.next_validators.into_iter().map(|validator| validator.account_id))
+            .collect();
+        account_ids.sort();
+        account_ids.dedup();
+        let my_account_id = validator_signer.validator_id();
+        let account_ids = if account_ids.contains(my_account_id) {
+            account_ids.retain(|account_id| account_id != my_account_id);
+            account_ids
+        } else {
+            vec![]
+        };
+
+        let changed = self.validator_peers.as_ref().map_or(true, |(_, last)| last != &account_ids);
+        self.validator_peers = Some((epoch_id, account_ids.clone()));
+        if changed {
+            debug!(target: "client", ?account_ids, "Updating validators to connect to directly");
+            self.network_adapter.do_send(PeerManagerMessageRequest::NetworkRequests(
+                NetworkRequests::SetValidatorPeers { account_ids },
+            ));
+        }
+    }
+
     /// Retrieves latest height, and checks if must produce next block.
     /// Otherwise wait for block arrival or suggest to skip after timeout.
     fn handle_block_production(&mut self) -> Result<(), Error> {
@@ -808,28 +987,52 @@ impl ClientActor {
         let epoch_id =
             self.client.runtime_adapter.get_epoch_id_from_prev_block(&head.last_block_hash)?;
 
-        for height in
-            latest_known.height + 1..=self.client.doomslug.get_largest_height_crossing_threshold()
-        {
+        // The next height is checked even if it hasn't crossed the threshold yet, so that waiting
+        // for its approvals shows up in the block production debug info.
+        let last_height = std::cmp::max(
+            latest_known.height + 1,
+            self.client.doomslug.get_largest_height_crossing_threshold(),
+        );
+        for height in latest_known.height + 1..=last_height {
             let next_block_producer_account =
                 self.client.runtime_adapter.get_block_producer(&epoch_id, height)?;
 
             if self.client.validator_signer.as_ref().map(|bp| bp.validator_id())
                 == Some(&next_block_producer_account)
             {
-                let num_chunks = self.client.shards_mgr.num_chunks_for_block(&head.last_block_hash);
-                let have_all_chunks = head.height == 0
-                    || num_chunks == self.client.runtime_adapter.num_shards(&epoch_id).unwrap();
-
-                if self.client.doomslug.ready_to_produce_block(
-                    Clock::instant(),
+                let shards_ready =
+                    self.client.shards_mgr.shards_ready_for_block(&head.last_block_hash);
+                let num_shards = self.client.runtime_adapter.num_shards(&epoch_id).unwrap();
+                let have_all_chunks =
+                    head.height == 0 || shards_ready.len() as NumShards == num_shards;
+
+                let now = Clock::instant();
+                let ready =
+                    self.client.doomslug.ready_to_produce_block(now, height, have_all_chunks);
+                let threshold_crossed = self.client.doomslug.get_threshold_crossing_time(height);
+                self.client.block_production_tracker.record_check(
                     height,
-                    have_all_chunks,
-                ) {
+                    shards_ready,
+                    num_shards,
+                    threshold_crossed,
+                    now,
+                );
+                if ready {
                     if let Err(err) = self.produce_block(height) {
                         // If there is an error, report it and let it retry on the next loop step.
                         error!(target: "client", "Block production failed: {}", err);
+                        self.client.block_production_tracker.record_status(
+                            height,
+                            BlockProductionStatusView::Failed { error: err.to_string() },
+                        );
                     }
+                } else {
+                    let status = if threshold_crossed.is_some() {
+                        BlockProductionStatusView::WaitingForChunks
+                    } else {
+                        BlockProductionStatusView::WaitingForApprovals
+                    };
+                    self.client.block_production_tracker.record_status(height, status);
                 }
             }
         }
@@ -1005,6 +1208,7 @@ impl ClientActor {
             let gas_used = Block::compute_gas_used(block.chunks().iter(), block.header().height());
 
             let last_final_hash = *block.header().last_final_block();
+            let epoch_id = block.header().epoch_id().clone();
 
             let chunks = block.chunks();
             for (chunk, &included) in chunks.iter().zip(block.header().chunk_mask().iter()) {
@@ -1017,6 +1221,7 @@ impl ClientActor {
 
             self.info_helper.block_processed(gas_used, chunks_in_block as u64);
             self.check_send_announce_account(last_final_hash);
+            self.update_validator_peers(epoch_id, accepted_block.hash);
         }
     }
 
@@ -1078,6 +1283,14 @@ impl ClientActor {
 
     /// Processes received block. Ban peer if the block header is invalid or the block is ill-formed.
     fn receive_block(&mut self, block: Block, peer_id: PeerId, was_requested: bool) {
+        if self.client.config.light_node {
+            // Light nodes only follow the header chain. Headers which don't connect to it are
+            // dropped, header sync catches up once the node falls behind.
+            if let Err(err) = self.client.sync_block_headers(vec![block.header().clone()]) {
+                debug!(target: "client", "Header of block {} from {} refused: {}", block.hash(), peer_id, err);
+            }
+            return;
+        }
         let hash = *block.hash();
         debug!(target: "client", "{:?} Received block {} <- {} at {} from {}, requested: {}", self.client.validator_signer.as_ref().map(|vs| vs.validator_id()), hash, block.header().prev_hash(), block.header().height(), peer_id, was_requested);
         let head = unwrap_or_return!(self.client.chain.head());
@@ -1129,6 +1342,11 @@ impl ClientActor {
 
     fn receive_headers(&mut self, headers: Vec<BlockHeader>, peer_id: PeerId) -> bool {
         info!(target: "client", "Received {} block headers from {}", headers.len(), peer_id);
+        let num_new_headers = headers
+            .iter()
+            .filter(|header| self.client.chain.get_block_header(header.hash()).is_err())
+            .count();
+        self.client.header_sync.on_headers_received(&peer_id, num_new_headers);
         if headers.len() == 0 {
             return true;
         }
@@ -1165,7 +1383,12 @@ impl ClientActor {
     /// Check whether need to (continue) sync.
     /// Also return higher height with known peers at that height.
     fn syncing_info(&self) -> Result<(bool, u64), near_chain::Error> {
-        let head = self.client.chain.head()?;
+        // Light nodes never process blocks, so they only keep the header chain in sync.
+        let head = if self.client.config.light_node {
+            self.client.chain.header_head()?
+        } else {
+            self.client.chain.head()?
+        };
         let mut is_syncing = self.client.sync_status.is_syncing();
 
         let full_peer_info = if let Some(full_peer_info) =
@@ -1249,6 +1472,10 @@ impl ClientActor {
         let header_head = self.client.chain.header_head()?;
         let mut sync_hash = header_head.prev_block_hash;
         for _ in 0..self.client.config.state_fetch_horizon {
+            // There are no headers before the block Epoch Sync has finished at.
+            if sync_hash == self.client.epoch_sync.sync_hash {
+                break;
+            }
             sync_hash = *self.client.chain.get_block_header(&sync_hash)?.prev_hash();
         }
         let mut epoch_start_sync_hash =
@@ -1268,6 +1495,20 @@ impl ClientActor {
         Ok(epoch_start_sync_hash)
     }
 
+    /// Runs a step of Epoch Sync if it's enabled. Returns true while Epoch Sync is in progress.
+    fn run_epoch_sync(&mut self, highest_height: BlockHeight) -> Result<bool, near_chain::Error> {
+        // Archival nodes need all the blocks, so they can't skip epochs.
+        if !self.client.config.epoch_sync_enabled || self.client.config.archive {
+            return Ok(false);
+        }
+        self.client.epoch_sync.run(
+            &mut self.client.sync_status,
+            &mut self.client.chain,
+            highest_height,
+            &self.network_info.highest_height_peers,
+        )
+    }
+
     /// Runs catchup on repeat, if this client is a validator.
     /// Schedules itself again if it was not ran as response to state parts job result
     fn catchup(&mut self, ctx: &mut Context<ClientActor>) {
@@ -1356,6 +1597,16 @@ impl ClientActor {
                 self.check_send_announce_account(head.prev_block_hash);
             }
             wait_period = self.client.config.sync_check_period;
+        } else if unwrap_or_run_later!(self.run_epoch_sync(highest_height)) {
+            // The other steps of syncing wait until Epoch Sync is done.
+        } else if self.client.config.light_node {
+            // Light nodes don't download blocks or state.
+            unwrap_or_run_later!(self.client.header_sync.run(
+                &mut self.client.sync_status,
+                &mut self.client.chain,
+                highest_height,
+                &self.network_info.highest_height_peers
+            ));
         } else {
             // Run each step of syncing separately.
             unwrap_or_run_later!(self.client.header_sync.run(
@@ -1652,6 +1903,7 @@ impl Handler<StateSplitRequest> for SyncJobsActor {
             msg.shard_uid,
             &msg.state_root,
             &msg.next_epoch_shard_layout,
+            msg.protocol_version,
         );
 
         self.client_addr.do_send(StateSplitResponse {
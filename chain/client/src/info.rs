@@ -19,7 +19,7 @@ use std::cmp::min;
 use std::fmt::Write;
 use std::sync::Arc;
 use sysinfo::{get_current_pid, set_open_files_limit, Pid, ProcessExt, System, SystemExt};
-use tracing::info;
+use tracing::{info, warn};
 
 const TERAGAS: f64 = 1_000_000_000_000_f64;
 
@@ -73,6 +73,10 @@ impl InfoHelper {
         }
     }
 
+    pub fn set_validator_signer(&mut self, validator_signer: Option<Arc<dyn ValidatorSigner>>) {
+        self.validator_signer = validator_signer;
+    }
+
     pub fn chunk_processed(&mut self, shard_id: ShardId, gas_used: Gas) {
         metrics::TGAS_USAGE_HIST
             .with_label_values(&[&format!("{}", shard_id)])
@@ -225,7 +229,13 @@ impl InfoHelper {
         };
         // Sign telemetry if there is a signer present.
         let content = if let Some(vs) = self.validator_signer.as_ref() {
-            vs.sign_telemetry(&info)
+            match vs.sign_telemetry(&info) {
+                Ok(content) => content,
+                Err(err) => {
+                    warn!(target: "telemetry", "Not sending telemetry: {}", err);
+                    return;
+                }
+            }
         } else {
             serde_json::to_value(&info).expect("Telemetry must serialize to json")
         };
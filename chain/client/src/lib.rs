@@ -1,10 +1,12 @@
 pub use near_client_primitives::types::{
-    Error, GetBlock, GetBlockHash, GetBlockProof, GetBlockProofResponse, GetBlockWithMerkleTree,
-    GetChunk, GetExecutionOutcome, GetExecutionOutcomeResponse, GetExecutionOutcomesForBlock,
-    GetGasPrice, GetNetworkInfo, GetNextLightClientBlock, GetProtocolConfig, GetReceipt,
-    GetStateChanges, GetStateChangesInBlock, GetStateChangesWithCauseInBlock,
-    GetStateChangesWithCauseInBlockForTrackedShards, GetValidatorInfo, GetValidatorOrdered, Query,
-    QueryError, Status, StatusResponse, SyncStatus, TxStatus, TxStatusError,
+    Error, GetBlock, GetBlockHash, GetBlockProductionDebugInfo, GetBlockProductionDebugInfoError,
+    GetBlockProof, GetBlockProofResponse, GetBlockWithMerkleTree, GetChunk, GetExecutionOutcome,
+    GetExecutionOutcomeResponse, GetExecutionOutcomesForBlock, GetGasPrice, GetNetworkInfo,
+    GetNextLightClientBlock, GetProtocolConfig, GetReceipt, GetStateChanges,
+    GetStateChangesInBlock, GetStateChangesWithCauseInBlock,
+    GetStateChangesWithCauseInBlockForTrackedShards, GetValidatorInfo, GetValidatorOrdered,
+    GetValidatorPerformance, Query, QueryError, ReloadValidatorKey, ReloadValidatorKeyError,
+    ReloadValidatorKeyResponse, Status, StatusResponse, SyncStatus, TxStatus, TxStatusError,
 };
 
 pub use crate::client::Client;
@@ -13,11 +15,13 @@ pub use crate::client_actor::{start_client, ClientActor};
 pub use crate::view_client::AdversarialControls;
 pub use crate::view_client::{start_view_client, ViewClientActor};
 
+mod block_production_tracker;
 mod chunks_delay_tracker;
 mod client;
 mod client_actor;
 mod info;
 mod metrics;
+mod state_parts_storage;
 pub mod sync;
 pub mod test_utils;
 #[cfg(test)]
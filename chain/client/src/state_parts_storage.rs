@@ -0,0 +1,271 @@
+//! Storage of state parts outside of the peer-to-peer network.
+//!
+//! Nodes configured with `state_parts.dump` write all parts of the shards they track at the start
+//! of every epoch, and nodes configured with `state_parts.fetch` read them from there during state
+//! sync, so that serving nodes don't have to compute the parts from the trie for every request.
+//! A part is stored under `{chain_id}/{sync_hash}/shard_{shard_id}/part_{part_id}_of_{num_parts}`.
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use hyper::body::HttpBody;
+use hyper::client::HttpConnector;
+use hyper_tls::HttpsConnector;
+use tracing::{error, info};
+
+use near_chain::RuntimeAdapter;
+use near_chain_configs::StatePartsLocation;
+use near_primitives::hash::CryptoHash;
+use near_primitives::types::{ShardId, StateRoot};
+
+/// Parts larger than this are refused when fetched over HTTP. A part can't be larger than a
+/// network message when served by peers either.
+const MAX_STATE_PART_SIZE: usize = 512 * 1024 * 1024;
+
+#[derive(thiserror::Error, Debug)]
+pub enum StatePartsStorageError {
+    #[error("I/O error: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("HTTP error: {0}")]
+    Http(#[from] hyper::Error),
+    #[error("Invalid request: {0}")]
+    Request(#[from] hyper::http::Error),
+    #[error("Unexpected HTTP status: {0}")]
+    Status(hyper::StatusCode),
+    #[error("State part larger than {} bytes", MAX_STATE_PART_SIZE)]
+    TooLarge,
+}
+
+enum Backend {
+    Filesystem { root_dir: PathBuf },
+    Http { client: hyper::Client<HttpsConnector<HttpConnector>>, url: String },
+}
+
+/// Reads and writes the state parts of one chain in a `StatePartsLocation`.
+pub struct StatePartsStorage {
+    chain_id: String,
+    backend: Backend,
+}
+
+impl StatePartsStorage {
+    pub fn new(chain_id: &str, location: &StatePartsLocation) -> Self {
+        let backend = match location {
+            StatePartsLocation::Filesystem { root_dir } => {
+                Backend::Filesystem { root_dir: root_dir.clone() }
+            }
+            StatePartsLocation::Http { url } => Backend::Http {
+                client: hyper::Client::builder().build(HttpsConnector::new()),
+                url: url.trim_end_matches('/').to_string(),
+            },
+        };
+        Self { chain_id: chain_id.to_string(), backend }
+    }
+
+    fn key(
+        &self,
+        sync_hash: &CryptoHash,
+        shard_id: ShardId,
+        part_id: u64,
+        num_parts: u64,
+    ) -> String {
+        format!(
+            "{}/{}/shard_{}/part_{}_of_{}",
+            self.chain_id, sync_hash, shard_id, part_id, num_parts
+        )
+    }
+
+    pub async fn get_part(
+        &self,
+        sync_hash: &CryptoHash,
+        shard_id: ShardId,
+        part_id: u64,
+        num_parts: u64,
+    ) -> Result<Vec<u8>, StatePartsStorageError> {
+        let key = self.key(sync_hash, shard_id, part_id, num_parts);
+        match &self.backend {
+            Backend::Filesystem { root_dir } => Ok(tokio::fs::read(root_dir.join(key)).await?),
+            Backend::Http { client, url } => {
+                let request =
+                    hyper::Request::get(format!("{}/{}", url, key)).body(hyper::Body::empty())?;
+                let response = client.request(request).await?;
+                if !response.status().is_success() {
+                    return Err(StatePartsStorageError::Status(response.status()));
+                }
+                let mut body = response.into_body();
+                let mut data = vec![];
+                while let Some(chunk) = body.data().await {
+                    let chunk = chunk?;
+                    if data.len() + chunk.len() > MAX_STATE_PART_SIZE {
+                        return Err(StatePartsStorageError::TooLarge);
+                    }
+                    data.extend_from_slice(&chunk);
+                }
+                Ok(data)
+            }
+        }
+    }
+
+    pub async fn put_part(
+        &self,
+        sync_hash: &CryptoHash,
+        shard_id: ShardId,
+        part_id: u64,
+        num_parts: u64,
+        data: Vec<u8>,
+    ) -> Result<(), StatePartsStorageError> {
+        let key = self.key(sync_hash, shard_id, part_id, num_parts);
+        match &self.backend {
+            Backend::Filesystem { root_dir } => {
+                let path = root_dir.join(key);
+                if let Some(dir) = path.parent() {
+                    tokio::fs::create_dir_all(dir).await?;
+                }
+                // Readers must never see a partially written part.
+                let tmp_path = path.with_extension("tmp");
+                tokio::fs::write(&tmp_path, data).await?;
+                tokio::fs::rename(tmp_path, path).await?;
+                Ok(())
+            }
+            Backend::Http { client, url } => {
+                let request = hyper::Request::put(format!("{}/{}", url, key))
+                    .body(hyper::Body::from(data))?;
+                let response = client.request(request).await?;
+                if !response.status().is_success() {
+                    return Err(StatePartsStorageError::Status(response.status()));
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+/// Computes all parts of the state of a shard at `sync_hash` and writes them to the storage, in a
+/// separate thread as obtaining the parts from the trie is expensive.
+/// `sync_prev_hash` is a block whose `prev_state_root` is `state_root`.
+pub(crate) fn spawn_dump_state_parts(
+    storage: Arc<StatePartsStorage>,
+    runtime_adapter: Arc<dyn RuntimeAdapter>,
+    shard_id: ShardId,
+    sync_hash: CryptoHash,
+    sync_prev_hash: CryptoHash,
+    state_root: StateRoot,
+    num_parts: u64,
+) {
+    let result = std::thread::Builder::new().name("state_parts_dump".to_string()).spawn(move || {
+        let runtime = match actix_rt::Runtime::new() {
+            Ok(runtime) => runtime,
+            Err(err) => {
+                error!(target: "sync", "Failed to start state parts dump: {}", err);
+                return;
+            }
+        };
+        for part_id in 0..num_parts {
+            let part = match runtime_adapter.obtain_state_part(
+                shard_id,
+                &sync_prev_hash,
+                &state_root,
+                part_id,
+                num_parts,
+            ) {
+                Ok(part) => part,
+                Err(err) => {
+                    error!(target: "sync", "Failed to obtain state part {} of shard {} at {}: {}", part_id, shard_id, sync_hash, err);
+                    return;
+                }
+            };
+            if let Err(err) =
+                runtime.block_on(storage.put_part(&sync_hash, shard_id, part_id, num_parts, part))
+            {
+                error!(target: "sync", "Failed to dump state part {} of shard {} at {}: {}", part_id, shard_id, sync_hash, err);
+                return;
+            }
+        }
+        info!(target: "sync", "Dumped {} state parts of shard {} at {}", num_parts, shard_id, sync_hash);
+    });
+    if let Err(err) = result {
+        error!(target: "sync", "Failed to start state parts dump: {}", err);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::convert::Infallible;
+    use std::sync::Mutex;
+
+    use hyper::service::{make_service_fn, service_fn};
+    use hyper::{Body, Method, Request, Response, Server, StatusCode};
+    use near_primitives::hash::hash;
+
+    use super::*;
+
+    async fn check_storage(storage: StatePartsStorage) {
+        let sync_hash = hash(b"sync");
+        storage.put_part(&sync_hash, 1, 0, 2, vec![1, 2, 3]).await.unwrap();
+        storage.put_part(&sync_hash, 1, 1, 2, vec![4, 5]).await.unwrap();
+        assert_eq!(storage.get_part(&sync_hash, 1, 0, 2).await.unwrap(), vec![1, 2, 3]);
+        assert_eq!(storage.get_part(&sync_hash, 1, 1, 2).await.unwrap(), vec![4, 5]);
+        // Parts of another shard, or of a different split of the state, are missing.
+        assert!(storage.get_part(&sync_hash, 0, 0, 2).await.is_err());
+        assert!(storage.get_part(&sync_hash, 1, 0, 3).await.is_err());
+    }
+
+    #[test]
+    fn test_filesystem_storage() {
+        let dir = tempfile::tempdir().unwrap();
+        let location = StatePartsLocation::Filesystem { root_dir: dir.path().to_path_buf() };
+        actix_rt::System::new().block_on(check_storage(StatePartsStorage::new("test", &location)));
+    }
+
+    /// Serves `PUT` and `GET` requests of objects kept in memory, like an object store would.
+    async fn handle(
+        objects: Arc<Mutex<HashMap<String, Vec<u8>>>>,
+        request: Request<Body>,
+    ) -> Result<Response<Body>, Infallible> {
+        let path = request.uri().path().to_string();
+        let response = match *request.method() {
+            Method::PUT => {
+                let data = hyper::body::to_bytes(request.into_body()).await.unwrap();
+                objects.lock().unwrap().insert(path, data.to_vec());
+                Response::new(Body::empty())
+            }
+            Method::GET => match objects.lock().unwrap().get(&path) {
+                Some(data) => Response::new(Body::from(data.clone())),
+                None => {
+                    Response::builder().status(StatusCode::NOT_FOUND).body(Body::empty()).unwrap()
+                }
+            },
+            _ => Response::builder()
+                .status(StatusCode::METHOD_NOT_ALLOWED)
+                .body(Body::empty())
+                .unwrap(),
+        };
+        Ok(response)
+    }
+
+    #[test]
+    fn test_http_storage() {
+        actix_rt::System::new().block_on(async {
+            let objects = Arc::new(Mutex::new(HashMap::new()));
+            let server_objects = objects.clone();
+            let make_service = make_service_fn(move |_| {
+                let objects = server_objects.clone();
+                async move {
+                    Ok::<_, Infallible>(service_fn(move |request| handle(objects.clone(), request)))
+                }
+            });
+            let server = Server::bind(&"127.0.0.1:0".parse().unwrap()).serve(make_service);
+            let url = format!("http://{}/parts/", server.local_addr());
+            actix_rt::spawn(async move {
+                server.await.unwrap();
+            });
+
+            let location = StatePartsLocation::Http { url };
+            check_storage(StatePartsStorage::new("test", &location)).await;
+            let sync_hash = hash(b"sync");
+            assert!(objects
+                .lock()
+                .unwrap()
+                .contains_key(&format!("/parts/test/{}/shard_1/part_0_of_2", sync_hash)));
+        });
+    }
+}
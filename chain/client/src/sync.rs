@@ -3,7 +3,7 @@ use std::cmp::min;
 use std::collections::{HashMap, HashSet};
 use std::ops::Add;
 use std::sync::atomic::{AtomicBool, Ordering};
-use std::sync::Arc;
+use std::sync::{mpsc, Arc};
 use std::time::Duration as TimeDuration;
 
 use ansi_term::Color::{Purple, Yellow};
@@ -13,12 +13,14 @@ use rand::seq::{IteratorRandom, SliceRandom};
 use rand::{thread_rng, Rng};
 use tracing::{debug, error, info, warn};
 
-use near_chain::{Chain, RuntimeAdapter};
+use near_chain::{validate_light_client_block, Chain, RuntimeAdapter};
 use near_network::types::{FullPeerInfo, NetworkRequests, NetworkResponses, PeerManagerAdapter};
 use near_primitives::block::Tip;
 use near_primitives::hash::CryptoHash;
 use near_primitives::network::PeerId;
-use near_primitives::syncing::get_num_state_parts;
+use near_primitives::syncing::{
+    get_num_state_parts, EpochSyncFinalizationResponse, EpochSyncResponse,
+};
 use near_primitives::time::{Clock, Utc};
 use near_primitives::types::validator_stake::ValidatorStake;
 use near_primitives::types::{
@@ -31,9 +33,11 @@ use near_client_primitives::types::{
     DownloadStatus, ShardSyncDownload, ShardSyncStatus, SyncStatus,
 };
 use near_network::types::PeerManagerMessageRequest;
-use near_network_primitives::types::AccountOrPeerIdOrHash;
+use near_network_primitives::types::{AccountOrPeerIdOrHash, PeerScoreEvent};
 use near_primitives::shard_layout::ShardUId;
 
+use crate::state_parts_storage::StatePartsStorage;
+
 /// Maximum number of block headers send over the network.
 pub const MAX_BLOCK_HEADERS: u64 = 512;
 
@@ -51,11 +55,16 @@ pub const MAX_STATE_PART_REQUEST: u64 = 16;
 /// This number should not exceed MAX_STATE_PART_REQUEST times (number of peers in the network).
 pub const MAX_PENDING_PART: u64 = MAX_STATE_PART_REQUEST * 10000;
 
+/// Maximum number of state parts being fetched from the external storage at once.
+const MAX_EXTERNAL_STATE_PART_REQUESTS: usize = 64;
+
 pub const NS_PER_SECOND: u128 = 1_000_000_000;
 
-/// Helper to keep track of the Epoch Sync
-// TODO #3488
-#[allow(dead_code)]
+/// Helper to keep track of the Epoch Sync.
+/// A node without any history downloads one light client block per epoch, verifying each of them
+/// with the block producers from the previous one, until it gets to the current epoch. Then it
+/// downloads the first block of that epoch together with the epoch data, and header sync and
+/// state sync continue from there.
 pub struct EpochSync {
     network_adapter: Arc<dyn PeerManagerAdapter>,
     /// Datastructure to keep track of when the last request to each peer was made.
@@ -90,7 +99,10 @@ pub struct EpochSync {
 
     pub sync_hash: CryptoHash,
 
+    /// Whether the response to the last request has been received
     received_epoch: bool,
+    /// Number of epochs verified so far
+    epoch_ord: u64,
 
     is_just_started: bool,
 }
@@ -117,12 +129,165 @@ impl EpochSync {
             request_timeout: Duration::from_std(request_timeout).unwrap(),
             peer_timeout: Duration::from_std(peer_timeout).unwrap(),
             received_epoch: false,
+            epoch_ord: 0,
             have_all_epochs: false,
             done: false,
             sync_hash: CryptoHash::default(),
             is_just_started: true,
         }
     }
+
+    /// Runs a step of Epoch Sync. Returns true while Epoch Sync is in progress, in which case the
+    /// other stages of sync should wait.
+    pub fn run(
+        &mut self,
+        sync_status: &mut SyncStatus,
+        chain: &mut Chain,
+        highest_height: BlockHeight,
+        highest_height_peers: &Vec<FullPeerInfo>,
+    ) -> Result<bool, near_chain::Error> {
+        if self.done {
+            return Ok(false);
+        }
+        if highest_height_peers.is_empty() {
+            return Ok(!self.is_just_started);
+        }
+        if self.is_just_started {
+            self.is_just_started = false;
+            // Epoch Sync only helps a node without any history, and only if the chain is a few
+            // epochs ahead. Otherwise header sync is good enough.
+            let genesis_height = chain.genesis().height();
+            if chain.header_head()?.height != genesis_height
+                || highest_height <= genesis_height + 2 * chain.epoch_length
+            {
+                self.done = true;
+                return Ok(false);
+            }
+            info!(target: "sync", "Epoch sync: starting, highest height {}", highest_height);
+        }
+        *sync_status = SyncStatus::EpochSync { epoch_ord: self.epoch_ord };
+
+        if !self.have_all_epochs
+            && highest_height_peers
+                .iter()
+                .all(|peer| self.peers_reporting_up_to_date.contains(&peer.peer_info.id))
+        {
+            info!(target: "sync", "Epoch sync: verified {} epochs, finalizing at {:?}", self.epoch_ord, self.next_epoch_id);
+            self.have_all_epochs = true;
+            self.received_epoch = false;
+            self.last_request_peer_id = None;
+        }
+
+        let now = Clock::utc();
+        let request_is_due = self.last_request_peer_id.is_none()
+            || self.received_epoch
+            || self.requested_epoch_id != self.next_epoch_id
+            || now - self.last_request_time > self.request_timeout;
+        if !request_is_due {
+            return Ok(true);
+        }
+
+        // Peers which have already told us they don't have anything newer are not asked again.
+        let peer = highest_height_peers
+            .iter()
+            .filter(|peer| {
+                self.have_all_epochs
+                    || !self.peers_reporting_up_to_date.contains(&peer.peer_info.id)
+            })
+            .filter(|peer| {
+                self.peer_to_last_request_time
+                    .get(&peer.peer_info.id)
+                    .map_or(true, |last_request_time| now - *last_request_time > self.peer_timeout)
+            })
+            .choose(&mut thread_rng());
+        let peer_id = match peer {
+            Some(peer) => peer.peer_info.id.clone(),
+            None => return Ok(true),
+        };
+
+        let epoch_id = self.next_epoch_id.clone();
+        debug!(target: "sync", "Epoch sync: requesting {}{:?} from {}", if self.have_all_epochs { "finalization of " } else { "" }, epoch_id, peer_id);
+        let request = if self.have_all_epochs {
+            NetworkRequests::EpochSyncFinalizationRequest { peer_id: peer_id.clone(), epoch_id }
+        } else {
+            NetworkRequests::EpochSyncRequest { peer_id: peer_id.clone(), epoch_id }
+        };
+        self.network_adapter.do_send(PeerManagerMessageRequest::NetworkRequests(request));
+        self.peer_to_last_request_time.insert(peer_id.clone(), now);
+        self.requested_epoch_id = self.next_epoch_id.clone();
+        self.last_request_time = now;
+        self.last_request_peer_id = Some(peer_id);
+        self.received_epoch = false;
+        Ok(true)
+    }
+
+    /// Processes the response to `EpochSyncRequest`. An error means that the peer has sent an
+    /// invalid light client block.
+    pub fn on_response(
+        &mut self,
+        peer_id: PeerId,
+        response: EpochSyncResponse,
+    ) -> Result<(), near_chain::Error> {
+        if self.done || self.have_all_epochs {
+            return Ok(());
+        }
+        match response {
+            EpochSyncResponse::UpToDate => {
+                // The response may be to a request for an epoch we have already verified.
+                if self.requested_epoch_id == self.next_epoch_id {
+                    debug!(target: "sync", "Epoch sync: {} has nothing after {:?}", peer_id, self.current_epoch_id);
+                    self.peers_reporting_up_to_date.insert(peer_id.clone());
+                }
+            }
+            EpochSyncResponse::Advance { light_client_block_view } => {
+                // Responses to the requests for the epochs we have already verified are ignored.
+                if EpochId(light_client_block_view.inner_lite.epoch_id) != self.next_epoch_id {
+                    return Ok(());
+                }
+                validate_light_client_block(
+                    &light_client_block_view,
+                    &self.next_epoch_id,
+                    &self.next_block_producers,
+                )?;
+                self.current_epoch_id = self.next_epoch_id.clone();
+                self.next_epoch_id = EpochId(light_client_block_view.inner_lite.next_epoch_id);
+                self.next_block_producers = light_client_block_view
+                    .next_bps
+                    .unwrap_or_default()
+                    .into_iter()
+                    .map(Into::into)
+                    .collect();
+                self.peers_reporting_up_to_date.clear();
+                self.epoch_ord += 1;
+                debug!(target: "sync", "Epoch sync: verified {:?}, {} epochs so far", self.current_epoch_id, self.epoch_ord);
+            }
+        }
+        if self.last_request_peer_id.as_ref() == Some(&peer_id) {
+            self.received_epoch = true;
+        }
+        Ok(())
+    }
+
+    /// Processes the response to `EpochSyncFinalizationRequest`. An error means that the response
+    /// doesn't match the verified light client blocks.
+    pub fn on_finalization_response(
+        &mut self,
+        chain: &mut Chain,
+        peer_id: PeerId,
+        response: EpochSyncFinalizationResponse,
+    ) -> Result<(), near_chain::Error> {
+        if self.done || !self.have_all_epochs {
+            return Ok(());
+        }
+        self.sync_hash = chain.init_after_epoch_sync(
+            &self.next_epoch_id,
+            &self.next_block_producers,
+            response,
+        )?;
+        self.done = true;
+        info!(target: "sync", "Epoch sync: done, received {} from {}, starting header sync", self.sync_hash, peer_id);
+        Ok(())
+    }
 }
 
 /// Helper to keep track of sync headers.
@@ -132,6 +297,8 @@ pub struct HeaderSync {
     history_locator: Vec<(BlockHeight, CryptoHash)>,
     prev_header_sync: (DateTime<Utc>, BlockHeight, BlockHeight, BlockHeight),
     syncing_peer: Option<FullPeerInfo>,
+    /// When we requested headers from `syncing_peer`, if it hasn't answered yet.
+    headers_requested: Option<DateTime<Utc>>,
     stalling_ts: Option<DateTime<Utc>>,
 
     initial_timeout: Duration,
@@ -153,6 +320,7 @@ impl HeaderSync {
             history_locator: vec![],
             prev_header_sync: (Clock::utc(), 0, 0, 0),
             syncing_peer: None,
+            headers_requested: None,
             stalling_ts: None,
             initial_timeout: Duration::from_std(initial_timeout).unwrap(),
             progress_timeout: Duration::from_std(progress_timeout).unwrap(),
@@ -231,7 +399,7 @@ impl HeaderSync {
 
         // Always enable header sync on initial state transition from NoSync / NoSyncFewBlocksBehind / AwaitingPeers.
         let force_sync = match sync_status {
-            SyncStatus::NoSync | SyncStatus::AwaitingPeers => true,
+            SyncStatus::NoSync | SyncStatus::AwaitingPeers | SyncStatus::EpochSync { .. } => true,
             _ => false,
         };
 
@@ -247,6 +415,11 @@ impl HeaderSync {
                 if self.stalling_ts.is_none() {
                     self.stalling_ts = Some(now);
                 }
+                if let Some(peer) = &self.syncing_peer {
+                    if self.headers_requested.take().is_some() {
+                        self.update_peer_score(&peer.peer_info.id, PeerScoreEvent::RequestTimedOut);
+                    }
+                }
             } else {
                 self.stalling_ts = None;
             }
@@ -315,11 +488,36 @@ impl HeaderSync {
                     peer_id: peer.peer_info.id.clone(),
                 },
             ));
+            self.headers_requested = Some(Clock::utc());
             return Some(peer);
         }
         None
     }
 
+    /// Updates the score of the peer we requested headers from once its answer arrives, given
+    /// the number of headers in the answer we didn't have yet.
+    pub fn on_headers_received(&mut self, peer_id: &PeerId, num_new_headers: usize) {
+        if self.syncing_peer.as_ref().map_or(true, |peer| &peer.peer_info.id != peer_id) {
+            return;
+        }
+        if let Some(requested) = self.headers_requested.take() {
+            let event = if num_new_headers == 0 {
+                PeerScoreEvent::UselessResponse
+            } else {
+                PeerScoreEvent::ResponseReceived {
+                    latency: (Clock::utc() - requested).to_std().unwrap_or_default(),
+                }
+            };
+            self.update_peer_score(peer_id, event);
+        }
+    }
+
+    fn update_peer_score(&self, peer_id: &PeerId, event: PeerScoreEvent) {
+        self.network_adapter.do_send(PeerManagerMessageRequest::NetworkRequests(
+            NetworkRequests::UpdatePeerScore { peer_id: peer_id.clone(), event },
+        ));
+    }
+
     fn get_locator(&mut self, chain: &mut Chain) -> Result<Vec<CryptoHash>, near_chain::Error> {
         let tip = chain.header_head()?;
         let genesis_height = chain.genesis().height();
@@ -597,6 +795,14 @@ pub enum StateSyncResult {
     Completed,
 }
 
+/// A state part fetched from the external storage, or why it couldn't be.
+struct ExternalStatePart {
+    shard_id: ShardId,
+    sync_hash: CryptoHash,
+    part_id: u64,
+    result: Result<Vec<u8>, String>,
+}
+
 struct PendingRequestStatus {
     missing_parts: usize,
     wait_until: DateTime<Utc>,
@@ -629,10 +835,22 @@ pub struct StateSync {
 
     /// Maps shard_id to result of splitting state for resharding
     split_state_roots: HashMap<ShardId, Result<HashMap<ShardUId, StateRoot>, Error>>,
+
+    /// Storage to fetch each state part from once, before requesting it from peers.
+    external_storage: Option<Arc<StatePartsStorage>>,
+    /// Number of state parts being fetched from the external storage.
+    external_requests_in_flight: usize,
+    external_parts_sender: mpsc::Sender<ExternalStatePart>,
+    external_parts_receiver: mpsc::Receiver<ExternalStatePart>,
 }
 
 impl StateSync {
-    pub fn new(network_adapter: Arc<dyn PeerManagerAdapter>, timeout: TimeDuration) -> Self {
+    pub fn new(
+        network_adapter: Arc<dyn PeerManagerAdapter>,
+        timeout: TimeDuration,
+        external_storage: Option<Arc<StatePartsStorage>>,
+    ) -> Self {
+        let (external_parts_sender, external_parts_receiver) = mpsc::channel();
         StateSync {
             network_adapter,
             state_sync_time: Default::default(),
@@ -642,6 +860,10 @@ impl StateSync {
             timeout: Duration::from_std(timeout).unwrap(),
             state_parts_apply_results: HashMap::new(),
             split_state_roots: HashMap::new(),
+            external_storage,
+            external_requests_in_flight: 0,
+            external_parts_sender,
+            external_parts_receiver,
         }
     }
 
@@ -673,6 +895,18 @@ impl StateSync {
         Ok((request_block, have_block))
     }
 
+    /// Lowers the score of the peer we requested the timed out state header or part from.
+    fn on_download_timeout(&self, download: &DownloadStatus) {
+        if let Some(AccountOrPeerIdOrHash::PeerId(peer_id)) = &download.last_target {
+            self.network_adapter.do_send(PeerManagerMessageRequest::NetworkRequests(
+                NetworkRequests::UpdatePeerScore {
+                    peer_id: peer_id.clone(),
+                    event: PeerScoreEvent::RequestTimedOut,
+                },
+            ));
+        }
+    }
+
     pub fn sync_shards_status(
         &mut self,
         me: &Option<AccountId>,
@@ -714,6 +948,7 @@ impl StateSync {
             panic!("cannot sync to the first epoch after sharding upgrade. Please wait for the next epoch or find peers that are more up to date");
         }
         let split_states = runtime_adapter.will_shard_layout_change_next_epoch(&prev_hash)?;
+        let mut external_parts = self.take_external_parts(sync_hash);
 
         for shard_id in tracking_shards {
             let mut download_timeout = false;
@@ -749,6 +984,9 @@ impl StateSync {
                         let prev = shard_sync_download.downloads[0].prev_update_time;
                         let error = shard_sync_download.downloads[0].error;
                         download_timeout = now - prev > self.timeout;
+                        if download_timeout {
+                            self.on_download_timeout(&shard_sync_download.downloads[0]);
+                        }
                         if download_timeout || error {
                             shard_sync_download.downloads[0].run_me.store(true, Ordering::SeqCst);
                             shard_sync_download.downloads[0].error = false;
@@ -760,6 +998,15 @@ impl StateSync {
                     }
                 }
                 ShardSyncStatus::StateDownloadParts => {
+                    if let Some(parts) = external_parts.remove(&shard_id) {
+                        Self::save_external_parts(
+                            chain,
+                            shard_id,
+                            sync_hash,
+                            shard_sync_download,
+                            parts,
+                        );
+                    }
                     let mut parts_done = true;
                     for part_download in shard_sync_download.downloads.iter_mut() {
                         if !part_download.done {
@@ -767,6 +1014,9 @@ impl StateSync {
                             let prev = part_download.prev_update_time;
                             let error = part_download.error;
                             let part_timeout = now - prev > self.timeout;
+                            if part_timeout {
+                                self.on_download_timeout(part_download);
+                            }
                             if part_timeout || error {
                                 download_timeout |= part_timeout;
                                 part_download.run_me.store(true, Ordering::SeqCst);
@@ -1013,6 +1263,96 @@ impl StateSync {
         }
     }
 
+    /// Fetches the parts that were never requested yet from the external storage, if there is one.
+    /// The parts it fails to provide are then requested from peers.
+    fn request_external_parts(
+        &mut self,
+        shard_id: ShardId,
+        sync_hash: CryptoHash,
+        shard_sync_download: &mut ShardSyncDownload,
+    ) {
+        let storage = match &self.external_storage {
+            Some(storage) => storage,
+            None => return,
+        };
+        let num_parts = shard_sync_download.downloads.len() as u64;
+        let timeout = self.timeout.to_std().unwrap_or_default();
+        for (part_id, download) in shard_sync_download.downloads.iter_mut().enumerate() {
+            if self.external_requests_in_flight >= MAX_EXTERNAL_STATE_PART_REQUESTS {
+                break;
+            }
+            if download.state_requests_count > 0 || !download.run_me.load(Ordering::SeqCst) {
+                continue;
+            }
+            download.run_me.store(false, Ordering::SeqCst);
+            download.state_requests_count += 1;
+            download.last_target = None;
+            self.external_requests_in_flight += 1;
+
+            let part_id = part_id as u64;
+            let storage = storage.clone();
+            let sender = self.external_parts_sender.clone();
+            near_performance_metrics::actix::spawn(std::any::type_name::<Self>(), async move {
+                let result = match actix_rt::time::timeout(
+                    timeout,
+                    storage.get_part(&sync_hash, shard_id, part_id, num_parts),
+                )
+                .await
+                {
+                    Ok(result) => result.map_err(|err| err.to_string()),
+                    Err(_) => Err("timed out".to_string()),
+                };
+                // The receiver is gone if the state sync is over.
+                let _ = sender.send(ExternalStatePart { shard_id, sync_hash, part_id, result });
+            });
+        }
+    }
+
+    /// Collects the parts fetched from the external storage since the last call, by shard.
+    fn take_external_parts(
+        &mut self,
+        sync_hash: CryptoHash,
+    ) -> HashMap<ShardId, Vec<(u64, Result<Vec<u8>, String>)>> {
+        let mut parts: HashMap<ShardId, Vec<_>> = HashMap::new();
+        for part in self.external_parts_receiver.try_iter() {
+            self.external_requests_in_flight = self.external_requests_in_flight.saturating_sub(1);
+            if part.sync_hash == sync_hash {
+                parts.entry(part.shard_id).or_default().push((part.part_id, part.result));
+            }
+        }
+        parts
+    }
+
+    /// Validates and saves the parts fetched from the external storage. The missing or invalid
+    /// ones are marked as failed, so that they are requested from peers.
+    fn save_external_parts(
+        chain: &mut Chain,
+        shard_id: ShardId,
+        sync_hash: CryptoHash,
+        shard_sync_download: &mut ShardSyncDownload,
+        parts: Vec<(u64, Result<Vec<u8>, String>)>,
+    ) {
+        let num_parts = shard_sync_download.downloads.len() as u64;
+        for (part_id, result) in parts {
+            let download = match shard_sync_download.downloads.get_mut(part_id as usize) {
+                Some(download) if !download.done => download,
+                _ => continue,
+            };
+            let result = result.and_then(|data| {
+                chain
+                    .set_state_part(shard_id, sync_hash, part_id, num_parts, &data)
+                    .map_err(|err| err.to_string())
+            });
+            match result {
+                Ok(()) => download.done = true,
+                Err(err) => {
+                    debug!(target: "sync", "State sync couldn't get part {} of shard {} from the external storage, requesting it from peers: {}", part_id, shard_id, err);
+                    download.error = true;
+                }
+            }
+        }
+    }
+
     /// Find possible targets to download state from.
     /// Candidates are validators at current epoch and peers at highest height.
     /// Only select candidates that we have no pending request currently ongoing.
@@ -1075,6 +1415,11 @@ impl StateSync {
         shard_sync_download: ShardSyncDownload,
         highest_height_peers: &Vec<FullPeerInfo>,
     ) -> Result<ShardSyncDownload, near_chain::Error> {
+        let mut new_shard_sync_download = shard_sync_download.clone();
+        if matches!(shard_sync_download.status, ShardSyncStatus::StateDownloadParts) {
+            self.request_external_parts(shard_id, sync_hash, &mut new_shard_sync_download);
+        }
+
         let possible_targets = self.possible_targets(
             me,
             shard_id,
@@ -1085,11 +1430,10 @@ impl StateSync {
         )?;
 
         if possible_targets.is_empty() {
-            return Ok(shard_sync_download);
+            return Ok(new_shard_sync_download);
         }
 
         // Downloading strategy starts here
-        let mut new_shard_sync_download = shard_sync_download.clone();
 
         match shard_sync_download.status {
             ShardSyncStatus::StateDownloadHeader => {
@@ -1468,6 +1812,7 @@ mod test {
                             current_height,
                             &signer,
                         )
+                        .unwrap()
                         .signature
                     })
                 })
@@ -1501,7 +1846,8 @@ mod test {
                 &*signers[3],
                 *last_block.header().next_bp_hash(),
                 block_merkle_tree.root(),
-            );
+            )
+            .unwrap();
             block_merkle_tree.insert(*block.hash());
 
             all_blocks.push(block);
@@ -639,7 +639,10 @@ pub fn setup_mock_all_validators(
                         }
                         NetworkRequests::PartialEncodedChunkResponse { route_back, response } => {
                             let create_msg = || {
-                                NetworkClientMessages::PartialEncodedChunkResponse(response.clone())
+                                NetworkClientMessages::PartialEncodedChunkResponse(
+                                    response.clone(),
+                                    my_key_pair.id.clone(),
+                                )
                             };
                             send_chunks(
                                 Arc::clone(&connectors1),
@@ -976,10 +979,13 @@ pub fn setup_mock_all_validators(
                         }
                         NetworkRequests::ForwardTx(_, _)
                         | NetworkRequests::SyncRoutingTable { .. }
+                        | NetworkRequests::SyncAnnounceAddresses { .. }
+                        | NetworkRequests::SetValidatorPeers { .. }
                         | NetworkRequests::FetchRoutingTable
                         | NetworkRequests::PingTo(_, _)
                         | NetworkRequests::FetchPingPongInfo
                         | NetworkRequests::BanPeer { .. }
+                        | NetworkRequests::UpdatePeerScore { .. }
                         | NetworkRequests::TxStatus(_, _, _)
                         | NetworkRequests::Query { .. }
                         | NetworkRequests::Challenge(_)
@@ -1400,8 +1406,9 @@ impl TestEnv {
         {
             let target_id = self.account_to_client_index[&target.account_id.unwrap()];
             let response = self.get_partial_encoded_chunk_response(target_id, request);
-            let accepted_blocks =
-                self.clients[id].process_partial_encoded_chunk_response(response).unwrap();
+            let accepted_blocks = self.clients[id]
+                .process_partial_encoded_chunk_response(response, PeerInfo::random().id)
+                .unwrap();
             for block in accepted_blocks {
                 self.clients[id].on_block_accepted(block.hash, block.status, block.provenance);
             }
@@ -1668,7 +1675,8 @@ pub fn create_chunk(
         &*client.validator_signer.as_ref().unwrap().clone(),
         *last_block.header().next_bp_hash(),
         block_merkle_tree.root(),
-    );
+    )
+    .unwrap();
     (chunk, merkle_paths, receipts, block)
 }
 
@@ -1710,6 +1718,7 @@ pub fn run_catchup(
                 msg.shard_uid,
                 &msg.state_root,
                 &msg.next_epoch_shard_layout,
+                msg.protocol_version,
             );
             if let Some((sync, _, _)) = client.catchup_state_syncs.get_mut(&msg.sync_hash) {
                 // We are doing catchup
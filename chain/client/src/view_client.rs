@@ -40,24 +40,24 @@ use near_primitives::merkle::{merklize, PartialMerkleTree};
 use near_primitives::network::AnnounceAccount;
 use near_primitives::sharding::ShardChunk;
 use near_primitives::syncing::{
-    ShardStateSyncResponse, ShardStateSyncResponseHeader, ShardStateSyncResponseV1,
-    ShardStateSyncResponseV2,
+    EpochSyncResponse, ShardStateSyncResponse, ShardStateSyncResponseHeader,
+    ShardStateSyncResponseV1, ShardStateSyncResponseV2,
 };
 use near_primitives::types::{
-    AccountId, BlockHeight, BlockId, BlockReference, EpochId, EpochReference, Finality,
-    MaybeBlockId, ShardId, TransactionOrReceiptId,
+    AccountId, BlockHeight, BlockId, BlockReference, EpochHeight, EpochId, EpochReference,
+    Finality, MaybeBlockId, ShardId, TransactionOrReceiptId,
 };
 use near_primitives::views::validator_stake_view::ValidatorStakeView;
 use near_primitives::views::{
     BlockView, ChunkView, EpochValidatorInfo, ExecutionOutcomeWithIdView,
     FinalExecutionOutcomeView, FinalExecutionOutcomeViewEnum, FinalExecutionStatus, GasPriceView,
     LightClientBlockView, QueryRequest, QueryResponse, ReceiptView, StateChangesKindsView,
-    StateChangesView,
+    StateChangesView, ValidatorEpochPerformanceView,
 };
 
 use crate::{
     sync, GetChunk, GetExecutionOutcomeResponse, GetNextLightClientBlock, GetStateChanges,
-    GetStateChangesInBlock, GetValidatorInfo, GetValidatorOrdered,
+    GetStateChangesInBlock, GetValidatorInfo, GetValidatorOrdered, GetValidatorPerformance,
 };
 
 /// Max number of queries that we keep.
@@ -712,6 +712,24 @@ impl Handler<GetValidatorInfo> for ViewClientActor {
     }
 }
 
+impl Handler<GetValidatorPerformance> for ViewClientActor {
+    type Result = Result<Vec<ValidatorEpochPerformanceView>, GetValidatorInfoError>;
+
+    #[perf]
+    fn handle(&mut self, msg: GetValidatorPerformance, _: &mut Self::Context) -> Self::Result {
+        // use header head because this is latest from the perspective of epoch manager
+        let head = self.chain.header_head()?;
+        self.runtime_adapter
+            .get_validator_performance(
+                &msg.account_id,
+                &head.last_block_hash,
+                msg.from_epoch_height,
+                msg.to_epoch_height.unwrap_or(EpochHeight::MAX),
+            )
+            .map_err(GetValidatorInfoError::from)
+    }
+}
+
 impl Handler<GetValidatorOrdered> for ViewClientActor {
     type Result = Result<Vec<ValidatorStakeView>, GetValidatorInfoError>;
 
@@ -835,7 +853,8 @@ impl Handler<GetNextLightClientBlock> for ViewClientActor {
         let last_epoch_id = last_block_header.epoch_id().clone();
         let last_next_epoch_id = last_block_header.next_epoch_id().clone();
         let last_height = last_block_header.height();
-        let head = self.chain.head()?;
+        let head =
+            if self.config.light_node { self.chain.header_head()? } else { self.chain.head()? };
 
         if last_epoch_id == head.epoch_id || last_next_epoch_id == head.epoch_id {
             let head_header = self.chain.get_block_header(&head.last_block_hash)?;
@@ -870,6 +889,10 @@ impl Handler<GetExecutionOutcome> for ViewClientActor {
 
     #[perf]
     fn handle(&mut self, msg: GetExecutionOutcome, _: &mut Self::Context) -> Self::Result {
+        // A light node only has headers, so it can't prove the outcomes of their chunks.
+        if self.config.light_node {
+            return Err(GetExecutionOutcomeError::LightNode);
+        }
         let (id, account_id) = match msg.id {
             TransactionOrReceiptId::Transaction { transaction_hash, sender_id } => {
                 (transaction_hash, sender_id)
@@ -1354,13 +1377,39 @@ impl Handler<NetworkViewClientMessages> for ViewClientActor {
 
                 NetworkViewClientResponses::AnnounceAccount(filtered_announce_accounts)
             }
-            NetworkViewClientMessages::EpochSyncRequest { epoch_id: _epoch_id } => {
-                // TODO #3488
-                NetworkViewClientResponses::NoResponse
+            NetworkViewClientMessages::EpochSyncRequest { epoch_id } => {
+                // The light client block of an epoch is saved once the epoch is over.
+                match self.chain.mut_store().get_epoch_light_client_block(&epoch_id.0) {
+                    Ok(light_client_block) => NetworkViewClientResponses::EpochSyncResponse(
+                        Box::new(EpochSyncResponse::Advance {
+                            light_client_block_view: light_client_block.clone(),
+                        }),
+                    ),
+                    Err(e) => match e.kind() {
+                        ErrorKind::DBNotFoundErr(_)
+                            if self.runtime_adapter.epoch_exists(&epoch_id) =>
+                        {
+                            NetworkViewClientResponses::EpochSyncResponse(Box::new(
+                                EpochSyncResponse::UpToDate,
+                            ))
+                        }
+                        _ => {
+                            debug!(target: "sync", "Can't respond to epoch sync request for {:?}: {}", epoch_id, e);
+                            NetworkViewClientResponses::NoResponse
+                        }
+                    },
+                }
             }
-            NetworkViewClientMessages::EpochSyncFinalizationRequest { epoch_id: _epoch_id } => {
-                // TODO #3488
-                NetworkViewClientResponses::NoResponse
+            NetworkViewClientMessages::EpochSyncFinalizationRequest { epoch_id } => {
+                match self.chain.get_epoch_sync_finalization_response(&epoch_id) {
+                    Ok(response) => NetworkViewClientResponses::EpochSyncFinalizationResponse(
+                        Box::new(response),
+                    ),
+                    Err(e) => {
+                        debug!(target: "sync", "Can't finalize epoch sync for {:?}: {}", epoch_id, e);
+                        NetworkViewClientResponses::NoResponse
+                    }
+                }
             }
         }
     }
@@ -4,6 +4,7 @@ use std::collections::{BTreeMap, HashMap, HashSet};
 use primitive_types::U256;
 use tracing::{debug, warn};
 
+use near_crypto::{KeyType, PublicKey};
 use near_primitives::epoch_manager::block_info::BlockInfo;
 use near_primitives::epoch_manager::epoch_info::{EpochInfo, EpochSummary};
 use near_primitives::epoch_manager::{
@@ -13,16 +14,17 @@ use near_primitives::errors::EpochError;
 use near_primitives::hash::CryptoHash;
 use near_primitives::types::validator_stake::ValidatorStake;
 use near_primitives::types::{
-    AccountId, ApprovalStake, Balance, BlockChunkValidatorStats, BlockHeight, EpochId, ShardId,
-    ValidatorId, ValidatorKickoutReason, ValidatorStats,
+    AccountId, ApprovalStake, Balance, BlockChunkValidatorStats, BlockHeight, EpochHeight, EpochId,
+    ShardId, ValidatorId, ValidatorKickoutReason, ValidatorStats,
 };
 use near_primitives::version::{ProtocolVersion, UPGRADABILITY_FIX_PROTOCOL_VERSION};
 use near_primitives::views::{
-    CurrentEpochValidatorInfo, EpochValidatorInfo, NextEpochValidatorInfo, ValidatorKickoutView,
+    CurrentEpochValidatorInfo, EpochValidatorInfo, NextEpochValidatorInfo,
+    ValidatorEpochPerformanceView, ValidatorKickoutView,
 };
 use near_store::{ColBlockInfo, ColEpochInfo, ColEpochStart, Store, StoreUpdate};
 
-use crate::proposals::proposals_to_epoch_info;
+pub use crate::proposals::proposals_to_epoch_info;
 pub use crate::reward_calculator::RewardCalculator;
 use crate::types::EpochInfoAggregator;
 pub use crate::types::RngSeed;
@@ -141,6 +143,8 @@ impl EpochManager {
         Ok(epoch_manager)
     }
 
+    /// Saves the epoch data received by Epoch Sync, so that the blocks starting from the first
+    /// block of `epoch_id` can be processed without knowing the earlier history of the chain.
     pub fn init_after_epoch_sync(
         &mut self,
         prev_epoch_first_block_info: BlockInfo,
@@ -154,16 +158,18 @@ impl EpochManager {
         next_epoch_info: EpochInfo,
     ) -> Result<StoreUpdate, EpochError> {
         let mut store_update = self.store.store_update();
+        self.save_epoch_start(
+            &mut store_update,
+            prev_epoch_id,
+            *prev_epoch_first_block_info.height(),
+        )?;
         self.save_block_info(&mut store_update, prev_epoch_first_block_info)?;
         self.save_block_info(&mut store_update, prev_epoch_prev_last_block_info)?;
         self.save_block_info(&mut store_update, prev_epoch_last_block_info)?;
         self.save_epoch_info(&mut store_update, prev_epoch_id, prev_epoch_info)?;
         self.save_epoch_info(&mut store_update, epoch_id, epoch_info)?;
         self.save_epoch_info(&mut store_update, next_epoch_id, next_epoch_info)?;
-        // TODO #3488
-        // put unreachable! here to avoid warnings
-        unreachable!();
-        // Ok(store_update)
+        Ok(store_update)
     }
 
     /// # Parameters
@@ -266,6 +272,7 @@ impl EpochManager {
         &mut self,
         last_block_info: &BlockInfo,
         last_block_hash: &CryptoHash,
+        copy_only: bool,
     ) -> Result<EpochSummary, EpochError> {
         let epoch_info = self.get_epoch_info(last_block_info.epoch_id())?.clone();
         let next_epoch_id = self.get_next_epoch_id(last_block_hash)?;
@@ -279,7 +286,7 @@ impl EpochManager {
         } = self.get_and_update_epoch_info_aggregator(
             last_block_info.epoch_id(),
             last_block_hash,
-            false,
+            copy_only,
         )?;
         let mut proposals = vec![];
         let mut validator_kickout = HashMap::new();
@@ -368,6 +375,35 @@ impl EpochManager {
         })
     }
 
+    /// Computes the rewards of the validators of the epoch which ends with `last_block_info`, and
+    /// the amount of tokens minted in it.
+    fn calculate_epoch_reward(
+        &mut self,
+        last_block_info: &BlockInfo,
+        validator_block_chunk_stats: HashMap<AccountId, BlockChunkValidatorStats>,
+    ) -> Result<(HashMap<AccountId, Balance>, Balance), EpochError> {
+        let last_epoch_last_block_hash =
+            *self.get_block_info(last_block_info.epoch_first_block())?.prev_hash();
+        let last_block_in_last_epoch = self.get_block_info(&last_epoch_last_block_hash)?;
+        if last_block_info.timestamp_nanosec() <= last_block_in_last_epoch.timestamp_nanosec() {
+            return Err(EpochError::InvalidEpochDuration(last_block_info.epoch_id().clone()));
+        }
+        let epoch_duration =
+            last_block_info.timestamp_nanosec() - last_block_in_last_epoch.timestamp_nanosec();
+        let epoch_info = self.get_epoch_info(last_block_info.epoch_id())?;
+        let epoch_protocol_version = epoch_info.protocol_version();
+        let validator_stake =
+            epoch_info.validators_iter().map(|r| r.account_and_stake()).collect::<HashMap<_, _>>();
+        Ok(self.reward_calculator.calculate_reward(
+            validator_block_chunk_stats,
+            &validator_stake,
+            *last_block_info.total_supply(),
+            epoch_protocol_version,
+            self.genesis_protocol_version,
+            epoch_duration,
+        ))
+    }
+
     /// Finalizes epoch (T), where given last block hash is given, and returns next next epoch id (T + 2).
     fn finalize_epoch(
         &mut self,
@@ -376,11 +412,8 @@ impl EpochManager {
         last_block_hash: &CryptoHash,
         rng_seed: RngSeed,
     ) -> Result<(), EpochError> {
-        let epoch_summary = self.collect_blocks_info(block_info, last_block_hash)?;
-        let epoch_info = self.get_epoch_info(block_info.epoch_id())?;
-        let epoch_protocol_version = epoch_info.protocol_version();
-        let validator_stake =
-            epoch_info.validators_iter().map(|r| r.account_and_stake()).collect::<HashMap<_, _>>();
+        let epoch_summary = self.collect_blocks_info(block_info, last_block_hash, false)?;
+        let epoch_protocol_version = self.get_epoch_info(block_info.epoch_id())?.protocol_version();
         let next_epoch_id = self.get_next_epoch_id_from_info(block_info)?;
         let next_epoch_info = self.get_epoch_info(&next_epoch_id)?.clone();
         self.save_epoch_validator_info(store_update, block_info.epoch_id(), &epoch_summary)?;
@@ -393,22 +426,8 @@ impl EpochManager {
             ..
         } = epoch_summary;
 
-        let (validator_reward, minted_amount) = {
-            let last_epoch_last_block_hash =
-                *self.get_block_info(block_info.epoch_first_block())?.prev_hash();
-            let last_block_in_last_epoch = self.get_block_info(&last_epoch_last_block_hash)?;
-            assert!(block_info.timestamp_nanosec() > last_block_in_last_epoch.timestamp_nanosec());
-            let epoch_duration =
-                block_info.timestamp_nanosec() - last_block_in_last_epoch.timestamp_nanosec();
-            self.reward_calculator.calculate_reward(
-                validator_block_chunk_stats,
-                &validator_stake,
-                *block_info.total_supply(),
-                epoch_protocol_version,
-                self.genesis_protocol_version,
-                epoch_duration,
-            )
-        };
+        let (validator_reward, minted_amount) =
+            self.calculate_epoch_reward(block_info, validator_block_chunk_stats)?;
         let next_next_epoch_config = self.config.for_protocol_version(next_version);
         let next_next_epoch_info = match proposals_to_epoch_info(
             next_next_epoch_config,
@@ -448,6 +467,67 @@ impl EpochManager {
         Ok(())
     }
 
+    /// Runs validator selection as if the epoch of `last_block_hash` ended at that block, with
+    /// the stakes of the accounts in `stake_changes` replaced by the given amounts, and returns
+    /// the resulting epoch info of the epoch after next (T + 2). Nothing is written to the store.
+    /// Accounts which are neither proposing nor validating get an empty ed25519 public key.
+    pub fn simulate_next_next_epoch_info(
+        &mut self,
+        last_block_hash: &CryptoHash,
+        stake_changes: &HashMap<AccountId, Balance>,
+        rng_seed: RngSeed,
+    ) -> Result<EpochInfo, EpochError> {
+        let block_info = self.get_block_info(last_block_hash)?.clone();
+        let epoch_info = self.get_epoch_info(block_info.epoch_id())?.clone();
+        let next_epoch_id = self.get_next_epoch_id_from_info(&block_info)?;
+        let next_epoch_info = self.get_epoch_info(&next_epoch_id)?.clone();
+        let EpochSummary {
+            all_proposals,
+            validator_kickout,
+            validator_block_chunk_stats,
+            next_version,
+            ..
+        } = self.collect_blocks_info(&block_info, last_block_hash, true)?;
+
+        let mut proposals: HashMap<_, _> = all_proposals
+            .into_iter()
+            .map(|proposal| (proposal.account_id().clone(), proposal))
+            .collect();
+        for (account_id, stake) in stake_changes {
+            let mut proposal = match proposals.remove(account_id) {
+                Some(proposal) => proposal,
+                None => next_epoch_info
+                    .get_validator_by_account(account_id)
+                    .or_else(|| epoch_info.get_validator_by_account(account_id))
+                    .unwrap_or_else(|| {
+                        ValidatorStake::new_v1(
+                            account_id.clone(),
+                            PublicKey::empty(KeyType::ED25519),
+                            0,
+                        )
+                    }),
+            };
+            *proposal.stake_mut() = *stake;
+            proposals.insert(account_id.clone(), proposal);
+        }
+        let mut proposals: Vec<_> = proposals.into_values().collect();
+        proposals.sort_by(|a, b| a.account_id().cmp(b.account_id()));
+
+        let (validator_reward, minted_amount) =
+            self.calculate_epoch_reward(&block_info, validator_block_chunk_stats)?;
+        proposals_to_epoch_info(
+            self.config.for_protocol_version(next_version),
+            rng_seed,
+            &next_epoch_info,
+            proposals,
+            validator_kickout,
+            validator_reward,
+            minted_amount,
+            next_version,
+            epoch_info.protocol_version(),
+        )
+    }
+
     pub fn record_block_info(
         &mut self,
         mut block_info: BlockInfo,
@@ -1116,6 +1196,122 @@ impl EpochManager {
         })
     }
 
+    /// Returns the performance of `account_id` in the finished epochs with heights between
+    /// `from_epoch_height` and `to_epoch_height` inclusive, ordered by epoch height. Epochs are
+    /// found by going back from the epoch of `block_hash` (which is not included), and the search
+    /// stops at the first epoch which has been garbage collected, even partially. Epochs whose
+    /// reward can't be computed are reported with no `reward`.
+    pub fn get_validator_performance(
+        &mut self,
+        account_id: &AccountId,
+        block_hash: &CryptoHash,
+        from_epoch_height: EpochHeight,
+        to_epoch_height: EpochHeight,
+    ) -> Result<Vec<ValidatorEpochPerformanceView>, EpochError> {
+        let epoch_first_block = *self.get_block_info(block_hash)?.epoch_first_block();
+        let mut last_block_hash = *self.get_block_info(&epoch_first_block)?.prev_hash();
+        let mut result = vec![];
+        while last_block_hash != CryptoHash::default() {
+            let last_block_info = match self.get_block_info(&last_block_hash) {
+                Ok(block_info) => block_info.clone(),
+                Err(EpochError::MissingBlock(_)) => break,
+                Err(err) => return Err(err),
+            };
+            let epoch_info = self.get_epoch_info(last_block_info.epoch_id())?.clone();
+            if epoch_info.epoch_height() < from_epoch_height {
+                break;
+            }
+            // There is no summary for the genesis epoch.
+            let epoch_summary = match self.get_epoch_validator_info(last_block_info.epoch_id()) {
+                Ok(epoch_summary) => epoch_summary,
+                Err(EpochError::EpochOutOfBounds(_)) => break,
+                Err(err) => return Err(err),
+            };
+            last_block_hash = epoch_summary.prev_epoch_last_block_hash;
+            if epoch_info.epoch_height() <= to_epoch_height {
+                match self.get_validator_epoch_performance(
+                    account_id,
+                    &last_block_info,
+                    &epoch_info,
+                    epoch_summary,
+                ) {
+                    Ok(performance) => result.push(performance),
+                    Err(EpochError::MissingBlock(_)) => break,
+                    Err(err) => return Err(err),
+                }
+            }
+        }
+        result.reverse();
+        Ok(result)
+    }
+
+    fn get_validator_epoch_performance(
+        &mut self,
+        account_id: &AccountId,
+        last_block_info: &BlockInfo,
+        epoch_info: &EpochInfo,
+        epoch_summary: EpochSummary,
+    ) -> Result<ValidatorEpochPerformanceView, EpochError> {
+        let epoch_id = last_block_info.epoch_id();
+        let stake = epoch_info.get_validator_by_account(account_id).map(|v| v.stake());
+        let kickout_reason = epoch_summary.validator_kickout.get(account_id).cloned();
+        // Validators which were kicked out are not present in the summary stats, so their
+        // production numbers come from the kickout reason instead.
+        let (block_stats, chunk_stats) =
+            match epoch_summary.validator_block_chunk_stats.get(account_id) {
+                Some(stats) => (stats.block_stats.clone(), stats.chunk_stats.clone()),
+                None => match &kickout_reason {
+                    Some(ValidatorKickoutReason::NotEnoughBlocks { produced, expected }) => (
+                        ValidatorStats { produced: *produced, expected: *expected },
+                        ValidatorStats::default(),
+                    ),
+                    Some(ValidatorKickoutReason::NotEnoughChunks { produced, expected }) => (
+                        ValidatorStats::default(),
+                        ValidatorStats { produced: *produced, expected: *expected },
+                    ),
+                    _ => (ValidatorStats::default(), ValidatorStats::default()),
+                },
+            };
+        let ratios = [&block_stats, &chunk_stats]
+            .iter()
+            .filter(|stats| stats.expected > 0)
+            .map(|stats| stats.produced as f64 / stats.expected as f64)
+            .collect::<Vec<_>>();
+        let uptime = if ratios.is_empty() {
+            None
+        } else {
+            Some(ratios.iter().sum::<f64>() / ratios.len() as f64)
+        };
+        let reward = if stake.is_some() {
+            match self
+                .calculate_epoch_reward(last_block_info, epoch_summary.validator_block_chunk_stats)
+            {
+                Ok((rewards, _)) => Some(*rewards.get(account_id).unwrap_or(&0)),
+                Err(EpochError::InvalidEpochDuration(epoch_id)) => {
+                    warn!(target: "epoch_manager", "Can't compute the rewards of epoch {:?}", epoch_id);
+                    None
+                }
+                Err(err) => return Err(err),
+            }
+        } else {
+            None
+        };
+        Ok(ValidatorEpochPerformanceView {
+            epoch_id: epoch_id.clone(),
+            epoch_height: epoch_info.epoch_height(),
+            epoch_start_height: self.get_epoch_start_from_epoch_id(epoch_id)?,
+            stake,
+            stake_change: epoch_info.stake_change().get(account_id).cloned(),
+            num_produced_blocks: block_stats.produced,
+            num_expected_blocks: block_stats.expected,
+            num_produced_chunks: chunk_stats.produced,
+            num_expected_chunks: chunk_stats.expected,
+            uptime,
+            kickout_reason,
+            reward,
+        })
+    }
+
     /// Compare two epoch ids based on their start height. This works because finality gadget
     /// guarantees that we cannot have two different epochs on two forks
     pub fn compare_epoch_id(
@@ -1879,6 +2075,98 @@ mod tests2 {
         check_reward(epoch_info, vec![("test2".parse().unwrap(), 0), ("near".parse().unwrap(), 0)]);
     }
 
+    #[test]
+    fn test_validator_performance() {
+        let amount_staked = 1_000_000;
+        let validators = vec![
+            ("test1".parse().unwrap(), amount_staked),
+            ("test2".parse().unwrap(), amount_staked),
+        ];
+        let epoch_length = 10;
+        let mut epoch_manager =
+            setup_default_epoch_manager(validators, epoch_length, 1, 2, 0, 90, 60);
+        let h = hash_range((4 * epoch_length) as usize);
+
+        record_block(&mut epoch_manager, CryptoHash::default(), h[0], 0, vec![]);
+        let mut prev_block = h[0];
+        let mut test2_expected_blocks = 0;
+        let init_epoch_id = epoch_manager.get_epoch_id_from_prev_block(&prev_block).unwrap();
+        for (i, curr_block) in h.iter().enumerate().skip(1) {
+            let height = i as u64;
+            let epoch_id = epoch_manager.get_epoch_id_from_prev_block(&prev_block).unwrap();
+            let block_producer = epoch_manager.get_block_producer_info(&epoch_id, height).unwrap();
+            if block_producer.account_id().as_ref() == "test2" && epoch_id == init_epoch_id {
+                // test2 skips its blocks in the first epoch
+                test2_expected_blocks += 1;
+            } else {
+                record_block(&mut epoch_manager, prev_block, *curr_block, height, vec![]);
+                prev_block = *curr_block;
+            }
+        }
+
+        let test2 = "test2".parse().unwrap();
+        let performance = epoch_manager
+            .get_validator_performance(&test2, &prev_block, 0, EpochHeight::MAX)
+            .unwrap();
+        assert!(performance.len() >= 2);
+        let first_epoch = &performance[0];
+        assert_eq!(first_epoch.epoch_id, init_epoch_id);
+        assert_eq!(first_epoch.stake, Some(amount_staked));
+        assert_eq!(first_epoch.num_produced_blocks, 0);
+        assert_eq!(first_epoch.num_expected_blocks, test2_expected_blocks);
+        assert_eq!(first_epoch.uptime, Some(0.0));
+        assert_eq!(
+            first_epoch.kickout_reason,
+            Some(ValidatorKickoutReason::NotEnoughBlocks {
+                produced: 0,
+                expected: test2_expected_blocks
+            })
+        );
+        assert_eq!(first_epoch.reward, Some(0));
+        // test2 is not a validator once it has been kicked out.
+        let last_epoch = performance.last().unwrap();
+        assert_eq!(last_epoch.stake, None);
+        assert_eq!(last_epoch.reward, None);
+        for window in performance.windows(2) {
+            assert_eq!(window[0].epoch_height + 1, window[1].epoch_height);
+        }
+
+        let from_epoch_height = performance[1].epoch_height;
+        let filtered = epoch_manager
+            .get_validator_performance(&test2, &prev_block, from_epoch_height, from_epoch_height)
+            .unwrap();
+        assert_eq!(filtered, vec![performance[1].clone()]);
+    }
+
+    #[test]
+    fn test_simulate_next_next_epoch_info() {
+        let amount_staked = 1_000_000;
+        let validators = vec![
+            ("test1".parse().unwrap(), amount_staked),
+            ("test2".parse().unwrap(), amount_staked),
+        ];
+        let mut epoch_manager = setup_default_epoch_manager(validators, 10, 1, 2, 0, 90, 60);
+        let h = hash_range(3);
+        record_block(&mut epoch_manager, CryptoHash::default(), h[0], 0, vec![]);
+        record_block(&mut epoch_manager, h[0], h[1], 1, vec![]);
+        record_block(&mut epoch_manager, h[1], h[2], 2, vec![]);
+
+        let stake_changes = vec![("test2".parse().unwrap(), 0)].into_iter().collect();
+        let epoch_info =
+            epoch_manager.simulate_next_next_epoch_info(&h[2], &stake_changes, [0; 32]).unwrap();
+        check_validators(&epoch_info, &[("test1", amount_staked)]);
+        assert!(epoch_info.get_validator_by_account(&"test2".parse().unwrap()).is_none());
+
+        let stake_changes =
+            vec![("test3".parse().unwrap(), 2 * amount_staked)].into_iter().collect();
+        let epoch_info =
+            epoch_manager.simulate_next_next_epoch_info(&h[2], &stake_changes, [0; 32]).unwrap();
+        assert!(epoch_info.get_validator_by_account(&"test3".parse().unwrap()).is_some());
+
+        // The simulation does not finalize the epoch.
+        assert!(epoch_manager.get_epoch_info(&EpochId(h[2])).is_err());
+    }
+
     #[test]
     fn test_validator_unstake() {
         let store = create_test_store();
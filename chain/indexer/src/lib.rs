@@ -97,7 +97,8 @@ impl Indexer {
         );
 
         let near_config =
-            nearcore::config::load_config(&indexer_config.home_dir, GenesisValidationMode::Full);
+            nearcore::config::load_config(&indexer_config.home_dir, GenesisValidationMode::Full)
+                .unwrap_or_else(|e| panic!("Error loading config: {:#}", e));
 
         assert!(
             !&near_config.client_config.tracked_shards.is_empty(),
@@ -0,0 +1,100 @@
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct RpcReloadValidatorKeyRequest {
+    /// Switch to the new key even if it does not match the key staked for the current epoch.
+    #[serde(default)]
+    pub force: bool,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct RpcReloadValidatorKeyResponse {
+    pub account_id: near_primitives::types::AccountId,
+    pub public_key: near_crypto::PublicKey,
+}
+
+#[derive(thiserror::Error, Debug, Serialize, Deserialize)]
+#[serde(tag = "name", content = "info", rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum RpcReloadValidatorKeyError {
+    #[error("Validator key file is not configured")]
+    KeyFileNotConfigured,
+    #[error("Failed to read validator key file: {error_message}")]
+    KeyFileError { error_message: String },
+    #[error("Key {new_key} of {account_id} does not match the key {staked_key} staked for the current epoch")]
+    KeyMismatch {
+        account_id: near_primitives::types::AccountId,
+        staked_key: near_crypto::PublicKey,
+        new_key: near_crypto::PublicKey,
+    },
+    #[error("The node reached its limits. Try again later. More details: {error_message}")]
+    InternalError { error_message: String },
+}
+
+impl RpcReloadValidatorKeyRequest {
+    pub fn parse(value: Option<Value>) -> Result<Self, crate::errors::RpcParseError> {
+        if value.is_none() {
+            return Ok(Self::default());
+        }
+        Ok(crate::utils::parse_params::<RpcReloadValidatorKeyRequest>(value)?)
+    }
+}
+
+impl From<near_client_primitives::types::ReloadValidatorKeyResponse>
+    for RpcReloadValidatorKeyResponse
+{
+    fn from(response: near_client_primitives::types::ReloadValidatorKeyResponse) -> Self {
+        Self { account_id: response.account_id, public_key: response.public_key }
+    }
+}
+
+impl From<near_client_primitives::types::ReloadValidatorKeyError> for RpcReloadValidatorKeyError {
+    fn from(error: near_client_primitives::types::ReloadValidatorKeyError) -> Self {
+        match error {
+            near_client_primitives::types::ReloadValidatorKeyError::KeyFileNotConfigured => {
+                Self::KeyFileNotConfigured
+            }
+            near_client_primitives::types::ReloadValidatorKeyError::KeyFileError(error_message) => {
+                Self::KeyFileError { error_message }
+            }
+            near_client_primitives::types::ReloadValidatorKeyError::KeyMismatch {
+                account_id,
+                staked_key,
+                new_key,
+            } => Self::KeyMismatch { account_id, staked_key, new_key },
+            near_client_primitives::types::ReloadValidatorKeyError::IOError(error_message) => {
+                Self::InternalError { error_message }
+            }
+            near_client_primitives::types::ReloadValidatorKeyError::Unreachable(
+                ref error_message,
+            ) => {
+                tracing::warn!(target: "jsonrpc", "Unreachable error occurred: {}", &error_message);
+                crate::metrics::RPC_UNREACHABLE_ERROR_COUNT
+                    .with_label_values(&["RpcReloadValidatorKeyError"])
+                    .inc();
+                Self::InternalError { error_message: error.to_string() }
+            }
+        }
+    }
+}
+
+impl From<actix::MailboxError> for RpcReloadValidatorKeyError {
+    fn from(error: actix::MailboxError) -> Self {
+        Self::InternalError { error_message: error.to_string() }
+    }
+}
+
+impl From<RpcReloadValidatorKeyError> for crate::errors::RpcError {
+    fn from(error: RpcReloadValidatorKeyError) -> Self {
+        let error_data = match serde_json::to_value(error) {
+            Ok(value) => value,
+            Err(err) => {
+                return Self::new_internal_error(
+                    None,
+                    format!("Failed to serialize RpcReloadValidatorKeyError: {:?}", err),
+                )
+            }
+        };
+        Self::new_internal_or_handler_error(Some(error_data.clone()), error_data)
+    }
+}
@@ -0,0 +1,56 @@
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct RpcBlockProductionDebugResponse {
+    #[serde(flatten)]
+    pub block_production_info: near_primitives::views::BlockProductionDebugInfoView,
+}
+
+#[derive(thiserror::Error, Debug, Serialize, Deserialize)]
+#[serde(tag = "name", content = "info", rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum RpcBlockProductionDebugError {
+    #[error("The node reached its limits. Try again later. More details: {error_message}")]
+    InternalError { error_message: String },
+}
+
+impl From<near_client_primitives::types::GetBlockProductionDebugInfoError>
+    for RpcBlockProductionDebugError
+{
+    fn from(error: near_client_primitives::types::GetBlockProductionDebugInfoError) -> Self {
+        match error {
+            near_client_primitives::types::GetBlockProductionDebugInfoError::IOError(
+                error_message,
+            ) => Self::InternalError { error_message },
+            near_client_primitives::types::GetBlockProductionDebugInfoError::Unreachable(
+                ref error_message,
+            ) => {
+                tracing::warn!(target: "jsonrpc", "Unreachable error occurred: {}", &error_message);
+                crate::metrics::RPC_UNREACHABLE_ERROR_COUNT
+                    .with_label_values(&["RpcBlockProductionDebugError"])
+                    .inc();
+                Self::InternalError { error_message: error.to_string() }
+            }
+        }
+    }
+}
+
+impl From<actix::MailboxError> for RpcBlockProductionDebugError {
+    fn from(error: actix::MailboxError) -> Self {
+        Self::InternalError { error_message: error.to_string() }
+    }
+}
+
+impl From<RpcBlockProductionDebugError> for crate::errors::RpcError {
+    fn from(error: RpcBlockProductionDebugError) -> Self {
+        let error_data = match serde_json::to_value(error) {
+            Ok(value) => value,
+            Err(err) => {
+                return Self::new_internal_error(
+                    None,
+                    format!("Failed to serialize RpcBlockProductionDebugError: {:?}", err),
+                )
+            }
+        };
+        Self::new_internal_or_handler_error(Some(error_data.clone()), error_data)
+    }
+}
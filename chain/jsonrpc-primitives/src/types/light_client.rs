@@ -49,6 +49,8 @@ pub enum RpcLightClientProofError {
         transaction_or_receipt_id: near_primitives::hash::CryptoHash,
         shard_id: near_primitives::types::ShardId,
     },
+    #[error("Light nodes don't store execution outcomes, query a full node instead")]
+    LightNode,
     #[error("Internal error: {error_message}")]
     InternalError { error_message: String },
 }
@@ -112,6 +114,7 @@ impl From<near_client_primitives::types::GetExecutionOutcomeError> for RpcLightC
                 transaction_or_receipt_id,
                 shard_id
             } => Self::UnavailableShard { transaction_or_receipt_id, shard_id },
+            near_client_primitives::types::GetExecutionOutcomeError::LightNode => Self::LightNode,
             near_client_primitives::types::GetExecutionOutcomeError::InternalError { error_message } => {
                 Self::InternalError { error_message }
             },
@@ -1,7 +1,9 @@
+pub mod admin;
 pub mod blocks;
 pub mod changes;
 pub mod chunks;
 pub mod config;
+pub mod debug;
 pub mod gas_price;
 pub mod light_client;
 pub mod network_info;
@@ -1,7 +1,12 @@
-use near_network_primitives::types::{KnownProducer, PeerInfo};
-use near_primitives::network::PeerId;
-use near_primitives::types::AccountId;
+use near_network_primitives::types::{
+    EdgeState, KnownPeerState, KnownPeerStatus, KnownProducer, PeerInfo, RoutingTableDebugInfo,
+    SimpleEdge,
+};
+use near_primitives::network::{AnnounceAccount, PeerId};
+use near_primitives::time::Clock;
+use near_primitives::types::{AccountId, EpochId};
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
 use std::net::SocketAddr;
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -29,6 +34,66 @@ pub struct RpcNetworkInfoResponse {
     pub known_producers: Vec<RpcKnownProducer>,
 }
 
+#[derive(Serialize, Deserialize, Debug)]
+pub struct RpcPeerScore {
+    pub id: PeerId,
+    pub addr: Option<SocketAddr>,
+    pub account_id: Option<AccountId>,
+    /// One of `unknown`, `not_connected`, `connected` or `banned`.
+    pub status: String,
+    /// Current score of the peer, already decayed towards zero.
+    pub score: i32,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct RpcPeerScoresResponse {
+    /// Known peers ordered from the best to the worst score.
+    pub peers: Vec<RpcPeerScore>,
+}
+
+#[derive(thiserror::Error, Debug, Serialize, Deserialize)]
+#[serde(tag = "name", content = "info", rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum RpcPeerScoresError {
+    #[error("Internal error: {error_message}")]
+    InternalError { error_message: String },
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct RpcEdge {
+    pub peer0: PeerId,
+    pub peer1: PeerId,
+    pub nonce: u64,
+    /// Either `active` or `removed`.
+    pub state: String,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct RpcAccountAnnouncement {
+    pub account_id: AccountId,
+    pub peer_id: PeerId,
+    pub epoch_id: EpochId,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct RpcRoutingTableResponse {
+    pub my_peer_id: PeerId,
+    /// All edges known to the node, including removed ones.
+    pub edges: Vec<RpcEdge>,
+    /// For every reachable peer, the connected peers which are on a shortest path to it.
+    pub next_hops: BTreeMap<PeerId, Vec<PeerId>>,
+    /// Account announcements persisted by the node.
+    pub account_announcements: Vec<RpcAccountAnnouncement>,
+    /// Peers which are known to the node but for which there is no route.
+    pub unreachable_peers: Vec<PeerId>,
+}
+
+#[derive(thiserror::Error, Debug, Serialize, Deserialize)]
+#[serde(tag = "name", content = "info", rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum RpcRoutingTableError {
+    #[error("Internal error: {error_message}")]
+    InternalError { error_message: String },
+}
+
 #[derive(thiserror::Error, Debug, Serialize, Deserialize)]
 #[serde(tag = "name", content = "info", rename_all = "SCREAMING_SNAKE_CASE")]
 pub enum RpcNetworkInfoError {
@@ -73,6 +138,112 @@ impl From<near_client_primitives::types::NetworkInfoResponse> for RpcNetworkInfo
     }
 }
 
+impl From<KnownPeerState> for RpcPeerScore {
+    fn from(peer_state: KnownPeerState) -> Self {
+        let status = match peer_state.status {
+            KnownPeerStatus::Unknown => "unknown",
+            KnownPeerStatus::NotConnected => "not_connected",
+            KnownPeerStatus::Connected => "connected",
+            KnownPeerStatus::Banned(_, _) => "banned",
+        };
+        Self {
+            id: peer_state.peer_info.id,
+            addr: peer_state.peer_info.addr,
+            account_id: peer_state.peer_info.account_id,
+            status: status.to_string(),
+            score: peer_state.score.value(Clock::utc()),
+        }
+    }
+}
+
+impl From<Vec<KnownPeerState>> for RpcPeerScoresResponse {
+    fn from(peers: Vec<KnownPeerState>) -> Self {
+        Self { peers: peers.into_iter().map(Into::into).collect() }
+    }
+}
+
+impl From<actix::MailboxError> for RpcPeerScoresError {
+    fn from(error: actix::MailboxError) -> Self {
+        Self::InternalError { error_message: error.to_string() }
+    }
+}
+
+impl From<RpcPeerScoresError> for crate::errors::RpcError {
+    fn from(error: RpcPeerScoresError) -> Self {
+        let error_data = match serde_json::to_value(error) {
+            Ok(value) => value,
+            Err(err) => {
+                return Self::new_internal_error(
+                    None,
+                    format!("Failed to serialize RpcPeerScoresError: {:?}", err),
+                )
+            }
+        };
+        Self::new_internal_or_handler_error(Some(error_data.clone()), error_data)
+    }
+}
+
+impl From<SimpleEdge> for RpcEdge {
+    fn from(edge: SimpleEdge) -> Self {
+        let state = match edge.edge_state() {
+            EdgeState::Active => "active",
+            EdgeState::Removed => "removed",
+        };
+        let (peer0, peer1) = edge.key().clone();
+        Self { peer0, peer1, nonce: edge.nonce(), state: state.to_string() }
+    }
+}
+
+impl From<AnnounceAccount> for RpcAccountAnnouncement {
+    fn from(announce_account: AnnounceAccount) -> Self {
+        Self {
+            account_id: announce_account.account_id,
+            peer_id: announce_account.peer_id,
+            epoch_id: announce_account.epoch_id,
+        }
+    }
+}
+
+impl From<RoutingTableDebugInfo> for RpcRoutingTableResponse {
+    fn from(info: RoutingTableDebugInfo) -> Self {
+        let unreachable_peers = info.unreachable_peers().into_iter().collect();
+        Self {
+            my_peer_id: info.my_peer_id,
+            edges: info.edges.into_iter().map(Into::into).collect(),
+            next_hops: info.next_hops.into_iter().collect(),
+            account_announcements: info.account_announcements.into_iter().map(Into::into).collect(),
+            unreachable_peers,
+        }
+    }
+}
+
+impl From<actix::MailboxError> for RpcRoutingTableError {
+    fn from(error: actix::MailboxError) -> Self {
+        Self::InternalError { error_message: error.to_string() }
+    }
+}
+
+impl From<String> for RpcRoutingTableError {
+    fn from(error_message: String) -> Self {
+        Self::InternalError { error_message }
+    }
+}
+
+impl From<RpcRoutingTableError> for crate::errors::RpcError {
+    fn from(error: RpcRoutingTableError) -> Self {
+        let error_data = match serde_json::to_value(error) {
+            Ok(value) => value,
+            Err(err) => {
+                return Self::new_internal_error(
+                    None,
+                    format!("Failed to serialize RpcRoutingTableError: {:?}", err),
+                )
+            }
+        };
+        Self::new_internal_or_handler_error(Some(error_data.clone()), error_data)
+    }
+}
+
 impl From<actix::MailboxError> for RpcNetworkInfoError {
     fn from(error: actix::MailboxError) -> Self {
         Self::InternalError { error_message: error.to_string() }
@@ -1,5 +1,7 @@
+use near_primitives::hash::CryptoHash;
 use near_primitives::state_record::StateRecord;
-use near_primitives::types::BlockHeightDelta;
+use near_primitives::types::{AccountId, BlockHeightDelta};
+use near_primitives::views::ActionView;
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
 
@@ -86,3 +88,183 @@ impl From<RpcSandboxFastForwardError> for crate::errors::RpcError {
         Self::new_internal_or_handler_error(Some(error_data.clone()), error_data)
     }
 }
+
+#[derive(Deserialize, Serialize)]
+pub struct RpcSandboxSnapshotRequest {}
+
+impl RpcSandboxSnapshotRequest {
+    pub fn parse(value: Option<Value>) -> Result<Self, crate::errors::RpcParseError> {
+        Ok(crate::utils::parse_params::<RpcSandboxSnapshotRequest>(value)?)
+    }
+}
+
+#[derive(Deserialize, Serialize)]
+pub struct RpcSandboxSnapshotResponse {
+    pub snapshot_id: u64,
+}
+
+#[derive(thiserror::Error, Debug, Serialize, Deserialize)]
+#[serde(tag = "name", content = "info", rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum RpcSandboxSnapshotError {
+    #[error("The node reached its limits. Try again later. More details: {error_message}")]
+    InternalError { error_message: String },
+}
+
+impl From<actix::MailboxError> for RpcSandboxSnapshotError {
+    fn from(error: actix::MailboxError) -> Self {
+        Self::InternalError { error_message: error.to_string() }
+    }
+}
+
+impl From<RpcSandboxSnapshotError> for crate::errors::RpcError {
+    fn from(error: RpcSandboxSnapshotError) -> Self {
+        let error_data = match serde_json::to_value(error) {
+            Ok(value) => value,
+            Err(err) => {
+                return Self::new_internal_error(
+                    None,
+                    format!("Failed to serialize RpcSandboxSnapshotError: {:?}", err),
+                )
+            }
+        };
+        Self::new_internal_or_handler_error(Some(error_data.clone()), error_data)
+    }
+}
+
+#[derive(Deserialize, Serialize)]
+pub struct RpcSandboxDeleteSnapshotRequest {
+    pub snapshot_id: u64,
+}
+
+impl RpcSandboxDeleteSnapshotRequest {
+    pub fn parse(value: Option<Value>) -> Result<Self, crate::errors::RpcParseError> {
+        Ok(crate::utils::parse_params::<RpcSandboxDeleteSnapshotRequest>(value)?)
+    }
+}
+
+#[derive(Deserialize, Serialize)]
+pub struct RpcSandboxDeleteSnapshotResponse {}
+
+#[derive(thiserror::Error, Debug, Serialize, Deserialize)]
+#[serde(tag = "name", content = "info", rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum RpcSandboxDeleteSnapshotError {
+    #[error("Cannot delete snapshot {snapshot_id}: {error_message}")]
+    DeleteFailed { snapshot_id: u64, error_message: String },
+    #[error("The node reached its limits. Try again later. More details: {error_message}")]
+    InternalError { error_message: String },
+}
+
+impl From<actix::MailboxError> for RpcSandboxDeleteSnapshotError {
+    fn from(error: actix::MailboxError) -> Self {
+        Self::InternalError { error_message: error.to_string() }
+    }
+}
+
+impl From<RpcSandboxDeleteSnapshotError> for crate::errors::RpcError {
+    fn from(error: RpcSandboxDeleteSnapshotError) -> Self {
+        let error_data = match serde_json::to_value(error) {
+            Ok(value) => value,
+            Err(err) => {
+                return Self::new_internal_error(
+                    None,
+                    format!("Failed to serialize RpcSandboxDeleteSnapshotError: {:?}", err),
+                )
+            }
+        };
+        Self::new_internal_or_handler_error(Some(error_data.clone()), error_data)
+    }
+}
+
+#[derive(Deserialize, Serialize)]
+pub struct RpcSandboxRevertRequest {
+    pub snapshot_id: u64,
+}
+
+impl RpcSandboxRevertRequest {
+    pub fn parse(value: Option<Value>) -> Result<Self, crate::errors::RpcParseError> {
+        Ok(crate::utils::parse_params::<RpcSandboxRevertRequest>(value)?)
+    }
+}
+
+#[derive(Deserialize, Serialize)]
+pub struct RpcSandboxRevertResponse {}
+
+#[derive(thiserror::Error, Debug, Serialize, Deserialize)]
+#[serde(tag = "name", content = "info", rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum RpcSandboxRevertError {
+    #[error("Cannot revert to snapshot {snapshot_id}: {error_message}")]
+    RevertFailed { snapshot_id: u64, error_message: String },
+    #[error("The node reached its limits. Try again later. More details: {error_message}")]
+    InternalError { error_message: String },
+}
+
+impl From<actix::MailboxError> for RpcSandboxRevertError {
+    fn from(error: actix::MailboxError) -> Self {
+        Self::InternalError { error_message: error.to_string() }
+    }
+}
+
+impl From<RpcSandboxRevertError> for crate::errors::RpcError {
+    fn from(error: RpcSandboxRevertError) -> Self {
+        let error_data = match serde_json::to_value(error) {
+            Ok(value) => value,
+            Err(err) => {
+                return Self::new_internal_error(
+                    None,
+                    format!("Failed to serialize RpcSandboxRevertError: {:?}", err),
+                )
+            }
+        };
+        Self::new_internal_or_handler_error(Some(error_data.clone()), error_data)
+    }
+}
+
+#[derive(Deserialize, Serialize)]
+pub struct RpcSandboxImpersonateRequest {
+    pub signer_id: AccountId,
+    pub receiver_id: AccountId,
+    pub actions: Vec<ActionView>,
+}
+
+impl RpcSandboxImpersonateRequest {
+    pub fn parse(value: Option<Value>) -> Result<Self, crate::errors::RpcParseError> {
+        Ok(crate::utils::parse_params::<RpcSandboxImpersonateRequest>(value)?)
+    }
+}
+
+#[derive(Deserialize, Serialize)]
+pub struct RpcSandboxImpersonateResponse {
+    pub transaction_hash: CryptoHash,
+}
+
+#[derive(thiserror::Error, Debug, Serialize, Deserialize)]
+#[serde(tag = "name", content = "info", rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum RpcSandboxImpersonateError {
+    #[error("Invalid action: {error_message}")]
+    InvalidAction { error_message: String },
+    #[error("Invalid transaction: {error_message}")]
+    InvalidTransaction { error_message: String },
+    #[error("The node reached its limits. Try again later. More details: {error_message}")]
+    InternalError { error_message: String },
+}
+
+impl From<actix::MailboxError> for RpcSandboxImpersonateError {
+    fn from(error: actix::MailboxError) -> Self {
+        Self::InternalError { error_message: error.to_string() }
+    }
+}
+
+impl From<RpcSandboxImpersonateError> for crate::errors::RpcError {
+    fn from(error: RpcSandboxImpersonateError) -> Self {
+        let error_data = match serde_json::to_value(error) {
+            Ok(value) => value,
+            Err(err) => {
+                return Self::new_internal_error(
+                    None,
+                    format!("Failed to serialize RpcSandboxImpersonateError: {:?}", err),
+                )
+            }
+        };
+        Self::new_internal_or_handler_error(Some(error_data.clone()), error_data)
+    }
+}
@@ -4,6 +4,9 @@ use serde_json::Value;
 pub type RpcValidatorsOrderedResponse =
     Vec<near_primitives::views::validator_stake_view::ValidatorStakeView>;
 
+pub type RpcValidatorPerformanceResponse =
+    Vec<near_primitives::views::ValidatorEpochPerformanceView>;
+
 #[derive(thiserror::Error, Debug, Serialize, Deserialize)]
 #[serde(tag = "name", content = "info", rename_all = "SCREAMING_SNAKE_CASE")]
 pub enum RpcValidatorError {
@@ -26,6 +29,15 @@ pub struct RpcValidatorsOrderedRequest {
     pub block_id: near_primitives::types::MaybeBlockId,
 }
 
+#[derive(Serialize, Deserialize, Debug)]
+pub struct RpcValidatorPerformanceRequest {
+    pub account_id: near_primitives::types::AccountId,
+    pub from_epoch_height: near_primitives::types::EpochHeight,
+    /// Defaults to the last finished epoch.
+    #[serde(default)]
+    pub to_epoch_height: Option<near_primitives::types::EpochHeight>,
+}
+
 #[derive(Serialize, Deserialize, Debug)]
 pub struct RpcValidatorResponse {
     #[serde(flatten)]
@@ -85,6 +97,12 @@ impl RpcValidatorsOrderedRequest {
     }
 }
 
+impl RpcValidatorPerformanceRequest {
+    pub fn parse(value: Option<Value>) -> Result<Self, crate::errors::RpcParseError> {
+        Ok(crate::utils::parse_params::<RpcValidatorPerformanceRequest>(value)?)
+    }
+}
+
 impl From<RpcValidatorError> for crate::errors::RpcError {
     fn from(error: RpcValidatorError) -> Self {
         let error_data = match &error {
@@ -152,7 +152,7 @@ macro_rules! jsonrpc_client {
         $(#[$struct_attr:meta])*
         pub struct $struct_name:ident {$(
             $(#[$attr:meta])*
-            pub fn $method:ident(&$selff:ident $(, $arg_name:ident: $arg_ty:ty)*)
+            pub fn $method:ident(&$selff:ident $(, $arg_name:ident: $arg_ty:ty)* $(,)?)
                 -> RpcRequest<$return_ty:ty>;
         )*}
     ) => (
@@ -191,6 +191,18 @@ jsonrpc_client!(pub struct JsonRpcClient {
     #[allow(non_snake_case)]
     pub fn EXPERIMENTAL_genesis_config(&self) -> RpcRequest<serde_json::Value>;
     #[allow(non_snake_case)]
+    pub fn EXPERIMENTAL_block_production_debug(
+        &self,
+    ) -> RpcRequest<near_jsonrpc_primitives::types::debug::RpcBlockProductionDebugResponse>;
+    #[allow(non_snake_case)]
+    pub fn EXPERIMENTAL_peer_scores(
+        &self,
+    ) -> RpcRequest<near_jsonrpc_primitives::types::network_info::RpcPeerScoresResponse>;
+    #[allow(non_snake_case)]
+    pub fn EXPERIMENTAL_routing_table(
+        &self,
+    ) -> RpcRequest<near_jsonrpc_primitives::types::network_info::RpcRoutingTableResponse>;
+    #[allow(non_snake_case)]
     pub fn EXPERIMENTAL_broadcast_tx_sync(&self, tx: String) -> RpcRequest<serde_json::Value>;
     #[allow(non_snake_case)]
     pub fn EXPERIMENTAL_tx_status(&self, tx: String) -> RpcRequest<serde_json::Value>;
@@ -243,6 +255,15 @@ impl JsonRpcClient {
         call_method(&self.client, &self.server_addr, "EXPERIMENTAL_validators_ordered", request)
     }
 
+    #[allow(non_snake_case)]
+    pub fn EXPERIMENTAL_validator_performance(
+        &self,
+        request: near_jsonrpc_primitives::types::validator::RpcValidatorPerformanceRequest,
+    ) -> RpcRequest<near_jsonrpc_primitives::types::validator::RpcValidatorPerformanceResponse>
+    {
+        call_method(&self.client, &self.server_addr, "EXPERIMENTAL_validator_performance", request)
+    }
+
     #[allow(non_snake_case)]
     pub fn EXPERIMENTAL_receipt(
         &self,
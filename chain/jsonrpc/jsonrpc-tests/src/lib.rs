@@ -53,6 +53,8 @@ pub fn start_all_with_validity_period_and_no_epoch_sync(
         TEST_GENESIS_CONFIG.clone(),
         client_addr.clone(),
         view_client_addr.clone(),
+        None,
+        None,
         #[cfg(feature = "test_features")]
         peer_manager_addr,
         #[cfg(feature = "test_features")]
@@ -2,7 +2,7 @@
 
 use std::time::{Duration, Instant};
 
-use actix::Addr;
+use actix::{Addr, Recipient};
 use actix_cors::Cors;
 use actix_web::{http, middleware, web, App, Error as HttpError, HttpResponse, HttpServer};
 use futures::Future;
@@ -15,10 +15,11 @@ use tracing::info;
 
 use near_chain_configs::GenesisConfig;
 use near_client::{
-    ClientActor, GetBlock, GetBlockProof, GetChunk, GetExecutionOutcome, GetGasPrice,
-    GetNetworkInfo, GetNextLightClientBlock, GetProtocolConfig, GetReceipt, GetStateChanges,
-    GetStateChangesInBlock, GetValidatorInfo, GetValidatorOrdered, Query, Status, TxStatus,
-    TxStatusError, ViewClientActor,
+    ClientActor, GetBlock, GetBlockProductionDebugInfo, GetBlockProof, GetChunk,
+    GetExecutionOutcome, GetGasPrice, GetNetworkInfo, GetNextLightClientBlock, GetProtocolConfig,
+    GetReceipt, GetStateChanges, GetStateChangesInBlock, GetValidatorInfo, GetValidatorOrdered,
+    GetValidatorPerformance, Query, ReloadValidatorKey, Status, TxStatus, TxStatusError,
+    ViewClientActor,
 };
 pub use near_jsonrpc_client as client;
 use near_jsonrpc_primitives::errors::RpcError;
@@ -26,6 +27,9 @@ use near_jsonrpc_primitives::message::{Message, Request};
 use near_jsonrpc_primitives::types::config::RpcProtocolConfigResponse;
 use near_metrics::{Encoder, TextEncoder};
 use near_network::types::{NetworkClientMessages, NetworkClientResponses};
+use near_network_primitives::types::{
+    GetPeerScores, GetRoutingTableDebugInfo, RoutingTableDebugInfo,
+};
 use near_primitives::hash::CryptoHash;
 use near_primitives::serialize::BaseEncode;
 use near_primitives::transaction::SignedTransaction;
@@ -70,6 +74,10 @@ pub struct RpcConfig {
     pub polling_config: RpcPollingConfig,
     #[serde(default)]
     pub limits_config: RpcLimitsConfig,
+    /// Enables the `admin_*` methods, which change the state of the node. They should only be
+    /// enabled when the RPC port is not reachable from untrusted networks.
+    #[serde(default)]
+    pub enable_admin_rpc: bool,
 }
 
 impl Default for RpcConfig {
@@ -80,6 +88,7 @@ impl Default for RpcConfig {
             cors_allowed_origins: vec!["*".to_owned()],
             polling_config: Default::default(),
             limits_config: Default::default(),
+            enable_admin_rpc: false,
         }
     }
 }
@@ -199,6 +208,11 @@ struct JsonRpcHandler {
     view_client_addr: Addr<ViewClientActor>,
     polling_config: RpcPollingConfig,
     genesis_config: GenesisConfig,
+    enable_admin_rpc: bool,
+    /// Not set when the node runs without a peer manager, e.g. in tests.
+    routing_table_debug: Option<Recipient<GetRoutingTableDebugInfo>>,
+    /// Not set when the node runs without a peer manager, e.g. in tests.
+    peer_scores: Option<Recipient<GetPeerScores>>,
     #[cfg(feature = "test_features")]
     peer_manager_addr: Addr<near_network::PeerManagerActor>,
     #[cfg(feature = "test_features")]
@@ -549,6 +563,31 @@ impl JsonRpcHandler {
                 serde_json::to_value(validators)
                     .map_err(|err| RpcError::serialization_error(err.to_string()))
             }
+            "EXPERIMENTAL_validator_performance" => {
+                let rpc_validator_performance_request =
+                    near_jsonrpc_primitives::types::validator::RpcValidatorPerformanceRequest::parse(
+                        request.params,
+                    )?;
+                let performance =
+                    self.validator_performance(rpc_validator_performance_request).await?;
+                serde_json::to_value(performance)
+                    .map_err(|err| RpcError::serialization_error(err.to_string()))
+            }
+            "EXPERIMENTAL_peer_scores" => {
+                let peer_scores_response = self.peer_scores().await?;
+                serde_json::to_value(peer_scores_response)
+                    .map_err(|err| RpcError::serialization_error(err.to_string()))
+            }
+            "EXPERIMENTAL_routing_table" => {
+                let routing_table_response = self.routing_table().await?;
+                serde_json::to_value(routing_table_response)
+                    .map_err(|err| RpcError::serialization_error(err.to_string()))
+            }
+            "EXPERIMENTAL_block_production_debug" => {
+                let block_production_debug_response = self.block_production_debug().await?;
+                serde_json::to_value(block_production_debug_response)
+                    .map_err(|err| RpcError::serialization_error(err.to_string()))
+            }
             #[cfg(feature = "sandbox")]
             "sandbox_patch_state" => {
                 let sandbox_patch_state_request =
@@ -571,6 +610,59 @@ impl JsonRpcHandler {
                 serde_json::to_value(sandbox_fast_forward_response)
                     .map_err(|err| RpcError::serialization_error(err.to_string()))
             }
+            #[cfg(feature = "sandbox")]
+            "sandbox_snapshot" => {
+                let sandbox_snapshot_request =
+                    near_jsonrpc_primitives::types::sandbox::RpcSandboxSnapshotRequest::parse(
+                        request.params,
+                    )?;
+                let sandbox_snapshot_response =
+                    self.sandbox_snapshot(sandbox_snapshot_request).await?;
+                serde_json::to_value(sandbox_snapshot_response)
+                    .map_err(|err| RpcError::serialization_error(err.to_string()))
+            }
+            #[cfg(feature = "sandbox")]
+            "sandbox_delete_snapshot" => {
+                let sandbox_delete_snapshot_request =
+                    near_jsonrpc_primitives::types::sandbox::RpcSandboxDeleteSnapshotRequest::parse(
+                        request.params,
+                    )?;
+                let sandbox_delete_snapshot_response =
+                    self.sandbox_delete_snapshot(sandbox_delete_snapshot_request).await?;
+                serde_json::to_value(sandbox_delete_snapshot_response)
+                    .map_err(|err| RpcError::serialization_error(err.to_string()))
+            }
+            #[cfg(feature = "sandbox")]
+            "sandbox_revert" => {
+                let sandbox_revert_request =
+                    near_jsonrpc_primitives::types::sandbox::RpcSandboxRevertRequest::parse(
+                        request.params,
+                    )?;
+                let sandbox_revert_response = self.sandbox_revert(sandbox_revert_request).await?;
+                serde_json::to_value(sandbox_revert_response)
+                    .map_err(|err| RpcError::serialization_error(err.to_string()))
+            }
+            #[cfg(feature = "sandbox")]
+            "sandbox_impersonate" => {
+                let sandbox_impersonate_request =
+                    near_jsonrpc_primitives::types::sandbox::RpcSandboxImpersonateRequest::parse(
+                        request.params,
+                    )?;
+                let sandbox_impersonate_response =
+                    self.sandbox_impersonate(sandbox_impersonate_request).await?;
+                serde_json::to_value(sandbox_impersonate_response)
+                    .map_err(|err| RpcError::serialization_error(err.to_string()))
+            }
+            "admin_reload_validator_key" if self.enable_admin_rpc => {
+                let rpc_reload_validator_key_request =
+                    near_jsonrpc_primitives::types::admin::RpcReloadValidatorKeyRequest::parse(
+                        request.params,
+                    )?;
+                let reload_validator_key_response =
+                    self.reload_validator_key(rpc_reload_validator_key_request).await?;
+                serde_json::to_value(reload_validator_key_response)
+                    .map_err(|err| RpcError::serialization_error(err.to_string()))
+            }
             _ => Err(RpcError::method_not_found(request.method.clone())),
         };
 
@@ -1103,6 +1195,89 @@ impl JsonRpcHandler {
             request;
         Ok(self.view_client_addr.send(GetValidatorOrdered { block_id }).await??.into())
     }
+
+    /// Reloads the validator key file and switches the client to the new key.
+    async fn reload_validator_key(
+        &self,
+        request: near_jsonrpc_primitives::types::admin::RpcReloadValidatorKeyRequest,
+    ) -> Result<
+        near_jsonrpc_primitives::types::admin::RpcReloadValidatorKeyResponse,
+        near_jsonrpc_primitives::types::admin::RpcReloadValidatorKeyError,
+    > {
+        Ok(self.client_addr.send(ReloadValidatorKey { force: request.force }).await??.into())
+    }
+
+    /// Returns the known peers together with their current scores.
+    async fn peer_scores(
+        &self,
+    ) -> Result<
+        near_jsonrpc_primitives::types::network_info::RpcPeerScoresResponse,
+        near_jsonrpc_primitives::types::network_info::RpcPeerScoresError,
+    > {
+        let recipient = self.peer_scores.as_ref().ok_or_else(|| {
+            near_jsonrpc_primitives::types::network_info::RpcPeerScoresError::InternalError {
+                error_message: "Peer scores are not available".to_string(),
+            }
+        })?;
+        Ok(recipient.send(GetPeerScores {}).await?.into())
+    }
+
+    /// Returns the peer graph known to the node together with the routes computed from it.
+    async fn routing_table(
+        &self,
+    ) -> Result<
+        near_jsonrpc_primitives::types::network_info::RpcRoutingTableResponse,
+        near_jsonrpc_primitives::types::network_info::RpcRoutingTableError,
+    > {
+        Ok(self.routing_table_debug_info().await?.into())
+    }
+
+    async fn routing_table_debug_info(
+        &self,
+    ) -> Result<
+        RoutingTableDebugInfo,
+        near_jsonrpc_primitives::types::network_info::RpcRoutingTableError,
+    > {
+        let recipient = self.routing_table_debug.as_ref().ok_or_else(|| {
+            near_jsonrpc_primitives::types::network_info::RpcRoutingTableError::InternalError {
+                error_message: "Routing table is not available".to_string(),
+            }
+        })?;
+        Ok(recipient.send(GetRoutingTableDebugInfo {}).await??)
+    }
+
+    /// Returns the doomslug state and the timings of the block production at the recent heights
+    /// the node was the block producer for.
+    async fn block_production_debug(
+        &self,
+    ) -> Result<
+        near_jsonrpc_primitives::types::debug::RpcBlockProductionDebugResponse,
+        near_jsonrpc_primitives::types::debug::RpcBlockProductionDebugError,
+    > {
+        let block_production_info = self.client_addr.send(GetBlockProductionDebugInfo {}).await??;
+        Ok(near_jsonrpc_primitives::types::debug::RpcBlockProductionDebugResponse {
+            block_production_info,
+        })
+    }
+
+    /// Returns the per-epoch performance of a validator over a range of finished epochs.
+    async fn validator_performance(
+        &self,
+        request: near_jsonrpc_primitives::types::validator::RpcValidatorPerformanceRequest,
+    ) -> Result<
+        near_jsonrpc_primitives::types::validator::RpcValidatorPerformanceResponse,
+        near_jsonrpc_primitives::types::validator::RpcValidatorError,
+    > {
+        let near_jsonrpc_primitives::types::validator::RpcValidatorPerformanceRequest {
+            account_id,
+            from_epoch_height,
+            to_epoch_height,
+        } = request;
+        Ok(self
+            .view_client_addr
+            .send(GetValidatorPerformance { account_id, from_epoch_height, to_epoch_height })
+            .await??)
+    }
 }
 
 #[cfg(feature = "sandbox")]
@@ -1161,6 +1336,162 @@ impl JsonRpcHandler {
             .await?;
         Ok(near_jsonrpc_primitives::types::sandbox::RpcSandboxFastForwardResponse {})
     }
+
+    async fn sandbox_snapshot(
+        &self,
+        _snapshot_request: near_jsonrpc_primitives::types::sandbox::RpcSandboxSnapshotRequest,
+    ) -> Result<
+        near_jsonrpc_primitives::types::sandbox::RpcSandboxSnapshotResponse,
+        near_jsonrpc_primitives::types::sandbox::RpcSandboxSnapshotError,
+    > {
+        let response = self
+            .client_addr
+            .send(NetworkClientMessages::Sandbox(
+                near_network_primitives::types::NetworkSandboxMessage::SandboxSnapshot,
+            ))
+            .await?;
+        match response {
+            NetworkClientResponses::SandboxResult(
+                near_network_primitives::types::SandboxResponse::SandboxSnapshotCreated(result),
+            ) => result
+                .map(|snapshot_id| {
+                    near_jsonrpc_primitives::types::sandbox::RpcSandboxSnapshotResponse {
+                        snapshot_id,
+                    }
+                })
+                .map_err(|error_message| {
+                    near_jsonrpc_primitives::types::sandbox::RpcSandboxSnapshotError::InternalError {
+                        error_message,
+                    }
+                }),
+            _ => Err(
+                near_jsonrpc_primitives::types::sandbox::RpcSandboxSnapshotError::InternalError {
+                    error_message: "Unexpected response to snapshot request".to_string(),
+                },
+            ),
+        }
+    }
+
+    async fn sandbox_delete_snapshot(
+        &self,
+        delete_snapshot_request: near_jsonrpc_primitives::types::sandbox::RpcSandboxDeleteSnapshotRequest,
+    ) -> Result<
+        near_jsonrpc_primitives::types::sandbox::RpcSandboxDeleteSnapshotResponse,
+        near_jsonrpc_primitives::types::sandbox::RpcSandboxDeleteSnapshotError,
+    > {
+        let snapshot_id = delete_snapshot_request.snapshot_id;
+        let response = self
+            .client_addr
+            .send(NetworkClientMessages::Sandbox(
+                near_network_primitives::types::NetworkSandboxMessage::SandboxDeleteSnapshot(
+                    snapshot_id,
+                ),
+            ))
+            .await?;
+        match response {
+            NetworkClientResponses::SandboxResult(
+                near_network_primitives::types::SandboxResponse::SandboxSnapshotDeleted(result),
+            ) => result
+                .map(|()| {
+                    near_jsonrpc_primitives::types::sandbox::RpcSandboxDeleteSnapshotResponse {}
+                })
+                .map_err(|error_message| {
+                    near_jsonrpc_primitives::types::sandbox::RpcSandboxDeleteSnapshotError::DeleteFailed {
+                        snapshot_id,
+                        error_message,
+                    }
+                }),
+            _ => Err(
+                near_jsonrpc_primitives::types::sandbox::RpcSandboxDeleteSnapshotError::InternalError {
+                    error_message: "Unexpected response to delete snapshot request".to_string(),
+                },
+            ),
+        }
+    }
+
+    async fn sandbox_revert(
+        &self,
+        revert_request: near_jsonrpc_primitives::types::sandbox::RpcSandboxRevertRequest,
+    ) -> Result<
+        near_jsonrpc_primitives::types::sandbox::RpcSandboxRevertResponse,
+        near_jsonrpc_primitives::types::sandbox::RpcSandboxRevertError,
+    > {
+        let snapshot_id = revert_request.snapshot_id;
+        let response = self
+            .client_addr
+            .send(NetworkClientMessages::Sandbox(
+                near_network_primitives::types::NetworkSandboxMessage::SandboxRevert(snapshot_id),
+            ))
+            .await?;
+        match response {
+            NetworkClientResponses::SandboxResult(
+                near_network_primitives::types::SandboxResponse::SandboxReverted(result),
+            ) => result
+                .map(|()| near_jsonrpc_primitives::types::sandbox::RpcSandboxRevertResponse {})
+                .map_err(|error_message| {
+                    near_jsonrpc_primitives::types::sandbox::RpcSandboxRevertError::RevertFailed {
+                        snapshot_id,
+                        error_message,
+                    }
+                }),
+            _ => {
+                Err(near_jsonrpc_primitives::types::sandbox::RpcSandboxRevertError::InternalError {
+                    error_message: "Unexpected response to revert request".to_string(),
+                })
+            }
+        }
+    }
+
+    async fn sandbox_impersonate(
+        &self,
+        impersonate_request: near_jsonrpc_primitives::types::sandbox::RpcSandboxImpersonateRequest,
+    ) -> Result<
+        near_jsonrpc_primitives::types::sandbox::RpcSandboxImpersonateResponse,
+        near_jsonrpc_primitives::types::sandbox::RpcSandboxImpersonateError,
+    > {
+        let near_jsonrpc_primitives::types::sandbox::RpcSandboxImpersonateRequest {
+            signer_id,
+            receiver_id,
+            actions,
+        } = impersonate_request;
+        let actions = actions
+            .into_iter()
+            .map(near_primitives::transaction::Action::try_from)
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|err| {
+                near_jsonrpc_primitives::types::sandbox::RpcSandboxImpersonateError::InvalidAction {
+                    error_message: err.to_string(),
+                }
+            })?;
+        let response = self
+            .client_addr
+            .send(NetworkClientMessages::Sandbox(
+                near_network_primitives::types::NetworkSandboxMessage::SandboxImpersonate {
+                    signer_id,
+                    receiver_id,
+                    actions,
+                },
+            ))
+            .await?;
+        match response {
+            NetworkClientResponses::SandboxResult(
+                near_network_primitives::types::SandboxResponse::SandboxImpersonated(result),
+            ) => result
+                .map(|transaction_hash| {
+                    near_jsonrpc_primitives::types::sandbox::RpcSandboxImpersonateResponse {
+                        transaction_hash,
+                    }
+                })
+                .map_err(|error_message| {
+                    near_jsonrpc_primitives::types::sandbox::RpcSandboxImpersonateError::InvalidTransaction {
+                        error_message,
+                    }
+                }),
+            _ => Err(near_jsonrpc_primitives::types::sandbox::RpcSandboxImpersonateError::InternalError {
+                error_message: "Unexpected response to impersonate request".to_string(),
+            }),
+        }
+    }
 }
 
 #[cfg(feature = "test_features")]
@@ -1334,6 +1665,56 @@ fn network_info_handler(
     response.boxed()
 }
 
+fn peer_scores_handler(
+    handler: web::Data<JsonRpcHandler>,
+) -> impl Future<Output = Result<HttpResponse, HttpError>> {
+    let response = async move {
+        match handler.peer_scores().await {
+            Ok(value) => Ok(HttpResponse::Ok().json(&value)),
+            Err(_) => Ok(HttpResponse::ServiceUnavailable().finish()),
+        }
+    };
+    response.boxed()
+}
+
+fn routing_table_handler(
+    handler: web::Data<JsonRpcHandler>,
+) -> impl Future<Output = Result<HttpResponse, HttpError>> {
+    let response = async move {
+        match handler.routing_table().await {
+            Ok(value) => Ok(HttpResponse::Ok().json(&value)),
+            Err(_) => Ok(HttpResponse::ServiceUnavailable().finish()),
+        }
+    };
+    response.boxed()
+}
+
+fn routing_table_dot_handler(
+    handler: web::Data<JsonRpcHandler>,
+) -> impl Future<Output = Result<HttpResponse, HttpError>> {
+    let response = async move {
+        match handler.routing_table_debug_info().await {
+            Ok(info) => {
+                Ok(HttpResponse::Ok().content_type("text/vnd.graphviz").body(info.to_dot()))
+            }
+            Err(_) => Ok(HttpResponse::ServiceUnavailable().finish()),
+        }
+    };
+    response.boxed()
+}
+
+fn block_production_debug_handler(
+    handler: web::Data<JsonRpcHandler>,
+) -> impl Future<Output = Result<HttpResponse, HttpError>> {
+    let response = async move {
+        match handler.block_production_debug().await {
+            Ok(value) => Ok(HttpResponse::Ok().json(&value)),
+            Err(_) => Ok(HttpResponse::ServiceUnavailable().finish()),
+        }
+    };
+    response.boxed()
+}
+
 pub async fn prometheus_handler() -> Result<HttpResponse, HttpError> {
     metrics::PROMETHEUS_REQUEST_COUNT.inc();
 
@@ -1376,11 +1757,19 @@ pub fn start_http(
     genesis_config: GenesisConfig,
     client_addr: Addr<ClientActor>,
     view_client_addr: Addr<ViewClientActor>,
+    routing_table_debug: Option<Recipient<GetRoutingTableDebugInfo>>,
+    peer_scores: Option<Recipient<GetPeerScores>>,
     #[cfg(feature = "test_features")] peer_manager_addr: Addr<near_network::PeerManagerActor>,
     #[cfg(feature = "test_features")] routing_table_addr: Addr<near_network::RoutingTableActor>,
 ) -> Vec<(&'static str, actix_web::dev::Server)> {
-    let RpcConfig { addr, prometheus_addr, cors_allowed_origins, polling_config, limits_config } =
-        config;
+    let RpcConfig {
+        addr,
+        prometheus_addr,
+        cors_allowed_origins,
+        polling_config,
+        limits_config,
+        enable_admin_rpc,
+    } = config;
     let prometheus_addr = prometheus_addr.filter(|it| it != &addr);
     let cors_allowed_origins_clone = cors_allowed_origins.clone();
     info!(target:"network", "Starting http server at {}", addr);
@@ -1393,6 +1782,9 @@ pub fn start_http(
                 view_client_addr: view_client_addr.clone(),
                 polling_config,
                 genesis_config: genesis_config.clone(),
+                enable_admin_rpc,
+                routing_table_debug: routing_table_debug.clone(),
+                peer_scores: peer_scores.clone(),
                 #[cfg(feature = "test_features")]
                 peer_manager_addr: peer_manager_addr.clone(),
                 #[cfg(feature = "test_features")]
@@ -1412,6 +1804,18 @@ pub fn start_http(
                     .route(web::head().to(health_handler)),
             )
             .service(web::resource("/network_info").route(web::get().to(network_info_handler)))
+            .service(
+                web::resource("/debug/block_production")
+                    .route(web::get().to(block_production_debug_handler)),
+            )
+            .service(web::resource("/debug/peer_scores").route(web::get().to(peer_scores_handler)))
+            .service(
+                web::resource("/debug/routing_table").route(web::get().to(routing_table_handler)),
+            )
+            .service(
+                web::resource("/debug/routing_table.dot")
+                    .route(web::get().to(routing_table_dot_handler)),
+            )
             .service(web::resource("/metrics").route(web::get().to(prometheus_handler)))
     })
     .bind(addr)
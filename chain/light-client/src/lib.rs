@@ -0,0 +1,401 @@
+//! Light client which follows the chain using `LightClientBlockView`s and verifies execution
+//! outcome proofs, as described in the
+//! [light client spec](https://nomicon.io/ChainSpec/LightClient.html).
+//!
+//! The client doesn't do any networking: blocks are obtained by the caller, e.g. with the
+//! `next_light_client_block` JSON-RPC method, and fed into `LightClient::validate_and_update_head`.
+use std::collections::HashMap;
+
+use near_jsonrpc_primitives::types::light_client::RpcLightClientExecutionProofResponse;
+use near_primitives::hash::CryptoHash;
+use near_primitives::light_client::{
+    validate_next_block_producers, verify_approvals, LightClientBlockError,
+};
+use near_primitives::merkle::{compute_root_from_path_and_item, verify_hash, verify_path};
+use near_primitives::types::validator_stake::ValidatorStake;
+use near_primitives::types::{AccountId, BlockHeight, EpochId};
+use near_primitives::views::validator_stake_view::ValidatorStakeView;
+use near_primitives::views::LightClientBlockView;
+
+#[derive(thiserror::Error, Debug, Clone, PartialEq, Eq)]
+pub enum LightClientError {
+    #[error("Block at height {height} is not above the head at height {head_height}")]
+    OldBlock { height: BlockHeight, head_height: BlockHeight },
+    #[error("Block is neither in the epoch of the head nor in the next one")]
+    UnknownEpoch,
+    #[error("Block starts a new epoch but doesn't have the block producers of the next epoch")]
+    MissingNextBlockProducers,
+    #[error("Block has more approvals than there are block producers")]
+    InvalidApprovals,
+    #[error("Invalid approval signature of {0}")]
+    InvalidSignature(AccountId),
+    #[error("Block is not approved by more than 2/3 of the stake")]
+    NotEnoughApprovals,
+    #[error("Block producers of the next epoch don't match next_bp_hash")]
+    InvalidNextBlockProducers,
+    #[error("Invalid execution outcome: {0}")]
+    InvalidOutcome(String),
+    #[error("Execution outcome is not included in the outcome root of its block")]
+    InvalidOutcomeProof,
+    #[error("Block header doesn't match the block of the execution outcome")]
+    InvalidBlockHeader,
+    #[error("Block is not included in the trusted block merkle root")]
+    InvalidBlockProof,
+}
+
+impl From<LightClientBlockError> for LightClientError {
+    fn from(error: LightClientBlockError) -> Self {
+        match error {
+            LightClientBlockError::InvalidApprovals => LightClientError::InvalidApprovals,
+            LightClientBlockError::InvalidSignature(account_id) => {
+                LightClientError::InvalidSignature(account_id)
+            }
+            LightClientBlockError::NotEnoughApprovals => LightClientError::NotEnoughApprovals,
+            LightClientBlockError::InvalidNextBlockProducers => {
+                LightClientError::InvalidNextBlockProducers
+            }
+        }
+    }
+}
+
+/// Light client which keeps track of the latest verified block and of the block producers of its
+/// epoch and of the next one.
+pub struct LightClient {
+    head: LightClientBlockView,
+    /// Ordered block producers of the epoch of the head and, if known, of the next epoch.
+    epoch_block_producers: HashMap<EpochId, Vec<ValidatorStake>>,
+}
+
+impl LightClient {
+    /// Creates a light client starting from a trusted `head`, e.g. a checkpoint shipped with the
+    /// application. `block_producers` are the ordered block producers of the epoch of the head.
+    /// If `head.next_bps` is set, the client is able to follow the chain into the next epoch.
+    pub fn new(
+        head: LightClientBlockView,
+        block_producers: Vec<ValidatorStakeView>,
+    ) -> Result<Self, LightClientError> {
+        let mut epoch_block_producers = HashMap::new();
+        epoch_block_producers.insert(
+            EpochId(head.inner_lite.epoch_id),
+            block_producers.into_iter().map(Into::into).collect(),
+        );
+        if let Some(next_bps) = validate_next_block_producers(&head)? {
+            epoch_block_producers.insert(EpochId(head.inner_lite.next_epoch_id), next_bps);
+        }
+        Ok(Self { head, epoch_block_producers })
+    }
+
+    pub fn head(&self) -> &LightClientBlockView {
+        &self.head
+    }
+
+    pub fn head_hash(&self) -> CryptoHash {
+        self.head.current_block_hash()
+    }
+
+    /// Ordered block producers of `epoch_id`, if it is the epoch of the head or the next one.
+    pub fn block_producers(&self, epoch_id: &EpochId) -> Option<&[ValidatorStake]> {
+        self.epoch_block_producers.get(epoch_id).map(Vec::as_slice)
+    }
+
+    /// Verifies that `block` follows the head and is approved by the block producers of its
+    /// epoch, and makes it the new head.
+    pub fn validate_and_update_head(
+        &mut self,
+        block: &LightClientBlockView,
+    ) -> Result<(), LightClientError> {
+        let inner_lite = &block.inner_lite;
+        let head_inner_lite = &self.head.inner_lite;
+        if inner_lite.height <= head_inner_lite.height {
+            return Err(LightClientError::OldBlock {
+                height: inner_lite.height,
+                head_height: head_inner_lite.height,
+            });
+        }
+        if inner_lite.epoch_id != head_inner_lite.epoch_id
+            && inner_lite.epoch_id != head_inner_lite.next_epoch_id
+        {
+            return Err(LightClientError::UnknownEpoch);
+        }
+        if inner_lite.epoch_id == head_inner_lite.next_epoch_id && block.next_bps.is_none() {
+            return Err(LightClientError::MissingNextBlockProducers);
+        }
+        let block_producers = self
+            .epoch_block_producers
+            .get(&EpochId(inner_lite.epoch_id))
+            .ok_or(LightClientError::UnknownEpoch)?;
+        verify_approvals(&block.approvals_after_next, &block.approval_message(), block_producers)?;
+        let next_bps = validate_next_block_producers(block)?;
+
+        let epoch_id = EpochId(inner_lite.epoch_id);
+        let next_epoch_id = EpochId(inner_lite.next_epoch_id);
+        self.epoch_block_producers.retain(|id, _| id == &epoch_id || id == &next_epoch_id);
+        if let Some(next_bps) = next_bps {
+            self.epoch_block_producers.insert(next_epoch_id, next_bps);
+        }
+        self.head = block.clone();
+        Ok(())
+    }
+
+    /// Verifies a proof returned by the `light_client_proof` JSON-RPC method which was requested
+    /// with the hash of the head as `light_client_head`.
+    pub fn verify_execution_proof(
+        &self,
+        proof: &RpcLightClientExecutionProofResponse,
+    ) -> Result<(), LightClientError> {
+        verify_execution_proof(proof, &self.head.inner_lite.block_merkle_root)
+    }
+}
+
+/// Verifies that the execution outcome in `proof` is included in a block which is in turn included
+/// in the trusted `block_merkle_root`, i.e. the `block_merkle_root` of a later verified block.
+pub fn verify_execution_proof(
+    proof: &RpcLightClientExecutionProofResponse,
+    block_merkle_root: &CryptoHash,
+) -> Result<(), LightClientError> {
+    let outcome_hashes = proof
+        .outcome_proof
+        .to_hashes()
+        .map_err(|err| LightClientError::InvalidOutcome(err.to_string()))?;
+    let shard_outcome_root =
+        compute_root_from_path_and_item(&proof.outcome_proof.proof, &outcome_hashes);
+    if !verify_path(
+        proof.block_header_lite.inner_lite.outcome_root,
+        &proof.outcome_root_proof,
+        &shard_outcome_root,
+    ) {
+        return Err(LightClientError::InvalidOutcomeProof);
+    }
+    let block_hash = proof.block_header_lite.hash();
+    if block_hash != proof.outcome_proof.block_hash {
+        return Err(LightClientError::InvalidBlockHeader);
+    }
+    if !verify_hash(*block_merkle_root, &proof.block_proof, block_hash) {
+        return Err(LightClientError::InvalidBlockProof);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use borsh::BorshSerialize;
+    use near_crypto::{InMemorySigner, KeyType, Signer};
+    use near_primitives::hash::hash;
+    use near_primitives::merkle::{combine_hash, merklize, Direction, MerklePathItem};
+    use near_primitives::transaction::{
+        ExecutionOutcome, ExecutionOutcomeWithId, ExecutionOutcomeWithIdAndProof, ExecutionStatus,
+    };
+    use near_primitives::views::{
+        BlockHeaderInnerLiteView, ExecutionOutcomeWithIdView, LightClientBlockLiteView,
+    };
+
+    use super::*;
+
+    fn signers(names: &[&str]) -> Vec<InMemorySigner> {
+        names
+            .iter()
+            .map(|name| InMemorySigner::from_seed(name.parse().unwrap(), KeyType::ED25519, name))
+            .collect()
+    }
+
+    fn block_producers(signers: &[InMemorySigner]) -> Vec<ValidatorStakeView> {
+        signers
+            .iter()
+            .map(|signer| {
+                ValidatorStake::new_v1(signer.account_id.clone(), signer.public_key(), 100).into()
+            })
+            .collect()
+    }
+
+    fn bp_hash(block_producers: &[ValidatorStakeView]) -> CryptoHash {
+        let block_producers: Vec<ValidatorStake> =
+            block_producers.iter().cloned().map(Into::into).collect();
+        hash(&block_producers.try_to_vec().unwrap())
+    }
+
+    /// Creates a block at `height` approved by `approvers`, which are the block producers of its
+    /// epoch. `next_bps` are only included in the block if `with_next_bps` is set.
+    fn light_client_block(
+        height: BlockHeight,
+        epoch_id: CryptoHash,
+        next_epoch_id: CryptoHash,
+        next_bps: &[ValidatorStakeView],
+        with_next_bps: bool,
+        approvers: &[Option<&InMemorySigner>],
+    ) -> LightClientBlockView {
+        let mut block = LightClientBlockView {
+            prev_block_hash: hash(&height.to_le_bytes()),
+            next_block_inner_hash: hash(b"next"),
+            inner_lite: BlockHeaderInnerLiteView {
+                height,
+                epoch_id,
+                next_epoch_id,
+                prev_state_root: CryptoHash::default(),
+                outcome_root: CryptoHash::default(),
+                timestamp: height,
+                timestamp_nanosec: height,
+                next_bp_hash: bp_hash(next_bps),
+                block_merkle_root: CryptoHash::default(),
+            },
+            inner_rest_hash: CryptoHash::default(),
+            next_bps: if with_next_bps { Some(next_bps.to_vec()) } else { None },
+            approvals_after_next: vec![],
+        };
+        let approval_message = block.approval_message();
+        block.approvals_after_next = approvers
+            .iter()
+            .map(|signer| signer.map(|signer| signer.sign(&approval_message)))
+            .collect();
+        block
+    }
+
+    #[test]
+    fn test_follow_epochs() {
+        let epoch1 = hash(b"epoch1");
+        let epoch2 = hash(b"epoch2");
+        let epoch3 = hash(b"epoch3");
+        let signers1 = signers(&["test0", "test1", "test2"]);
+        let signers2 = signers(&["test3", "test4"]);
+        let bps1 = block_producers(&signers1);
+        let bps2 = block_producers(&signers2);
+
+        let head = light_client_block(10, epoch1, epoch2, &bps2, true, &[]);
+        let mut client = LightClient::new(head, bps1.clone()).unwrap();
+
+        // Block in the same epoch, approved by all the block producers.
+        let approvers1: Vec<_> = signers1.iter().map(Some).collect();
+        let block = light_client_block(12, epoch1, epoch2, &bps2, false, &approvers1);
+        client.validate_and_update_head(&block).unwrap();
+        assert_eq!(client.head_hash(), block.current_block_hash());
+
+        // The first block of the next epoch must carry the block producers of the epoch after it.
+        let approvers2: Vec<_> = signers2.iter().map(Some).collect();
+        let block = light_client_block(15, epoch2, epoch3, &bps1, false, &approvers2);
+        assert_eq!(
+            client.validate_and_update_head(&block),
+            Err(LightClientError::MissingNextBlockProducers)
+        );
+        let block = light_client_block(15, epoch2, epoch3, &bps1, true, &approvers2);
+        client.validate_and_update_head(&block).unwrap();
+        assert!(client.block_producers(&EpochId(epoch1)).is_none());
+        assert_eq!(client.block_producers(&EpochId(epoch3)).unwrap().len(), 3);
+
+        assert_eq!(
+            client.validate_and_update_head(&block),
+            Err(LightClientError::OldBlock { height: 15, head_height: 15 })
+        );
+        let block = light_client_block(16, epoch1, epoch2, &bps2, false, &approvers1);
+        assert_eq!(client.validate_and_update_head(&block), Err(LightClientError::UnknownEpoch));
+    }
+
+    #[test]
+    fn test_invalid_approvals() {
+        let epoch1 = hash(b"epoch1");
+        let epoch2 = hash(b"epoch2");
+        let signers1 = signers(&["test0", "test1", "test2"]);
+        let bps1 = block_producers(&signers1);
+        let head = light_client_block(10, epoch1, epoch2, &bps1, false, &[]);
+        let mut client = LightClient::new(head, bps1.clone()).unwrap();
+
+        // Exactly 2/3 of the stake is not enough.
+        let block = light_client_block(
+            11,
+            epoch1,
+            epoch2,
+            &bps1,
+            false,
+            &[Some(&signers1[0]), Some(&signers1[1]), None],
+        );
+        assert_eq!(
+            client.validate_and_update_head(&block),
+            Err(LightClientError::NotEnoughApprovals)
+        );
+
+        // Approval signed by someone else in place of a block producer.
+        let outsider = signers(&["test3"]).pop().unwrap();
+        let block = light_client_block(
+            11,
+            epoch1,
+            epoch2,
+            &bps1,
+            false,
+            &[Some(&signers1[0]), Some(&outsider), Some(&signers1[2])],
+        );
+        assert_eq!(
+            client.validate_and_update_head(&block),
+            Err(LightClientError::InvalidSignature("test1".parse().unwrap()))
+        );
+
+        // Block producers which don't match `next_bp_hash`.
+        let approvers: Vec<_> = signers1.iter().map(Some).collect();
+        let mut block = light_client_block(11, epoch1, epoch2, &bps1, true, &approvers);
+        block.next_bps.as_mut().unwrap().pop();
+        assert_eq!(
+            client.validate_and_update_head(&block),
+            Err(LightClientError::InvalidNextBlockProducers)
+        );
+        assert_eq!(client.head().inner_lite.height, 10);
+    }
+
+    #[test]
+    fn test_verify_execution_proof() {
+        let outcomes: Vec<ExecutionOutcomeWithId> = (0..3)
+            .map(|i| ExecutionOutcomeWithId {
+                id: hash(&[i]),
+                outcome: ExecutionOutcome {
+                    logs: vec![format!("log {}", i)],
+                    executor_id: "test0".parse().unwrap(),
+                    status: ExecutionStatus::SuccessValue(vec![i]),
+                    ..Default::default()
+                },
+            })
+            .collect();
+        let outcome_hashes: Vec<_> = outcomes.iter().map(|outcome| outcome.to_hashes()).collect();
+        let (shard_outcome_root, outcome_proofs) = merklize(&outcome_hashes);
+        let (outcome_root, shard_proofs) = merklize(&[hash(b"shard0"), shard_outcome_root]);
+
+        let block_header_lite = LightClientBlockLiteView {
+            prev_block_hash: hash(b"prev"),
+            inner_rest_hash: hash(b"rest"),
+            inner_lite: BlockHeaderInnerLiteView {
+                height: 5,
+                epoch_id: hash(b"epoch1"),
+                next_epoch_id: hash(b"epoch2"),
+                prev_state_root: CryptoHash::default(),
+                outcome_root,
+                timestamp: 5,
+                timestamp_nanosec: 5,
+                next_bp_hash: CryptoHash::default(),
+                block_merkle_root: CryptoHash::default(),
+            },
+        };
+        let block_hash = block_header_lite.hash();
+        // Leaves of the block merkle tree are the block hashes themselves.
+        let prev_block_hash = hash(b"block0");
+        let block_merkle_root = combine_hash(&prev_block_hash, &block_hash);
+        let block_proof =
+            vec![MerklePathItem { hash: prev_block_hash, direction: Direction::Left }];
+
+        let mut proof = RpcLightClientExecutionProofResponse {
+            outcome_proof: ExecutionOutcomeWithIdView::from(ExecutionOutcomeWithIdAndProof {
+                proof: outcome_proofs[1].clone(),
+                block_hash,
+                outcome_with_id: outcomes[1].clone(),
+            }),
+            outcome_root_proof: shard_proofs[1].clone(),
+            block_header_lite,
+            block_proof,
+        };
+        verify_execution_proof(&proof, &block_merkle_root).unwrap();
+        assert_eq!(
+            verify_execution_proof(&proof, &CryptoHash::default()),
+            Err(LightClientError::InvalidBlockProof)
+        );
+
+        proof.outcome_proof.outcome.logs.clear();
+        assert_eq!(
+            verify_execution_proof(&proof, &block_merkle_root),
+            Err(LightClientError::InvalidOutcomeProof)
+        );
+    }
+}
@@ -1,9 +1,11 @@
 use crate::network_protocol::PeerInfo;
 use crate::types::ROUTED_MESSAGE_TTL;
 use near_crypto::{KeyType, PublicKey, SecretKey};
+use near_primitives::network::PeerId;
 use near_primitives::types::AccountId;
 use std::collections::{HashMap, HashSet};
 use std::net::{AddrParseError, IpAddr, SocketAddr};
+use std::path::PathBuf;
 use std::str::FromStr;
 use std::time::Duration;
 
@@ -64,10 +66,36 @@ pub struct NetworkConfig {
     /// are satisfied.
     /// This flag should be ALWAYS FALSE. Only set to true for testing purposes.
     pub outbound_disabled: bool,
+    /// Peers to always stay connected with, e.g. the sentries of a validator or the validator
+    /// behind a sentry. They are never disconnected to make room for other peers, and their
+    /// addresses are never shared with other peers.
+    pub trusted_peers: Vec<PeerInfo>,
+    /// Only connect to and accept connections from `trusted_peers`. Set on a validator hidden
+    /// behind sentry nodes, which then route all its messages.
+    pub whitelist_only: bool,
+    /// Address other validators should use to connect to us directly, announced together with
+    /// our account. Nothing is announced if not set.
+    pub public_addr: Option<SocketAddr>,
+    /// Maximum number of direct connections to the other block and chunk producers of the current
+    /// and next epoch. These don't count towards `max_num_peers`.
+    pub max_num_validator_peers: u32,
+    /// Record all messages exchanged with peers, to reproduce networking issues offline.
+    pub message_recorder: Option<MessageRecorderConfig>,
     /// Not clear old data, set `true` for archive nodes.
     pub archive: bool,
 }
 
+/// Where and how much to record of the messages exchanged with peers.
+#[derive(Clone, Debug)]
+pub struct MessageRecorderConfig {
+    /// Directory to write the capture files to.
+    pub dir: PathBuf,
+    /// A new file is started once the current one reaches this size.
+    pub max_file_size: u64,
+    /// Number of files to keep, the oldest ones are removed.
+    pub max_files: usize,
+}
+
 impl NetworkConfig {
     /// Returns network config with given seed used for peer id.
     pub fn from_seed(seed: &str, port: u16) -> Self {
@@ -100,10 +128,29 @@ impl NetworkConfig {
             push_info_period: Duration::from_millis(100),
             blacklist: HashMap::new(),
             outbound_disabled: false,
+            trusted_peers: vec![],
+            whitelist_only: false,
+            public_addr: None,
+            max_num_validator_peers: 50,
+            message_recorder: None,
             archive: false,
         }
     }
 
+    pub fn is_trusted(&self, peer_id: &PeerId) -> bool {
+        self.trusted_peers.iter().any(|peer_info| &peer_info.id == peer_id)
+    }
+
+    /// Address announced to other validators. There's none in `whitelist_only` mode, as they
+    /// couldn't connect to us anyway.
+    pub fn announced_addr(&self) -> Option<SocketAddr> {
+        if self.whitelist_only {
+            None
+        } else {
+            self.public_addr
+        }
+    }
+
     pub fn verify(&self) -> Result<(), anyhow::Error> {
         if !(self.ideal_connections_lo <= self.ideal_connections_hi) {
             anyhow::bail!(
@@ -124,6 +171,10 @@ impl NetworkConfig {
             anyhow::bail!("Outbound connections are disabled.");
         }
 
+        if self.whitelist_only && self.trusted_peers.is_empty() {
+            anyhow::bail!("whitelist_only is set, but there are no trusted_peers to connect to.");
+        }
+
         if !(self.safe_set_size > self.minimum_outbound_peers) {
             anyhow::bail!(
                 "safe_set_size({}) must be larger than minimum_outbound_peers({}).",
@@ -213,7 +264,7 @@ pub const UPDATE_INTERVAL_LAST_TIME_RECEIVED_MESSAGE: Duration = Duration::from_
 
 #[cfg(test)]
 mod test {
-    use crate::types::{NetworkConfig, UPDATE_INTERVAL_LAST_TIME_RECEIVED_MESSAGE};
+    use crate::types::{NetworkConfig, PeerInfo, UPDATE_INTERVAL_LAST_TIME_RECEIVED_MESSAGE};
 
     #[test]
     fn test_network_config() {
@@ -239,5 +290,24 @@ mod test {
         nc.peer_recent_time_window = UPDATE_INTERVAL_LAST_TIME_RECEIVED_MESSAGE;
         let res = nc.verify();
         assert!(res.is_err(), "{:?}", res);
+
+        let mut nc = NetworkConfig::from_seed("123", 213);
+        nc.whitelist_only = true;
+        let res = nc.verify();
+        assert!(res.is_err(), "{:?}", res);
+        nc.trusted_peers.push(PeerInfo::random());
+        assert!(nc.verify().is_ok());
+    }
+
+    #[test]
+    fn test_announced_addr() {
+        let mut nc = NetworkConfig::from_seed("123", 213);
+        assert!(nc.addr.is_some());
+        assert_eq!(nc.announced_addr(), None);
+        let public_addr = "1.2.3.4:24567".parse().unwrap();
+        nc.public_addr = Some(public_addr);
+        assert_eq!(nc.announced_addr(), Some(public_addr));
+        nc.whitelist_only = true;
+        assert_eq!(nc.announced_addr(), None);
     }
 }
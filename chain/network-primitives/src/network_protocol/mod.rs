@@ -4,7 +4,7 @@
 ///
 /// TODO: - document all types in this file
 use borsh::{BorshDeserialize, BorshSerialize};
-use near_crypto::Signature;
+use near_crypto::{SecretKey, Signature};
 use near_primitives::block::{Approval, GenesisId};
 use near_primitives::hash::CryptoHash;
 use near_primitives::merkle::combine_hash;
@@ -108,6 +108,57 @@ impl TryFrom<&str> for PeerInfo {
     }
 }
 
+/// Address at which the node owning `account_id` accepts direct connections, signed by the peer
+/// the account is announced at. Validators use it to connect directly to the other block and
+/// chunk producers, so it's only trusted if it matches the `AnnounceAccount` of the account.
+#[derive(BorshSerialize, BorshDeserialize, Clone, Debug, Eq, PartialEq)]
+pub struct AnnounceAddress {
+    pub account_id: AccountId,
+    pub peer_id: PeerId,
+    pub addr: SocketAddr,
+    /// Announcements with a higher nonce replace the older ones.
+    pub nonce: u64,
+    /// Signature of the other fields with the key of `peer_id`.
+    pub signature: Signature,
+}
+
+#[cfg(feature = "deepsize_feature")]
+impl deepsize::DeepSizeOf for AnnounceAddress {
+    fn deep_size_of_children(&self, context: &mut deepsize::Context) -> usize {
+        self.account_id.deep_size_of_children(context)
+            + self.peer_id.deep_size_of_children(context)
+            + self.signature.deep_size_of_children(context)
+    }
+}
+
+impl AnnounceAddress {
+    pub fn new(
+        account_id: AccountId,
+        addr: SocketAddr,
+        nonce: u64,
+        secret_key: &SecretKey,
+    ) -> Self {
+        let peer_id = PeerId::new(secret_key.public_key());
+        let hash = Self::build_hash(&account_id, &peer_id, &addr, nonce);
+        let signature = secret_key.sign(hash.as_ref());
+        Self { account_id, peer_id, addr, nonce, signature }
+    }
+
+    fn build_hash(
+        account_id: &AccountId,
+        peer_id: &PeerId,
+        addr: &SocketAddr,
+        nonce: u64,
+    ) -> CryptoHash {
+        CryptoHash::hash_borsh(&(account_id, peer_id, addr, nonce))
+    }
+
+    pub fn verify(&self) -> bool {
+        let hash = Self::build_hash(&self.account_id, &self.peer_id, &self.addr, self.nonce);
+        self.signature.verify(hash.as_ref(), self.peer_id.public_key())
+    }
+}
+
 /// Peer chain information.
 /// TODO: Remove in next version
 #[cfg_attr(feature = "deepsize_feature", derive(deepsize::DeepSizeOf))]
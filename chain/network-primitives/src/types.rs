@@ -21,6 +21,7 @@ use near_primitives::transaction::ExecutionOutcomeWithIdAndProof;
 use near_primitives::types::{AccountId, BlockHeight, EpochId, ShardId};
 use near_primitives::utils::{from_timestamp, to_timestamp};
 use near_primitives::views::{FinalExecutionOutcomeView, QueryResponse};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
 use std::fmt::Debug;
 use std::hash::Hash;
 use std::net::SocketAddr;
@@ -30,12 +31,13 @@ use tokio::net::TcpStream;
 
 /// Exported types, which are part of network protocol.
 pub use crate::network_protocol::{
-    PartialEncodedChunkForwardMsg, PartialEncodedChunkRequestMsg, PartialEncodedChunkResponseMsg,
-    PeerChainInfo, PeerChainInfoV2, PeerIdOrHash, PeerInfo, Ping, Pong, RoutedMessage,
-    RoutedMessageBody, StateResponseInfo, StateResponseInfoV1, StateResponseInfoV2,
+    AnnounceAddress, PartialEncodedChunkForwardMsg, PartialEncodedChunkRequestMsg,
+    PartialEncodedChunkResponseMsg, PeerChainInfo, PeerChainInfoV2, PeerIdOrHash, PeerInfo, Ping,
+    Pong, RoutedMessage, RoutedMessageBody, StateResponseInfo, StateResponseInfoV1,
+    StateResponseInfoV2,
 };
 
-pub use crate::config::{blacklist_from_iter, BlockedPorts, NetworkConfig};
+pub use crate::config::{blacklist_from_iter, BlockedPorts, MessageRecorderConfig, NetworkConfig};
 
 pub use crate::network_protocol::edge::{Edge, EdgeState, PartialEdgeInfo, SimpleEdge};
 
@@ -150,6 +152,74 @@ impl KnownPeerStatus {
     }
 }
 
+/// Outcome of a request sent to a peer, used to update its score.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PeerScoreEvent {
+    /// The peer answered with useful data. Fast answers are rewarded more.
+    ResponseReceived { latency: Duration },
+    /// The peer didn't answer in time.
+    RequestTimedOut,
+    /// The peer answered with valid but useless data, e.g. headers we already have.
+    UselessResponse,
+    /// The peer answered with invalid data.
+    InvalidResponse,
+}
+
+/// Reputation of a peer, based on how it answered our requests.
+///
+/// Every peer starts at zero. The score then goes up for fast and useful responses and down for
+/// timeouts and useless or invalid responses, staying within `[MIN, MAX]`. It also decays back
+/// towards zero over time, so that peers recover from a bad period and can't live forever on
+/// their past merits.
+#[derive(BorshSerialize, BorshDeserialize, Eq, PartialEq, Debug, Clone, Copy, Default)]
+pub struct PeerScore {
+    value: i32,
+    /// Time of the last update, the decay since then is not applied to `value` yet.
+    last_updated: u64,
+}
+
+impl PeerScore {
+    pub const MIN: i32 = -100;
+    pub const MAX: i32 = 100;
+    /// Peers with a lower score are avoided when picking whom to connect to or to request data
+    /// from, as long as there are alternatives.
+    pub const LOW: i32 = -25;
+    /// The score moves one point towards zero per this period.
+    pub const DECAY_PERIOD: Duration = Duration::from_secs(60);
+    /// Responses faster than this get the full reward.
+    pub const FAST_RESPONSE: Duration = Duration::from_secs(1);
+    /// Responses slower than this get no reward.
+    pub const SLOW_RESPONSE: Duration = Duration::from_secs(5);
+
+    /// Returns the score at `now`.
+    pub fn value(&self, now: DateTime<Utc>) -> i32 {
+        let elapsed = (now - from_timestamp(self.last_updated)).to_std().unwrap_or_default();
+        let decay = (elapsed.as_secs() / Self::DECAY_PERIOD.as_secs()).min(Self::MAX as u64) as i32;
+        if self.value > 0 {
+            (self.value - decay).max(0)
+        } else {
+            (self.value + decay).min(0)
+        }
+    }
+
+    pub fn is_low(&self, now: DateTime<Utc>) -> bool {
+        self.value(now) < Self::LOW
+    }
+
+    pub fn update(&mut self, event: PeerScoreEvent, now: DateTime<Utc>) {
+        let delta = match event {
+            PeerScoreEvent::ResponseReceived { latency } if latency <= Self::FAST_RESPONSE => 2,
+            PeerScoreEvent::ResponseReceived { latency } if latency <= Self::SLOW_RESPONSE => 1,
+            PeerScoreEvent::ResponseReceived { .. } => 0,
+            PeerScoreEvent::RequestTimedOut => -5,
+            PeerScoreEvent::UselessResponse => -3,
+            PeerScoreEvent::InvalidResponse => -20,
+        };
+        self.value = (self.value(now) + delta).clamp(Self::MIN, Self::MAX);
+        self.last_updated = to_timestamp(now);
+    }
+}
+
 /// not part of protocol, probably doesn't need `borsh`
 /// Information node stores about known peers.
 #[derive(BorshSerialize, BorshDeserialize, Debug, Clone)]
@@ -159,6 +229,7 @@ pub struct KnownPeerState {
     /// Unused
     first_seen: u64,
     pub last_seen: u64,
+    pub score: PeerScore,
 }
 
 impl KnownPeerState {
@@ -168,6 +239,7 @@ impl KnownPeerState {
             status: KnownPeerStatus::Unknown,
             first_seen: to_timestamp(Clock::utc()),
             last_seen: to_timestamp(Clock::utc()),
+            score: PeerScore::default(),
         }
     }
 
@@ -277,12 +349,24 @@ pub enum NetworkSandboxMessage {
     SandboxPatchState(Vec<near_primitives::state_record::StateRecord>),
     SandboxPatchStateStatus,
     SandboxFastForward(near_primitives::types::BlockHeightDelta),
+    SandboxSnapshot,
+    SandboxDeleteSnapshot(u64),
+    SandboxRevert(u64),
+    SandboxImpersonate {
+        signer_id: AccountId,
+        receiver_id: AccountId,
+        actions: Vec<near_primitives::transaction::Action>,
+    },
 }
 
 #[cfg(feature = "sandbox")]
 #[derive(Eq, PartialEq, Debug)]
 pub enum SandboxResponse {
     SandboxPatchStateFinished(bool),
+    SandboxSnapshotCreated(Result<u64, String>),
+    SandboxSnapshotDeleted(Result<(), String>),
+    SandboxReverted(Result<(), String>),
+    SandboxImpersonated(Result<CryptoHash, String>),
 }
 
 #[derive(actix::Message, AsStaticStr)]
@@ -372,6 +456,92 @@ pub struct PeerStatsResult {
     pub message_counts: (usize, usize),
 }
 
+/// Known peers with their current scores, best first.
+#[derive(actix::Message, Debug)]
+#[rtype(result = "Vec<KnownPeerState>")]
+pub struct GetPeerScores {}
+
+/// Routing table debug info query.
+#[derive(actix::Message, Debug)]
+#[rtype(result = "Result<RoutingTableDebugInfo, String>")]
+pub struct GetRoutingTableDebugInfo {}
+
+/// Snapshot of the network topology as known by the node, for diagnosing routing issues.
+#[derive(Debug, Clone)]
+pub struct RoutingTableDebugInfo {
+    pub my_peer_id: PeerId,
+    /// All edges known to the routing table, including removed ones.
+    pub edges: Vec<SimpleEdge>,
+    /// For every reachable peer, the connected peers which are on a shortest path to it.
+    pub next_hops: HashMap<PeerId, Vec<PeerId>>,
+    /// Account announcements stored in `ColAccountAnnouncements`.
+    pub account_announcements: Vec<AnnounceAccount>,
+}
+
+impl RoutingTableDebugInfo {
+    /// Peers which are part of an active edge, or own an announced account, but for which
+    /// there is no route. Messages sent to them are dropped.
+    pub fn unreachable_peers(&self) -> BTreeSet<PeerId> {
+        self.edges
+            .iter()
+            .filter(|edge| edge.edge_state() == EdgeState::Active)
+            .flat_map(|edge| vec![&edge.key().0, &edge.key().1])
+            .chain(self.account_announcements.iter().map(|announce| &announce.peer_id))
+            .filter(|peer_id| **peer_id != self.my_peer_id && !self.next_hops.contains_key(peer_id))
+            .cloned()
+            .collect()
+    }
+
+    /// Renders the peer graph in the GraphViz DOT format. Active edges are solid and removed
+    /// ones dashed, both labelled with their nonce. Our own node is filled and unreachable
+    /// peers are red.
+    pub fn to_dot(&self) -> String {
+        let mut accounts: BTreeMap<&PeerId, Vec<String>> = BTreeMap::new();
+        for announce in self.account_announcements.iter() {
+            accounts.entry(&announce.peer_id).or_default().push(announce.account_id.to_string());
+        }
+        let mut peers: BTreeSet<&PeerId> = self.next_hops.keys().collect();
+        peers.insert(&self.my_peer_id);
+        peers.extend(self.edges.iter().flat_map(|edge| vec![&edge.key().0, &edge.key().1]));
+        peers.extend(accounts.keys());
+        let unreachable = self.unreachable_peers();
+
+        let mut dot = String::from("graph network {\n");
+        for peer_id in peers {
+            let mut label = peer_id.to_string();
+            for account_id in accounts.get(peer_id).into_iter().flatten() {
+                label.push_str("\\n");
+                label.push_str(account_id);
+            }
+            let style = if peer_id == &self.my_peer_id {
+                ", style=filled, fillcolor=lightblue"
+            } else if unreachable.contains(peer_id) {
+                ", color=red, fontcolor=red"
+            } else {
+                ""
+            };
+            dot.push_str(&format!("  \"{}\" [label=\"{}\"{}];\n", peer_id, label, style));
+        }
+        let mut edges: Vec<&SimpleEdge> = self.edges.iter().collect();
+        edges.sort_by(|a, b| a.key().cmp(b.key()));
+        for edge in edges {
+            let style = match edge.edge_state() {
+                EdgeState::Active => "",
+                EdgeState::Removed => ", style=dashed",
+            };
+            dot.push_str(&format!(
+                "  \"{}\" -- \"{}\" [label=\"{}\"{}];\n",
+                edge.key().0,
+                edge.key().1,
+                edge.nonce(),
+                style
+            ));
+        }
+        dot.push_str("}\n");
+        dot
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -406,6 +576,7 @@ mod tests {
         assert_size!(PeerInfo);
         assert_size!(PeerChainInfoV2);
         assert_size!(AnnounceAccount);
+        assert_size!(AnnounceAddress);
         assert_size!(Ping);
         assert_size!(Pong);
         assert_size!(RawRoutedMessage);
@@ -420,6 +591,83 @@ mod tests {
         assert_size!(PartialEncodedChunkRequestMsg);
     }
 
+    #[test]
+    fn test_peer_score() {
+        let now = Clock::utc();
+        let mut score = PeerScore::default();
+        assert_eq!(score.value(now), 0);
+
+        score.update(PeerScoreEvent::ResponseReceived { latency: Duration::from_millis(10) }, now);
+        score.update(PeerScoreEvent::ResponseReceived { latency: Duration::from_secs(3) }, now);
+        score.update(PeerScoreEvent::ResponseReceived { latency: Duration::from_secs(30) }, now);
+        assert_eq!(score.value(now), 3);
+
+        // The score decays towards zero, but not past it.
+        let later = now + chrono::Duration::from_std(PeerScore::DECAY_PERIOD * 2).unwrap();
+        assert_eq!(score.value(later), 1);
+        let much_later = now + chrono::Duration::from_std(PeerScore::DECAY_PERIOD * 10).unwrap();
+        assert_eq!(score.value(much_later), 0);
+
+        for _ in 0..10 {
+            score.update(PeerScoreEvent::InvalidResponse, now);
+        }
+        assert_eq!(score.value(now), PeerScore::MIN);
+        assert!(score.is_low(now));
+        assert_eq!(score.value(much_later), PeerScore::MIN + 10);
+    }
+
+    #[test]
+    fn test_routing_table_debug_info() {
+        use near_crypto::{KeyType, Signature};
+
+        let peer =
+            |seed: &str| PeerId::new(SecretKey::from_seed(KeyType::ED25519, seed).public_key());
+        let (me, a, b, c) = (peer("me"), peer("a"), peer("b"), peer("c"));
+        let info = RoutingTableDebugInfo {
+            my_peer_id: me.clone(),
+            edges: vec![
+                SimpleEdge::new(me.clone(), a.clone(), 1),
+                SimpleEdge::new(a.clone(), b.clone(), 3),
+                SimpleEdge::new(b.clone(), c.clone(), 2),
+            ],
+            next_hops: vec![(a.clone(), vec![a.clone()]), (b.clone(), vec![a.clone()])]
+                .into_iter()
+                .collect(),
+            account_announcements: vec![AnnounceAccount {
+                account_id: "test.near".parse().unwrap(),
+                peer_id: c.clone(),
+                epoch_id: EpochId::default(),
+                signature: Signature::empty(KeyType::ED25519),
+            }],
+        };
+
+        // `c` lost its only edge but is still announced as the owner of an account.
+        assert_eq!(info.unreachable_peers().into_iter().collect::<Vec<_>>(), vec![c.clone()]);
+
+        let dot = info.to_dot();
+        assert!(dot.starts_with("graph network {\n") && dot.ends_with("}\n"));
+        assert!(dot.contains(&format!("\"{}\" [label=\"{}\", style=filled", me, me)));
+        assert!(dot.contains(&format!("\"{}\" [label=\"{}\\ntest.near\", color=red", c, c)));
+        let (c0, c1) = SimpleEdge::new(b.clone(), c.clone(), 2).key().clone();
+        assert!(dot.contains(&format!("\"{}\" -- \"{}\" [label=\"2\", style=dashed];", c0, c1)));
+        let (a0, a1) = SimpleEdge::new(a, b, 3).key().clone();
+        assert!(dot.contains(&format!("\"{}\" -- \"{}\" [label=\"3\"];", a0, a1)));
+    }
+
+    #[test]
+    fn test_announce_address() {
+        use near_crypto::KeyType;
+
+        let secret_key = SecretKey::from_seed(KeyType::ED25519, "test.near");
+        let addr: SocketAddr = "127.0.0.1:24567".parse().unwrap();
+        let announce = AnnounceAddress::new("test.near".parse().unwrap(), addr, 1, &secret_key);
+        assert_eq!(announce.peer_id, PeerId::new(secret_key.public_key()));
+        assert!(announce.verify());
+
+        let other_addr = AnnounceAddress { addr: "127.0.0.2:24567".parse().unwrap(), ..announce };
+        assert!(!other_addr.verify());
+    }
+
     #[test]
     fn routed_message_body_compatibility_smoke_test() {
         #[track_caller]
@@ -1,5 +1,5 @@
 pub use crate::peer_manager::peer_manager_actor::PeerManagerActor;
-pub use crate::peer_manager::peer_store::iter_peers_from_store;
+pub use crate::peer_manager::peer_store::{iter_peers_from_store, load_known_peers};
 /// For benchmarks only
 pub use crate::routing::routing_table_actor::RoutingTableActor;
 #[cfg(feature = "test_features")]
@@ -14,6 +14,7 @@ mod peer_manager;
 pub mod private_actix;
 #[cfg(not(feature = "test_features"))]
 pub(crate) mod private_actix;
+pub mod recorder;
 pub mod routing;
 pub(crate) mod stats;
 pub mod test_utils;
@@ -3,14 +3,18 @@
 /// WARNING WARNING WARNING
 /// WARNING WARNING WARNING
 /// We need to maintain backwards compatibility, all changes to this file needs to be reviews.
+use crate::types::NetworkClientMessages;
 use borsh::{BorshDeserialize, BorshSerialize};
 use near_network_primitives::types::{
-    Edge, PartialEdgeInfo, PeerChainInfoV2, PeerInfo, RoutedMessage, RoutedMessageBody,
+    AnnounceAddress, Edge, NetworkViewClientMessages, PartialEdgeInfo, PeerChainInfoV2, PeerInfo,
+    RoutedMessage, RoutedMessageBody, StateResponseInfo,
 };
 use near_primitives::block::{Block, BlockHeader, GenesisId};
 use near_primitives::challenge::Challenge;
+use near_primitives::checked_feature;
 use near_primitives::hash::CryptoHash;
 use near_primitives::network::{AnnounceAccount, PeerId};
+use near_primitives::sharding::PartialEncodedChunk;
 use near_primitives::syncing::{EpochSyncFinalizationResponse, EpochSyncResponse};
 use near_primitives::transaction::SignedTransaction;
 use near_primitives::types::{EpochId, ProtocolVersion};
@@ -20,8 +24,17 @@ use std::{fmt, io};
 
 const ERROR_UNEXPECTED_LENGTH_OF_INPUT: &str = "Unexpected length of input";
 
+/// Optional features a peer advertises in its `Handshake`, as a bit set.
+/// The peer can receive messages compressed with zstd.
+pub(crate) const CAPABILITY_ZSTD_COMPRESSION: u64 = 1 << 0;
+/// The peer can receive `PeerMessage::SyncAnnounceAddresses`.
+pub(crate) const CAPABILITY_ANNOUNCE_ADDRESSES: u64 = 1 << 1;
+/// Capabilities of this node.
+pub(crate) const SUPPORTED_CAPABILITIES: u64 =
+    CAPABILITY_ZSTD_COMPRESSION | CAPABILITY_ANNOUNCE_ADDRESSES;
+
 #[cfg_attr(feature = "deepsize_feature", derive(deepsize::DeepSizeOf))]
-#[derive(BorshSerialize, PartialEq, Eq, Clone, Debug)]
+#[derive(PartialEq, Eq, Clone, Debug)]
 /// Structure representing handshake between peers.
 /// This replaces deprecated handshake `HandshakeV2`.
 pub struct Handshake {
@@ -39,6 +52,9 @@ pub struct Handshake {
     pub(crate) sender_chain_info: PeerChainInfoV2,
     /// Represents new `edge`. Contains only `none` and `Signature` from the sender.
     pub(crate) partial_edge_info: PartialEdgeInfo,
+    /// Sender's capabilities, see `CAPABILITY_*`. Only sent from `NetworkMessageCompression` on,
+    /// zero for older versions.
+    pub(crate) capabilities: u64,
 }
 
 /// Struct describing the layout for Handshake.
@@ -78,7 +94,38 @@ impl Handshake {
             sender_listen_port: listen_port,
             sender_chain_info: chain_info,
             partial_edge_info,
+            capabilities: SUPPORTED_CAPABILITIES,
+        }
+    }
+
+    pub(crate) fn has_capability(&self, capability: u64) -> bool {
+        self.capabilities & capability != 0
+    }
+}
+
+/// Whether the `Handshake` of the given version contains the capabilities of the sender.
+fn handshake_has_capabilities(version: ProtocolVersion) -> bool {
+    checked_feature!(
+        "protocol_feature_network_message_compression",
+        NetworkMessageCompression,
+        version
+    )
+}
+
+// Capabilities are appended to the layout of `HandshakeAutoDes` only for versions supporting them.
+impl BorshSerialize for Handshake {
+    fn serialize<W: io::Write>(&self, writer: &mut W) -> io::Result<()> {
+        self.protocol_version.serialize(writer)?;
+        self.oldest_supported_version.serialize(writer)?;
+        self.sender_peer_id.serialize(writer)?;
+        self.target_peer_id.serialize(writer)?;
+        self.sender_listen_port.serialize(writer)?;
+        self.sender_chain_info.serialize(writer)?;
+        self.partial_edge_info.serialize(writer)?;
+        if handshake_has_capabilities(self.protocol_version) {
+            self.capabilities.serialize(writer)?;
         }
+        Ok(())
     }
 }
 
@@ -98,7 +145,12 @@ impl BorshDeserialize for Handshake {
 
         if PEER_MIN_ALLOWED_PROTOCOL_VERSION <= version && version <= PROTOCOL_VERSION {
             // If we support this version, then try to deserialize with custom deserializer
-            <HandshakeAutoDes as BorshDeserialize>::deserialize(buf).map(Into::into)
+            let mut handshake: Handshake =
+                <HandshakeAutoDes as BorshDeserialize>::deserialize(buf)?.into();
+            if handshake_has_capabilities(version) {
+                handshake.capabilities = u64::deserialize(buf)?;
+            }
+            Ok(handshake)
         } else {
             Err(std::io::Error::new(
                 std::io::ErrorKind::InvalidData,
@@ -121,6 +173,7 @@ impl From<HandshakeAutoDes> for Handshake {
             sender_listen_port: handshake.sender_listen_port,
             sender_chain_info: handshake.sender_chain_info,
             partial_edge_info: handshake.partial_edge_info,
+            capabilities: 0,
         }
     }
 }
@@ -211,10 +264,18 @@ pub enum PeerMessage {
     EpochSyncRequest(EpochId),
     EpochSyncResponse(Box<EpochSyncResponse>),
     EpochSyncFinalizationRequest(EpochId),
-    EpochSyncFinalizationResponse(Box<EpochSyncFinalizationResponse>),
+    /// Replaced by `EpochSyncFinalizationResponse` at the end of the list, whose payload carries
+    /// `cur_epoch_next_headers`. Nodes never sent this one, as they didn't serve epoch sync yet.
+    _EpochSyncFinalizationResponse,
 
     #[cfg(feature = "protocol_feature_routing_exchange_algorithm")]
     RoutingTableSyncV2(RoutingSyncV2),
+    /// Keeps the position of the following items independent of the feature.
+    #[cfg(not(feature = "protocol_feature_routing_exchange_algorithm"))]
+    _RoutingTableSyncV2,
+    /// Addresses at which validators accept direct connections.
+    SyncAnnounceAddresses(Vec<AnnounceAddress>),
+    EpochSyncFinalizationResponse(Box<EpochSyncFinalizationResponse>),
 }
 #[cfg(target_arch = "x86_64")] // Non-x86_64 doesn't match this requirement yet but it's not bad as it's not production-ready
 const _: () = assert!(std::mem::size_of::<PeerMessage>() <= 1144, "PeerMessage > 1144 bytes");
@@ -264,7 +325,7 @@ impl fmt::Display for PeerMessage {
 }
 
 impl PeerMessage {
-    pub(crate) fn msg_variant(&self) -> &str {
+    pub fn msg_variant(&self) -> &'static str {
         match self {
             PeerMessage::Routed(routed_message) => {
                 strum::AsStaticRef::as_static(&routed_message.body)
@@ -273,7 +334,7 @@ impl PeerMessage {
         }
     }
 
-    pub(crate) fn is_client_message(&self) -> bool {
+    pub fn is_client_message(&self) -> bool {
         match self {
             PeerMessage::Block(_)
             | PeerMessage::BlockHeaders(_)
@@ -297,7 +358,165 @@ impl PeerMessage {
         }
     }
 
-    pub(crate) fn is_view_client_message(&self) -> bool {
+    /// Whether the message can be large enough to be worth compressing.
+    pub(crate) fn is_compressible(&self) -> bool {
+        match self {
+            PeerMessage::Block(_) | PeerMessage::BlockHeaders(_) => true,
+            PeerMessage::Routed(r) => matches!(
+                r.body,
+                RoutedMessageBody::PartialEncodedChunk(_)
+                    | RoutedMessageBody::PartialEncodedChunkResponse(_)
+                    | RoutedMessageBody::StateResponse(_)
+                    | RoutedMessageBody::VersionedPartialEncodedChunk(_)
+                    | RoutedMessageBody::VersionedStateResponse(_)
+            ),
+            _ => false,
+        }
+    }
+
+    /// Converts a message for the client into what is sent to it. `peer_id` is the peer the
+    /// message was received from, and `was_requested` whether it's a block we requested.
+    /// Returns `None` for the messages which aren't for the client.
+    pub fn into_client_message(
+        self,
+        peer_id: PeerId,
+        was_requested: bool,
+    ) -> Option<NetworkClientMessages> {
+        let msg = match self {
+            PeerMessage::Block(block) => {
+                NetworkClientMessages::Block(block, peer_id, was_requested)
+            }
+            PeerMessage::Transaction(transaction) => NetworkClientMessages::Transaction {
+                transaction,
+                is_forwarded: false,
+                check_only: false,
+            },
+            PeerMessage::BlockHeaders(headers) => {
+                NetworkClientMessages::BlockHeaders(headers, peer_id)
+            }
+            // All Routed messages received at this point are for us.
+            PeerMessage::Routed(routed_message) => {
+                let msg_hash = routed_message.hash();
+
+                match routed_message.body {
+                    RoutedMessageBody::BlockApproval(approval) => {
+                        NetworkClientMessages::BlockApproval(approval, peer_id)
+                    }
+                    RoutedMessageBody::ForwardTx(transaction) => {
+                        NetworkClientMessages::Transaction {
+                            transaction,
+                            is_forwarded: true,
+                            check_only: false,
+                        }
+                    }
+
+                    RoutedMessageBody::StateResponse(info) => {
+                        NetworkClientMessages::StateResponse(StateResponseInfo::V1(info))
+                    }
+                    RoutedMessageBody::VersionedStateResponse(info) => {
+                        NetworkClientMessages::StateResponse(info)
+                    }
+                    RoutedMessageBody::PartialEncodedChunkRequest(request) => {
+                        NetworkClientMessages::PartialEncodedChunkRequest(request, msg_hash)
+                    }
+                    RoutedMessageBody::PartialEncodedChunkResponse(response) => {
+                        NetworkClientMessages::PartialEncodedChunkResponse(
+                            response,
+                            routed_message.author,
+                        )
+                    }
+                    RoutedMessageBody::PartialEncodedChunk(partial_encoded_chunk) => {
+                        NetworkClientMessages::PartialEncodedChunk(PartialEncodedChunk::V1(
+                            partial_encoded_chunk,
+                        ))
+                    }
+                    RoutedMessageBody::VersionedPartialEncodedChunk(chunk) => {
+                        NetworkClientMessages::PartialEncodedChunk(chunk)
+                    }
+                    RoutedMessageBody::PartialEncodedChunkForward(forward) => {
+                        NetworkClientMessages::PartialEncodedChunkForward(forward)
+                    }
+                    RoutedMessageBody::Ping(_)
+                    | RoutedMessageBody::Pong(_)
+                    | RoutedMessageBody::TxStatusRequest(_, _)
+                    | RoutedMessageBody::TxStatusResponse(_)
+                    | RoutedMessageBody::QueryRequest { .. }
+                    | RoutedMessageBody::QueryResponse { .. }
+                    | RoutedMessageBody::ReceiptOutcomeRequest(_)
+                    | RoutedMessageBody::StateRequestHeader(_, _)
+                    | RoutedMessageBody::StateRequestPart(_, _, _)
+                    | RoutedMessageBody::Unused => return None,
+                }
+            }
+            PeerMessage::Challenge(challenge) => NetworkClientMessages::Challenge(challenge),
+            PeerMessage::EpochSyncResponse(response) => {
+                NetworkClientMessages::EpochSyncResponse(peer_id, response)
+            }
+            PeerMessage::EpochSyncFinalizationResponse(response) => {
+                NetworkClientMessages::EpochSyncFinalizationResponse(peer_id, response)
+            }
+            PeerMessage::Handshake(_)
+            | PeerMessage::_HandshakeV2
+            | PeerMessage::_EpochSyncFinalizationResponse
+            | PeerMessage::HandshakeFailure(_, _)
+            | PeerMessage::PeersRequest
+            | PeerMessage::PeersResponse(_)
+            | PeerMessage::SyncRoutingTable(_)
+            | PeerMessage::SyncAnnounceAddresses(_)
+            | PeerMessage::LastEdge(_)
+            | PeerMessage::Disconnect
+            | PeerMessage::RequestUpdateNonce(_)
+            | PeerMessage::ResponseUpdateNonce(_)
+            | PeerMessage::BlockRequest(_)
+            | PeerMessage::BlockHeadersRequest(_)
+            | PeerMessage::EpochSyncRequest(_)
+            | PeerMessage::EpochSyncFinalizationRequest(_) => return None,
+            #[cfg(feature = "protocol_feature_routing_exchange_algorithm")]
+            PeerMessage::RoutingTableSyncV2(_) => return None,
+            #[cfg(not(feature = "protocol_feature_routing_exchange_algorithm"))]
+            PeerMessage::_RoutingTableSyncV2 => return None,
+        };
+        Some(msg)
+    }
+
+    /// Converts a message for the view client into what is sent to it. Returns `None` for the
+    /// messages which aren't for the view client.
+    pub fn into_view_client_message(self) -> Option<NetworkViewClientMessages> {
+        let msg = match self {
+            PeerMessage::Routed(message) => match message.body {
+                RoutedMessageBody::TxStatusRequest(account_id, tx_hash) => {
+                    NetworkViewClientMessages::TxStatus { tx_hash, signer_account_id: account_id }
+                }
+                RoutedMessageBody::TxStatusResponse(tx_result) => {
+                    NetworkViewClientMessages::TxStatusResponse(Box::new(tx_result))
+                }
+                RoutedMessageBody::ReceiptOutcomeRequest(receipt_id) => {
+                    NetworkViewClientMessages::ReceiptOutcomeRequest(receipt_id)
+                }
+                RoutedMessageBody::StateRequestHeader(shard_id, sync_hash) => {
+                    NetworkViewClientMessages::StateRequestHeader { shard_id, sync_hash }
+                }
+                RoutedMessageBody::StateRequestPart(shard_id, sync_hash, part_id) => {
+                    NetworkViewClientMessages::StateRequestPart { shard_id, sync_hash, part_id }
+                }
+                _ => return None,
+            },
+            PeerMessage::BlockRequest(hash) => NetworkViewClientMessages::BlockRequest(hash),
+            PeerMessage::BlockHeadersRequest(hashes) => {
+                NetworkViewClientMessages::BlockHeadersRequest(hashes)
+            }
+            PeerMessage::EpochSyncRequest(epoch_id) => {
+                NetworkViewClientMessages::EpochSyncRequest { epoch_id }
+            }
+            PeerMessage::EpochSyncFinalizationRequest(epoch_id) => {
+                NetworkViewClientMessages::EpochSyncFinalizationRequest { epoch_id }
+            }
+            _ => return None,
+        };
+        Some(msg)
+    }
+
+    pub fn is_view_client_message(&self) -> bool {
         match self {
             PeerMessage::BlockHeadersRequest(_)
             | PeerMessage::BlockRequest(_)
@@ -5,20 +5,115 @@
 ///
 /// NOTES:
 ///     - Code has an extra logic to ban peers if they sent messages that are too large.
-use crate::stats::metrics;
+///     - Large messages may be compressed with zstd by `Compression` before being framed, if the
+///       peer advertised `CAPABILITY_ZSTD_COMPRESSION` in its `Handshake`. Compressed messages
+///       start with the zstd magic number, which can't start a borsh-encoded `PeerMessage`.
+///       Compression happens before the connection encryption, so it can't be done in `Codec`,
+///       which frames the encrypted messages.
+use crate::stats::metrics::{self, NetworkMetrics};
+use crate::types::PeerMessage;
 use bytes::{Buf, BufMut, BytesMut};
-use bytesize::{GIB, MIB};
+use bytesize::{GIB, KIB, MIB};
 use near_network_primitives::types::ReasonForBan;
 use near_performance_metrics::framed_write::EncoderCallBack;
-use std::io::{Error, ErrorKind};
+use std::io::{Error, ErrorKind, Read};
+use strum::VariantNames;
 use tokio_util::codec::{Decoder, Encoder};
 use tracing::error;
 
 /// Maximum size of network message in encoded format.
 /// We encode length as `u32`, and therefore maximum size can't be larger than `u32::MAX`.
-const NETWORK_MESSAGE_MAX_SIZE_BYTES: usize = 512 * MIB as usize;
+pub(crate) const NETWORK_MESSAGE_MAX_SIZE_BYTES: usize = 512 * MIB as usize;
 /// Maximum capacity of write buffer in bytes.
 const MAX_WRITE_BUFFER_CAPACITY_BYTES: usize = GIB as usize;
+/// Compressible messages of at least this size are compressed.
+const COMPRESSION_THRESHOLD_BYTES: usize = 4 * KIB as usize;
+const ZSTD_COMPRESSION_LEVEL: i32 = 3;
+const ZSTD_MAGIC_NUMBER: [u8; 4] = [0x28, 0xb5, 0x2f, 0xfd];
+/// Maximum size of a decompressed message. It's much lower than the maximum size of a frame, as
+/// a small compressed frame could otherwise make us allocate 512 MiB. The largest messages,
+/// state parts, are a few tens of MiB.
+const MAX_DECOMPRESSED_MESSAGE_SIZE_BYTES: usize = 64 * MIB as usize;
+
+/// Compression of the messages of a connection, negotiated in the `Handshake`s.
+#[derive(Default)]
+pub(crate) struct Compression {
+    /// Whether the peer can receive compressed messages.
+    peer_supports_compression: bool,
+}
+
+impl Compression {
+    pub(crate) fn set_peer_supports_compression(&mut self, supported: bool) {
+        self.peer_supports_compression = supported;
+    }
+
+    /// Compresses large messages if the peer supports it. Messages larger than the peer would
+    /// decompress are sent as is.
+    pub(crate) fn compress(
+        &self,
+        msg: &PeerMessage,
+        bytes: Vec<u8>,
+        network_metrics: &NetworkMetrics,
+    ) -> Vec<u8> {
+        if !self.peer_supports_compression
+            || bytes.len() < COMPRESSION_THRESHOLD_BYTES
+            || bytes.len() > MAX_DECOMPRESSED_MESSAGE_SIZE_BYTES
+            || !msg.is_compressible()
+        {
+            return bytes;
+        }
+        match compress(&bytes) {
+            Some(compressed) => {
+                network_metrics.inc_by(
+                    NetworkMetrics::peer_message_compression_saved_bytes(msg.msg_variant())
+                        .as_ref(),
+                    (bytes.len() - compressed.len()) as u64,
+                );
+                compressed
+            }
+            None => bytes,
+        }
+    }
+
+    /// Decompresses a received message if it's compressed. Compressed messages are accepted even
+    /// if we didn't advertise compression: it's harmless, as their size is capped.
+    pub(crate) fn decompress(&self, bytes: Vec<u8>) -> Result<Vec<u8>, Error> {
+        if is_compressed(&bytes) {
+            decompress(&bytes)
+        } else {
+            Ok(bytes)
+        }
+    }
+}
+
+/// Compresses a serialized message. Returns `None` if that doesn't make it smaller.
+fn compress(msg: &[u8]) -> Option<Vec<u8>> {
+    match zstd::bulk::compress(msg, ZSTD_COMPRESSION_LEVEL) {
+        Ok(compressed) if compressed.len() < msg.len() => Some(compressed),
+        Ok(_) => None,
+        Err(err) => {
+            error!(target: "network", "Failed to compress message: {}", err);
+            None
+        }
+    }
+}
+
+fn is_compressed(msg: &[u8]) -> bool {
+    msg.starts_with(&ZSTD_MAGIC_NUMBER)
+}
+
+/// Decompresses a message compressed with `compress`. Fails if the result would be larger than
+/// `MAX_DECOMPRESSED_MESSAGE_SIZE_BYTES`.
+fn decompress(msg: &[u8]) -> Result<Vec<u8>, Error> {
+    let mut decompressed = Vec::new();
+    zstd::stream::read::Decoder::new(msg)?
+        .take(MAX_DECOMPRESSED_MESSAGE_SIZE_BYTES as u64 + 1)
+        .read_to_end(&mut decompressed)?;
+    if decompressed.len() > MAX_DECOMPRESSED_MESSAGE_SIZE_BYTES {
+        return Err(Error::new(ErrorKind::InvalidData, "Decompressed message is too long"));
+    }
+    Ok(decompressed)
+}
 
 #[derive(Default)]
 pub(crate) struct Codec {}
@@ -106,7 +201,12 @@ impl Decoder for Codec {
 
 #[cfg(test)]
 mod test {
-    use crate::peer::codec::{Codec, NETWORK_MESSAGE_MAX_SIZE_BYTES};
+    use crate::network_protocol::SUPPORTED_CAPABILITIES;
+    use crate::peer::codec::{
+        compress, decompress, is_compressed, Codec, Compression, COMPRESSION_THRESHOLD_BYTES,
+        MAX_DECOMPRESSED_MESSAGE_SIZE_BYTES, NETWORK_MESSAGE_MAX_SIZE_BYTES, ZSTD_MAGIC_NUMBER,
+    };
+    use crate::stats::metrics::NetworkMetrics;
     use crate::types::{Handshake, PeerMessage, RoutingTableUpdate};
     use borsh::{BorshDeserialize, BorshSerialize};
     use bytes::{BufMut, BytesMut};
@@ -116,6 +216,7 @@ mod test {
         RoutedMessageBody,
     };
     use near_primitives::block::{Approval, ApprovalInner};
+    use near_primitives::checked_feature;
     use near_primitives::hash::CryptoHash;
     use near_primitives::network::{AnnounceAccount, PeerId};
     use near_primitives::types::EpochId;
@@ -174,11 +275,92 @@ mod test {
                 archival: false,
             },
             partial_edge_info: PartialEdgeInfo::default(),
+            capabilities: if checked_feature!(
+                "protocol_feature_network_message_compression",
+                NetworkMessageCompression,
+                PROTOCOL_VERSION
+            ) {
+                SUPPORTED_CAPABILITIES
+            } else {
+                0
+            },
         };
         let msg = PeerMessage::Handshake(fake_handshake);
         test_codec(msg);
     }
 
+    #[test]
+    fn test_handshake_capabilities_of_old_version() {
+        let peer_info = PeerInfo::random();
+        let mut handshake = Handshake::new(
+            PEER_MIN_ALLOWED_PROTOCOL_VERSION,
+            peer_info.id.clone(),
+            peer_info.id,
+            None,
+            PeerChainInfoV2 {
+                genesis_id: Default::default(),
+                height: 0,
+                tracked_shards: vec![],
+                archival: false,
+            },
+            PartialEdgeInfo::default(),
+        );
+        let bytes = PeerMessage::Handshake(handshake.clone()).try_to_vec().unwrap();
+        // Old versions don't know about capabilities.
+        handshake.capabilities = 0;
+        assert_eq!(PeerMessage::try_from_slice(&bytes).unwrap(), PeerMessage::Handshake(handshake));
+    }
+
+    #[test]
+    fn test_compression() {
+        let msg = PeerMessage::BlockHeadersRequest(vec![CryptoHash::default(); 1000]);
+        let bytes = msg.try_to_vec().unwrap();
+        assert!(bytes.len() >= COMPRESSION_THRESHOLD_BYTES);
+        assert!(!is_compressed(&bytes));
+
+        let compressed = compress(&bytes).unwrap();
+        assert!(compressed.len() < bytes.len());
+        assert!(is_compressed(&compressed));
+        assert_eq!(PeerMessage::try_from_slice(&decompress(&compressed).unwrap()).unwrap(), msg);
+
+        // Incompressible data.
+        let random: Vec<u8> = (0..1000).map(|_| rand::random()).collect();
+        assert_eq!(compress(&random), None);
+
+        // Garbage after the magic number, with reserved bits of the frame header set.
+        let mut invalid = ZSTD_MAGIC_NUMBER.to_vec();
+        invalid.extend([0xff; 16]);
+        assert!(decompress(&invalid).is_err());
+
+        // Decompression bombs.
+        let bomb = compress(&vec![0u8; MAX_DECOMPRESSED_MESSAGE_SIZE_BYTES + 1]).unwrap();
+        assert!(bomb.len() < 64 * 1024);
+        assert!(decompress(&bomb).is_err());
+        let largest = compress(&vec![0u8; MAX_DECOMPRESSED_MESSAGE_SIZE_BYTES]).unwrap();
+        assert_eq!(decompress(&largest).unwrap().len(), MAX_DECOMPRESSED_MESSAGE_SIZE_BYTES);
+    }
+
+    #[test]
+    fn test_compression_size_limits() {
+        let mut compression = Compression::default();
+        compression.set_peer_supports_compression(true);
+        let msg = PeerMessage::BlockHeaders(vec![]);
+        let metrics = NetworkMetrics::new();
+
+        let small = vec![0u8; COMPRESSION_THRESHOLD_BYTES - 1];
+        assert_eq!(compression.compress(&msg, small.clone(), &metrics), small);
+        let large = vec![0u8; COMPRESSION_THRESHOLD_BYTES];
+        assert!(is_compressed(&compression.compress(&msg, large, &metrics)));
+        // The peer would refuse to decompress it.
+        let too_large = vec![0u8; MAX_DECOMPRESSED_MESSAGE_SIZE_BYTES + 1];
+        assert_eq!(compression.compress(&msg, too_large.clone(), &metrics), too_large);
+    }
+
+    #[test]
+    fn test_discriminants_dont_collide_with_zstd_magic_number() {
+        assert!(PeerMessage::VARIANTS.len() < ZSTD_MAGIC_NUMBER[0] as usize);
+    }
+
     #[test]
     fn test_peer_message_info_gossip() {
         let peer_info1 = PeerInfo::random();
@@ -1,4 +1,6 @@
 pub(crate) mod codec;
+mod noise;
+mod outbound_queue;
 pub(crate) mod peer_actor;
 mod tracker;
 mod transfer_stats;
@@ -0,0 +1,386 @@
+//! Encryption of the connections between peers.
+//!
+//! Once both peers exchanged `Handshake`s and agreed on a protocol version with
+//! `EncryptedPeerConnections`, the outbound peer starts a Noise XX handshake
+//! (`Noise_XX_25519_ChaChaPoly_SHA256`, implemented by `snow`), in which the static keys are the
+//! node keys converted to X25519. Its three messages are sent as raw frames:
+//!     -> e
+//!     <- e, ee, s, es
+//!     -> s, se
+//! The prologue contains both `Handshake`s, so tampering with them is detected as well.
+//! The static key each peer proves to hold has to be the one of its `PeerId`, so a man in the
+//! middle can't complete the handshake with either side.
+//! After that every frame is sealed with ChaCha20-Poly1305 under a per-direction key and a
+//! counter nonce, so frames can't be modified, truncated, dropped, reordered or replayed without
+//! the receiver noticing.
+//!
+//! Peers whose protocol version doesn't support encryption keep talking in plaintext. If it's
+//! supported but the handshake can't be done, e.g. because of a SECP256K1 node key, the
+//! connection is closed instead.
+use near_crypto::key_conversion::{convert_public_key_to_x25519, convert_secret_key_to_x25519};
+use near_crypto::{PublicKey, SecretKey};
+use near_primitives::network::PeerId;
+use std::collections::VecDeque;
+
+const NOISE_PARAMS: &str = "Noise_XX_25519_ChaChaPoly_SHA256";
+/// Noise messages are limited to 64 KiB, so larger frames are sealed in several chunks.
+const MAX_MESSAGE_LEN: usize = 65535;
+const TAG_LEN: usize = 16;
+const MAX_CHUNK_LEN: usize = MAX_MESSAGE_LEN - TAG_LEN;
+/// Length of the sealed length that starts every frame.
+const LEN_SEALED_LEN: usize = 8 + TAG_LEN;
+
+#[derive(Debug, PartialEq, Eq)]
+pub(crate) enum NoiseError {
+    /// The message is too short or has an unexpected payload.
+    InvalidMessage,
+    /// The message wasn't sealed with the expected key and nonce, i.e. it was tampered with.
+    DecryptionFailed,
+    /// The remote peer authenticated with a key different from the one of its `PeerId`.
+    UnexpectedStaticKey,
+    /// A handshake message was received after the handshake was finished.
+    UnexpectedMessage,
+    /// Diffie-Hellman with a low order point.
+    InvalidPublicKey,
+}
+
+impl From<snow::Error> for NoiseError {
+    fn from(err: snow::Error) -> Self {
+        match err {
+            snow::Error::Decrypt => NoiseError::DecryptionFailed,
+            snow::Error::Dh => NoiseError::InvalidPublicKey,
+            snow::Error::State(_) => NoiseError::UnexpectedMessage,
+            _ => NoiseError::InvalidMessage,
+        }
+    }
+}
+
+/// X25519 key corresponding to a node key. Only ED25519 node keys can be converted.
+fn x25519_public_key(key: &PublicKey) -> Option<[u8; 32]> {
+    match key {
+        PublicKey::ED25519(key) => convert_public_key_to_x25519(key),
+        PublicKey::SECP256K1(_) => None,
+    }
+}
+
+/// Whether the connection between the nodes with the given keys can be encrypted.
+pub(crate) fn can_encrypt(my_key: &SecretKey, peer_id: &PeerId) -> bool {
+    matches!(my_key, SecretKey::ED25519(_)) && x25519_public_key(peer_id.public_key()).is_some()
+}
+
+/// State of the Noise XX handshake of one side of a connection.
+pub(crate) struct NoiseHandshake {
+    /// Taken once the handshake is finished, to become the `Transport`.
+    state: Option<snow::HandshakeState>,
+    /// Static key the remote peer has to authenticate with, derived from its `PeerId`.
+    expected_rs: [u8; 32],
+}
+
+/// Result of processing a handshake message: a message to send back, if any, and the keys of
+/// the connection once the handshake is finished.
+pub(crate) struct HandshakeOutput {
+    pub reply: Option<Vec<u8>>,
+    pub transport: Option<Transport>,
+}
+
+impl NoiseHandshake {
+    fn new(initiator: bool, my_key: &SecretKey, peer_id: &PeerId, prologue: &[u8]) -> Option<Self> {
+        let s = match my_key {
+            SecretKey::ED25519(key) => convert_secret_key_to_x25519(key),
+            SecretKey::SECP256K1(_) => return None,
+        };
+        let expected_rs = x25519_public_key(peer_id.public_key())?;
+        let builder = snow::Builder::new(NOISE_PARAMS.parse().expect("Valid Noise parameters"))
+            .local_private_key(&s)
+            .prologue(prologue);
+        let state = if initiator { builder.build_initiator() } else { builder.build_responder() }
+            .expect("All the keys of the Noise XX pattern are set");
+        Some(Self { state: Some(state), expected_rs })
+    }
+
+    /// Starts the handshake on the outbound side, returning the first message.
+    /// Returns `None` if either key can't be used for encryption.
+    pub(crate) fn initiate(
+        my_key: &SecretKey,
+        peer_id: &PeerId,
+        prologue: &[u8],
+    ) -> Option<(Self, Vec<u8>)> {
+        let mut handshake = Self::new(true, my_key, peer_id, prologue)?;
+        let state = handshake.state.as_mut().unwrap();
+        let message = Self::write_message(state).expect("The initiator writes the first message");
+        Some((handshake, message))
+    }
+
+    /// Prepares the inbound side for the first message of the handshake.
+    /// Returns `None` if either key can't be used for encryption.
+    pub(crate) fn respond(my_key: &SecretKey, peer_id: &PeerId, prologue: &[u8]) -> Option<Self> {
+        Self::new(false, my_key, peer_id, prologue)
+    }
+
+    fn write_message(state: &mut snow::HandshakeState) -> Result<Vec<u8>, NoiseError> {
+        let mut message = vec![0u8; MAX_MESSAGE_LEN];
+        let len = state.write_message(&[], &mut message)?;
+        message.truncate(len);
+        Ok(message)
+    }
+
+    pub(crate) fn read_message(&mut self, message: &[u8]) -> Result<HandshakeOutput, NoiseError> {
+        let state = match self.state.as_mut() {
+            Some(state) if !state.is_my_turn() => state,
+            _ => return Err(NoiseError::UnexpectedMessage),
+        };
+        let mut payload = vec![0u8; MAX_MESSAGE_LEN];
+        if state.read_message(message, &mut payload)? != 0 {
+            return Err(NoiseError::InvalidMessage);
+        }
+        // The remote static key is known once it's read, in the second message for the
+        // initiator and in the third one for the responder. It's checked before answering.
+        if let Some(rs) = state.get_remote_static() {
+            if rs != &self.expected_rs[..] {
+                return Err(NoiseError::UnexpectedStaticKey);
+            }
+        }
+        let reply =
+            if state.is_handshake_finished() { None } else { Some(Self::write_message(state)?) };
+        let transport = match self.state.take() {
+            Some(state) if state.is_handshake_finished() => {
+                Some(Transport { state: state.into_transport_mode()? })
+            }
+            state => {
+                self.state = state;
+                None
+            }
+        };
+        Ok(HandshakeOutput { reply, transport })
+    }
+}
+
+/// Keys of an established encrypted connection.
+pub(crate) struct Transport {
+    state: snow::TransportState,
+}
+
+impl Transport {
+    /// Seals a frame: its length, followed by the message in chunks of at most `MAX_CHUNK_LEN`
+    /// bytes. The sealed length lets the receiver detect truncated frames.
+    pub(crate) fn encrypt(&mut self, message: &[u8]) -> Vec<u8> {
+        let num_chunks = (message.len() + MAX_CHUNK_LEN - 1) / MAX_CHUNK_LEN;
+        let mut sealed = vec![0u8; LEN_SEALED_LEN + message.len() + num_chunks * TAG_LEN];
+        // Fails only once the 2^64 nonces are exhausted.
+        let mut offset = self
+            .state
+            .write_message(&(message.len() as u64).to_le_bytes(), &mut sealed)
+            .expect("Encryption with ChaCha20-Poly1305 can't fail");
+        for chunk in message.chunks(MAX_CHUNK_LEN) {
+            offset += self
+                .state
+                .write_message(chunk, &mut sealed[offset..])
+                .expect("Encryption with ChaCha20-Poly1305 can't fail");
+        }
+        sealed
+    }
+
+    /// Opens a frame sealed by `encrypt`.
+    pub(crate) fn decrypt(&mut self, message: &[u8]) -> Result<Vec<u8>, NoiseError> {
+        if message.len() < LEN_SEALED_LEN {
+            return Err(NoiseError::InvalidMessage);
+        }
+        let (sealed_len, chunks) = message.split_at(LEN_SEALED_LEN);
+        let mut len = [0u8; 8];
+        self.state.read_message(sealed_len, &mut len)?;
+        let len = u64::from_le_bytes(len);
+        let num_chunks = (chunks.len() + MAX_MESSAGE_LEN - 1) / MAX_MESSAGE_LEN;
+        if chunks.len() as u64 != len + (num_chunks * TAG_LEN) as u64 {
+            return Err(NoiseError::InvalidMessage);
+        }
+        let mut plaintext = vec![0u8; chunks.len()];
+        let mut offset = 0;
+        for chunk in chunks.chunks(MAX_MESSAGE_LEN) {
+            offset += self.state.read_message(chunk, &mut plaintext[offset..])?;
+        }
+        plaintext.truncate(offset);
+        Ok(plaintext)
+    }
+}
+
+/// Encryption state of a connection.
+pub(crate) enum ConnectionEncryption {
+    /// The peers agreed not to encrypt, or haven't agreed on anything yet.
+    Plaintext,
+    /// Outgoing messages are queued until the handshake is finished.
+    Handshaking {
+        handshake: NoiseHandshake,
+        pending: VecDeque<Vec<u8>>,
+    },
+    Established(Transport),
+}
+
+impl ConnectionEncryption {
+    pub(crate) fn is_handshaking(&self) -> bool {
+        matches!(self, ConnectionEncryption::Handshaking { .. })
+    }
+}
+
+/// Prologue of the Noise handshake: the serialized `Handshake`s of the outbound peer and of the
+/// inbound peer, each prefixed with its length.
+pub(crate) fn prologue(outbound_handshake: &[u8], inbound_handshake: &[u8]) -> Vec<u8> {
+    let mut prologue = Vec::with_capacity(8 + outbound_handshake.len() + inbound_handshake.len());
+    for handshake in [outbound_handshake, inbound_handshake] {
+        prologue.extend((handshake.len() as u32).to_le_bytes());
+        prologue.extend(handshake);
+    }
+    prologue
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::PeerMessage;
+    use borsh::{BorshDeserialize, BorshSerialize};
+    use near_crypto::{KeyType, Signature};
+    use near_network_primitives::types::{PeerIdOrHash, Ping, RoutedMessage, RoutedMessageBody};
+
+    struct Node {
+        key: SecretKey,
+        peer_id: PeerId,
+    }
+
+    impl Node {
+        fn new(seed: &str) -> Self {
+            let key = SecretKey::from_seed(KeyType::ED25519, seed);
+            Self { peer_id: PeerId::new(key.public_key()), key }
+        }
+    }
+
+    /// Runs the handshake, passing the messages through `mitm`.
+    fn handshake(
+        outbound: &Node,
+        inbound: &Node,
+        prologue: &[u8],
+        mut mitm: impl FnMut(usize, &mut Vec<u8>),
+    ) -> Result<(Transport, Transport), NoiseError> {
+        let (mut initiator, mut msg1) =
+            NoiseHandshake::initiate(&outbound.key, &inbound.peer_id, prologue).unwrap();
+        let mut responder =
+            NoiseHandshake::respond(&inbound.key, &outbound.peer_id, prologue).unwrap();
+        mitm(1, &mut msg1);
+        let mut msg2 = responder.read_message(&msg1)?.reply.unwrap();
+        mitm(2, &mut msg2);
+        let output = initiator.read_message(&msg2)?;
+        let mut msg3 = output.reply.unwrap();
+        mitm(3, &mut msg3);
+        let inbound_transport = responder.read_message(&msg3)?.transport.unwrap();
+        Ok((output.transport.unwrap(), inbound_transport))
+    }
+
+    fn routed_message(author: &Node, target: &Node) -> Vec<u8> {
+        let body = RoutedMessageBody::Ping(Ping { nonce: 0, source: author.peer_id.clone() });
+        let msg = RoutedMessage {
+            target: PeerIdOrHash::PeerId(target.peer_id.clone()),
+            author: author.peer_id.clone(),
+            signature: Signature::default(),
+            ttl: 100,
+            body,
+        };
+        PeerMessage::Routed(Box::new(msg)).try_to_vec().unwrap()
+    }
+
+    #[test]
+    fn test_noise_handshake() {
+        let (a, b) = (Node::new("a"), Node::new("b"));
+        let (mut ta, mut tb) = handshake(&a, &b, b"prologue", |_, _| {}).unwrap();
+        for _ in 0..3 {
+            let msg = routed_message(&a, &b);
+            let sealed = ta.encrypt(&msg);
+            assert_ne!(sealed, msg);
+            assert_eq!(tb.decrypt(&sealed).unwrap(), msg);
+            let reply = routed_message(&b, &a);
+            assert_eq!(ta.decrypt(&tb.encrypt(&reply)).unwrap(), reply);
+        }
+        let decoded =
+            PeerMessage::try_from_slice(&tb.decrypt(&ta.encrypt(&routed_message(&a, &b))).unwrap())
+                .unwrap();
+        assert!(matches!(decoded, PeerMessage::Routed(_)));
+    }
+
+    #[test]
+    fn test_large_messages() {
+        let (a, b) = (Node::new("a"), Node::new("b"));
+        let (mut ta, mut tb) = handshake(&a, &b, b"prologue", |_, _| {}).unwrap();
+        for len in [0, MAX_CHUNK_LEN, MAX_CHUNK_LEN + 1, 3 * MAX_CHUNK_LEN + 7] {
+            let msg: Vec<u8> = (0..len).map(|i| i as u8).collect();
+            let sealed = ta.encrypt(&msg);
+            assert_eq!(tb.decrypt(&sealed).unwrap(), msg);
+        }
+
+        // Dropping the last chunk of a frame is detected.
+        let mut sealed = ta.encrypt(&vec![0u8; 2 * MAX_CHUNK_LEN]);
+        sealed.truncate(LEN_SEALED_LEN + MAX_MESSAGE_LEN);
+        assert_eq!(tb.decrypt(&sealed), Err(NoiseError::InvalidMessage));
+    }
+
+    #[test]
+    fn test_tampered_messages() {
+        let (a, b) = (Node::new("a"), Node::new("b"));
+        let (mut ta, mut tb) = handshake(&a, &b, b"prologue", |_, _| {}).unwrap();
+
+        // Flipped bit.
+        let mut sealed = ta.encrypt(&routed_message(&a, &b));
+        sealed[10] ^= 1;
+        assert_eq!(tb.decrypt(&sealed), Err(NoiseError::DecryptionFailed));
+
+        // Reordered messages.
+        let (mut ta, mut tb) = handshake(&a, &b, b"prologue", |_, _| {}).unwrap();
+        let _first = ta.encrypt(&routed_message(&a, &b));
+        let second = ta.encrypt(&routed_message(&a, &b));
+        assert_eq!(tb.decrypt(&second), Err(NoiseError::DecryptionFailed));
+
+        // Replayed messages.
+        let (mut ta, mut tb) = handshake(&a, &b, b"prologue", |_, _| {}).unwrap();
+        let first = ta.encrypt(&routed_message(&a, &b));
+        assert!(tb.decrypt(&first).is_ok());
+        assert_eq!(tb.decrypt(&first), Err(NoiseError::DecryptionFailed));
+
+        // A message sent in the other direction can't be reflected back.
+        let (mut ta, _) = handshake(&a, &b, b"prologue", |_, _| {}).unwrap();
+        let sealed = ta.encrypt(&routed_message(&a, &b));
+        assert_eq!(ta.decrypt(&sealed), Err(NoiseError::DecryptionFailed));
+    }
+
+    #[test]
+    fn test_tampered_handshake() {
+        let (a, b) = (Node::new("a"), Node::new("b"));
+        // Different prologues, i.e. tampered `Handshake`s.
+        let (mut initiator, msg1) = NoiseHandshake::initiate(&a.key, &b.peer_id, b"one").unwrap();
+        let mut responder = NoiseHandshake::respond(&b.key, &a.peer_id, b"two").unwrap();
+        let msg2 = responder.read_message(&msg1).unwrap().reply.unwrap();
+        assert_eq!(initiator.read_message(&msg2).err(), Some(NoiseError::DecryptionFailed));
+
+        for step in 1..=3 {
+            let result = handshake(&a, &b, b"prologue", |i, msg| {
+                if i == step {
+                    msg[0] ^= 1;
+                }
+            });
+            assert!(result.is_err());
+        }
+    }
+
+    #[test]
+    fn test_man_in_the_middle() {
+        let (a, b, mitm) = (Node::new("a"), Node::new("b"), Node::new("mitm"));
+        // The man in the middle answers `a` with its own key: `a` expects the key of `b`.
+        let (mut initiator, msg1) = NoiseHandshake::initiate(&a.key, &b.peer_id, b"").unwrap();
+        let mut fake_responder = NoiseHandshake::respond(&mitm.key, &a.peer_id, b"").unwrap();
+        let msg2 = fake_responder.read_message(&msg1).unwrap().reply.unwrap();
+        assert_eq!(initiator.read_message(&msg2).err(), Some(NoiseError::UnexpectedStaticKey));
+
+        // The man in the middle connects to `b` pretending to be `a`.
+        let (mut fake_initiator, msg1) =
+            NoiseHandshake::initiate(&mitm.key, &b.peer_id, b"").unwrap();
+        let mut responder = NoiseHandshake::respond(&b.key, &a.peer_id, b"").unwrap();
+        let msg2 = responder.read_message(&msg1).unwrap().reply.unwrap();
+        let msg3 = fake_initiator.read_message(&msg2).unwrap().reply.unwrap();
+        assert_eq!(responder.read_message(&msg3).err(), Some(NoiseError::UnexpectedStaticKey));
+    }
+}
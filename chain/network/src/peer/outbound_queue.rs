@@ -0,0 +1,260 @@
+/// Outbound queues of a single peer.
+///
+/// Messages are written to the connection only while its write buffer is small, the rest wait in
+/// one queue per priority class. Whenever there is room in the buffer, the oldest message of the
+/// highest non-empty class is sent, so approvals and chunks don't wait behind blocks, sync
+/// responses or gossip. A message already in the write buffer is never preempted though.
+///
+/// Queues of the low priority classes are bounded and drop messages when full.
+use crate::stats::metrics;
+use crate::types::PeerMessage;
+use bytesize::MIB;
+use near_network_primitives::types::RoutedMessageBody;
+use std::collections::VecDeque;
+use std::time::Instant;
+
+/// Priority class of an outbound message, from the most to the least urgent.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum MessagePriority {
+    /// Approvals, chunks, and the handshake and edge messages keeping the connection alive.
+    Consensus = 0,
+    Blocks = 1,
+    /// Header and state sync.
+    Sync = 2,
+    /// Transactions, routing table and peer gossip, queries.
+    Gossip = 3,
+}
+
+const NUM_PRIORITIES: usize = 4;
+const PRIORITIES: [MessagePriority; NUM_PRIORITIES] = [
+    MessagePriority::Consensus,
+    MessagePriority::Blocks,
+    MessagePriority::Sync,
+    MessagePriority::Gossip,
+];
+
+/// What to do with a message whose queue is full.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum DropPolicy {
+    /// The queue is unbounded.
+    Never,
+    /// Drop the new message. Used for responses the other peer will request again.
+    DropNewest,
+    /// Drop the oldest message, which is the most likely to be stale.
+    DropOldest,
+}
+
+struct QueueLimits {
+    max_messages: usize,
+    max_bytes: usize,
+    drop_policy: DropPolicy,
+}
+
+impl MessagePriority {
+    pub(crate) fn of(msg: &PeerMessage) -> Self {
+        match msg {
+            PeerMessage::Handshake(_)
+            | PeerMessage::HandshakeFailure(_, _)
+            | PeerMessage::LastEdge(_)
+            | PeerMessage::RequestUpdateNonce(_)
+            | PeerMessage::ResponseUpdateNonce(_)
+            | PeerMessage::Disconnect => MessagePriority::Consensus,
+            PeerMessage::Block(_) | PeerMessage::BlockRequest(_) | PeerMessage::Challenge(_) => {
+                MessagePriority::Blocks
+            }
+            PeerMessage::BlockHeadersRequest(_)
+            | PeerMessage::BlockHeaders(_)
+            | PeerMessage::EpochSyncRequest(_)
+            | PeerMessage::EpochSyncResponse(_)
+            | PeerMessage::EpochSyncFinalizationRequest(_)
+            | PeerMessage::EpochSyncFinalizationResponse(_) => MessagePriority::Sync,
+            PeerMessage::Routed(routed) => match routed.body {
+                RoutedMessageBody::BlockApproval(_)
+                | RoutedMessageBody::PartialEncodedChunk(_)
+                | RoutedMessageBody::VersionedPartialEncodedChunk(_)
+                | RoutedMessageBody::PartialEncodedChunkForward(_)
+                | RoutedMessageBody::PartialEncodedChunkRequest(_)
+                | RoutedMessageBody::PartialEncodedChunkResponse(_) => MessagePriority::Consensus,
+                RoutedMessageBody::StateRequestHeader(_, _)
+                | RoutedMessageBody::StateRequestPart(_, _, _)
+                | RoutedMessageBody::StateResponse(_)
+                | RoutedMessageBody::VersionedStateResponse(_) => MessagePriority::Sync,
+                _ => MessagePriority::Gossip,
+            },
+            _ => MessagePriority::Gossip,
+        }
+    }
+
+    pub(crate) fn as_str(self) -> &'static str {
+        match self {
+            MessagePriority::Consensus => "consensus",
+            MessagePriority::Blocks => "blocks",
+            MessagePriority::Sync => "sync",
+            MessagePriority::Gossip => "gossip",
+        }
+    }
+
+    fn limits(self) -> QueueLimits {
+        match self {
+            MessagePriority::Consensus | MessagePriority::Blocks => QueueLimits {
+                max_messages: usize::MAX,
+                max_bytes: usize::MAX,
+                drop_policy: DropPolicy::Never,
+            },
+            MessagePriority::Sync => QueueLimits {
+                max_messages: 256,
+                max_bytes: 256 * MIB as usize,
+                drop_policy: DropPolicy::DropNewest,
+            },
+            MessagePriority::Gossip => QueueLimits {
+                max_messages: 4096,
+                max_bytes: 32 * MIB as usize,
+                drop_policy: DropPolicy::DropOldest,
+            },
+        }
+    }
+}
+
+pub(crate) struct QueuedMessage {
+    pub bytes: Vec<u8>,
+    /// Name of the message variant, for logging.
+    pub variant: &'static str,
+    enqueued: Instant,
+}
+
+#[derive(Default)]
+struct Queue {
+    messages: VecDeque<QueuedMessage>,
+    bytes: usize,
+}
+
+impl Queue {
+    fn push(&mut self, msg: QueuedMessage) {
+        self.bytes += msg.bytes.len();
+        self.messages.push_back(msg);
+    }
+
+    fn pop(&mut self) -> Option<QueuedMessage> {
+        let msg = self.messages.pop_front()?;
+        self.bytes -= msg.bytes.len();
+        Some(msg)
+    }
+
+    fn fits(&self, limits: &QueueLimits, len: usize) -> bool {
+        self.messages.len() < limits.max_messages
+            && self.bytes.saturating_add(len) <= limits.max_bytes
+    }
+}
+
+#[derive(Default)]
+pub(crate) struct OutboundQueues {
+    queues: [Queue; NUM_PRIORITIES],
+}
+
+impl OutboundQueues {
+    /// Queues a serialized message. Returns false if the message was dropped, either because its
+    /// queue is full or because it's larger than the whole queue.
+    pub(crate) fn push(
+        &mut self,
+        priority: MessagePriority,
+        bytes: Vec<u8>,
+        variant: &'static str,
+        now: Instant,
+    ) -> bool {
+        let limits = priority.limits();
+        let queue = &mut self.queues[priority as usize];
+        let len = bytes.len();
+        if !queue.fits(&limits, len) {
+            let dropped =
+                metrics::PEER_OUTBOUND_QUEUE_DROPPED.with_label_values(&[priority.as_str()]);
+            match limits.drop_policy {
+                DropPolicy::Never => {}
+                DropPolicy::DropOldest if len <= limits.max_bytes => {
+                    while !queue.fits(&limits, len) {
+                        queue.pop();
+                        dropped.inc();
+                    }
+                }
+                DropPolicy::DropOldest | DropPolicy::DropNewest => {
+                    dropped.inc();
+                    return false;
+                }
+            }
+        }
+        queue.push(QueuedMessage { bytes, variant, enqueued: now });
+        true
+    }
+
+    /// Takes the oldest message of the highest priority class, recording how long it was queued.
+    pub(crate) fn pop(&mut self, now: Instant) -> Option<QueuedMessage> {
+        PRIORITIES.iter().find_map(|&priority| {
+            let msg = self.queues[priority as usize].pop()?;
+            metrics::PEER_OUTBOUND_QUEUE_DELAY
+                .with_label_values(&[priority.as_str()])
+                .observe(now.saturating_duration_since(msg.enqueued).as_secs_f64());
+            Some(msg)
+        })
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.queues.iter().all(|queue| queue.messages.is_empty())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pop_all(queues: &mut OutboundQueues) -> Vec<Vec<u8>> {
+        std::iter::from_fn(|| queues.pop(Instant::now())).map(|msg| msg.bytes).collect()
+    }
+
+    #[test]
+    fn test_priorities() {
+        let now = Instant::now();
+        let mut queues = OutboundQueues::default();
+        assert!(queues.push(MessagePriority::Gossip, vec![4], "", now));
+        assert!(queues.push(MessagePriority::Sync, vec![3], "", now));
+        assert!(queues.push(MessagePriority::Blocks, vec![2], "", now));
+        assert!(queues.push(MessagePriority::Consensus, vec![0], "", now));
+        assert!(queues.push(MessagePriority::Consensus, vec![1], "", now));
+        assert_eq!(pop_all(&mut queues), vec![vec![0], vec![1], vec![2], vec![3], vec![4]]);
+        assert!(queues.is_empty());
+    }
+
+    #[test]
+    fn test_drop_policies() {
+        let now = Instant::now();
+        let mut queues = OutboundQueues::default();
+
+        // New sync messages are dropped once the queue is full.
+        let limits = MessagePriority::Sync.limits();
+        for i in 0..limits.max_messages {
+            assert!(queues.push(MessagePriority::Sync, vec![i as u8], "", now));
+        }
+        assert!(!queues.push(MessagePriority::Sync, vec![255], "", now));
+        assert_eq!(pop_all(&mut queues)[0], vec![0]);
+
+        // Old gossip messages are dropped to make room for new ones.
+        let limits = MessagePriority::Gossip.limits();
+        for i in 0..=limits.max_messages {
+            assert!(queues.push(
+                MessagePriority::Gossip,
+                (i as u32).to_le_bytes().to_vec(),
+                "",
+                now
+            ));
+        }
+        let popped = pop_all(&mut queues);
+        assert_eq!(popped.len(), limits.max_messages);
+        assert_eq!(popped[0], 1u32.to_le_bytes().to_vec());
+        // A message larger than the whole queue can't be queued.
+        assert!(!queues.push(MessagePriority::Gossip, vec![0; limits.max_bytes + 1], "", now));
+
+        // Consensus messages are never dropped.
+        for _ in 0..limits.max_messages * 2 {
+            assert!(queues.push(MessagePriority::Consensus, vec![0], "", now));
+        }
+        assert_eq!(pop_all(&mut queues).len(), limits.max_messages * 2);
+    }
+}
@@ -1,9 +1,13 @@
-use crate::peer::codec::Codec;
+use crate::network_protocol::{CAPABILITY_ANNOUNCE_ADDRESSES, CAPABILITY_ZSTD_COMPRESSION};
+use crate::peer::codec::{Codec, Compression};
+use crate::peer::noise::{self, ConnectionEncryption, NoiseHandshake, Transport};
+use crate::peer::outbound_queue::{MessagePriority, OutboundQueues};
 use crate::peer::tracker::Tracker;
 use crate::peer::utils;
 use crate::private_actix::{
     PeersRequest, RegisterPeer, RegisterPeerResponse, SendMessage, Unregister,
 };
+use crate::recorder::{MessageDirection, MessageRecorder};
 use crate::stats::metrics::{self, NetworkMetrics};
 use crate::types::{
     Handshake, HandshakeFailureReason, NetworkClientMessages, NetworkClientResponses,
@@ -17,7 +21,7 @@ use actix::{
 };
 use borsh::{BorshDeserialize, BorshSerialize};
 use lru::LruCache;
-use near_crypto::Signature;
+use near_crypto::{SecretKey, Signature};
 use near_network_primitives::types::{
     Ban, NetworkViewClientMessages, NetworkViewClientResponses, PeerChainInfoV2, PeerIdOrHash,
     PeerInfo, PeerManagerRequest, PeerStatsResult, PeerType, QueryPeerStats, ReasonForBan,
@@ -29,9 +33,9 @@ use near_performance_metrics::framed_write::{FramedWrite, WriteHandler};
 use near_performance_metrics_macros::perf;
 use near_primitives::block::GenesisId;
 use near_primitives::borsh::maybestd::io::Error;
+use near_primitives::checked_feature;
 use near_primitives::logging;
 use near_primitives::network::PeerId;
-use near_primitives::sharding::PartialEncodedChunk;
 use near_primitives::time::Clock;
 use near_primitives::utils::DisplayOption;
 use near_primitives::version::{
@@ -39,6 +43,7 @@ use near_primitives::version::{
 };
 use near_rate_limiter::{ActixMessageWrapper, ThrottleController};
 use std::cmp::max;
+use std::collections::VecDeque;
 use std::fmt::Debug;
 use std::io;
 use std::net::SocketAddr;
@@ -61,6 +66,9 @@ const MAX_TRANSACTIONS_PER_BLOCK_MESSAGE: usize = 1000;
 const ROUTED_MESSAGE_CACHE_SIZE: usize = 1000;
 /// Duplicated messages will be dropped if routed through the same peer multiple times.
 const DROP_DUPLICATED_MESSAGES_PERIOD: Duration = Duration::from_millis(50);
+/// Queued messages are moved to the write buffer of the connection only while it holds less
+/// than this many bytes. The rest are moved once the buffer is written to the socket.
+const OUTBOUND_WRITE_BUFFER_WATERMARK: usize = 64 * 1024;
 
 pub(crate) struct PeerActor {
     /// This node's id and address (either listening or socket address).
@@ -106,6 +114,22 @@ pub(crate) struct PeerActor {
     routed_message_cache: LruCache<(PeerId, PeerIdOrHash, Signature), Instant>,
     /// A helper data structure for limiting reading
     throttle_controller: ThrottleController,
+    /// This node's key, which authenticates the encrypted connection.
+    node_key: SecretKey,
+    /// Whether the connection is encrypted.
+    encryption: ConnectionEncryption,
+    /// Serialized `Handshake`s last sent to and received from the peer. The encryption
+    /// handshake is bound to them.
+    sent_handshake: Option<Vec<u8>>,
+    received_handshake: Option<Vec<u8>>,
+    /// Compression of the messages, once negotiated in the `Handshake`s.
+    compression: Compression,
+    /// Whether the peer can receive `PeerMessage::SyncAnnounceAddresses`.
+    peer_supports_announce_addresses: bool,
+    /// Messages waiting for room in the write buffer, by priority.
+    outbound_queues: OutboundQueues,
+    /// Records the messages exchanged with the peer, if enabled.
+    message_recorder: Option<Arc<MessageRecorder>>,
 }
 
 impl Debug for PeerActor {
@@ -131,6 +155,8 @@ impl PeerActor {
         txns_since_last_block: Arc<AtomicUsize>,
         peer_counter: Arc<AtomicUsize>,
         throttle_controller: ThrottleController,
+        node_key: SecretKey,
+        message_recorder: Option<Arc<MessageRecorder>>,
     ) -> Self {
         PeerActor {
             my_node_info,
@@ -154,6 +180,14 @@ impl PeerActor {
             peer_counter,
             routed_message_cache: LruCache::new(ROUTED_MESSAGE_CACHE_SIZE),
             throttle_controller,
+            node_key,
+            encryption: ConnectionEncryption::Plaintext,
+            sent_handshake: None,
+            received_handshake: None,
+            compression: Compression::default(),
+            peer_supports_announce_addresses: false,
+            outbound_queues: Default::default(),
+            message_recorder,
         }
     }
 
@@ -163,30 +197,205 @@ impl PeerActor {
         match msg {
             PeerMessage::Block(b) if self.tracker.has_received(b.hash()) => return,
             PeerMessage::BlockRequest(h) => self.tracker.push_request(*h),
+            PeerMessage::SyncAnnounceAddresses(_) if !self.peer_supports_announce_addresses => {
+                return
+            }
             _ => (),
         };
 
         match msg.try_to_vec() {
             Ok(bytes) => {
-                self.tracker.increment_sent(bytes.len() as u64);
-                let bytes_len = bytes.len();
-                if !self.framed.write(bytes) {
-                    #[cfg(feature = "performance_stats")]
-                    let tid = near_rust_allocator_proxy::get_tid();
-                    #[cfg(not(feature = "performance_stats"))]
-                    let tid = 0;
-                    error!(
-                        "{} Failed to send message {} of size {}",
-                        tid,
-                        strum::AsStaticRef::as_static(msg),
-                        bytes_len,
-                    )
+                if let PeerMessage::Handshake(_) = msg {
+                    self.sent_handshake = Some(bytes.clone());
+                }
+                // Messages are recorded uncompressed, and only once they are queued.
+                let uncompressed = self.message_recorder.is_some().then(|| bytes.clone());
+                let bytes = self.compression.compress(msg, bytes, &self.network_metrics);
+                let priority = MessagePriority::of(msg);
+                if self.outbound_queues.push(
+                    priority,
+                    bytes,
+                    strum::AsStaticRef::as_static(msg),
+                    Clock::instant(),
+                ) {
+                    if let Some(uncompressed) = uncompressed {
+                        self.record_message(MessageDirection::Outbound, &uncompressed);
+                    }
+                } else {
+                    debug!(target: "network", "Dropping message {} to {}: {} queue is full", msg, self.peer_info, priority.as_str());
                 }
+                self.flush_outbound_queues(OUTBOUND_WRITE_BUFFER_WATERMARK);
             }
             Err(err) => error!(target: "network", "Error converting message to bytes: {}", err),
         };
     }
 
+    /// Records a serialized message exchanged with the peer, if recording is enabled.
+    fn record_message(&self, direction: MessageDirection, msg: &[u8]) {
+        if let Some(recorder) = &self.message_recorder {
+            if let Err(err) = recorder.record(self.other_peer_id(), direction, msg) {
+                warn!(target: "network", ?err, "Failed to record message");
+            }
+        }
+    }
+
+    /// Moves queued messages to the write buffer, highest priority first, until the buffer holds
+    /// at least `watermark` bytes.
+    fn flush_outbound_queues(&mut self, watermark: usize) {
+        while self.framed.buffer_len() < watermark {
+            let msg = match self.outbound_queues.pop(Clock::instant()) {
+                Some(msg) => msg,
+                None => break,
+            };
+            self.tracker.increment_sent(msg.bytes.len() as u64);
+            let bytes_len = msg.bytes.len();
+            if !self.write_frame(msg.bytes) {
+                #[cfg(feature = "performance_stats")]
+                let tid = near_rust_allocator_proxy::get_tid();
+                #[cfg(not(feature = "performance_stats"))]
+                let tid = 0;
+                error!("{} Failed to send message {} of size {}", tid, msg.variant, bytes_len)
+            }
+        }
+    }
+
+    /// Writes a serialized message to the connection, encrypting it if needed. Messages sent
+    /// during the encryption handshake are queued until it's finished.
+    fn write_frame(&mut self, bytes: Vec<u8>) -> bool {
+        match &mut self.encryption {
+            ConnectionEncryption::Plaintext => self.framed.write(bytes),
+            ConnectionEncryption::Handshaking { pending, .. } => {
+                pending.push_back(bytes);
+                true
+            }
+            ConnectionEncryption::Established(transport) => {
+                self.framed.write(transport.encrypt(&bytes))
+            }
+        }
+    }
+
+    /// Starts the encryption handshake once both `Handshake`s were exchanged, if the agreed
+    /// protocol version supports it. The outbound peer initiates it.
+    /// The agreed version is the lowest of the advertised ones, so both peers advertised
+    /// encryption if it supports it: the connection is closed rather than kept in plaintext if
+    /// encryption can't be started then.
+    ///
+    /// Note that during the transition a man in the middle can still downgrade the connection
+    /// to plaintext by tampering with the protocol version in the `Handshake`s.
+    fn start_encryption(&mut self, ctx: &mut Context<PeerActor>) {
+        if !checked_feature!(
+            "protocol_feature_encrypted_peer_connections",
+            EncryptedPeerConnections,
+            self.protocol_version
+        ) {
+            return;
+        }
+        let peer_id = match self.other_peer_id() {
+            Some(peer_id) => peer_id.clone(),
+            None => {
+                error!(target: "network", "Starting encryption with an unknown peer. Disconnecting.");
+                ctx.stop();
+                return;
+            }
+        };
+        if !noise::can_encrypt(&self.node_key, &peer_id) {
+            warn!(target: "network", "Can't encrypt connection with {}: unsupported key type. Disconnecting.", peer_id);
+            ctx.stop();
+            return;
+        }
+        let (outbound_handshake, inbound_handshake) = match self.peer_type {
+            PeerType::Outbound => (&self.sent_handshake, &self.received_handshake),
+            PeerType::Inbound => (&self.received_handshake, &self.sent_handshake),
+        };
+        let prologue = match (outbound_handshake, inbound_handshake) {
+            (Some(outbound), Some(inbound)) => noise::prologue(outbound, inbound),
+            _ => {
+                error!(target: "network", "Starting encryption with {} before exchanging handshakes. Disconnecting.", peer_id);
+                ctx.stop();
+                return;
+            }
+        };
+        // Messages queued so far, like our `Handshake`, are still sent in plaintext.
+        self.flush_outbound_queues(usize::MAX);
+        let handshake = match self.peer_type {
+            PeerType::Outbound => {
+                match NoiseHandshake::initiate(&self.node_key, &peer_id, &prologue) {
+                    Some((handshake, message)) => {
+                        self.framed.write(message);
+                        handshake
+                    }
+                    None => {
+                        ctx.stop();
+                        return;
+                    }
+                }
+            }
+            PeerType::Inbound => {
+                match NoiseHandshake::respond(&self.node_key, &peer_id, &prologue) {
+                    Some(handshake) => handshake,
+                    None => {
+                        ctx.stop();
+                        return;
+                    }
+                }
+            }
+        };
+        debug!(target: "network", "Starting encryption handshake with {}", peer_id);
+        self.encryption = ConnectionEncryption::Handshaking { handshake, pending: VecDeque::new() };
+    }
+
+    /// Flushes the messages queued during the encryption handshake.
+    fn finish_encryption(&mut self, transport: Transport) {
+        debug!(target: "network", "Connection with {} is encrypted", self.peer_info);
+        let pending = match std::mem::replace(
+            &mut self.encryption,
+            ConnectionEncryption::Established(transport),
+        ) {
+            ConnectionEncryption::Handshaking { pending, .. } => pending,
+            _ => VecDeque::new(),
+        };
+        for bytes in pending {
+            if !self.write_frame(bytes) {
+                error!(target: "network", "Failed to send queued message to {}", self.peer_info);
+            }
+        }
+    }
+
+    /// Returns the plaintext of a frame received from the peer, or `None` if the frame was part of
+    /// the encryption handshake or the connection should be closed.
+    /// Frames failing authentication could have been modified by anybody on path, so the peer
+    /// isn't banned for them.
+    fn decrypt_frame(&mut self, ctx: &mut Context<PeerActor>, frame: Vec<u8>) -> Option<Vec<u8>> {
+        match &mut self.encryption {
+            ConnectionEncryption::Plaintext => Some(frame),
+            ConnectionEncryption::Established(transport) => match transport.decrypt(&frame) {
+                Ok(msg) => Some(msg),
+                Err(err) => {
+                    warn!(target: "network", "Failed to decrypt message from {}: {:?}. Disconnecting.", self.peer_info, err);
+                    ctx.stop();
+                    None
+                }
+            },
+            ConnectionEncryption::Handshaking { handshake, .. } => {
+                match handshake.read_message(&frame) {
+                    Ok(output) => {
+                        if let Some(reply) = output.reply {
+                            self.framed.write(reply);
+                        }
+                        if let Some(transport) = output.transport {
+                            self.finish_encryption(transport);
+                        }
+                    }
+                    Err(err) => {
+                        warn!(target: "network", "Encryption handshake with {} failed: {:?}. Disconnecting.", self.peer_info, err);
+                        ctx.stop();
+                    }
+                }
+                None
+            }
+        }
+    }
+
     fn fetch_client_chain_info(&self, ctx: &mut Context<PeerActor>) {
         ctx.wait(
             self.view_client_addr
@@ -215,7 +424,7 @@ impl PeerActor {
         self.view_client_addr
             .send(NetworkViewClientMessages::GetChainInfo)
             .into_actor(self)
-            .then(move |res, act, _ctx| match res {
+            .then(move |res, act, ctx| match res {
                 Ok(NetworkViewClientResponses::ChainInfo {
                     genesis_id,
                     height,
@@ -238,6 +447,11 @@ impl PeerActor {
                     };
 
                     act.send_message(&handshake);
+                    // The inbound peer sends its handshake last, after which the outbound peer
+                    // starts encrypting.
+                    if act.peer_type == PeerType::Inbound {
+                        act.start_encryption(ctx);
+                    }
                     actix::fut::ready(())
                 }
                 Err(err) => {
@@ -277,47 +491,15 @@ impl PeerActor {
     }
 
     fn receive_view_client_message(&self, ctx: &mut Context<PeerActor>, msg: PeerMessage) {
-        let mut msg_hash = None;
-        let view_client_message = match msg {
-            PeerMessage::Routed(message) => {
-                msg_hash = Some(message.hash());
-                match message.body {
-                    RoutedMessageBody::TxStatusRequest(account_id, tx_hash) => {
-                        NetworkViewClientMessages::TxStatus {
-                            tx_hash,
-                            signer_account_id: account_id,
-                        }
-                    }
-                    RoutedMessageBody::TxStatusResponse(tx_result) => {
-                        NetworkViewClientMessages::TxStatusResponse(Box::new(tx_result))
-                    }
-                    RoutedMessageBody::ReceiptOutcomeRequest(receipt_id) => {
-                        NetworkViewClientMessages::ReceiptOutcomeRequest(receipt_id)
-                    }
-                    RoutedMessageBody::StateRequestHeader(shard_id, sync_hash) => {
-                        NetworkViewClientMessages::StateRequestHeader { shard_id, sync_hash }
-                    }
-                    RoutedMessageBody::StateRequestPart(shard_id, sync_hash, part_id) => {
-                        NetworkViewClientMessages::StateRequestPart { shard_id, sync_hash, part_id }
-                    }
-                    body => {
-                        error!(target: "network", "Peer receive_view_client_message received unexpected type: {:?}", body);
-                        return;
-                    }
-                }
-            }
-            PeerMessage::BlockRequest(hash) => NetworkViewClientMessages::BlockRequest(hash),
-            PeerMessage::BlockHeadersRequest(hashes) => {
-                NetworkViewClientMessages::BlockHeadersRequest(hashes)
-            }
-            PeerMessage::EpochSyncRequest(epoch_id) => {
-                NetworkViewClientMessages::EpochSyncRequest { epoch_id }
-            }
-            PeerMessage::EpochSyncFinalizationRequest(epoch_id) => {
-                NetworkViewClientMessages::EpochSyncFinalizationRequest { epoch_id }
-            }
-            peer_message => {
-                error!(target: "network", "Peer receive_view_client_message received unexpected type: {:?}", peer_message);
+        let msg_hash = match &msg {
+            PeerMessage::Routed(message) => Some(message.hash()),
+            _ => None,
+        };
+        let msg_variant = msg.msg_variant();
+        let view_client_message = match msg.into_view_client_message() {
+            Some(view_client_message) => view_client_message,
+            None => {
+                error!(target: "network", "Peer receive_view_client_message received unexpected type: {}", msg_variant);
                 return;
             }
         };
@@ -389,106 +571,25 @@ impl PeerActor {
             if let Some(peer_id) = self.other_peer_id() { peer_id.clone() } else { return };
 
         // Wrap peer message into what client expects.
-        let network_client_msg = match msg {
+        let was_requested = match &msg {
             PeerMessage::Block(block) => {
                 metrics::PEER_BLOCK_RECEIVED_TOTAL.inc();
                 let block_hash = *block.hash();
                 self.tracker.push_received(block_hash);
                 self.chain_info.height = max(self.chain_info.height, block.header().height());
-                NetworkClientMessages::Block(block, peer_id, self.tracker.has_request(&block_hash))
+                self.tracker.has_request(&block_hash)
             }
-            PeerMessage::Transaction(transaction) => {
+            PeerMessage::Transaction(_) => {
                 metrics::PEER_TRANSACTION_RECEIVED_TOTAL.inc();
-                NetworkClientMessages::Transaction {
-                    transaction,
-                    is_forwarded: false,
-                    check_only: false,
-                }
-            }
-            PeerMessage::BlockHeaders(headers) => {
-                NetworkClientMessages::BlockHeaders(headers, peer_id)
+                false
             }
-            // All Routed messages received at this point are for us.
-            PeerMessage::Routed(routed_message) => {
-                let msg_hash = routed_message.hash();
-
-                match routed_message.body {
-                    RoutedMessageBody::BlockApproval(approval) => {
-                        NetworkClientMessages::BlockApproval(approval, peer_id)
-                    }
-                    RoutedMessageBody::ForwardTx(transaction) => {
-                        NetworkClientMessages::Transaction {
-                            transaction,
-                            is_forwarded: true,
-                            check_only: false,
-                        }
-                    }
-
-                    RoutedMessageBody::StateResponse(info) => {
-                        NetworkClientMessages::StateResponse(StateResponseInfo::V1(info))
-                    }
-                    RoutedMessageBody::VersionedStateResponse(info) => {
-                        NetworkClientMessages::StateResponse(info)
-                    }
-                    RoutedMessageBody::PartialEncodedChunkRequest(request) => {
-                        NetworkClientMessages::PartialEncodedChunkRequest(request, msg_hash)
-                    }
-                    RoutedMessageBody::PartialEncodedChunkResponse(response) => {
-                        NetworkClientMessages::PartialEncodedChunkResponse(response)
-                    }
-                    RoutedMessageBody::PartialEncodedChunk(partial_encoded_chunk) => {
-                        NetworkClientMessages::PartialEncodedChunk(PartialEncodedChunk::V1(
-                            partial_encoded_chunk,
-                        ))
-                    }
-                    RoutedMessageBody::VersionedPartialEncodedChunk(chunk) => {
-                        NetworkClientMessages::PartialEncodedChunk(chunk)
-                    }
-                    RoutedMessageBody::PartialEncodedChunkForward(forward) => {
-                        NetworkClientMessages::PartialEncodedChunkForward(forward)
-                    }
-                    RoutedMessageBody::Ping(_)
-                    | RoutedMessageBody::Pong(_)
-                    | RoutedMessageBody::TxStatusRequest(_, _)
-                    | RoutedMessageBody::TxStatusResponse(_)
-                    | RoutedMessageBody::QueryRequest { .. }
-                    | RoutedMessageBody::QueryResponse { .. }
-                    | RoutedMessageBody::ReceiptOutcomeRequest(_)
-                    | RoutedMessageBody::StateRequestHeader(_, _)
-                    | RoutedMessageBody::StateRequestPart(_, _, _)
-                    | RoutedMessageBody::Unused => {
-                        error!(target: "network", "Peer receive_client_message received unexpected type: {:?}", routed_message);
-                        return;
-                    }
-                }
-            }
-            PeerMessage::Challenge(challenge) => NetworkClientMessages::Challenge(challenge),
-            PeerMessage::EpochSyncResponse(response) => {
-                NetworkClientMessages::EpochSyncResponse(peer_id, response)
-            }
-            PeerMessage::EpochSyncFinalizationResponse(response) => {
-                NetworkClientMessages::EpochSyncFinalizationResponse(peer_id, response)
-            }
-            PeerMessage::Handshake(_)
-            | PeerMessage::_HandshakeV2
-            | PeerMessage::HandshakeFailure(_, _)
-            | PeerMessage::PeersRequest
-            | PeerMessage::PeersResponse(_)
-            | PeerMessage::SyncRoutingTable(_)
-            | PeerMessage::LastEdge(_)
-            | PeerMessage::Disconnect
-            | PeerMessage::RequestUpdateNonce(_)
-            | PeerMessage::ResponseUpdateNonce(_)
-            | PeerMessage::BlockRequest(_)
-            | PeerMessage::BlockHeadersRequest(_)
-            | PeerMessage::EpochSyncRequest(_)
-            | PeerMessage::EpochSyncFinalizationRequest(_) => {
-                error!(target: "network", "Peer receive_client_message received unexpected type: {:?}", msg);
-                return;
-            }
-            #[cfg(feature = "protocol_feature_routing_exchange_algorithm")]
-            PeerMessage::RoutingTableSyncV2(_) => {
-                error!(target: "network", "Peer receive_client_message received unexpected type: {:?}", msg);
+            _ => false,
+        };
+        let msg_variant = msg.msg_variant();
+        let network_client_msg = match msg.into_client_message(peer_id, was_requested) {
+            Some(network_client_msg) => network_client_msg,
+            None => {
+                error!(target: "network", "Peer receive_client_message received unexpected type: {}", msg_variant);
                 return;
             }
         };
@@ -594,7 +695,7 @@ impl Actor for PeerActor {
         // Set Handshake timeout for stopping actor if peer is not ready after given period of time.
 
         near_performance_metrics::actix::run_later(ctx, self.handshake_timeout, move |act, ctx| {
-            if act.peer_status != PeerStatus::Ready {
+            if act.peer_status != PeerStatus::Ready || act.encryption.is_handshaking() {
                 info!(target: "network", "Handshake timeout expired for {}", act.peer_info);
                 ctx.stop();
             }
@@ -607,6 +708,8 @@ impl Actor for PeerActor {
     }
 
     fn stopping(&mut self, _: &mut Self::Context) -> Running {
+        // Whatever is still queued, e.g. `Disconnect`, is written out when the connection is dropped.
+        self.flush_outbound_queues(usize::MAX);
         self.peer_counter.fetch_sub(1, Ordering::SeqCst);
         metrics::PEER_CONNECTIONS_TOTAL.dec();
         debug!(target: "network", "{:?}: Peer {} disconnected. {:?}", self.my_node_info.id, self.peer_info, self.peer_status);
@@ -638,7 +741,14 @@ impl Actor for PeerActor {
     }
 }
 
-impl WriteHandler<io::Error> for PeerActor {}
+impl WriteHandler<io::Error> for PeerActor {
+    /// Moves queued messages to the write buffer once it was written to the socket.
+    fn drained(&mut self, _ctx: &mut Self::Context) {
+        if !self.outbound_queues.is_empty() {
+            self.flush_outbound_queues(OUTBOUND_WRITE_BUFFER_WATERMARK);
+        }
+    }
+}
 
 impl StreamHandler<Result<Vec<u8>, ReasonForBan>> for PeerActor {
     #[perf]
@@ -650,11 +760,28 @@ impl StreamHandler<Result<Vec<u8>, ReasonForBan>> for PeerActor {
                 return;
             }
         };
+        let msg = match self.decrypt_frame(ctx, msg) {
+            Some(msg) => msg,
+            None => return,
+        };
         // TODO(#5155) We should change our code to track size of messages received from Peer
         // as long as it travels to PeerManager, etc.
 
+        let frame_len = msg.len();
+        let msg = match self.compression.decompress(msg) {
+            Ok(msg) => msg,
+            Err(err) => {
+                info!(target: "network", "Failed to decompress message from {}: {}", self.peer_info, err);
+                return;
+            }
+        };
+        // Compressed messages are accounted for by their decompressed size, which is what they
+        // cost to handle. The bandwidth of the frame itself was already reported when read.
         self.update_stats_on_receiving_message(msg.len());
-
+        if msg.len() > frame_len {
+            self.throttle_controller.report_bandwidth_used(msg.len() - frame_len);
+        }
+        self.record_message(MessageDirection::Inbound, &msg);
         if self.should_we_drop_msg_without_decoding(&msg) {
             return;
         }
@@ -806,6 +933,12 @@ impl StreamHandler<Result<Vec<u8>, ReasonForBan>> for PeerActor {
                     account_id: None,
                 };
                 self.chain_info = handshake.sender_chain_info.clone();
+                self.received_handshake = Some(msg.clone());
+                self.compression.set_peer_supports_compression(
+                    handshake.has_capability(CAPABILITY_ZSTD_COMPRESSION),
+                );
+                self.peer_supports_announce_addresses =
+                    handshake.has_capability(CAPABILITY_ANNOUNCE_ADDRESSES);
                 self.peer_manager_addr
                     .send(ActixMessageWrapper::new_without_size(PeerManagerMessageRequest::RegisterPeer(RegisterPeer {
                         actor: ctx.address(),
@@ -827,6 +960,8 @@ impl StreamHandler<Result<Vec<u8>, ReasonForBan>> for PeerActor {
                                 if act.peer_type == PeerType::Inbound {
                                     act.partial_edge_info = edge_info;
                                     act.send_handshake(ctx);
+                                } else {
+                                    act.start_encryption(ctx);
                                 }
                                 actix::fut::ready(())
                             },
@@ -954,6 +1089,17 @@ impl StreamHandler<Result<Vec<u8>, ReasonForBan>> for PeerActor {
                     Some(self.throttle_controller.clone()),
                 ));
             }
+            (PeerStatus::Ready, PeerMessage::SyncAnnounceAddresses(addresses)) => {
+                self.peer_manager_addr.do_send(ActixMessageWrapper::new_without_size(
+                    PeerManagerMessageRequest::NetworkRequests(
+                        NetworkRequests::SyncAnnounceAddresses {
+                            peer_id: self.other_peer_id().unwrap().clone(),
+                            addresses,
+                        },
+                    ),
+                    Some(self.throttle_controller.clone()),
+                ));
+            }
             #[cfg(feature = "protocol_feature_routing_exchange_algorithm")]
             (PeerStatus::Ready, PeerMessage::RoutingTableSyncV2(ibf_message)) => {
                 // TODO(#5155) Add wrapper to be something like this for all messages.
@@ -5,6 +5,7 @@ use crate::private_actix::{
     PeerRequestResult, PeersRequest, RegisterPeer, RegisterPeerResponse, SendMessage, StopMsg,
     Unregister, ValidateEdgeList,
 };
+use crate::recorder::MessageRecorder;
 use crate::routing::edge_validator_actor::EdgeValidatorHelper;
 use crate::routing::routing_table_actor::{
     Prune, RoutingTableActor, RoutingTableMessages, RoutingTableMessagesResponse,
@@ -19,16 +20,18 @@ use crate::types::{
 };
 use actix::{
     Actor, ActorFuture, Addr, Arbiter, AsyncContext, Context, ContextFutureSpawner, Handler,
-    Recipient, Running, StreamHandler, WrapFuture,
+    Recipient, ResponseFuture, Running, StreamHandler, WrapFuture,
 };
 #[cfg(feature = "protocol_feature_routing_exchange_algorithm")]
 use futures::FutureExt;
+use lru::LruCache;
 use near_network_primitives::types::{
-    AccountOrPeerIdOrHash, Ban, BlockedPorts, Edge, InboundTcpConnect, KnownPeerStatus,
-    KnownProducer, NetworkConfig, NetworkViewClientMessages, NetworkViewClientResponses,
-    OutboundTcpConnect, PeerIdOrHash, PeerInfo, PeerManagerRequest, PeerType, Ping, Pong,
+    AccountOrPeerIdOrHash, AnnounceAddress, Ban, BlockedPorts, Edge, GetPeerScores,
+    GetRoutingTableDebugInfo, InboundTcpConnect, KnownPeerState, KnownPeerStatus, KnownProducer,
+    NetworkConfig, NetworkViewClientMessages, NetworkViewClientResponses, OutboundTcpConnect,
+    PeerIdOrHash, PeerInfo, PeerManagerRequest, PeerScore, PeerScoreEvent, PeerType, Ping, Pong,
     QueryPeerStats, RawRoutedMessage, ReasonForBan, RoutedMessage, RoutedMessageBody,
-    RoutedMessageFrom, StateResponseInfo,
+    RoutedMessageFrom, RoutingTableDebugInfo, StateResponseInfo,
 };
 use near_network_primitives::types::{EdgeState, PartialEdgeInfo};
 use near_performance_metrics::framed_write::FramedWrite;
@@ -36,6 +39,7 @@ use near_performance_metrics_macros::perf;
 use near_primitives::checked_feature;
 use near_primitives::hash::CryptoHash;
 use near_primitives::network::{AnnounceAccount, PeerId};
+use near_primitives::sharding::ChunkHash;
 use near_primitives::time::Clock;
 use near_primitives::types::{AccountId, ProtocolVersion};
 use near_primitives::utils::from_timestamp;
@@ -44,7 +48,7 @@ use near_rate_limiter::{
     ThrottleToken,
 };
 use near_store::Store;
-use rand::seq::IteratorRandom;
+use rand::seq::{IteratorRandom, SliceRandom};
 use rand::thread_rng;
 use std::cmp::{max, min};
 use std::collections::{HashMap, HashSet};
@@ -73,6 +77,8 @@ const EXPONENTIAL_BACKOFF_RATIO: f64 = 1.1;
 const MONITOR_PEERS_MAX_DURATION: Duration = Duration::from_millis(60_000);
 /// The initial waiting time between consecutive attempts to establish connection
 const MONITOR_PEERS_INITIAL_DURATION: Duration = Duration::from_millis(10);
+/// Number of validator addresses kept until the account announcement they belong to arrives.
+const PENDING_ANNOUNCE_ADDRESSES_CACHE_SIZE: usize = 1_000;
 /// Limit number of pending Peer actors to avoid OOM.
 const LIMIT_PENDING_PEERS: usize = 60;
 /// How ofter should we broadcast edges.
@@ -85,6 +91,8 @@ const WAIT_FOR_SYNC_DELAY: Duration = Duration::from_millis(1_000);
 const UPDATE_ROUTING_TABLE_INTERVAL: Duration = Duration::from_millis(1_000);
 /// How often to report bandwidth stats.
 const REPORT_BANDWIDTH_STATS_TRIGGER_INTERVAL: Duration = Duration::from_millis(60_000);
+/// How often to save the peer scores which changed.
+const FLUSH_PEER_SCORES_INTERVAL: Duration = Duration::from_millis(60_000);
 
 /// Max number of messages we received from peer, and they are in progress, before we start throttling.
 /// Disabled for now (TODO PUT UNDER FEATURE FLAG)
@@ -96,6 +104,8 @@ const MAX_MESSAGES_TOTAL_SIZE: usize = usize::MAX;
 const REPORT_BANDWIDTH_THRESHOLD_BYTES: usize = 10_000_000;
 /// If we received more than REPORT_BANDWIDTH_THRESHOLD_COUNT` of messages from given peer it's bandwidth stats will be reported.
 const REPORT_BANDWIDTH_THRESHOLD_COUNT: usize = 10_000;
+/// Peers which don't answer a chunk request within this time get their score lowered.
+const CHUNK_REQUEST_TIMEOUT: Duration = Duration::from_millis(3_000);
 
 /// Contains information relevant to a connected peer.
 struct ConnectedPeer {
@@ -186,6 +196,21 @@ pub struct PeerManagerActor {
     txns_since_last_block: Arc<AtomicUsize>,
     /// Number of active peers, used for rate limiting.
     peer_counter: Arc<AtomicUsize>,
+    /// Chunk requests we sent to peers and haven't got a response for yet, with the time of the
+    /// first request. Used to lower the score of peers which don't answer.
+    pending_chunk_requests: HashMap<(PeerId, ChunkHash), Instant>,
+    /// Records the messages exchanged with all peers, if enabled in the config.
+    message_recorder: Option<Arc<MessageRecorder>>,
+    /// Block and chunk producers of the current and next epoch to keep direct connections with,
+    /// as set by the client. Empty unless we are one of them.
+    validator_peers: HashSet<AccountId>,
+    /// Peers owning the accounts of `validator_peers`, according to the account announcements.
+    /// Connections to them use the slots reserved by `max_num_validator_peers`.
+    validator_peer_ids: HashSet<PeerId>,
+    /// Latest address announced for every validator account, matching its `AnnounceAccount`.
+    announce_addresses: HashMap<AccountId, AnnounceAddress>,
+    /// Addresses received before the account announcement they belong to.
+    pending_announce_addresses: LruCache<(AccountId, PeerId), AnnounceAddress>,
     /// Used for testing, for disabling features.
     adv_helper: AdvHelper,
 }
@@ -245,6 +270,9 @@ impl Actor for PeerManagerActor {
 
         // Periodically prints bandwidth stats for each peer.
         self.report_bandwidth_stats_trigger(ctx, REPORT_BANDWIDTH_STATS_TRIGGER_INTERVAL);
+
+        // Periodically saves the peer scores.
+        self.flush_peer_scores_trigger(ctx, FLUSH_PEER_SCORES_INTERVAL);
     }
 
     /// Try to gracefully disconnect from connected peers.
@@ -257,6 +285,8 @@ impl Actor for PeerManagerActor {
 
         self.routing_table_addr.do_send(StopMsg {});
 
+        self.flush_peer_scores();
+
         Running::Stop
     }
 }
@@ -269,8 +299,8 @@ impl PeerManagerActor {
         view_client_addr: Recipient<NetworkViewClientMessages>,
         routing_table_addr: Addr<RoutingTableActor>,
     ) -> Result<Self, Box<dyn std::error::Error>> {
-        let peer_store = PeerStore::new(store.clone(), &config.boot_nodes)?;
-        debug!(target: "network", len = peer_store.len(), boot_nodes = config.boot_nodes.len(), "Found known peers");
+        let peer_store = PeerStore::new(store.clone(), &config.boot_nodes, &config.trusted_peers)?;
+        debug!(target: "network", len = peer_store.len(), boot_nodes = config.boot_nodes.len(), trusted_peers = config.trusted_peers.len(), "Found known peers");
         debug!(target: "network", blacklist = ?config.blacklist, "Blacklist");
 
         let my_peer_id: PeerId = PeerId::new(config.public_key.clone());
@@ -278,6 +308,14 @@ impl PeerManagerActor {
 
         let txns_since_last_block = Arc::new(AtomicUsize::new(0));
 
+        let message_recorder = match &config.message_recorder {
+            Some(recorder_config) => {
+                info!(target: "network", dir = ?recorder_config.dir, "Recording peer messages");
+                Some(Arc::new(MessageRecorder::new(recorder_config.clone())?))
+            }
+            None => None,
+        };
+
         Ok(Self {
             my_peer_id,
             config,
@@ -294,6 +332,12 @@ impl PeerManagerActor {
             routing_table_addr,
             txns_since_last_block,
             peer_counter: Arc::new(AtomicUsize::new(0)),
+            pending_chunk_requests: HashMap::new(),
+            message_recorder,
+            validator_peers: HashSet::new(),
+            validator_peer_ids: HashSet::new(),
+            announce_addresses: HashMap::new(),
+            pending_announce_addresses: LruCache::new(PENDING_ANNOUNCE_ADDRESSES_CACHE_SIZE),
             adv_helper: AdvHelper::default(),
         })
     }
@@ -356,13 +400,68 @@ impl PeerManagerActor {
         for account in accounts.iter() {
             self.routing_table_view.add_account(account.clone());
         }
+        let addresses = (accounts.iter())
+            .filter_map(|account| {
+                self.pending_announce_addresses
+                    .pop(&(account.account_id.clone(), account.peer_id.clone()))
+            })
+            .collect();
 
         Self::broadcast_message(
             &self.connected_peers,
             SendMessage {
                 message: PeerMessage::SyncRoutingTable(RoutingTableUpdate::from_accounts(accounts)),
             },
-        )
+        );
+        self.add_announce_addresses(addresses);
+        self.update_validator_peer_ids();
+    }
+
+    /// Stores the addresses which are newer than the ones we know and belong to the peer their
+    /// account is announced at, and gossips them to our peers. Addresses of accounts we don't
+    /// know yet are kept until their announcement arrives.
+    fn add_announce_addresses(&mut self, addresses: Vec<AnnounceAddress>) {
+        let mut new_addresses = vec![];
+        for announce in addresses {
+            if (self.announce_addresses.get(&announce.account_id))
+                .map_or(false, |known| known.nonce >= announce.nonce)
+            {
+                continue;
+            }
+            match self.routing_table_view.account_owner(&announce.account_id) {
+                Ok(peer_id) if peer_id == announce.peer_id => {
+                    self.announce_addresses.insert(announce.account_id.clone(), announce.clone());
+                    new_addresses.push(announce);
+                }
+                _ => {
+                    let key = (announce.account_id.clone(), announce.peer_id.clone());
+                    self.pending_announce_addresses.put(key, announce);
+                }
+            }
+        }
+        if new_addresses.is_empty() {
+            return;
+        }
+        debug!(target: "network", addresses = ?new_addresses, "Received new validator addresses");
+        Self::broadcast_message(
+            &self.connected_peers,
+            SendMessage { message: PeerMessage::SyncAnnounceAddresses(new_addresses) },
+        );
+    }
+
+    /// Recomputes the peers owning the accounts of `validator_peers`, and which of them we are
+    /// directly connected to.
+    fn update_validator_peer_ids(&mut self) {
+        self.validator_peer_ids.clear();
+        for account_id in self.validator_peers.iter() {
+            if let Ok(peer_id) = self.routing_table_view.account_owner(account_id) {
+                self.validator_peer_ids.insert(peer_id);
+            }
+        }
+        self.routing_table_view.direct_validator_peers = (self.connected_peers.keys())
+            .filter(|peer_id| self.validator_peer_ids.contains(peer_id))
+            .cloned()
+            .collect();
     }
 
     /// `update_routing_table_trigger` schedule updating routing table to `RoutingTableActor`
@@ -604,6 +703,9 @@ impl PeerManagerActor {
                 throttle_controller: throttle_controller.clone(),
             },
         );
+        if self.validator_peer_ids.contains(&target_peer_id) {
+            self.routing_table_view.direct_validator_peers.insert(target_peer_id.clone());
+        }
 
         self.add_verified_edges_to_routing_table(vec![new_edge.clone()]);
 
@@ -676,6 +778,13 @@ impl PeerManagerActor {
                     known_accounts.cloned().collect(),
                 )),
             });
+            if !act.announce_addresses.is_empty() {
+                addr.do_send(SendMessage {
+                    message: PeerMessage::SyncAnnounceAddresses(
+                        act.announce_addresses.values().cloned().collect(),
+                    ),
+                });
+            }
 
             // Ask for peers list on connection.
             addr.do_send(SendMessage { message: PeerMessage::PeersRequest });
@@ -714,6 +823,7 @@ impl PeerManagerActor {
         // If the last edge we have with this peer represent a connection addition, create the edge
         // update that represents the connection removal.
         self.connected_peers.remove(peer_id);
+        self.routing_table_view.direct_validator_peers.remove(peer_id);
 
         #[cfg(feature = "protocol_feature_routing_exchange_algorithm")]
         self.routing_table_addr.do_send(RoutingTableMessages::RemovePeer(peer_id.clone()));
@@ -798,6 +908,8 @@ impl PeerManagerActor {
         let handshake_timeout = self.config.handshake_timeout;
         let client_addr = self.client_addr.clone();
         let view_client_addr = self.view_client_addr.clone();
+        let node_key = self.config.secret_key.clone();
+        let message_recorder = self.message_recorder.clone();
 
         let server_addr = match server_addr {
             Some(server_addr) => server_addr,
@@ -859,6 +971,8 @@ impl PeerManagerActor {
                 txns_since_last_block,
                 peer_counter,
                 rate_limiter,
+                node_key,
+                message_recorder,
             )
         });
     }
@@ -868,11 +982,16 @@ impl PeerManagerActor {
     /// (the number of outgoing connections is less than `minimum_outbound_peers`
     ///     and the total connections is less than `max_num_peers`)
     fn is_outbound_bootstrap_needed(&self) -> bool {
-        let total_connections = self.connected_peers.len() + self.outgoing_peers.len();
-        let potential_outgoing_connections = (self.connected_peers.values())
-            .filter(|connected_peer| connected_peer.peer_type == PeerType::Outbound)
+        let total_connections = self.num_regular_connections();
+        let potential_outgoing_connections = (self.connected_peers.iter())
+            .filter(|(peer_id, connected_peer)| {
+                connected_peer.peer_type == PeerType::Outbound
+                    && !self.validator_peer_ids.contains(peer_id)
+            })
             .count()
-            + self.outgoing_peers.len();
+            + (self.outgoing_peers.iter())
+                .filter(|peer_id| !self.validator_peer_ids.contains(peer_id))
+                .count();
 
         (total_connections < self.config.ideal_connections_lo as usize
             || (total_connections < self.config.max_num_peers as usize
@@ -881,7 +1000,56 @@ impl PeerManagerActor {
     }
 
     fn is_inbound_allowed(&self) -> bool {
-        self.connected_peers.len() + self.outgoing_peers.len() < self.config.max_num_peers as usize
+        self.num_regular_connections() < self.config.max_num_peers as usize
+    }
+
+    /// Number of connections, established or in progress, to the other validators we keep direct
+    /// connections with.
+    fn num_validator_connections(&self) -> usize {
+        (self.connected_peers.keys().chain(self.outgoing_peers.iter()))
+            .filter(|peer_id| self.validator_peer_ids.contains(peer_id))
+            .count()
+    }
+
+    /// Number of connections, established or in progress, counting towards `max_num_peers`.
+    fn num_regular_connections(&self) -> usize {
+        self.connected_peers.len() + self.outgoing_peers.len() - self.num_validator_connections()
+    }
+
+    /// Returns validators we want a direct connection with, whose address we know, and which we
+    /// are neither connected nor connecting to. Limited to the free validator connection slots.
+    fn unconnected_validator_peers(&self) -> Vec<PeerInfo> {
+        let free_slots = (self.config.max_num_validator_peers as usize)
+            .saturating_sub(self.num_validator_connections());
+        (self.announce_addresses.values())
+            .filter(|announce| {
+                self.validator_peers.contains(&announce.account_id)
+                    && self.validator_peer_ids.contains(&announce.peer_id)
+                    && announce.peer_id != self.my_peer_id
+                    && !self.connected_peers.contains_key(&announce.peer_id)
+                    && !self.outgoing_peers.contains(&announce.peer_id)
+                    && !self.peer_store.is_banned(&announce.peer_id)
+            })
+            .take(free_slots)
+            .map(|announce| PeerInfo {
+                id: announce.peer_id.clone(),
+                addr: Some(announce.addr),
+                account_id: Some(announce.account_id.clone()),
+            })
+            .collect()
+    }
+
+    /// Returns trusted peers with a known address we are neither connected nor connecting to.
+    fn unconnected_trusted_peers(&self) -> Vec<PeerInfo> {
+        (self.config.trusted_peers.iter())
+            .filter(|peer_info| {
+                peer_info.addr.is_some()
+                    && !self.connected_peers.contains_key(&peer_info.id)
+                    && !self.outgoing_peers.contains(&peer_info.id)
+                    && !self.peer_store.is_banned(&peer_info.id)
+            })
+            .cloned()
+            .collect()
     }
 
     /// Returns single random peer with close to the highest height
@@ -895,14 +1063,81 @@ impl PeerManagerActor {
             None => return vec![],
         };
         // Find all peers whose height is within `highest_peer_horizon` from max height peer(s).
-        self.connected_peers
+        let highest_height_peers = self
+            .connected_peers
             .values()
             .filter(|cp| {
                 cp.full_peer_info.chain_info.height.saturating_add(self.config.highest_peer_horizon)
                     >= max_height
             })
             .map(|cp| cp.full_peer_info.clone())
-            .collect::<Vec<_>>()
+            .collect::<Vec<_>>();
+        // Sync requests are sent to these peers, so leave out the ones with a low score unless
+        // they are all we have.
+        let (good_peers, low_score_peers): (Vec<_>, Vec<_>) =
+            highest_height_peers.into_iter().partition(|peer_info| {
+                self.peer_store.score(&peer_info.peer_info.id) >= PeerScore::LOW
+            });
+        if good_peers.is_empty() {
+            low_score_peers
+        } else {
+            good_peers
+        }
+    }
+
+    /// Picks a random peer, favoring peers with a higher score and avoiding peers with a low
+    /// score if there are others.
+    fn choose_peer_by_score<'a>(&self, peers: &'a [PeerId]) -> Option<&'a PeerId> {
+        let scores: Vec<i32> = peers.iter().map(|peer_id| self.peer_store.score(peer_id)).collect();
+        let has_good_peers = scores.iter().any(|score| *score >= PeerScore::LOW);
+        let candidates: Vec<(&PeerId, i32)> = peers
+            .iter()
+            .zip(scores)
+            .filter(|(_, score)| !has_good_peers || *score >= PeerScore::LOW)
+            .collect();
+        candidates
+            .choose_weighted(&mut thread_rng(), |(_, score)| (score - PeerScore::MIN + 1) as u32)
+            .ok()
+            .map(|(peer_id, _)| *peer_id)
+    }
+
+    fn flush_peer_scores(&mut self) {
+        if let Err(err) = self.peer_store.flush_scores() {
+            error!(target: "network", ?err, "Failed to save peer scores");
+        }
+    }
+
+    /// Periodically saves the peer scores which changed.
+    fn flush_peer_scores_trigger(&mut self, ctx: &mut Context<Self>, every: Duration) {
+        self.flush_peer_scores();
+
+        near_performance_metrics::actix::run_later(ctx, every, move |act, ctx| {
+            act.flush_peer_scores_trigger(ctx, every);
+        });
+    }
+
+    fn update_peer_score(&mut self, peer_id: &PeerId, event: PeerScoreEvent) {
+        trace!(target: "network", ?peer_id, ?event, "Updating peer score");
+        if let Err(err) = self.peer_store.update_score(peer_id, event) {
+            debug!(target: "network", ?peer_id, ?err, "Failed to update peer score");
+        }
+    }
+
+    /// Lowers the score of peers which didn't answer our chunk requests in time.
+    fn check_pending_chunk_requests(&mut self) {
+        let now = Clock::instant();
+        let mut timed_out = vec![];
+        self.pending_chunk_requests.retain(|(peer_id, _), requested| {
+            if now - *requested > CHUNK_REQUEST_TIMEOUT {
+                timed_out.push(peer_id.clone());
+                false
+            } else {
+                true
+            }
+        });
+        for peer_id in timed_out {
+            self.update_peer_score(&peer_id, PeerScoreEvent::RequestTimedOut);
+        }
     }
 
     /// Query current peers for more peers.
@@ -1127,9 +1362,13 @@ impl PeerManagerActor {
             safe_set.insert(peer_id);
         }
 
-        // Build valid candidate list to choose the peer to be removed. All peers outside the safe set.
+        // Build valid candidate list to choose the peer to be removed. All peers outside the safe
+        // set, except trusted peers and validators which we never disconnect from.
         let candidates = self.connected_peers.keys().filter_map(|peer_id| {
-            if safe_set.contains(peer_id) {
+            if safe_set.contains(peer_id)
+                || self.config.is_trusted(peer_id)
+                || self.validator_peer_ids.contains(peer_id)
+            {
                 None
             } else {
                 Some(peer_id)
@@ -1146,8 +1385,11 @@ impl PeerManagerActor {
 
     /// Periodically monitor list of peers and:
     ///  - request new peers from connected peers,
+    ///  - reconnect to trusted peers,
+    ///  - connect directly to the other validators,
     ///  - bootstrap outbound connections from known peers,
     ///  - unban peers that have been banned for awhile,
+    ///  - penalize peers which didn't answer chunk requests in time,
     ///  - remove expired peers,
     ///
     /// # Arguments:
@@ -1180,7 +1422,30 @@ impl PeerManagerActor {
             }
         }
 
-        if self.is_outbound_bootstrap_needed() {
+        // Trusted peers are connected to regardless of the number of connections we have.
+        if !self.config.outbound_disabled {
+            for peer_info in self.unconnected_trusted_peers() {
+                debug!(target: "network", ?peer_info, "Connecting to trusted peer");
+                self.outgoing_peers.insert(peer_info.id.clone());
+                ctx.notify(PeerManagerMessageRequest::OutboundTcpConnect(OutboundTcpConnect {
+                    peer_info,
+                }));
+            }
+        }
+
+        // Validators are connected to using their own slots, on top of `max_num_peers`.
+        if !self.config.outbound_disabled && !self.config.whitelist_only {
+            for peer_info in self.unconnected_validator_peers() {
+                debug!(target: "network", ?peer_info, "Connecting to validator");
+                self.outgoing_peers.insert(peer_info.id.clone());
+                ctx.notify(PeerManagerMessageRequest::OutboundTcpConnect(OutboundTcpConnect {
+                    peer_info,
+                }));
+            }
+        }
+
+        // In whitelist only mode we don't look for peers other than the trusted ones.
+        if !self.config.whitelist_only && self.is_outbound_bootstrap_needed() {
             if let Some(peer_info) = self.peer_store.unconnected_peer(|peer_state| {
                 // Ignore connecting to ourself
                 self.my_peer_id == peer_state.peer_info.id
@@ -1204,10 +1469,15 @@ impl PeerManagerActor {
         }
 
         // If there are too many active connections try to remove some connections
-        if self.connected_peers.len() > self.config.ideal_connections_hi as usize {
+        let num_regular_connected_peers = (self.connected_peers.keys())
+            .filter(|peer_id| !self.validator_peer_ids.contains(peer_id))
+            .count();
+        if num_regular_connected_peers > self.config.ideal_connections_hi as usize {
             self.try_stop_active_connection();
         }
 
+        self.check_pending_chunk_requests();
+
         if let Err(err) = self.peer_store.remove_expired(&self.config) {
             error!(target: "network", ?err, "Failed to remove expired peers");
         };
@@ -1593,12 +1863,32 @@ impl PeerManagerActor {
                 self.try_ban_peer(&peer_id, ban_reason);
                 NetworkResponses::NoResponse
             }
+            NetworkRequests::UpdatePeerScore { peer_id, event } => {
+                self.update_peer_score(&peer_id, event);
+                NetworkResponses::NoResponse
+            }
             NetworkRequests::AnnounceAccount(announce_account) => {
+                let account_id = announce_account.account_id.clone();
                 self.announce_account(announce_account);
+                // Let the other validators know where to connect to us directly.
+                if let Some(addr) = self.config.announced_addr() {
+                    let nonce = Clock::utc().timestamp_millis() as u64;
+                    self.add_announce_addresses(vec![AnnounceAddress::new(
+                        account_id,
+                        addr,
+                        nonce,
+                        &self.config.secret_key,
+                    )]);
+                }
+                NetworkResponses::NoResponse
+            }
+            NetworkRequests::SetValidatorPeers { account_ids } => {
+                self.validator_peers = account_ids.into_iter().collect();
+                self.update_validator_peer_ids();
                 NetworkResponses::NoResponse
             }
             NetworkRequests::PartialEncodedChunkRequest { target, request } => {
-                let mut success = false;
+                let mut requested_from = None;
 
                 // Make two attempts to send the message. First following the preference of `prefer_peer`,
                 // and if it fails, against the preference.
@@ -1609,7 +1899,8 @@ impl PeerManagerActor {
                                 account_id,
                                 RoutedMessageBody::PartialEncodedChunkRequest(request.clone()),
                             ) {
-                                success = true;
+                                requested_from =
+                                    self.routing_table_view.account_owner(account_id).ok();
                                 break;
                             }
                         }
@@ -1630,7 +1921,8 @@ impl PeerManagerActor {
                             }
                         }
 
-                        if let Some(matching_peer) = matching_peers.iter().choose(&mut thread_rng())
+                        if let Some(matching_peer) =
+                            self.choose_peer_by_score(&matching_peers).cloned()
                         {
                             if self.send_message_to_peer(RawRoutedMessage {
                                 target: AccountOrPeerIdOrHash::PeerId(matching_peer.clone()),
@@ -1638,14 +1930,18 @@ impl PeerManagerActor {
                                     request.clone(),
                                 ),
                             }) {
-                                success = true;
+                                requested_from = Some(matching_peer);
                                 break;
                             }
                         }
                     }
                 }
 
-                if success {
+                if let Some(peer_id) = requested_from {
+                    // Keep the time of the first request, retries shouldn't reset the timeout.
+                    self.pending_chunk_requests
+                        .entry((peer_id, request.chunk_hash))
+                        .or_insert_with(Clock::instant);
                     NetworkResponses::NoResponse
                 } else {
                     NetworkResponses::RouteNotFound
@@ -1719,6 +2015,14 @@ impl PeerManagerActor {
             NetworkRequests::FetchRoutingTable => {
                 NetworkResponses::RoutingTableInfo(self.routing_table_view.info())
             }
+            NetworkRequests::SyncAnnounceAddresses { peer_id, addresses } => {
+                if addresses.iter().all(|announce| announce.verify()) {
+                    self.add_announce_addresses(addresses);
+                } else {
+                    self.try_ban_peer(&peer_id, ReasonForBan::InvalidSignature);
+                }
+                NetworkResponses::NoResponse
+            }
             NetworkRequests::SyncRoutingTable { peer_id, routing_table_update } => {
                 // Process edges and add new edges to the routing table. Also broadcast new edges.
                 let edges = routing_table_update.edges;
@@ -1999,6 +2303,12 @@ impl PeerManagerActor {
             return RegisterPeerResponse::Reject;
         }
 
+        let is_trusted = self.config.is_trusted(&msg.peer_info.id);
+        if self.config.whitelist_only && !is_trusted {
+            debug!(target: "network", id = ?msg.peer_info.id, "Dropping connection from peer which is not trusted (whitelist only mode)");
+            return RegisterPeerResponse::Reject;
+        }
+
         // We already connected to this peer.
         if self.connected_peers.contains_key(&msg.peer_info.id) {
             debug!(target: "network", peer_info = ?self.my_peer_id, id = ?msg.peer_info.id, "Dropping handshake (Active Peer).");
@@ -2015,7 +2325,15 @@ impl PeerManagerActor {
             }
         }
 
-        if msg.peer_type == PeerType::Inbound && !self.is_inbound_allowed() {
+        // Trusted peers are accepted even if we are at max capacity, validators as long as there
+        // are free validator slots.
+        let is_validator_slot_free = self.validator_peer_ids.contains(&msg.peer_info.id)
+            && self.num_validator_connections() < self.config.max_num_validator_peers as usize;
+        if msg.peer_type == PeerType::Inbound
+            && !is_trusted
+            && !is_validator_slot_free
+            && !self.is_inbound_allowed()
+        {
             // TODO(1896): Gracefully drop inbound connection for other peer.
             debug!(target: "network",
                 connected_peers = self.connected_peers.len(), outgoing_peers = self.outgoing_peers.len(),
@@ -2195,6 +2513,11 @@ impl PeerManagerActor {
             match &msg.body {
                 RoutedMessageBody::Ping(ping) => self.handle_ping(ping.clone(), msg.hash()),
                 RoutedMessageBody::Pong(pong) => self.handle_pong(pong.clone()),
+                RoutedMessageBody::PartialEncodedChunkResponse(response) => {
+                    self.pending_chunk_requests
+                        .remove(&(msg.author.clone(), response.chunk_hash.clone()));
+                    return true;
+                }
                 _ => return true,
             }
 
@@ -2309,3 +2632,149 @@ impl Handler<PeerManagerMessageRequest> for PeerManagerActor {
         self.handle_peer_manager_message(msg, ctx, None)
     }
 }
+
+impl Handler<GetPeerScores> for PeerManagerActor {
+    type Result = Vec<KnownPeerState>;
+
+    fn handle(&mut self, _msg: GetPeerScores, _ctx: &mut Self::Context) -> Self::Result {
+        let mut peers: Vec<_> = self.peer_store.iter().map(|(_, state)| state.clone()).collect();
+        let now = Clock::utc();
+        peers.sort_by_key(|peer| std::cmp::Reverse(peer.score.value(now)));
+        peers
+    }
+}
+
+impl Handler<GetRoutingTableDebugInfo> for PeerManagerActor {
+    type Result = ResponseFuture<Result<RoutingTableDebugInfo, String>>;
+
+    fn handle(&mut self, _msg: GetRoutingTableDebugInfo, _ctx: &mut Self::Context) -> Self::Result {
+        let my_peer_id = self.my_peer_id.clone();
+        let next_hops = self.routing_table_view.peer_forwarding.as_ref().clone();
+        let account_announcements = self.routing_table_view.load_announce_accounts();
+        // The full edge set is only kept by `RoutingTableActor`.
+        let request = self.routing_table_addr.send(ActixMessageWrapper::new_without_size(
+            RoutingTableMessages::RequestRoutingTable,
+            None,
+        ));
+        Box::pin(async move {
+            match request.await.map(|response| response.into_inner()) {
+                Ok(RoutingTableMessagesResponse::RequestRoutingTableResponse { edges_info }) => {
+                    Ok(RoutingTableDebugInfo {
+                        my_peer_id,
+                        edges: edges_info.iter().map(Edge::to_simple_edge).collect(),
+                        next_hops,
+                        account_announcements,
+                    })
+                }
+                Ok(_) => Err("expected RequestRoutingTableResponse".to_string()),
+                Err(err) => Err(err.to_string()),
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use actix::actors::mocker::Mocker;
+    use actix::System;
+    use near_crypto::{KeyType, SecretKey};
+    use near_store::test_utils::create_test_store;
+
+    use crate::routing::routing_table_actor::start_routing_table_actor;
+    use crate::types::NetworkClientResponses;
+
+    use super::*;
+
+    fn make_peer_manager(config: NetworkConfig) -> PeerManagerActor {
+        let store = create_test_store();
+        let client_addr = Mocker::<NetworkClientMessages>::mock(Box::new(|_msg, _ctx| {
+            Box::new(Some(NetworkClientResponses::NoResponse))
+        }))
+        .start();
+        let view_client_addr = Mocker::<NetworkViewClientMessages>::mock(Box::new(|_msg, _ctx| {
+            Box::new(Some(NetworkViewClientResponses::NoResponse))
+        }))
+        .start();
+        let routing_table_addr =
+            start_routing_table_actor(PeerId::new(config.public_key.clone()), store.clone());
+        PeerManagerActor::new(
+            store,
+            config,
+            client_addr.recipient(),
+            view_client_addr.recipient(),
+            routing_table_addr,
+        )
+        .unwrap()
+    }
+
+    /// Makes `account_id` a validator we want a direct connection with, which announced its
+    /// address. The key of its peer is derived from the account id.
+    fn add_validator(peer_manager: &mut PeerManagerActor, account_id: &str, port: u16) -> PeerId {
+        let account_id: AccountId = account_id.parse().unwrap();
+        let secret_key = SecretKey::from_seed(KeyType::ED25519, account_id.as_ref());
+        let addr = SocketAddr::from(([127, 0, 0, 1], port));
+        let announce = AnnounceAddress::new(account_id.clone(), addr, 1, &secret_key);
+        let peer_id = announce.peer_id.clone();
+        peer_manager.validator_peers.insert(account_id.clone());
+        peer_manager.validator_peer_ids.insert(peer_id.clone());
+        peer_manager.announce_addresses.insert(account_id, announce);
+        peer_id
+    }
+
+    #[test]
+    fn test_validator_connections_dont_count_towards_max_num_peers() {
+        System::new().block_on(async {
+            let mut config = NetworkConfig::from_seed("test", 0);
+            config.max_num_peers = 2;
+            let mut peer_manager = make_peer_manager(config);
+            let validator = add_validator(&mut peer_manager, "validator0", 1);
+
+            peer_manager.outgoing_peers.insert(validator);
+            peer_manager.outgoing_peers.insert(PeerId::random());
+            assert_eq!(peer_manager.num_validator_connections(), 1);
+            assert_eq!(peer_manager.num_regular_connections(), 1);
+            assert!(peer_manager.is_inbound_allowed());
+
+            peer_manager.outgoing_peers.insert(PeerId::random());
+            assert_eq!(peer_manager.num_regular_connections(), 2);
+            assert!(!peer_manager.is_inbound_allowed());
+        });
+    }
+
+    #[test]
+    fn test_unconnected_validator_peers() {
+        System::new().block_on(async {
+            let mut config = NetworkConfig::from_seed("test", 0);
+            config.max_num_validator_peers = 2;
+            let mut peer_manager = make_peer_manager(config);
+            let validator0 = add_validator(&mut peer_manager, "validator0", 1);
+            let validator1 = add_validator(&mut peer_manager, "validator1", 2);
+            // Our own announcement and the ones of accounts which aren't validators are skipped.
+            add_validator(&mut peer_manager, "test", 3);
+            add_validator(&mut peer_manager, "other", 4);
+            peer_manager.validator_peers.remove(&"other".parse::<AccountId>().unwrap());
+
+            let mut peer_ids: Vec<PeerId> = peer_manager
+                .unconnected_validator_peers()
+                .into_iter()
+                .map(|info| info.id)
+                .collect();
+            peer_ids.sort();
+            let mut expected = vec![validator0.clone(), validator1.clone()];
+            expected.sort();
+            assert_eq!(peer_ids, expected);
+
+            peer_manager.outgoing_peers.insert(validator0);
+            let unconnected = peer_manager.unconnected_validator_peers();
+            assert_eq!(unconnected.len(), 1);
+            assert_eq!(unconnected[0].id, validator1);
+            assert_eq!(unconnected[0].account_id, Some("validator1".parse().unwrap()));
+            assert_eq!(unconnected[0].addr, Some(SocketAddr::from(([127, 0, 0, 1], 2))));
+
+            // No free slot is left once connected to `max_num_validator_peers` validators.
+            let validator2 = add_validator(&mut peer_manager, "validator2", 5);
+            peer_manager.outgoing_peers.insert(validator2);
+            assert!(peer_manager.unconnected_validator_peers().is_empty());
+        });
+    }
+}
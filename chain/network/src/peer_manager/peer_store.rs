@@ -1,6 +1,6 @@
 use borsh::{BorshDeserialize, BorshSerialize};
 use near_network_primitives::types::{
-    KnownPeerState, KnownPeerStatus, NetworkConfig, PeerInfo, ReasonForBan,
+    KnownPeerState, KnownPeerStatus, NetworkConfig, PeerInfo, PeerScoreEvent, ReasonForBan,
 };
 use near_primitives::network::PeerId;
 use near_primitives::time::Utc;
@@ -9,7 +9,7 @@ use near_store::{ColPeers, Store};
 use rand::seq::IteratorRandom;
 use rand::thread_rng;
 use std::collections::hash_map::{Entry, Iter};
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::error::Error;
 use std::net::SocketAddr;
 use std::ops::Not;
@@ -49,23 +49,30 @@ pub struct PeerStore {
     // It can happens that some peers don't have known address, so
     // they will not be present in this list, otherwise they will be present.
     addr_peers: HashMap<SocketAddr, VerifiedPeer>,
+    // Peers from `NetworkConfig::trusted_peers`. Their addresses are never shared with other
+    // peers, and they are never removed.
+    trusted_peers: HashSet<PeerId>,
+    // Peers whose score changed since it was last saved. Scores change on every response, so
+    // they are saved in batches by `flush_scores` rather than on every change.
+    unsaved_scores: HashSet<PeerId>,
 }
 
 impl PeerStore {
     pub(crate) fn new(
         store: Store,
         boot_nodes: &[PeerInfo],
+        trusted_peers: &[PeerInfo],
     ) -> Result<Self, Box<dyn std::error::Error>> {
         let mut peer_states = HashMap::default();
         let mut addr_peers = HashMap::default();
 
-        for peer_info in boot_nodes.iter() {
+        for peer_info in boot_nodes.iter().chain(trusted_peers.iter()) {
             if !peer_states.contains_key(&peer_info.id) {
                 if let Some(peer_addr) = peer_info.addr {
                     match addr_peers.entry(peer_addr) {
                         Entry::Occupied(entry) => {
                             // There is already a different peer_id with this address.
-                            error!(target: "network", "Two boot nodes have the same address {:?}", entry.key());
+                            error!(target: "network", "Two boot nodes or trusted peers have the same address {:?}", entry.key());
                         }
                         Entry::Vacant(entry) => {
                             entry.insert(VerifiedPeer::signed(peer_info.id.clone()));
@@ -91,10 +98,11 @@ impl PeerStore {
             };
 
             if let Some(current_peer_state) = peer_states.get_mut(&peer_id) {
-                // This peer is a boot node and was already added so skip.
+                // This peer is a boot node or a trusted peer and was already added so skip.
                 if peer_state.status.is_banned() {
                     current_peer_state.status = peer_state.status;
                 }
+                current_peer_state.score = peer_state.score;
                 continue;
             }
 
@@ -105,7 +113,14 @@ impl PeerStore {
                 }
             }
         }
-        Ok(PeerStore { store, peer_states, addr_peers })
+        let trusted_peers = trusted_peers.iter().map(|peer_info| peer_info.id.clone()).collect();
+        Ok(PeerStore {
+            store,
+            peer_states,
+            addr_peers,
+            trusted_peers,
+            unsaved_scores: HashSet::default(),
+        })
     }
 
     pub(crate) fn len(&self) -> usize {
@@ -178,6 +193,41 @@ impl PeerStore {
         }
     }
 
+    /// Updates the score of a peer after it answered one of our requests, or failed to.
+    /// The score is only saved by the next `flush_scores`.
+    pub(crate) fn update_score(
+        &mut self,
+        peer_id: &PeerId,
+        event: PeerScoreEvent,
+    ) -> Result<(), Box<dyn Error>> {
+        if let Some(peer_state) = self.peer_states.get_mut(peer_id) {
+            peer_state.score.update(event, Utc::now());
+            self.unsaved_scores.insert(peer_id.clone());
+            Ok(())
+        } else {
+            Err(format!("Peer {} is missing in the peer store", peer_id).into())
+        }
+    }
+
+    /// Saves the peers whose score changed since the last flush.
+    pub(crate) fn flush_scores(&mut self) -> Result<(), Box<dyn Error>> {
+        if self.unsaved_scores.is_empty() {
+            return Ok(());
+        }
+        let mut store_update = self.store.store_update();
+        for peer_id in self.unsaved_scores.drain() {
+            if let Some(peer_state) = self.peer_states.get(&peer_id) {
+                store_update.set_ser(ColPeers, &peer_id.try_to_vec()?, peer_state)?;
+            }
+        }
+        store_update.commit().map_err(|err| err.into())
+    }
+
+    /// Returns the current score of a peer, zero for unknown peers.
+    pub(crate) fn score(&self, peer_id: &PeerId) -> i32 {
+        self.peer_states.get(peer_id).map_or(0, |peer_state| peer_state.score.value(Utc::now()))
+    }
+
     /// Find a random subset of peers based on filter.
     fn find_peers<F>(&self, filter: F, count: usize) -> Vec<PeerInfo>
     where
@@ -192,26 +242,35 @@ impl PeerStore {
     }
 
     /// Return unconnected or peers with unknown status that we can try to connect to.
-    /// Peers with unknown addresses are filtered out.
+    /// Peers with unknown addresses are filtered out. Peers with a low score are only returned
+    /// if there is no other candidate.
     pub(crate) fn unconnected_peer(
         &self,
         ignore_fn: impl Fn(&KnownPeerState) -> bool,
     ) -> Option<PeerInfo> {
-        self.find_peers(
-            |p| {
-                (p.status == KnownPeerStatus::NotConnected || p.status == KnownPeerStatus::Unknown)
-                    && !ignore_fn(p)
-                    && p.peer_info.addr.is_some()
-            },
-            1,
-        )
-        .get(0)
-        .cloned()
+        let now = Utc::now();
+        let is_candidate = |p: &KnownPeerState| {
+            (p.status == KnownPeerStatus::NotConnected || p.status == KnownPeerStatus::Unknown)
+                && !ignore_fn(p)
+                && p.peer_info.addr.is_some()
+        };
+        self.find_peers(|p| is_candidate(p) && !p.score.is_low(now), 1)
+            .pop()
+            .or_else(|| self.find_peers(|p| is_candidate(p), 1).pop())
     }
 
     /// Return healthy known peers up to given amount.
+    /// Trusted peers are never returned, so their addresses are not shared with other peers.
     pub(crate) fn healthy_peers(&self, max_count: usize) -> Vec<PeerInfo> {
-        self.find_peers(|p| matches!(p.status, KnownPeerStatus::Banned(_, _)).not(), max_count)
+        let now = Utc::now();
+        self.find_peers(
+            |p| {
+                matches!(p.status, KnownPeerStatus::Banned(_, _)).not()
+                    && !p.score.is_low(now)
+                    && !self.trusted_peers.contains(&p.peer_info.id)
+            },
+            max_count,
+        )
     }
 
     /// Return iterator over all known peers.
@@ -229,6 +288,7 @@ impl PeerStore {
         for (peer_id, peer_status) in self.peer_states.iter() {
             let diff = (now - peer_status.last_seen()).to_std()?;
             if peer_status.status != KnownPeerStatus::Connected
+                && !self.trusted_peers.contains(peer_id)
                 && diff > config.peer_expiration_duration
             {
                 debug!(target: "network", "Removing peer: last seen {:?}", diff);
@@ -238,6 +298,7 @@ impl PeerStore {
         let mut store_update = self.store.store_update();
         for peer_id in to_remove {
             self.peer_states.remove(&peer_id);
+            self.unsaved_scores.remove(&peer_id);
             store_update.delete(ColPeers, &peer_id.try_to_vec()?);
         }
         store_update.commit().map_err(|err| err.into())
@@ -364,18 +425,27 @@ impl PeerStore {
     }
 }
 
+/// Returns the known peers as saved in the database, with their status and score.
+pub fn load_known_peers(store: &Store) -> Result<Vec<KnownPeerState>, Box<dyn Error>> {
+    store
+        .iter(ColPeers)
+        .map(|(_, value)| Ok(KnownPeerState::try_from_slice(value.as_ref())?))
+        .collect()
+}
+
 /// Public method used to iterate through all peers stored in the database.
 pub fn iter_peers_from_store<F>(store: Store, f: F)
 where
     F: Fn((&PeerId, &KnownPeerState)),
 {
-    let peer_store = PeerStore::new(store, &[]).unwrap();
+    let peer_store = PeerStore::new(store, &[], &[]).unwrap();
     peer_store.iter().for_each(f);
 }
 
 #[cfg(test)]
 mod test {
     use near_crypto::{KeyType, SecretKey};
+    use near_network_primitives::types::PeerScore;
     use near_store::create_store;
     use near_store::test_utils::create_test_store;
     use std::net::{Ipv4Addr, SocketAddrV4};
@@ -410,14 +480,14 @@ mod test {
         let boot_nodes = vec![peer_info_a, peer_info_to_ban.clone()];
         {
             let store = create_store(tmp_dir.path());
-            let mut peer_store = PeerStore::new(store, &boot_nodes).unwrap();
+            let mut peer_store = PeerStore::new(store, &boot_nodes, &[]).unwrap();
             assert_eq!(peer_store.healthy_peers(3).len(), 2);
             peer_store.peer_ban(&peer_info_to_ban.id, ReasonForBan::Abusive).unwrap();
             assert_eq!(peer_store.healthy_peers(3).len(), 1);
         }
         {
             let store_new = create_store(tmp_dir.path());
-            let peer_store_new = PeerStore::new(store_new, &boot_nodes).unwrap();
+            let peer_store_new = PeerStore::new(store_new, &boot_nodes, &[]).unwrap();
             assert_eq!(peer_store_new.healthy_peers(3).len(), 1);
         }
     }
@@ -430,12 +500,80 @@ mod test {
         let boot_nodes = vec![peer_info_a, peer_info_to_ban];
         {
             let store = create_store(tmp_dir.path());
-            let peer_store = PeerStore::new(store, &boot_nodes).unwrap();
+            let peer_store = PeerStore::new(store, &boot_nodes, &[]).unwrap();
             assert!(peer_store.unconnected_peer(|_| false).is_some());
             assert!(peer_store.unconnected_peer(|_| true).is_none());
         }
     }
 
+    #[test]
+    fn test_peer_score() {
+        let tmp_dir = tempfile::Builder::new().prefix("_test_peer_score").tempdir().unwrap();
+        let good_peer = gen_peer_info(0);
+        let bad_peer = gen_peer_info(1);
+        let boot_nodes = vec![good_peer.clone(), bad_peer.clone()];
+        {
+            let store = create_store(tmp_dir.path());
+            let mut peer_store = PeerStore::new(store.clone(), &boot_nodes, &[]).unwrap();
+            let latency = std::time::Duration::from_millis(100);
+            peer_store
+                .update_score(&good_peer.id, PeerScoreEvent::ResponseReceived { latency })
+                .unwrap();
+            for _ in 0..2 {
+                peer_store.update_score(&bad_peer.id, PeerScoreEvent::InvalidResponse).unwrap();
+            }
+            assert_eq!(peer_store.score(&good_peer.id), 2);
+            assert_eq!(peer_store.score(&bad_peer.id), -40);
+            assert!(peer_store
+                .update_score(&gen_peer_info(2).id, PeerScoreEvent::RequestTimedOut)
+                .is_err());
+
+            // Peers with a low score are not shared, and only connected to as a last resort.
+            assert_eq!(peer_store.healthy_peers(3), vec![good_peer.clone()]);
+            for _ in 0..10 {
+                assert_eq!(peer_store.unconnected_peer(|_| false), Some(good_peer.clone()));
+            }
+            assert_eq!(
+                peer_store.unconnected_peer(|p| p.peer_info.id == good_peer.id),
+                Some(bad_peer.clone())
+            );
+
+            // Scores are only saved when flushed.
+            let saved_scores = |store: &Store| -> Vec<PeerScore> {
+                load_known_peers(store).unwrap().into_iter().map(|state| state.score).collect()
+            };
+            assert!(saved_scores(&store).is_empty());
+            peer_store.flush_scores().unwrap();
+            assert_eq!(saved_scores(&store).len(), 2);
+        }
+        {
+            // Scores are persisted.
+            let store = create_store(tmp_dir.path());
+            let peer_store = PeerStore::new(store, &boot_nodes, &[]).unwrap();
+            assert_eq!(peer_store.score(&good_peer.id), 2);
+            assert_eq!(peer_store.score(&bad_peer.id), -40);
+        }
+    }
+
+    #[test]
+    fn trusted_peers_are_not_shared() {
+        let store = create_test_store();
+        let boot_node = gen_peer_info(0);
+        let trusted_peer = gen_peer_info(1);
+        let mut peer_store =
+            PeerStore::new(store, &[boot_node.clone()], &[trusted_peer.clone()]).unwrap();
+        assert!(peer_store.iter().any(|(peer_id, _)| peer_id == &trusted_peer.id));
+        assert_eq!(peer_store.healthy_peers(3), vec![boot_node.clone()]);
+
+        // Trusted peers are kept even when they haven't been seen for a long time.
+        let mut config = NetworkConfig::from_seed("test", 0);
+        config.peer_expiration_duration = std::time::Duration::from_secs(0);
+        std::thread::sleep(std::time::Duration::from_millis(10));
+        peer_store.remove_expired(&config).unwrap();
+        assert_eq!(peer_store.len(), 1);
+        assert!(peer_store.iter().any(|(peer_id, _)| peer_id == &trusted_peer.id));
+    }
+
     fn check_exist(
         peer_store: &PeerStore,
         peer_id: &PeerId,
@@ -478,7 +616,7 @@ mod test {
     #[test]
     fn handle_peer_id_change() {
         let store = create_test_store();
-        let mut peer_store = PeerStore::new(store, &[]).unwrap();
+        let mut peer_store = PeerStore::new(store, &[], &[]).unwrap();
 
         let peers_id = (0..2).map(|ix| get_peer_id(format!("node{}", ix))).collect::<Vec<_>>();
         let addr = get_addr(0);
@@ -501,7 +639,7 @@ mod test {
     #[test]
     fn dont_handle_address_change() {
         let store = create_test_store();
-        let mut peer_store = PeerStore::new(store, &[]).unwrap();
+        let mut peer_store = PeerStore::new(store, &[], &[]).unwrap();
 
         let peers_id = (0..1).map(|ix| get_peer_id(format!("node{}", ix))).collect::<Vec<_>>();
         let addrs = (0..2).map(get_addr).collect::<Vec<_>>();
@@ -519,7 +657,7 @@ mod test {
     #[test]
     fn check_add_peers_overriding() {
         let store = create_test_store();
-        let mut peer_store = PeerStore::new(store.clone(), &[]).unwrap();
+        let mut peer_store = PeerStore::new(store.clone(), &[], &[]).unwrap();
 
         // Five peers: A, B, C, D, X, T
         let peers_id = (0..6).map(|ix| get_peer_id(format!("node{}", ix))).collect::<Vec<_>>();
@@ -594,7 +732,7 @@ mod test {
         assert!(check_integrity(&peer_store));
 
         // Check we are able to recover from store previous signed connection
-        let peer_store_2 = PeerStore::new(store, &[]).unwrap();
+        let peer_store_2 = PeerStore::new(store, &[], &[]).unwrap();
         assert!(check_exist(&peer_store_2, &peers_id[0], Some((addrs[0], TrustLevel::Indirect))));
         assert!(check_integrity(&peer_store_2));
     }
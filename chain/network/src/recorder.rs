@@ -0,0 +1,274 @@
+//! Recording of the messages exchanged with peers, to reproduce networking issues offline.
+//!
+//! Every message sent to or received from a peer is appended to a capture file in the
+//! configured directory, together with the peer and the time. Files are named
+//! `capture-<index>.bin` and a new one is started once the current one reaches
+//! `max_file_size`, keeping at most `max_files` of them.
+//!
+//! Each record is a little endian `u32` length followed by a borsh serialized
+//! `RecordedMessage`. Messages are stored as serialized `PeerMessage`s, uncompressed and
+//! unencrypted, so that captures stay readable across changes to the transport.
+use crate::peer::codec::NETWORK_MESSAGE_MAX_SIZE_BYTES;
+use crate::types::PeerMessage;
+use borsh::{BorshDeserialize, BorshSerialize};
+use near_network_primitives::types::MessageRecorderConfig;
+use near_primitives::network::PeerId;
+use near_primitives::time::Clock;
+use near_primitives::utils::to_timestamp;
+use std::collections::VecDeque;
+use std::fs::{self, File};
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+const CAPTURE_FILE_PREFIX: &str = "capture-";
+const CAPTURE_FILE_SUFFIX: &str = ".bin";
+/// Records hold a message of at most `NETWORK_MESSAGE_MAX_SIZE_BYTES` and a few small fields.
+const MAX_RECORD_SIZE_BYTES: usize = NETWORK_MESSAGE_MAX_SIZE_BYTES + 1024;
+
+#[derive(BorshSerialize, BorshDeserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MessageDirection {
+    Inbound,
+    Outbound,
+}
+
+impl std::fmt::Display for MessageDirection {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            MessageDirection::Inbound => write!(f, "<-"),
+            MessageDirection::Outbound => write!(f, "->"),
+        }
+    }
+}
+
+/// A message exchanged with a peer.
+#[derive(BorshSerialize, BorshDeserialize, Clone, Debug, PartialEq, Eq)]
+pub struct RecordedMessage {
+    /// Time the message was sent or received, in nanoseconds since the unix epoch.
+    pub timestamp: u64,
+    /// The other side of the connection. Unknown for messages received on inbound connections
+    /// before the handshake.
+    pub peer_id: Option<PeerId>,
+    pub direction: MessageDirection,
+    /// Borsh serialized `PeerMessage`.
+    pub message: Vec<u8>,
+}
+
+impl RecordedMessage {
+    pub fn peer_message(&self) -> io::Result<PeerMessage> {
+        PeerMessage::try_from_slice(&self.message)
+    }
+}
+
+struct CaptureFile {
+    writer: BufWriter<File>,
+    index: u64,
+    size: u64,
+}
+
+/// Appends the messages exchanged with all peers to rotating capture files.
+/// Shared by all `PeerActor`s.
+pub struct MessageRecorder {
+    config: MessageRecorderConfig,
+    file: Mutex<CaptureFile>,
+}
+
+impl MessageRecorder {
+    /// Starts a new capture file in `config.dir`, after the files already there.
+    pub fn new(config: MessageRecorderConfig) -> io::Result<Self> {
+        fs::create_dir_all(&config.dir)?;
+        let index = capture_files(&config.dir)?.last().map_or(0, |(index, _)| index + 1);
+        let file = Mutex::new(Self::create_file(&config, index)?);
+        Ok(Self { config, file })
+    }
+
+    fn create_file(config: &MessageRecorderConfig, index: u64) -> io::Result<CaptureFile> {
+        let file = File::create(config.dir.join(capture_file_name(index)))?;
+        // Remove the oldest files so that at most `max_files` are left, including the new one.
+        let files = capture_files(&config.dir)?;
+        let num_to_remove = files.len().saturating_sub(config.max_files.max(1));
+        for (_, path) in files.into_iter().take(num_to_remove) {
+            fs::remove_file(path)?;
+        }
+        Ok(CaptureFile { writer: BufWriter::new(file), index, size: 0 })
+    }
+
+    /// Records a serialized `PeerMessage` sent to or received from `peer_id`.
+    pub fn record(
+        &self,
+        peer_id: Option<&PeerId>,
+        direction: MessageDirection,
+        message: &[u8],
+    ) -> io::Result<()> {
+        let record = RecordedMessage {
+            timestamp: to_timestamp(Clock::utc()),
+            peer_id: peer_id.cloned(),
+            direction,
+            message: message.to_vec(),
+        }
+        .try_to_vec()?;
+        let mut file = self.file.lock().unwrap();
+        let record_size = (4 + record.len()) as u64;
+        if file.size > 0 && file.size + record_size > self.config.max_file_size {
+            file.writer.flush()?;
+            let index = file.index + 1;
+            *file = Self::create_file(&self.config, index)?;
+        }
+        file.writer.write_all(&(record.len() as u32).to_le_bytes())?;
+        file.writer.write_all(&record)?;
+        // Keep the files readable while the node is running, the recorder is only enabled for
+        // debugging so we don't mind the cost.
+        file.writer.flush()?;
+        file.size += record_size;
+        Ok(())
+    }
+}
+
+fn capture_file_name(index: u64) -> String {
+    format!("{}{:08}{}", CAPTURE_FILE_PREFIX, index, CAPTURE_FILE_SUFFIX)
+}
+
+/// Returns the capture files in `dir` ordered by their index.
+fn capture_files(dir: &Path) -> io::Result<Vec<(u64, PathBuf)>> {
+    let mut files = vec![];
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        let index = path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .and_then(|name| name.strip_prefix(CAPTURE_FILE_PREFIX))
+            .and_then(|name| name.strip_suffix(CAPTURE_FILE_SUFFIX))
+            .and_then(|index| index.parse::<u64>().ok());
+        if let Some(index) = index {
+            files.push((index, path));
+        }
+    }
+    files.sort();
+    Ok(files)
+}
+
+/// Reads back the messages recorded by `MessageRecorder`, oldest first. The rest of a file is
+/// skipped after an error, e.g. a record cut short by a crash of the node.
+pub struct CaptureReader {
+    files: VecDeque<PathBuf>,
+    current: Option<BufReader<File>>,
+}
+
+impl CaptureReader {
+    /// Reads all capture files in `dir`.
+    pub fn open_dir(dir: &Path) -> io::Result<Self> {
+        let files = capture_files(dir)?.into_iter().map(|(_, path)| path).collect();
+        Ok(Self { files, current: None })
+    }
+
+    /// Reads a single capture file.
+    pub fn open_file(path: &Path) -> Self {
+        Self { files: VecDeque::from(vec![path.to_path_buf()]), current: None }
+    }
+
+    fn read_next(&mut self) -> io::Result<Option<RecordedMessage>> {
+        loop {
+            let reader = match &mut self.current {
+                Some(reader) => reader,
+                None => match self.files.pop_front() {
+                    Some(path) => self.current.insert(BufReader::new(File::open(path)?)),
+                    None => return Ok(None),
+                },
+            };
+            let mut len = [0u8; 4];
+            match reader.read_exact(&mut len) {
+                Ok(()) => {}
+                Err(err) if err.kind() == io::ErrorKind::UnexpectedEof => {
+                    self.current = None;
+                    continue;
+                }
+                Err(err) => return Err(err),
+            }
+            let len = u32::from_le_bytes(len) as usize;
+            if len > MAX_RECORD_SIZE_BYTES {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("Record of {} bytes is too long", len),
+                ));
+            }
+            let mut record = vec![0u8; len];
+            reader.read_exact(&mut record)?;
+            return RecordedMessage::try_from_slice(&record).map(Some);
+        }
+    }
+}
+
+impl Iterator for CaptureReader {
+    type Item = io::Result<RecordedMessage>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let result = self.read_next();
+        if result.is_err() {
+            self.current = None;
+        }
+        result.transpose()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use near_crypto::{KeyType, SecretKey};
+
+    #[test]
+    fn test_record_and_read_back() {
+        let tmp_dir = tempfile::Builder::new().prefix("message_recorder").tempdir().unwrap();
+        let config = MessageRecorderConfig {
+            dir: tmp_dir.path().to_path_buf(),
+            max_file_size: 200,
+            max_files: 3,
+        };
+        let peer_id = PeerId::new(SecretKey::from_random(KeyType::ED25519).public_key());
+        let messages: Vec<Vec<u8>> = (0..20u8).map(|i| vec![i; 10 + i as usize]).collect();
+
+        let recorder = MessageRecorder::new(config.clone()).unwrap();
+        for (i, message) in messages.iter().enumerate() {
+            let direction =
+                if i % 2 == 0 { MessageDirection::Inbound } else { MessageDirection::Outbound };
+            recorder.record(Some(&peer_id), direction, message).unwrap();
+        }
+
+        // Old files are removed, the remaining ones hold the latest messages in order.
+        let files = capture_files(tmp_dir.path()).unwrap();
+        assert_eq!(files.len(), 3);
+        let recorded: Vec<RecordedMessage> =
+            CaptureReader::open_dir(tmp_dir.path()).unwrap().map(Result::unwrap).collect();
+        assert!(!recorded.is_empty() && recorded.len() < messages.len());
+        let expected = &messages[messages.len() - recorded.len()..];
+        for (record, message) in recorded.iter().zip(expected) {
+            assert_eq!(&record.message, message);
+            assert_eq!(record.peer_id.as_ref(), Some(&peer_id));
+        }
+        assert_eq!(recorded.last().unwrap().direction, MessageDirection::Outbound);
+
+        // A restarted recorder appends new files after the existing ones.
+        let recorder = MessageRecorder::new(config).unwrap();
+        let message = PeerMessage::PeersRequest.try_to_vec().unwrap();
+        recorder.record(None, MessageDirection::Inbound, &message).unwrap();
+        let recorded: Vec<RecordedMessage> =
+            CaptureReader::open_dir(tmp_dir.path()).unwrap().map(Result::unwrap).collect();
+        let last = recorded.last().unwrap();
+        assert_eq!(last.peer_id, None);
+        assert_eq!(last.peer_message().unwrap(), PeerMessage::PeersRequest);
+        assert_eq!(
+            capture_files(tmp_dir.path()).unwrap().last().unwrap().0,
+            files.last().unwrap().0 + 1
+        );
+    }
+
+    #[test]
+    fn test_read_too_long_record() {
+        let tmp_dir = tempfile::Builder::new().prefix("message_recorder").tempdir().unwrap();
+        let path = tmp_dir.path().join(capture_file_name(0));
+        fs::write(&path, u32::MAX.to_le_bytes()).unwrap();
+        let mut reader = CaptureReader::open_file(&path);
+        let err = reader.next().unwrap().unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        assert!(reader.next().is_none());
+    }
+}
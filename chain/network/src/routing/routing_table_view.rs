@@ -1,4 +1,5 @@
 use crate::routing::route_back_cache::RouteBackCache;
+use borsh::BorshDeserialize;
 use itertools::Itertools;
 use lru::LruCache;
 use near_network_primitives::types::{Edge, PeerIdOrHash, Ping, Pong};
@@ -7,7 +8,7 @@ use near_primitives::network::{AnnounceAccount, PeerId};
 use near_primitives::time::Clock;
 use near_primitives::types::AccountId;
 use near_store::{ColAccountAnnouncements, Store};
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::sync::Arc;
 use std::time::{Duration, Instant};
 use tracing::warn;
@@ -29,6 +30,9 @@ pub struct RoutingTableView {
     pub(crate) peer_forwarding: Arc<HashMap<PeerId, Vec<PeerId>>>,
     /// Store last update for known edges. This is limited to list of adjacent edges to `my_peer_id`.
     pub(crate) local_edges_info: HashMap<PeerId, Edge>,
+    /// Validators we are directly connected to. Messages to them always take the direct link,
+    /// even before the next routing table update accounts for it.
+    pub(crate) direct_validator_peers: HashSet<PeerId>,
     /// Hash of messages that requires routing back to respective previous hop.
     route_back: RouteBackCache,
     /// Access to store on disk
@@ -61,6 +65,7 @@ impl RoutingTableView {
             account_peers: LruCache::new(ANNOUNCE_ACCOUNT_CACHE_SIZE),
             peer_forwarding: Default::default(),
             local_edges_info: Default::default(),
+            direct_validator_peers: Default::default(),
             route_back: RouteBackCache::default(),
             store,
             route_nonce: LruCache::new(ROUND_ROBIN_NONCE_CACHE_SIZE),
@@ -79,6 +84,9 @@ impl RoutingTableView {
     /// Find peer that is connected to `source` and belong to the shortest path
     /// from `source` to `peer_id`.
     fn find_route_from_peer_id(&mut self, peer_id: &PeerId) -> Result<PeerId, FindRouteError> {
+        if self.direct_validator_peers.contains(peer_id) {
+            return Ok(peer_id.clone());
+        }
         if let Some(routes) = self.peer_forwarding.get(peer_id) {
             match (routes.iter())
                 .map(|peer_id| {
@@ -261,6 +269,20 @@ impl RoutingTableView {
         }
     }
 
+    /// Get all account announces persisted on disk, which are a superset of the ones on cache.
+    pub(crate) fn load_announce_accounts(&self) -> Vec<AnnounceAccount> {
+        self.store
+            .iter(ColAccountAnnouncements)
+            .filter_map(|(_key, value)| match AnnounceAccount::try_from_slice(&value) {
+                Ok(announce_account) => Some(announce_account),
+                Err(e) => {
+                    warn!(target: "network", "Error loading announce account from store: {:?}", e);
+                    None
+                }
+            })
+            .collect()
+    }
+
     pub(crate) fn get_local_edge(&self, other_peer: &PeerId) -> Option<&Edge> {
         self.local_edges_info.get(other_peer)
     }
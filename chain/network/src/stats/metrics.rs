@@ -1,7 +1,8 @@
 use crate::types::PeerMessage;
 use near_metrics::{
-    inc_counter_by_opt, inc_counter_opt, try_create_histogram, try_create_int_counter,
-    try_create_int_gauge, Histogram, IntCounter, IntGauge,
+    inc_counter_by_opt, inc_counter_opt, try_create_histogram, try_create_histogram_vec,
+    try_create_int_counter, try_create_int_counter_vec, try_create_int_gauge, Histogram,
+    HistogramVec, IntCounter, IntCounterVec, IntGauge,
 };
 use near_network_primitives::types::RoutedMessageBody;
 use once_cell::sync::Lazy;
@@ -89,6 +90,23 @@ pub static DROPPED_MESSAGES_COUNT: Lazy<IntCounter> = Lazy::new(|| {
     )
     .unwrap()
 });
+pub static PEER_OUTBOUND_QUEUE_DELAY: Lazy<HistogramVec> = Lazy::new(|| {
+    try_create_histogram_vec(
+        "near_peer_outbound_queue_delay_seconds",
+        "Time messages spent in the outbound queue of a peer, by priority class",
+        &["class"],
+        Some(vec![0.0001, 0.0005, 0.001, 0.005, 0.01, 0.05, 0.1, 0.5, 1., 5., 10.]),
+    )
+    .unwrap()
+});
+pub static PEER_OUTBOUND_QUEUE_DROPPED: Lazy<IntCounterVec> = Lazy::new(|| {
+    try_create_int_counter_vec(
+        "near_peer_outbound_queue_dropped_total",
+        "Number of messages dropped because the outbound queue of a peer was full, by priority class",
+        &["class"],
+    )
+    .unwrap()
+});
 
 #[derive(Clone)]
 pub struct NetworkMetrics {
@@ -107,6 +125,7 @@ impl NetworkMetrics {
                         NetworkMetrics::peer_message_total_rx,
                         NetworkMetrics::peer_message_bytes_rx,
                         NetworkMetrics::peer_message_dropped,
+                        NetworkMetrics::peer_message_compression_saved_bytes,
                     ]
                     .map(|method| {
                         let counter_name = method(name);
@@ -130,6 +149,11 @@ impl NetworkMetrics {
         format!("near_{}_dropped", message_name.to_lowercase())
     }
 
+    /// Bytes saved by compressing the messages sent to peers.
+    pub fn peer_message_compression_saved_bytes(message_name: &str) -> String {
+        format!("near_{}_compression_saved_bytes", message_name.to_lowercase())
+    }
+
     pub fn inc(&self, message_name: &str) {
         if let Some(counter) = self.peer_messages.get(message_name) {
             inc_counter_opt(counter.as_ref());
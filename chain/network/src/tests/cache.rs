@@ -1,8 +1,10 @@
 use crate::routing::routing_table_view::RoutingTableView;
 use crate::test_utils::{random_epoch_id, random_peer_id};
 use near_crypto::Signature;
+use near_network_primitives::types::PeerIdOrHash;
 use near_primitives::network::AnnounceAccount;
 use near_store::test_utils::create_test_store;
+use std::sync::Arc;
 
 #[test]
 fn announcement_same_epoch() {
@@ -102,3 +104,18 @@ fn load_from_disk() {
     // Cache of second routing table should contain account loaded from disk
     assert_eq!(routing_table1.get_announce_accounts().count(), 1);
 }
+
+#[test]
+fn direct_validator_route() {
+    let mut routing_table = RoutingTableView::new(create_test_store());
+
+    let validator = random_peer_id();
+    let next_hop = random_peer_id();
+    routing_table.peer_forwarding =
+        Arc::new(vec![(validator.clone(), vec![next_hop.clone()])].into_iter().collect());
+    let target = PeerIdOrHash::PeerId(validator.clone());
+    assert_eq!(routing_table.find_route(&target).unwrap(), next_hop);
+
+    routing_table.direct_validator_peers.insert(validator.clone());
+    assert_eq!(routing_table.find_route(&target).unwrap(), validator);
+}
@@ -11,10 +11,11 @@ use crate::routing::routing_table_view::RoutingTableInfo;
 use actix::{MailboxError, Message};
 use futures::future::BoxFuture;
 use near_network_primitives::types::{
-    AccountIdOrPeerTrackingShard, AccountOrPeerIdOrHash, Ban, Edge, InboundTcpConnect,
-    KnownProducer, OutboundTcpConnect, PartialEdgeInfo, PartialEncodedChunkForwardMsg,
-    PartialEncodedChunkRequestMsg, PartialEncodedChunkResponseMsg, PeerChainInfoV2, PeerInfo, Ping,
-    Pong, ReasonForBan, RoutedMessageBody, RoutedMessageFrom, StateResponseInfo,
+    AccountIdOrPeerTrackingShard, AccountOrPeerIdOrHash, AnnounceAddress, Ban, Edge,
+    InboundTcpConnect, KnownProducer, OutboundTcpConnect, PartialEdgeInfo,
+    PartialEncodedChunkForwardMsg, PartialEncodedChunkRequestMsg, PartialEncodedChunkResponseMsg,
+    PeerChainInfoV2, PeerInfo, PeerScoreEvent, Ping, Pong, ReasonForBan, RoutedMessageBody,
+    RoutedMessageFrom, StateResponseInfo,
 };
 use near_primitives::block::{Approval, ApprovalMessage, Block, BlockHeader};
 use near_primitives::challenge::Challenge;
@@ -253,8 +254,18 @@ pub enum NetworkRequests {
         peer_id: PeerId,
         ban_reason: ReasonForBan,
     },
+    /// Update the score of given peer after it answered a request, or failed to.
+    UpdatePeerScore {
+        peer_id: PeerId,
+        event: PeerScoreEvent,
+    },
     /// Announce account
     AnnounceAccount(AnnounceAccount),
+    /// Block and chunk producers of the current and next epoch to keep direct connections with,
+    /// empty if we are not one of them.
+    SetValidatorPeers {
+        account_ids: Vec<AccountId>,
+    },
 
     /// Request chunk parts and/or receipts
     PartialEncodedChunkRequest {
@@ -299,6 +310,11 @@ pub enum NetworkRequests {
         peer_id: PeerId,
         routing_table_update: RoutingTableUpdate,
     },
+    /// Addresses of validators received from active peer.
+    SyncAnnounceAddresses {
+        peer_id: PeerId,
+        addresses: Vec<AnnounceAddress>,
+    },
 
     RequestUpdateNonce(PeerId, PartialEdgeInfo),
     ResponseUpdateNonce(Edge),
@@ -384,8 +400,8 @@ pub enum NetworkClientMessages {
 
     /// Request chunk parts and/or receipts.
     PartialEncodedChunkRequest(PartialEncodedChunkRequestMsg, CryptoHash),
-    /// Response to a request for  chunk parts and/or receipts.
-    PartialEncodedChunkResponse(PartialEncodedChunkResponseMsg),
+    /// Response to a request for  chunk parts and/or receipts, with the peer which sent it.
+    PartialEncodedChunkResponse(PartialEncodedChunkResponseMsg, PeerId),
     /// Information about chunk such as its header, some subset of parts and/or incoming receipts
     PartialEncodedChunk(PartialEncodedChunk),
     /// Forwarding parts to those tracking the shard (so they don't need to send requests)
@@ -475,4 +491,12 @@ mod tests {
         assert_size!(FullPeerInfo);
         assert_size!(NetworkInfo);
     }
+
+    #[test]
+    fn test_peer_message_positions() {
+        use borsh::BorshSerialize;
+
+        assert_eq!(PeerMessage::_EpochSyncFinalizationResponse.try_to_vec().unwrap(), vec![20]);
+        assert_eq!(PeerMessage::SyncAnnounceAddresses(vec![]).try_to_vec().unwrap()[0], 22);
+    }
 }
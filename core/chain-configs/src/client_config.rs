@@ -1,5 +1,6 @@
 //! Chain Client Configuration
 use std::cmp::min;
+use std::path::PathBuf;
 use std::time::Duration;
 
 use serde::{Deserialize, Serialize};
@@ -17,6 +18,43 @@ pub enum LogSummaryStyle {
     Colored,
 }
 
+/// Where a validator keeps the journal of the blocks, chunks and approvals it signed.
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+#[serde(tag = "mode", rename_all = "snake_case")]
+pub enum SigningJournalConfig {
+    /// In the node's database.
+    Local,
+    /// In a file shared with a standby node, locked while a signature is recorded.
+    SharedFile { path: PathBuf },
+    /// In a file next to the Unix socket at `path`, signing only while holding the socket.
+    LocalSocket { path: PathBuf },
+}
+
+/// Where state parts are stored outside of the nodes.
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+#[serde(tag = "location", rename_all = "snake_case")]
+pub enum StatePartsLocation {
+    /// In a directory, e.g. a mounted network drive.
+    Filesystem { root_dir: PathBuf },
+    /// On an HTTP server, e.g. an object store bucket, read with `GET {url}/{key}` and written
+    /// with `PUT {url}/{key}`. Requests aren't authenticated, so writes have to be restricted by
+    /// other means, like a proxy adding the credentials.
+    Http { url: String },
+}
+
+/// Dumping state parts for state sync, and fetching them, outside of the peer-to-peer network.
+#[derive(Clone, Debug, Default, Serialize, Deserialize, PartialEq)]
+#[serde(default)]
+pub struct StatePartsConfig {
+    /// Dump all parts of the tracked shards here at the start of every epoch.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub dump: Option<StatePartsLocation>,
+    /// Fetch the parts from here during state sync, and only ask peers for the missing or invalid
+    /// ones.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub fetch: Option<StatePartsLocation>,
+}
+
 #[derive(Clone, Serialize, Deserialize)]
 pub struct ClientConfig {
     /// Version of the binary.
@@ -53,6 +91,8 @@ pub struct ClientConfig {
     pub header_sync_expected_height_per_second: u64,
     /// How long to wait for a response during state sync
     pub state_sync_timeout: Duration,
+    /// Where to dump state parts to and fetch them from, besides the peers.
+    pub state_parts: StatePartsConfig,
     /// Minimum number of peers to start syncing.
     pub min_num_peers: usize,
     /// Period between logging summary information.
@@ -89,6 +129,13 @@ pub struct ClientConfig {
     pub tracked_shards: Vec<ShardId>,
     /// Not clear old data, set `true` for archive nodes.
     pub archive: bool,
+    /// Only follow the header chain and serve `next_light_client_block`, without downloading
+    /// blocks, chunks or state. `light_client_proof` needs execution outcomes and is rejected.
+    pub light_node: bool,
+    /// Path of the validator key file, reloaded on `ReloadValidatorKey`.
+    pub validator_key_file: Option<PathBuf>,
+    /// Refuse to release signatures conflicting with the ones recorded in this journal.
+    pub signing_journal: Option<SigningJournalConfig>,
     /// Number of threads for ViewClientActor pool.
     pub view_client_threads: usize,
     /// Run Epoch Sync on the start.
@@ -132,6 +179,7 @@ impl ClientConfig {
             header_sync_progress_timeout: Duration::from_secs(2),
             header_sync_stall_ban_timeout: Duration::from_secs(30),
             state_sync_timeout: Duration::from_secs(TEST_STATE_SYNC_TIMEOUT),
+            state_parts: StatePartsConfig::default(),
             header_sync_expected_height_per_second: 1,
             min_num_peers: 1,
             log_summary_period: Duration::from_secs(10),
@@ -153,6 +201,9 @@ impl ClientConfig {
             tracked_accounts: vec![],
             tracked_shards: vec![],
             archive,
+            light_node: false,
+            validator_key_file: None,
+            signing_journal: None,
             log_summary_style: LogSummaryStyle::Colored,
             view_client_threads: 1,
             epoch_sync_enabled,
@@ -0,0 +1,270 @@
+//! Key files encrypted with a passphrase.
+//!
+//! The content of a plaintext key file is sealed with XChaCha20-Poly1305 under a key derived
+//! from the passphrase with scrypt. The public key is kept in the clear, so that the file can be
+//! identified without the passphrase, and is authenticated along with the ciphertext.
+
+use std::fs::{File, OpenOptions};
+use std::io::{self, Write};
+use std::path::Path;
+use std::sync::Mutex;
+
+use chacha20poly1305::aead::{Aead, NewAead, Payload};
+use chacha20poly1305::{Key, XChaCha20Poly1305, XNonce};
+use once_cell::sync::Lazy;
+use rand_core::{OsRng, RngCore};
+use serde::{Deserialize, Serialize};
+
+use crate::PublicKey;
+
+/// Environment variable with the passphrase of encrypted key files.
+pub const KEY_PASSPHRASE_ENV: &str = "NEAR_KEY_PASSPHRASE";
+/// Environment variable with the path to a file containing the passphrase, e.g. a credential
+/// passed by the service manager.
+pub const KEY_PASSPHRASE_FILE_ENV: &str = "NEAR_KEY_PASSPHRASE_FILE";
+
+static KEY_PASSPHRASE: Lazy<Mutex<Option<String>>> = Lazy::new(|| Mutex::new(None));
+
+/// Sets the passphrase used to decrypt key files in this process, e.g. after prompting for it.
+pub fn set_key_passphrase(passphrase: String) {
+    *KEY_PASSPHRASE.lock().unwrap() = Some(passphrase);
+}
+
+/// Returns the passphrase of encrypted key files: the one given to `set_key_passphrase`, or else
+/// the one in `NEAR_KEY_PASSPHRASE`, or else the one in the file `NEAR_KEY_PASSPHRASE_FILE`.
+pub fn key_passphrase() -> io::Result<Option<String>> {
+    if let Some(passphrase) = KEY_PASSPHRASE.lock().unwrap().clone() {
+        return Ok(Some(passphrase));
+    }
+    if let Ok(passphrase) = std::env::var(KEY_PASSPHRASE_ENV) {
+        return Ok(Some(passphrase));
+    }
+    if let Some(path) = std::env::var_os(KEY_PASSPHRASE_FILE_ENV) {
+        let passphrase = std::fs::read_to_string(path)?;
+        return Ok(Some(passphrase.trim_end_matches(&['\r', '\n'][..]).to_string()));
+    }
+    Ok(None)
+}
+
+/// Parameters of the scrypt key derivation.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ScryptParams {
+    pub log_n: u8,
+    pub r: u32,
+    pub p: u32,
+}
+
+impl Default for ScryptParams {
+    fn default() -> Self {
+        Self { log_n: 15, r: 8, p: 1 }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct EncryptedKeyFile {
+    pub public_key: PublicKey,
+    pub kdf: ScryptParams,
+    /// Base58-encoded.
+    pub salt: String,
+    /// Base58-encoded.
+    pub nonce: String,
+    /// Base58-encoded.
+    pub ciphertext: String,
+}
+
+fn derive_key(passphrase: &str, salt: &[u8], params: &ScryptParams) -> io::Result<Key> {
+    let scrypt_params = scrypt::Params::new(params.log_n, params.r, params.p)
+        .map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "invalid scrypt parameters"))?;
+    let mut key = Key::default();
+    scrypt::scrypt(passphrase.as_bytes(), salt, &scrypt_params, &mut key)
+        .expect("Key length is valid");
+    Ok(key)
+}
+
+fn decode(value: &str) -> io::Result<Vec<u8>> {
+    bs58::decode(value).into_vec().map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))
+}
+
+impl EncryptedKeyFile {
+    /// Encrypts the content of a plaintext key file holding the secret key of `public_key`.
+    pub fn encrypt(
+        content: &[u8],
+        public_key: PublicKey,
+        passphrase: &str,
+        params: ScryptParams,
+    ) -> io::Result<Self> {
+        let mut salt = [0u8; 32];
+        OsRng.fill_bytes(&mut salt);
+        let mut nonce = [0u8; 24];
+        OsRng.fill_bytes(&mut nonce);
+        let key = derive_key(passphrase, &salt, &params)?;
+        let aad = public_key.to_string();
+        let ciphertext = XChaCha20Poly1305::new(&key)
+            .encrypt(XNonce::from_slice(&nonce), Payload { msg: content, aad: aad.as_bytes() })
+            .map_err(|_| io::Error::new(io::ErrorKind::Other, "failed to encrypt key file"))?;
+        Ok(Self {
+            public_key,
+            kdf: params,
+            salt: bs58::encode(salt).into_string(),
+            nonce: bs58::encode(nonce).into_string(),
+            ciphertext: bs58::encode(ciphertext).into_string(),
+        })
+    }
+
+    /// Returns the content of the plaintext key file.
+    pub fn decrypt(&self, passphrase: &str) -> io::Result<Vec<u8>> {
+        let nonce = decode(&self.nonce)?;
+        if nonce.len() != 24 {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "invalid nonce"));
+        }
+        let key = derive_key(passphrase, &decode(&self.salt)?, &self.kdf)?;
+        let aad = self.public_key.to_string();
+        XChaCha20Poly1305::new(&key)
+            .decrypt(
+                XNonce::from_slice(&nonce),
+                Payload { msg: &decode(&self.ciphertext)?, aad: aad.as_bytes() },
+            )
+            .map_err(|_| {
+                io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    "wrong passphrase or corrupted encrypted key file",
+                )
+            })
+    }
+
+    pub fn write_to_file(&self, path: &Path) -> io::Result<()> {
+        let str = serde_json::to_string_pretty(self)?;
+        replace_key_file(path, str.as_bytes())
+    }
+}
+
+/// Creates a file only readable and writable by the owner.
+pub fn create_key_file(path: &Path) -> io::Result<File> {
+    let mut options = OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(u32::from(libc::S_IWUSR | libc::S_IRUSR));
+    }
+    let file = options.open(path)?;
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        let perm = std::fs::Permissions::from_mode(u32::from(libc::S_IWUSR | libc::S_IRUSR));
+        file.set_permissions(perm)?;
+    }
+    Ok(file)
+}
+
+/// Writes a key file with the given content, replacing the existing one only once the new one
+/// is on disk, so that the key isn't lost if writing fails halfway.
+pub fn replace_key_file(path: &Path, content: &[u8]) -> io::Result<()> {
+    let file_name = path
+        .file_name()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "key file path has no name"))?;
+    let mut tmp_name = std::ffi::OsString::from(".");
+    tmp_name.push(file_name);
+    tmp_name.push(".tmp");
+    let tmp_path = path.with_file_name(tmp_name);
+    let mut file = create_key_file(&tmp_path)?;
+    file.write_all(content)?;
+    file.sync_all()?;
+    std::fs::rename(&tmp_path, path)?;
+    // Persist the rename itself.
+    #[cfg(unix)]
+    if let Some(dir) = path.parent() {
+        let dir = if dir.as_os_str().is_empty() { Path::new(".") } else { dir };
+        File::open(dir)?.sync_all()?;
+    }
+    Ok(())
+}
+
+/// Returns whether the key file at the given path is encrypted.
+pub fn is_key_file_encrypted(path: &Path) -> io::Result<bool> {
+    let content = std::fs::read_to_string(path)?;
+    Ok(serde_json::from_str::<EncryptedKeyFile>(&content).is_ok())
+}
+
+/// Reads the content of a key file, decrypting it with the passphrase from `key_passphrase` if
+/// it's encrypted.
+pub fn read_key_file(path: &Path) -> io::Result<String> {
+    read_key_file_with_passphrase(path, key_passphrase)
+}
+
+fn read_key_file_with_passphrase(
+    path: &Path,
+    passphrase: impl FnOnce() -> io::Result<Option<String>>,
+) -> io::Result<String> {
+    let content = std::fs::read_to_string(path)?;
+    let encrypted = match serde_json::from_str::<EncryptedKeyFile>(&content) {
+        Ok(encrypted) => encrypted,
+        Err(_) => return Ok(content),
+    };
+    let passphrase = passphrase()?.ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::PermissionDenied,
+            format!(
+                "key file {} is encrypted, but neither {} nor {} is set",
+                path.display(),
+                KEY_PASSPHRASE_ENV,
+                KEY_PASSPHRASE_FILE_ENV
+            ),
+        )
+    })?;
+    String::from_utf8(encrypted.decrypt(&passphrase)?)
+        .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{KeyFile, KeyType, SecretKey};
+
+    /// Cheap parameters, the defaults are too slow for tests in debug builds.
+    const TEST_PARAMS: ScryptParams = ScryptParams { log_n: 4, r: 8, p: 1 };
+
+    #[test]
+    fn test_encrypted_key_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("key.json");
+        let secret_key = SecretKey::from_seed(KeyType::ED25519, "test");
+        let key_file = KeyFile {
+            account_id: "test".parse().unwrap(),
+            public_key: secret_key.public_key(),
+            secret_key,
+        };
+        let content = serde_json::to_vec(&key_file).unwrap();
+        let encrypted = EncryptedKeyFile::encrypt(
+            &content,
+            key_file.public_key.clone(),
+            "passphrase",
+            TEST_PARAMS,
+        )
+        .unwrap();
+        encrypted.write_to_file(&path).unwrap();
+        assert!(is_key_file_encrypted(&path).unwrap());
+
+        let encrypted: EncryptedKeyFile =
+            serde_json::from_str(&std::fs::read_to_string(&path).unwrap()).unwrap();
+        assert_eq!(encrypted.decrypt("passphrase").unwrap(), content);
+        assert!(encrypted.decrypt("wrong").is_err());
+
+        // The public key in the clear can't be swapped.
+        let mut swapped = encrypted;
+        swapped.public_key = SecretKey::from_seed(KeyType::ED25519, "other").public_key();
+        assert!(swapped.decrypt("passphrase").is_err());
+
+        // Not through `set_key_passphrase`, which would leak into the other tests.
+        let content =
+            read_key_file_with_passphrase(&path, || Ok(Some("passphrase".to_string()))).unwrap();
+        let read_key_file: KeyFile = serde_json::from_str(&content).unwrap();
+        assert_eq!(read_key_file.public_key, key_file.public_key);
+        assert!(read_key_file_with_passphrase(&path, || Ok(None)).is_err());
+
+        // Replacing the file leaves no temporary file behind.
+        replace_key_file(&path, b"{}").unwrap();
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "{}");
+        assert_eq!(std::fs::read_dir(dir.path()).unwrap().count(), 1);
+    }
+}
@@ -32,6 +32,23 @@ pub fn convert_secret_key(key: &signature::ED25519SecretKey) -> vrf::SecretKey {
     vrf::SecretKey::from_scalar(Scalar::from_bytes_mod_order(*array_ref!(&b, 0, 32)))
 }
 
+/// Converts an ED25519 secret key into the X25519 secret key with the same public point, so that
+/// keys generated for signing can also be used for Diffie-Hellman.
+/// The returned scalar is clamped, as X25519 expects.
+pub fn convert_secret_key_to_x25519(key: &signature::ED25519SecretKey) -> [u8; 32] {
+    let b = ed25519_dalek::ExpandedSecretKey::from(
+        &ed25519_dalek::SecretKey::from_bytes(&key.0[..32]).unwrap(),
+    )
+    .to_bytes();
+    *array_ref!(&b, 0, 32)
+}
+
+/// Converts an ED25519 public key into the corresponding X25519 public key.
+pub fn convert_public_key_to_x25519(key: &signature::ED25519PublicKey) -> Option<[u8; 32]> {
+    let ep: EdwardsPoint = CompressedEdwardsY::from_slice(&key.0).decompress()?;
+    Some(ep.to_montgomery().to_bytes())
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -54,4 +71,26 @@ mod tests {
             );
         }
     }
+
+    #[test]
+    fn test_x25519_conversion() {
+        use curve25519_dalek::constants::X25519_BASEPOINT;
+
+        for _ in 0..10 {
+            let kk = signature::SecretKey::from_random(signature::KeyType::ED25519);
+            let pk = match kk.public_key() {
+                signature::PublicKey::ED25519(k) => k,
+                _ => unreachable!(),
+            };
+            let sk = match kk {
+                signature::SecretKey::ED25519(k) => k,
+                _ => unreachable!(),
+            };
+            let x25519_sk = Scalar::from_bits(convert_secret_key_to_x25519(&sk));
+            assert_eq!(
+                (X25519_BASEPOINT * x25519_sk).to_bytes(),
+                convert_public_key_to_x25519(&pk).unwrap()
+            );
+        }
+    }
 }
@@ -1,10 +1,10 @@
-use std::fs::File;
-use std::io::{Read, Write};
+use std::io::Write;
 use std::path::Path;
 
 use serde::{Deserialize, Serialize};
 
-use crate::{PublicKey, SecretKey};
+use crate::encrypted_key_file::{create_key_file, read_key_file, ScryptParams};
+use crate::{EncryptedKeyFile, PublicKey, SecretKey};
 
 use near_account_id::AccountId;
 
@@ -17,21 +17,32 @@ pub struct KeyFile {
 
 impl KeyFile {
     pub fn write_to_file(&self, path: &Path) -> std::io::Result<()> {
-        let mut file = File::create(path)?;
-        #[cfg(unix)]
-        {
-            use std::os::unix::fs::PermissionsExt;
-            let perm = std::fs::Permissions::from_mode(u32::from(libc::S_IWUSR | libc::S_IRUSR));
-            file.set_permissions(perm)?;
-        }
+        let mut file = create_key_file(path)?;
         let str = serde_json::to_string_pretty(self)?;
         file.write_all(str.as_bytes())
     }
 
+    /// Writes the key file encrypted with the given passphrase.
+    pub fn write_encrypted_to_file(&self, path: &Path, passphrase: &str) -> std::io::Result<()> {
+        let content = serde_json::to_vec_pretty(self)?;
+        EncryptedKeyFile::encrypt(
+            &content,
+            self.public_key.clone(),
+            passphrase,
+            ScryptParams::default(),
+        )?
+        .write_to_file(path)
+    }
+
+    /// Reads the key file, which is decrypted if it's encrypted, see `read_key_file`.
     pub fn from_file(path: &Path) -> Self {
-        let mut file = File::open(path).expect("Could not open key file.");
-        let mut content = String::new();
-        file.read_to_string(&mut content).expect("Could not read from key file.");
+        let content = read_key_file(path).expect("Could not read key file.");
         serde_json::from_str(&content).expect("Failed to deserialize KeyFile")
     }
+
+    /// Same as `from_file`, but returns an error instead of panicking.
+    pub fn try_from_file(path: &Path) -> std::io::Result<Self> {
+        let content = read_key_file(path)?;
+        Ok(serde_json::from_str(&content)?)
+    }
 }
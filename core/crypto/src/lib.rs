@@ -1,3 +1,8 @@
+pub use encrypted_key_file::{
+    create_key_file, is_key_file_encrypted, key_passphrase, read_key_file, replace_key_file,
+    set_key_passphrase, EncryptedKeyFile, ScryptParams, KEY_PASSPHRASE_ENV,
+    KEY_PASSPHRASE_FILE_ENV,
+};
 pub use errors::{ParseKeyError, ParseKeyTypeError, ParseSignatureError};
 pub use key_file::KeyFile;
 pub use signature::{
@@ -13,6 +18,7 @@ mod traits;
 #[macro_use]
 mod util;
 
+mod encrypted_key_file;
 mod errors;
 pub mod key_conversion;
 mod key_file;
@@ -63,6 +63,10 @@ impl InMemorySigner {
     pub fn from_file(path: &Path) -> Self {
         KeyFile::from_file(path).into()
     }
+
+    pub fn try_from_file(path: &Path) -> std::io::Result<Self> {
+        KeyFile::try_from_file(path).map(Into::into)
+    }
 }
 
 impl Signer for InMemorySigner {
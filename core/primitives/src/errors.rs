@@ -777,6 +777,8 @@ pub enum EpochError {
         num_validators: u64,
         num_shards: u64,
     },
+    /// The last block of the epoch isn't later than the last block of the previous epoch.
+    InvalidEpochDuration(EpochId),
 }
 
 impl std::error::Error for EpochError {}
@@ -801,6 +803,9 @@ impl Display for EpochError {
             EpochError::NotEnoughValidators { num_shards, num_validators } => {
                 write!(f, "There were not enough validator proposals to fill all shards. num_proposals: {}, num_shards: {}", num_validators, num_shards)
             }
+            EpochError::InvalidEpochDuration(epoch_id) => {
+                write!(f, "Epoch {:?} doesn't end after the previous epoch", epoch_id)
+            }
         }
     }
 }
@@ -821,6 +826,9 @@ impl Debug for EpochError {
             EpochError::NotEnoughValidators { num_shards, num_validators } => {
                 write!(f, "NotEnoughValidators({}, {})", num_validators, num_shards)
             }
+            EpochError::InvalidEpochDuration(epoch_id) => {
+                write!(f, "InvalidEpochDuration({:?})", epoch_id)
+            }
         }
     }
 }
@@ -10,6 +10,7 @@ pub use near_primitives_core::contract;
 pub mod epoch_manager;
 pub mod errors;
 pub use near_primitives_core::hash;
+pub mod light_client;
 pub use near_primitives_core::logging;
 pub mod merkle;
 pub mod network;
@@ -0,0 +1,69 @@
+//! Checks of light client blocks shared by the chain, which validates the light client blocks
+//! received during epoch sync, and by the standalone light client.
+use borsh::BorshSerialize;
+use near_crypto::Signature;
+
+use crate::hash::hash;
+use crate::types::validator_stake::ValidatorStake;
+use crate::types::{AccountId, Balance};
+use crate::views::LightClientBlockView;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LightClientBlockError {
+    /// There are more approvals than block producers.
+    InvalidApprovals,
+    /// The approval of the given block producer has an invalid signature.
+    InvalidSignature(AccountId),
+    /// The approvals don't reach more than 2/3 of the stake.
+    NotEnoughApprovals,
+    /// The block producers of the next epoch don't match `next_bp_hash`.
+    InvalidNextBlockProducers,
+}
+
+/// Checks that `approvals` of `approval_data` are signed by more than 2/3 of the stake of
+/// `block_producers`, which are ordered as the approvals of the blocks of their epoch.
+pub fn verify_approvals(
+    approvals: &[Option<Signature>],
+    approval_data: &[u8],
+    block_producers: &[ValidatorStake],
+) -> Result<(), LightClientBlockError> {
+    if approvals.len() > block_producers.len() {
+        return Err(LightClientBlockError::InvalidApprovals);
+    }
+    let mut total_stake: Balance = 0;
+    let mut approved_stake: Balance = 0;
+    for (i, block_producer) in block_producers.iter().enumerate() {
+        total_stake += block_producer.stake();
+        if let Some(Some(signature)) = approvals.get(i) {
+            if !signature.verify(approval_data, block_producer.public_key()) {
+                return Err(LightClientBlockError::InvalidSignature(
+                    block_producer.account_id().clone(),
+                ));
+            }
+            approved_stake += block_producer.stake();
+        }
+    }
+    if approved_stake * 3 <= total_stake * 2 {
+        return Err(LightClientBlockError::NotEnoughApprovals);
+    }
+    Ok(())
+}
+
+/// Returns `next_bps` of the block if they are set and match its `next_bp_hash`.
+pub fn validate_next_block_producers(
+    block: &LightClientBlockView,
+) -> Result<Option<Vec<ValidatorStake>>, LightClientBlockError> {
+    let next_bps: Vec<ValidatorStake> = match &block.next_bps {
+        Some(next_bps) => next_bps.iter().cloned().map(Into::into).collect(),
+        None => return Ok(None),
+    };
+    // Headers before `BlockHeaderV3` commit to the V1 representation of the validator stakes.
+    let next_bps_v1: Vec<_> = next_bps.iter().cloned().map(ValidatorStake::into_v1).collect();
+    let next_bp_hash = block.inner_lite.next_bp_hash;
+    if hash(&next_bps.try_to_vec().expect("Failed to serialize")) != next_bp_hash
+        && hash(&next_bps_v1.try_to_vec().expect("Failed to serialize")) != next_bp_hash
+    {
+        return Err(LightClientBlockError::InvalidNextBlockProducers);
+    }
+    Ok(Some(next_bps))
+}
@@ -10,7 +10,7 @@ use crate::receipt::Receipt;
 use crate::transaction::SignedTransaction;
 use crate::types::validator_stake::{ValidatorStake, ValidatorStakeIter, ValidatorStakeV1};
 use crate::types::{Balance, BlockHeight, Gas, MerkleHash, ShardId, StateRoot};
-use crate::validator_signer::ValidatorSigner;
+use crate::validator_signer::{SigningError, ValidatorSigner};
 use crate::version::{
     ProtocolFeature, ProtocolVersion, ProtocolVersionRange, SHARD_CHUNK_HEADER_UPGRADE_VERSION,
 };
@@ -126,7 +126,7 @@ impl ShardChunkHeaderV2 {
         tx_root: CryptoHash,
         validator_proposals: Vec<ValidatorStakeV1>,
         signer: &dyn ValidatorSigner,
-    ) -> Self {
+    ) -> Result<Self, SigningError> {
         let inner = ShardChunkHeaderInnerV1 {
             prev_block_hash,
             prev_state_root,
@@ -143,8 +143,8 @@ impl ShardChunkHeaderV2 {
             validator_proposals,
         };
         let hash = Self::compute_hash(&inner);
-        let signature = signer.sign_chunk_hash(&hash);
-        Self { inner, height_included: 0, signature, hash }
+        let signature = signer.sign_chunk_hash(&hash)?;
+        Ok(Self { inner, height_included: 0, signature, hash })
     }
 }
 
@@ -191,7 +191,7 @@ impl ShardChunkHeaderV3 {
         tx_root: CryptoHash,
         validator_proposals: Vec<ValidatorStake>,
         signer: &dyn ValidatorSigner,
-    ) -> Self {
+    ) -> Result<Self, SigningError> {
         let inner = ShardChunkHeaderInner::V2(ShardChunkHeaderInnerV2 {
             prev_block_hash,
             prev_state_root,
@@ -208,8 +208,8 @@ impl ShardChunkHeaderV3 {
             validator_proposals,
         });
         let hash = Self::compute_hash(&inner);
-        let signature = signer.sign_chunk_hash(&hash);
-        Self { inner, height_included: 0, signature, hash }
+        let signature = signer.sign_chunk_hash(&hash)?;
+        Ok(Self { inner, height_included: 0, signature, hash })
     }
 }
 
@@ -443,7 +443,7 @@ impl ShardChunkHeaderV1 {
         tx_root: CryptoHash,
         validator_proposals: Vec<ValidatorStakeV1>,
         signer: &dyn ValidatorSigner,
-    ) -> Self {
+    ) -> Result<Self, SigningError> {
         let inner = ShardChunkHeaderInnerV1 {
             prev_block_hash,
             prev_state_root,
@@ -460,8 +460,8 @@ impl ShardChunkHeaderV1 {
             validator_proposals,
         };
         let hash = Self::compute_hash(&inner);
-        let signature = signer.sign_chunk_hash(&hash);
-        Self { inner, height_included: 0, signature, hash }
+        let signature = signer.sign_chunk_hash(&hash)?;
+        Ok(Self { inner, height_included: 0, signature, hash })
     }
 }
 
@@ -1013,7 +1013,8 @@ impl EncodedShardChunk {
                 tx_root,
                 validator_proposals,
                 signer,
-            );
+            )
+            .map_err(|err| std::io::Error::new(std::io::ErrorKind::Other, err))?;
             let chunk = EncodedShardChunkV1 { header, content };
             Ok((Self::V1(chunk), merkle_paths))
         } else if block_header_v3_version.is_none()
@@ -1036,7 +1037,8 @@ impl EncodedShardChunk {
                 tx_root,
                 validator_proposals,
                 signer,
-            );
+            )
+            .map_err(|err| std::io::Error::new(std::io::ErrorKind::Other, err))?;
             let chunk = EncodedShardChunkV2 { header: ShardChunkHeader::V2(header), content };
             Ok((Self::V2(chunk), merkle_paths))
         } else {
@@ -1055,7 +1057,8 @@ impl EncodedShardChunk {
                 tx_root,
                 validator_proposals,
                 signer,
-            );
+            )
+            .map_err(|err| std::io::Error::new(std::io::ErrorKind::Other, err))?;
             let chunk = EncodedShardChunkV2 { header: ShardChunkHeader::V3(header), content };
             Ok((Self::V2(chunk), merkle_paths))
         }
@@ -96,6 +96,10 @@ impl StateRecord {
                 Some(StateRecord::DelayedReceipt(Box::new(receipt)))
             }
             col::DELAYED_RECEIPT_INDICES => None,
+            // Shared contract code is not tied to any account. It is reported through the
+            // `Contract` records of the accounts referring to it, see `state_dump`.
+            col::SHARED_CONTRACT_CODE => None,
+            col::SHARED_CONTRACT_CODE_REFCOUNT => None,
             _ => unreachable!(),
         }
     }
@@ -162,5 +166,5 @@ pub fn state_record_to_account_id(state_record: &StateRecord) -> &AccountId {
 }
 
 pub fn is_contract_code_key(key: &[u8]) -> bool {
-    &key[0..1] == col::CONTRACT_CODE
+    &key[0..1] == col::CONTRACT_CODE || &key[0..1] == col::SHARED_CONTRACT_CODE
 }
@@ -3,7 +3,7 @@ use borsh::{BorshDeserialize, BorshSerialize};
 use crate::block_header::BlockHeader;
 use crate::epoch_manager::block_info::BlockInfo;
 use crate::epoch_manager::epoch_info::EpochInfo;
-use crate::hash::CryptoHash;
+use crate::hash::{hash, CryptoHash};
 use crate::merkle::{MerklePath, PartialMerkleTree};
 use crate::sharding::{
     ReceiptProof, ShardChunk, ShardChunkHeader, ShardChunkHeaderV1, ShardChunkV1,
@@ -218,6 +218,44 @@ pub struct EpochSyncFinalizationResponse {
     // Next Epoch Info is required by Block Sync when Blocks of current Epoch will come.
     // It asks in `process_block_single`, returns `Epoch Out Of Bounds` error otherwise.
     pub next_epoch_info: EpochInfo,
+    // Headers following `cur_epoch_header` up to the first one produced right after its parent,
+    // whose approvals endorse the parent and thus prove `cur_epoch_header` to the syncing node.
+    // Only sent in `PeerMessage::EpochSyncFinalizationResponse`, added with this field.
+    pub cur_epoch_next_headers: Vec<BlockHeader>,
+}
+
+impl EpochSyncFinalizationResponse {
+    /// Hash of the epoch manager data in the response. It must match
+    /// `cur_epoch_header.epoch_sync_data_hash()`.
+    pub fn epoch_sync_data_hash(&self) -> CryptoHash {
+        get_epoch_sync_data_hash(
+            &self.prev_epoch_first_block_info,
+            &self.prev_epoch_prev_last_block_info,
+            &self.prev_epoch_last_block_info,
+            &self.prev_epoch_info,
+            &self.cur_epoch_info,
+            &self.next_epoch_info,
+        )
+    }
+}
+
+/// Hash of the data which is necessary to initialize the epoch manager after Epoch Sync.
+/// It is included in the header of the first block of every epoch.
+pub fn get_epoch_sync_data_hash(
+    prev_epoch_first_block_info: &BlockInfo,
+    prev_epoch_prev_last_block_info: &BlockInfo,
+    prev_epoch_last_block_info: &BlockInfo,
+    prev_epoch_info: &EpochInfo,
+    cur_epoch_info: &EpochInfo,
+    next_epoch_info: &EpochInfo,
+) -> CryptoHash {
+    let mut data = prev_epoch_first_block_info.try_to_vec().unwrap();
+    data.extend(prev_epoch_prev_last_block_info.try_to_vec().unwrap());
+    data.extend(prev_epoch_last_block_info.try_to_vec().unwrap());
+    data.extend(prev_epoch_info.try_to_vec().unwrap());
+    data.extend(cur_epoch_info.try_to_vec().unwrap());
+    data.extend(next_epoch_info.try_to_vec().unwrap());
+    hash(data.as_slice())
 }
 
 #[cfg_attr(feature = "deepsize_feature", derive(deepsize::DeepSizeOf))]
@@ -43,6 +43,10 @@ struct MockClockPerState {
     utc_call_count: u64,
     /// Number of times `Clock::instant()` method was called since we started mocking.
     instant_call_count: u64,
+    /// Returned by `Clock::utc()` once `utc_list` is empty.
+    utc_now: Option<DateTime<Utc>>,
+    /// Returned by `Clock::instant()` once `instant_list` is empty.
+    instant_now: Option<Instant>,
 }
 
 /// Stores the mocking state.
@@ -90,6 +94,32 @@ impl MockClockGuard {
         });
     }
 
+    /// Sets the timestamp returned by `Self::utc()` once the ones added with `Self::add_utc()`
+    /// run out. Used to drive the clock of the code under test from a virtual clock.
+    pub fn set_utc(&self, now: DateTime<chrono::Utc>) {
+        MockClockPerThread::with(|clock| match &mut clock.mock {
+            Some(clock) => {
+                clock.utc_now = Some(now);
+            }
+            None => {
+                panic!("Use MockClockGuard in your test");
+            }
+        });
+    }
+
+    /// Sets the timestamp returned by `Self::instant()` once the ones added with
+    /// `Self::add_instant()` run out.
+    pub fn set_instant(&self, now: Instant) {
+        MockClockPerThread::with(|clock| match &mut clock.mock {
+            Some(clock) => {
+                clock.instant_now = Some(now);
+            }
+            None => {
+                panic!("Use MockClockGuard in your test");
+            }
+        });
+    }
+
     /// Returns number of calls  to `Self::utc` since `Self::mock()` was called.
     pub fn utc_call_count(&self) -> u64 {
         MockClockPerThread::with(|clock| match &mut clock.mock {
@@ -148,7 +178,7 @@ impl Clock {
         MockClockPerThread::with(|clock| match &mut clock.mock {
             Some(clock) => {
                 clock.instant_call_count += 1;
-                let x = clock.instant_list.pop_front();
+                let x = clock.instant_list.pop_front().or(clock.instant_now);
                 match x {
                     Some(t) => t,
                     None => {
@@ -166,7 +196,7 @@ impl Clock {
         MockClockPerThread::with(|clock| match &mut clock.mock {
             Some(clock) => {
                 clock.utc_call_count += 1;
-                let x = clock.utc_list.pop_front();
+                let x = clock.utc_list.pop_front().or(clock.utc_now);
                 match x {
                     Some(t) => t,
                     None => {
@@ -274,6 +304,27 @@ mod tests {
         assert_eq!(mock_clock_guard.instant_call_count(), 0);
     }
 
+    #[test]
+    fn test_clock_set_now() {
+        let mock_clock_guard = MockClockGuard::default();
+
+        let utc_now = Utc::now();
+        let instant_now = Instant::now();
+        mock_clock_guard.add_instant(instant_now.add(Duration::from_secs(1)));
+        mock_clock_guard.set_instant(instant_now);
+        mock_clock_guard.set_utc(utc_now);
+
+        // Queued samples are returned first, then the time that was set.
+        assert_eq!(Clock::instant(), instant_now.add(Duration::from_secs(1)));
+        assert_eq!(Clock::instant(), instant_now);
+        assert_eq!(Clock::instant(), instant_now);
+        assert_eq!(Clock::utc(), utc_now);
+
+        mock_clock_guard.set_instant(instant_now.add(Duration::from_secs(2)));
+        assert_eq!(Clock::instant(), instant_now.add(Duration::from_secs(2)));
+        assert_eq!(mock_clock_guard.instant_call_count(), 4);
+    }
+
     #[test]
     fn test_threading() {
         thread::spawn(|| {
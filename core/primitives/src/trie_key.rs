@@ -35,6 +35,12 @@ pub(crate) mod col {
     pub const DELAYED_RECEIPT: &[u8] = &[8];
     /// This column id is used when storing Key-Value data from a contract on an `account_id`.
     pub const CONTRACT_DATA: &[u8] = &[9];
+    /// This column id is used when storing contract code blob for a given code hash. The code is
+    /// shared between all the accounts in the shard which have this code deployed.
+    pub const SHARED_CONTRACT_CODE: &[u8] = &[10];
+    /// This column id is used when storing the number of accounts `u64` in the shard which have
+    /// the code with a given code hash deployed.
+    pub const SHARED_CONTRACT_CODE_REFCOUNT: &[u8] = &[11];
     /// All columns
     pub const NON_DELAYED_RECEIPT_COLUMNS: &[(&[u8], &str)] = &[
         (ACCOUNT, "Account"),
@@ -83,6 +89,13 @@ pub enum TrieKey {
     /// Used to store a key-value record `Vec<u8>` within a contract deployed on a given `AccountId`
     /// and a given key.
     ContractData { account_id: AccountId, key: Vec<u8> },
+    /// Used to store `Vec<u8>` contract code for a given code hash. Accounts refer to it through
+    /// `Account::code_hash`.
+    /// NOTE: It is not tied to any account and is stored in every shard which uses the code.
+    SharedContractCode { code_hash: CryptoHash },
+    /// Used to store the number of accounts `u64` in the shard which refer to
+    /// `SharedContractCode` with a given code hash.
+    SharedContractCodeRefcount { code_hash: CryptoHash },
 }
 
 impl TrieKey {
@@ -125,6 +138,12 @@ impl TrieKey {
                     + ACCOUNT_DATA_SEPARATOR.len()
                     + key.len()
             }
+            TrieKey::SharedContractCode { code_hash } => {
+                col::SHARED_CONTRACT_CODE.len() + code_hash.as_ref().len()
+            }
+            TrieKey::SharedContractCodeRefcount { code_hash } => {
+                col::SHARED_CONTRACT_CODE_REFCOUNT.len() + code_hash.as_ref().len()
+            }
         }
     }
 
@@ -183,6 +202,14 @@ impl TrieKey {
                 res.extend(ACCOUNT_DATA_SEPARATOR);
                 res.extend(key);
             }
+            TrieKey::SharedContractCode { code_hash } => {
+                res.extend(col::SHARED_CONTRACT_CODE);
+                res.extend(code_hash.as_ref());
+            }
+            TrieKey::SharedContractCodeRefcount { code_hash } => {
+                res.extend(col::SHARED_CONTRACT_CODE_REFCOUNT);
+                res.extend(code_hash.as_ref());
+            }
         };
         debug_assert_eq!(res.len(), expected_len);
         res
@@ -315,6 +342,37 @@ pub mod trie_key_parsers {
         parse_account_id_from_slice(account_id, "ContractCode")
     }
 
+    pub fn parse_code_hash_from_shared_contract_code_key(
+        raw_key: &[u8],
+    ) -> Result<CryptoHash, std::io::Error> {
+        let code_hash = parse_account_id_prefix(col::SHARED_CONTRACT_CODE, raw_key)?;
+        CryptoHash::try_from(code_hash).map_err(|_| {
+            std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                "Can't parse CryptoHash for TrieKey::SharedContractCode",
+            )
+        })
+    }
+
+    /// Returns true if the raw key belongs to one of the per-shard columns used for shared
+    /// contract code, which are not associated with any account.
+    pub fn is_shared_contract_code_key(raw_key: &[u8]) -> bool {
+        raw_key.starts_with(col::SHARED_CONTRACT_CODE)
+            || raw_key.starts_with(col::SHARED_CONTRACT_CODE_REFCOUNT)
+    }
+
+    pub fn is_shared_contract_code_refcount_key(raw_key: &[u8]) -> bool {
+        raw_key.starts_with(col::SHARED_CONTRACT_CODE_REFCOUNT)
+    }
+
+    pub fn is_account_key(raw_key: &[u8]) -> bool {
+        raw_key.starts_with(col::ACCOUNT)
+    }
+
+    pub fn is_contract_code_key(raw_key: &[u8]) -> bool {
+        raw_key.starts_with(col::CONTRACT_CODE)
+    }
+
     pub fn parse_trie_key_access_key_from_raw_key(
         raw_key: &[u8],
     ) -> Result<TrieKey, std::io::Error> {
@@ -399,6 +457,10 @@ pub mod trie_key_parsers {
         res
     }
 
+    pub fn get_raw_prefix_for_contract_code() -> Vec<u8> {
+        col::CONTRACT_CODE.to_vec()
+    }
+
     pub fn get_raw_prefix_for_contract_data(account_id: &AccountId, prefix: &[u8]) -> Vec<u8> {
         let mut res = Vec::with_capacity(
             col::CONTRACT_DATA.len()
@@ -618,4 +680,23 @@ mod tests {
         let raw_key = key.to_vec();
         assert!(trie_key_parsers::parse_account_id_from_raw_key(&raw_key).unwrap().is_none());
     }
+
+    #[test]
+    fn test_key_for_shared_contract_code_consistency() {
+        let code_hash = crate::hash::hash(b"code");
+        let key = TrieKey::SharedContractCode { code_hash };
+        let raw_key = key.to_vec();
+        assert_eq!(raw_key.len(), key.len());
+        assert!(trie_key_parsers::is_shared_contract_code_key(&raw_key));
+        assert_eq!(
+            trie_key_parsers::parse_code_hash_from_shared_contract_code_key(&raw_key).unwrap(),
+            code_hash
+        );
+        assert!(trie_key_parsers::parse_account_id_from_raw_key(&raw_key).unwrap().is_none());
+        let key = TrieKey::SharedContractCodeRefcount { code_hash };
+        let raw_key = key.to_vec();
+        assert_eq!(raw_key.len(), key.len());
+        assert!(trie_key_parsers::is_shared_contract_code_key(&raw_key));
+        assert!(trie_key_parsers::parse_account_id_from_raw_key(&raw_key).unwrap().is_none());
+    }
 }
@@ -344,6 +344,8 @@ impl StateChanges {
                 TrieKey::PostponedReceipt { .. } => {}
                 TrieKey::DelayedReceiptIndices => {}
                 TrieKey::DelayedReceipt { .. } => {}
+                TrieKey::SharedContractCode { .. } => {}
+                TrieKey::SharedContractCodeRefcount { .. } => {}
             }
         }
 
@@ -13,6 +13,19 @@ use crate::sharding::ChunkHash;
 use crate::telemetry::TelemetryInfo;
 use crate::types::{AccountId, BlockHeight, EpochId};
 
+/// Error of a validator signer which failed to sign, e.g. a remote signer which is unreachable.
+/// Nothing may be released without its signature, so callers drop whatever they were signing.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SigningError(pub String);
+
+impl std::fmt::Display for SigningError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Failed to sign: {}", self.0)
+    }
+}
+
+impl std::error::Error for SigningError {}
+
 /// Validator signer that is used to sign blocks and approvals.
 pub trait ValidatorSigner: Sync + Send {
     /// Account id of the given validator.
@@ -22,7 +35,7 @@ pub trait ValidatorSigner: Sync + Send {
     fn public_key(&self) -> PublicKey;
 
     /// Serializes telemetry info to JSON and signs it, returning JSON with "signature" field.
-    fn sign_telemetry(&self, info: &TelemetryInfo) -> serde_json::Value;
+    fn sign_telemetry(&self, info: &TelemetryInfo) -> Result<serde_json::Value, SigningError>;
 
     /// Signs given parts of the header.
     fn sign_block_header_parts(
@@ -30,16 +43,23 @@ pub trait ValidatorSigner: Sync + Send {
         prev_hash: CryptoHash,
         inner_lite: &[u8],
         inner_rest: &[u8],
-    ) -> (CryptoHash, Signature);
+    ) -> Result<(CryptoHash, Signature), SigningError>;
 
     /// Signs given inner of the chunk header.
-    fn sign_chunk_hash(&self, chunk_hash: &ChunkHash) -> Signature;
+    fn sign_chunk_hash(&self, chunk_hash: &ChunkHash) -> Result<Signature, SigningError>;
 
     /// Signs approval of given parent hash and reference hash.
-    fn sign_approval(&self, inner: &ApprovalInner, target_height: BlockHeight) -> Signature;
+    fn sign_approval(
+        &self,
+        inner: &ApprovalInner,
+        target_height: BlockHeight,
+    ) -> Result<Signature, SigningError>;
 
     /// Signs challenge body.
-    fn sign_challenge(&self, challenge_body: &ChallengeBody) -> (CryptoHash, Signature);
+    fn sign_challenge(
+        &self,
+        challenge_body: &ChallengeBody,
+    ) -> Result<(CryptoHash, Signature), SigningError>;
 
     /// Signs account announce.
     fn sign_account_announce(
@@ -47,12 +67,12 @@ pub trait ValidatorSigner: Sync + Send {
         account_id: &AccountId,
         peer_id: &PeerId,
         epoch_id: &EpochId,
-    ) -> Signature;
+    ) -> Result<Signature, SigningError>;
 
     fn compute_vrf_with_proof(
         &self,
         data: &[u8],
-    ) -> (near_crypto::vrf::Value, near_crypto::vrf::Proof);
+    ) -> Result<(near_crypto::vrf::Value, near_crypto::vrf::Proof), SigningError>;
 
     /// Used by test infrastructure, only implement if make sense for testing otherwise raise `unimplemented`.
     fn write_to_file(&self, path: &Path) -> std::io::Result<()>;
@@ -75,8 +95,8 @@ impl ValidatorSigner for EmptyValidatorSigner {
         PublicKey::empty(KeyType::ED25519)
     }
 
-    fn sign_telemetry(&self, _info: &TelemetryInfo) -> serde_json::Value {
-        serde_json::Value::default()
+    fn sign_telemetry(&self, _info: &TelemetryInfo) -> Result<serde_json::Value, SigningError> {
+        Ok(serde_json::Value::default())
     }
 
     fn sign_block_header_parts(
@@ -84,22 +104,29 @@ impl ValidatorSigner for EmptyValidatorSigner {
         prev_hash: CryptoHash,
         inner_lite: &[u8],
         inner_rest: &[u8],
-    ) -> (CryptoHash, Signature) {
+    ) -> Result<(CryptoHash, Signature), SigningError> {
         let hash = BlockHeader::compute_hash(prev_hash, inner_lite, inner_rest);
-        (hash, Signature::default())
+        Ok((hash, Signature::default()))
     }
 
-    fn sign_chunk_hash(&self, _chunk_hash: &ChunkHash) -> Signature {
-        Signature::default()
+    fn sign_chunk_hash(&self, _chunk_hash: &ChunkHash) -> Result<Signature, SigningError> {
+        Ok(Signature::default())
     }
 
-    fn sign_approval(&self, _inner: &ApprovalInner, _target_height: BlockHeight) -> Signature {
-        Signature::default()
+    fn sign_approval(
+        &self,
+        _inner: &ApprovalInner,
+        _target_height: BlockHeight,
+    ) -> Result<Signature, SigningError> {
+        Ok(Signature::default())
     }
 
-    fn sign_challenge(&self, challenge_body: &ChallengeBody) -> (CryptoHash, Signature) {
+    fn sign_challenge(
+        &self,
+        challenge_body: &ChallengeBody,
+    ) -> Result<(CryptoHash, Signature), SigningError> {
         let hash = hash(&challenge_body.try_to_vec().expect("Failed to serialize"));
-        (hash, Signature::default())
+        Ok((hash, Signature::default()))
     }
 
     fn sign_account_announce(
@@ -107,14 +134,14 @@ impl ValidatorSigner for EmptyValidatorSigner {
         _account_id: &AccountId,
         _peer_id: &PeerId,
         _epoch_id: &EpochId,
-    ) -> Signature {
-        Signature::default()
+    ) -> Result<Signature, SigningError> {
+        Ok(Signature::default())
     }
 
     fn compute_vrf_with_proof(
         &self,
         _data: &[u8],
-    ) -> (near_crypto::vrf::Value, near_crypto::vrf::Proof) {
+    ) -> Result<(near_crypto::vrf::Value, near_crypto::vrf::Proof), SigningError> {
         unimplemented!()
     }
 
@@ -149,6 +176,11 @@ impl InMemoryValidatorSigner {
         let signer = InMemorySigner::from_file(path);
         Self { account_id: signer.account_id.clone(), signer: Arc::new(signer) }
     }
+
+    pub fn try_from_file(path: &Path) -> std::io::Result<Self> {
+        let signer = InMemorySigner::try_from_file(path)?;
+        Ok(Self { account_id: signer.account_id.clone(), signer: Arc::new(signer) })
+    }
 }
 
 impl ValidatorSigner for InMemoryValidatorSigner {
@@ -160,11 +192,11 @@ impl ValidatorSigner for InMemoryValidatorSigner {
         self.signer.public_key()
     }
 
-    fn sign_telemetry(&self, info: &TelemetryInfo) -> serde_json::Value {
+    fn sign_telemetry(&self, info: &TelemetryInfo) -> Result<serde_json::Value, SigningError> {
         let mut value = serde_json::to_value(info).expect("Telemetry must serialize to JSON");
         let content = serde_json::to_string(&value).expect("Telemetry must serialize to JSON");
         value["signature"] = format!("{}", self.signer.sign(content.as_bytes())).into();
-        value
+        Ok(value)
     }
 
     fn sign_block_header_parts(
@@ -172,23 +204,30 @@ impl ValidatorSigner for InMemoryValidatorSigner {
         prev_hash: CryptoHash,
         inner_lite: &[u8],
         inner_rest: &[u8],
-    ) -> (CryptoHash, Signature) {
+    ) -> Result<(CryptoHash, Signature), SigningError> {
         let hash = BlockHeader::compute_hash(prev_hash, inner_lite, inner_rest);
-        (hash, self.signer.sign(hash.as_ref()))
+        Ok((hash, self.signer.sign(hash.as_ref())))
     }
 
-    fn sign_chunk_hash(&self, chunk_hash: &ChunkHash) -> Signature {
-        self.signer.sign(chunk_hash.as_ref())
+    fn sign_chunk_hash(&self, chunk_hash: &ChunkHash) -> Result<Signature, SigningError> {
+        Ok(self.signer.sign(chunk_hash.as_ref()))
     }
 
-    fn sign_approval(&self, inner: &ApprovalInner, target_height: BlockHeight) -> Signature {
-        self.signer.sign(&Approval::get_data_for_sig(inner, target_height))
+    fn sign_approval(
+        &self,
+        inner: &ApprovalInner,
+        target_height: BlockHeight,
+    ) -> Result<Signature, SigningError> {
+        Ok(self.signer.sign(&Approval::get_data_for_sig(inner, target_height)))
     }
 
-    fn sign_challenge(&self, challenge_body: &ChallengeBody) -> (CryptoHash, Signature) {
+    fn sign_challenge(
+        &self,
+        challenge_body: &ChallengeBody,
+    ) -> Result<(CryptoHash, Signature), SigningError> {
         let hash = hash(&challenge_body.try_to_vec().expect("Failed to serialize"));
         let signature = self.signer.sign(hash.as_ref());
-        (hash, signature)
+        Ok((hash, signature))
     }
 
     fn sign_account_announce(
@@ -196,16 +235,16 @@ impl ValidatorSigner for InMemoryValidatorSigner {
         account_id: &AccountId,
         peer_id: &PeerId,
         epoch_id: &EpochId,
-    ) -> Signature {
+    ) -> Result<Signature, SigningError> {
         let hash = AnnounceAccount::build_header_hash(account_id, peer_id, epoch_id);
-        self.signer.sign(hash.as_ref())
+        Ok(self.signer.sign(hash.as_ref()))
     }
 
     fn compute_vrf_with_proof(
         &self,
         data: &[u8],
-    ) -> (near_crypto::vrf::Value, near_crypto::vrf::Proof) {
-        self.signer.compute_vrf_with_proof(data)
+    ) -> Result<(near_crypto::vrf::Value, near_crypto::vrf::Proof), SigningError> {
+        Ok(self.signer.compute_vrf_with_proof(data))
     }
 
     fn write_to_file(&self, path: &Path) -> std::io::Result<()> {
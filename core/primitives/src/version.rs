@@ -17,7 +17,7 @@ pub struct Version {
 pub type DbVersion = u32;
 
 /// Current version of the database.
-pub const DB_VERSION: DbVersion = 31;
+pub const DB_VERSION: DbVersion = 32;
 
 use crate::upgrade_schedule::{get_protocol_version_internal, ProtocolUpgradeVotingSchedule};
 /// Protocol version type.
@@ -150,6 +150,18 @@ pub enum ProtocolFeature {
     /// alpha is min stake ratio
     #[cfg(feature = "protocol_feature_fix_staking_threshold")]
     FixStakingThreshold,
+    /// Store contract code once per shard, addressed by its hash and shared by all the accounts
+    /// that deploy it, instead of keeping a separate copy under every account.
+    #[cfg(feature = "protocol_feature_contract_code_deduplication")]
+    ContractCodeDeduplication,
+    /// Encrypt and authenticate the traffic between peers with a Noise XX handshake keyed by
+    /// the node keys, run right after the plaintext `Handshake`.
+    #[cfg(feature = "protocol_feature_encrypted_peer_connections")]
+    EncryptedPeerConnections,
+    /// Advertise support of zstd compressed messages in the `Handshake` and compress large
+    /// messages sent to peers supporting it.
+    #[cfg(feature = "protocol_feature_network_message_compression")]
+    NetworkMessageCompression,
 }
 
 /// Both, outgoing and incoming tcp connections to peers, will be rejected if `peer's`
@@ -166,7 +178,7 @@ const STABLE_PROTOCOL_VERSION: ProtocolVersion = 52;
 pub const PROTOCOL_VERSION: ProtocolVersion = STABLE_PROTOCOL_VERSION;
 /// Current latest nightly version of the protocol.
 #[cfg(feature = "nightly_protocol")]
-pub const PROTOCOL_VERSION: ProtocolVersion = 126;
+pub const PROTOCOL_VERSION: ProtocolVersion = 129;
 
 /// The points in time after which the voting for the protocol version should start.
 #[allow(dead_code)]
@@ -226,6 +238,12 @@ impl ProtocolFeature {
             ProtocolFeature::RoutingExchangeAlgorithm => 117,
             #[cfg(feature = "protocol_feature_fix_staking_threshold")]
             ProtocolFeature::FixStakingThreshold => 126,
+            #[cfg(feature = "protocol_feature_contract_code_deduplication")]
+            ProtocolFeature::ContractCodeDeduplication => 127,
+            #[cfg(feature = "protocol_feature_encrypted_peer_connections")]
+            ProtocolFeature::EncryptedPeerConnections => 128,
+            #[cfg(feature = "protocol_feature_network_message_compression")]
+            ProtocolFeature::NetworkMessageCompression => 129,
         }
     }
 }
@@ -15,15 +15,15 @@ use near_crypto::{PublicKey, Signature};
 use crate::account::{AccessKey, AccessKeyPermission, Account, FunctionCallPermission};
 use crate::block::{Block, BlockHeader};
 use crate::block_header::{
-    BlockHeaderInnerLite, BlockHeaderInnerRest, BlockHeaderInnerRestV2, BlockHeaderInnerRestV3,
-    BlockHeaderV1, BlockHeaderV2, BlockHeaderV3,
+    Approval, ApprovalInner, BlockHeaderInnerLite, BlockHeaderInnerRest, BlockHeaderInnerRestV2,
+    BlockHeaderInnerRestV3, BlockHeaderV1, BlockHeaderV2, BlockHeaderV3,
 };
 use crate::challenge::{Challenge, ChallengesResult};
 use crate::contract::ContractCode;
 use crate::errors::TxExecutionError;
 use crate::hash::{hash, CryptoHash};
 use crate::logging;
-use crate::merkle::MerklePath;
+use crate::merkle::{combine_hash, MerklePath};
 use crate::profile::Cost;
 use crate::receipt::{ActionReceipt, DataReceipt, DataReceiver, Receipt, ReceiptEnum};
 use crate::serialize::{
@@ -37,13 +37,14 @@ use crate::sharding::{
 use crate::transaction::{
     Action, AddKeyAction, CreateAccountAction, DeleteAccountAction, DeleteKeyAction,
     DeployContractAction, ExecutionMetadata, ExecutionOutcome, ExecutionOutcomeWithIdAndProof,
-    ExecutionStatus, FunctionCallAction, SignedTransaction, StakeAction, TransferAction,
+    ExecutionStatus, FunctionCallAction, PartialExecutionOutcome, PartialExecutionStatus,
+    SignedTransaction, StakeAction, TransferAction,
 };
 use crate::types::{
     AccountId, AccountWithPublicKey, Balance, BlockHeight, CompiledContractCache, EpochHeight,
-    EpochId, FunctionArgs, Gas, Nonce, NumBlocks, ShardId, StateChangeCause, StateChangeKind,
-    StateChangeValue, StateChangeWithCause, StateChangesRequest, StateRoot, StorageUsage, StoreKey,
-    StoreValue, ValidatorKickoutReason,
+    EpochId, FunctionArgs, Gas, Nonce, NumBlocks, NumShards, ShardId, StateChangeCause,
+    StateChangeKind, StateChangeValue, StateChangeWithCause, StateChangesRequest, StateRoot,
+    StorageUsage, StoreKey, StoreValue, ValidatorKickoutReason,
 };
 use crate::version::{ProtocolVersion, Version};
 use validator_stake_view::ValidatorStakeView;
@@ -1096,6 +1097,34 @@ pub struct ExecutionOutcomeWithIdView {
     pub outcome: ExecutionOutcomeView,
 }
 
+impl ExecutionOutcomeWithIdView {
+    /// Hashes of the outcome which are merklized into the outcome root of the chunk, the same
+    /// way as `ExecutionOutcome::to_hashes`.
+    pub fn to_hashes(&self) -> Result<Vec<CryptoHash>, Box<dyn std::error::Error>> {
+        let status = match &self.outcome.status {
+            ExecutionStatusView::Unknown => PartialExecutionStatus::Unknown,
+            ExecutionStatusView::Failure(_) => PartialExecutionStatus::Failure,
+            ExecutionStatusView::SuccessValue(value) => {
+                PartialExecutionStatus::SuccessValue(from_base64(value)?)
+            }
+            ExecutionStatusView::SuccessReceiptId(id) => {
+                PartialExecutionStatus::SuccessReceiptId(*id)
+            }
+        };
+        let partial_outcome = PartialExecutionOutcome {
+            receipt_ids: self.outcome.receipt_ids.clone(),
+            gas_burnt: self.outcome.gas_burnt,
+            tokens_burnt: self.outcome.tokens_burnt,
+            executor_id: self.outcome.executor_id.clone(),
+            status,
+        };
+        let mut result =
+            vec![self.id, hash(&partial_outcome.try_to_vec().expect("Failed to serialize"))];
+        result.extend(self.outcome.logs.iter().map(|log| hash(log.as_bytes())));
+        Ok(result)
+    }
+}
+
 impl From<ExecutionOutcomeWithIdAndProof> for ExecutionOutcomeWithIdView {
     fn from(outcome_with_id_and_proof: ExecutionOutcomeWithIdAndProof) -> Self {
         Self {
@@ -1421,6 +1450,102 @@ pub struct CurrentEpochValidatorInfo {
     pub num_expected_chunks: NumBlocks,
 }
 
+/// Performance of a validator in a finished epoch.
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
+pub struct ValidatorEpochPerformanceView {
+    pub epoch_id: EpochId,
+    pub epoch_height: EpochHeight,
+    pub epoch_start_height: BlockHeight,
+    /// Stake of the account in the epoch, `None` if it wasn't a validator.
+    #[serde(with = "option_u128_dec_format")]
+    pub stake: Option<Balance>,
+    /// Stake of the account after the stake changes applied at the start of the epoch.
+    #[serde(with = "option_u128_dec_format")]
+    pub stake_change: Option<Balance>,
+    /// Produced and expected blocks and chunks. Only recorded for validators which weren't
+    /// kicked out, otherwise `kickout_reason` has the numbers which got the validator kicked out.
+    pub num_produced_blocks: NumBlocks,
+    pub num_expected_blocks: NumBlocks,
+    pub num_produced_chunks: NumBlocks,
+    pub num_expected_chunks: NumBlocks,
+    /// Average of the produced to expected ratios of blocks and chunks, which is the uptime used
+    /// for computing the reward.
+    pub uptime: Option<f64>,
+    /// Reason for kicking the validator out based on this epoch.
+    pub kickout_reason: Option<ValidatorKickoutReason>,
+    /// Reward of the validator for the epoch, `None` if it wasn't a validator or if the reward
+    /// can't be computed.
+    #[serde(with = "option_u128_dec_format")]
+    pub reward: Option<Balance>,
+}
+
+/// An approval received by a block producer for a block it's going to produce.
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
+pub struct ApprovalDebugView {
+    pub account_id: AccountId,
+    /// Whether the approval endorses the parent block, otherwise it skips it.
+    pub is_endorsement: bool,
+    pub received_at: DateTime<chrono::Utc>,
+}
+
+/// When the chunk of a shard became ready for inclusion into a block.
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
+pub struct ChunkReadyDebugView {
+    pub shard_id: ShardId,
+    pub ready_at: DateTime<chrono::Utc>,
+}
+
+/// What happened to the block at a height this node was the block producer for.
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
+pub enum BlockProductionStatusView {
+    /// Not enough stake approved the block yet.
+    WaitingForApprovals,
+    /// Enough stake approved the block, but not all chunks are ready and the doomslug delay
+    /// for waiting on them hasn't passed yet.
+    WaitingForChunks,
+    Produced {
+        block_hash: CryptoHash,
+        num_chunks: usize,
+        produced_at: DateTime<chrono::Utc>,
+    },
+    /// Ready to be produced, but not produced, e.g. because a block at a greater height is known.
+    Skipped {
+        reason: String,
+    },
+    Failed {
+        error: String,
+    },
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
+pub struct BlockProductionDebugView {
+    pub height: BlockHeight,
+    pub approvals: Vec<ApprovalDebugView>,
+    /// Chunks ready for inclusion, as first seen when checking whether to produce the block.
+    pub chunks_ready: Vec<ChunkReadyDebugView>,
+    pub num_shards: Option<NumShards>,
+    /// When the approvals crossed the doomslug threshold.
+    pub threshold_crossed_at: Option<DateTime<chrono::Utc>>,
+    pub last_checked_at: Option<DateTime<chrono::Utc>>,
+    pub status: Option<BlockProductionStatusView>,
+}
+
+/// Recent block production decisions of a block producer and the state of its doomslug timer.
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
+pub struct BlockProductionDebugInfoView {
+    pub head_height: BlockHeight,
+    pub largest_final_height: BlockHeight,
+    pub largest_threshold_height: BlockHeight,
+    pub largest_target_height: BlockHeight,
+    /// Height whose block producer the node sends a skip approval to once the timer fires.
+    pub timer_height: BlockHeight,
+    pub timer_started_at: DateTime<chrono::Utc>,
+    /// Delay before the skip, which grows up to `max_block_production_delay` as heights pass
+    /// without a final block.
+    pub timer_delay_ms: u64,
+    pub heights: Vec<BlockProductionDebugView>,
+}
+
 #[cfg_attr(feature = "deepsize_feature", derive(deepsize::DeepSizeOf))]
 #[derive(BorshSerialize, BorshDeserialize, Serialize, Deserialize, Debug, PartialEq, Eq, Clone)]
 pub struct NextEpochValidatorInfo {
@@ -1449,6 +1574,44 @@ pub struct LightClientBlockLiteView {
     pub inner_lite: BlockHeaderInnerLiteView,
 }
 
+/// Same as `BlockHeader::compute_hash`, but from the parts of the header available in light
+/// client views.
+fn compute_block_hash(
+    inner_lite: &BlockHeaderInnerLiteView,
+    inner_rest_hash: &CryptoHash,
+    prev_block_hash: &CryptoHash,
+) -> CryptoHash {
+    let inner_lite = BlockHeaderInnerLite::from(inner_lite.clone());
+    let inner_lite_hash = hash(&inner_lite.try_to_vec().expect("Failed to serialize"));
+    combine_hash(&combine_hash(&inner_lite_hash, inner_rest_hash), prev_block_hash)
+}
+
+impl LightClientBlockView {
+    /// Hash of the block this light client block describes.
+    pub fn current_block_hash(&self) -> CryptoHash {
+        compute_block_hash(&self.inner_lite, &self.inner_rest_hash, &self.prev_block_hash)
+    }
+
+    /// Hash of the block following the one this light client block describes.
+    pub fn next_block_hash(&self) -> CryptoHash {
+        combine_hash(&self.next_block_inner_hash, &self.current_block_hash())
+    }
+
+    /// Message signed by the block producers in `approvals_after_next`.
+    pub fn approval_message(&self) -> Vec<u8> {
+        Approval::get_data_for_sig(
+            &ApprovalInner::Endorsement(self.next_block_hash()),
+            self.inner_lite.height + 2,
+        )
+    }
+}
+
+impl LightClientBlockLiteView {
+    pub fn hash(&self) -> CryptoHash {
+        compute_block_hash(&self.inner_lite, &self.inner_rest_hash, &self.prev_block_hash)
+    }
+}
+
 impl From<BlockHeader> for LightClientBlockLiteView {
     fn from(header: BlockHeader) -> Self {
         Self {
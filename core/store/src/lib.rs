@@ -23,7 +23,7 @@ use near_primitives::receipt::{DelayedReceiptIndices, Receipt, ReceivedData};
 use near_primitives::serialize::to_base;
 pub use near_primitives::shard_layout::ShardUId;
 use near_primitives::trie_key::{trie_key_parsers, TrieKey};
-use near_primitives::types::{AccountId, CompiledContractCache, StateRoot};
+use near_primitives::types::{AccountId, CompiledContractCache, StateChangeCause, StateRoot};
 
 pub use crate::db::refcount::decode_value_with_rc;
 use crate::db::refcount::encode_value_with_rc;
@@ -32,6 +32,8 @@ use crate::db::{
 };
 pub use crate::trie::iterator::TrieIterator;
 pub use crate::trie::update::{TrieUpdate, TrieUpdateIterator, TrieUpdateValuePtr};
+#[cfg(feature = "sandbox")]
+pub use crate::trie::TrieFallback;
 pub use crate::trie::{
     split_state, ApplyStatePartResult, KeyForStateChanges, PartialStorage, ShardTries, Trie,
     TrieChanges, WrappedTrieChanges,
@@ -39,6 +41,7 @@ pub use crate::trie::{
 
 pub mod db;
 pub mod migrations;
+pub mod signing_journal;
 pub mod test_utils;
 mod trie;
 
@@ -428,14 +431,182 @@ pub fn set_code(state_update: &mut TrieUpdate, account_id: AccountId, code: &Con
     state_update.set(TrieKey::ContractCode { account_id }, code.code().to_vec());
 }
 
+/// Returns the code deployed on the account.
+///
+/// The code is looked up under the account first. If it is not there, it is resolved through
+/// `code_hash` in the shared contract code of the shard, see `set_shared_code`.
 pub fn get_code(
     state_update: &TrieUpdate,
     account_id: &AccountId,
     code_hash: Option<CryptoHash>,
 ) -> Result<Option<ContractCode>, StorageError> {
-    state_update
-        .get(&TrieKey::ContractCode { account_id: account_id.clone() })
-        .map(|opt| opt.map(|code| ContractCode::new(code, code_hash)))
+    if let Some(code) =
+        state_update.get(&TrieKey::ContractCode { account_id: account_id.clone() })?
+    {
+        return Ok(Some(ContractCode::new(code, code_hash)));
+    }
+    match code_hash {
+        Some(code_hash) if code_hash != CryptoHash::default() => state_update
+            .get(&TrieKey::SharedContractCode { code_hash })
+            .map(|opt| opt.map(|code| ContractCode::new(code, Some(code_hash)))),
+        _ => Ok(None),
+    }
+}
+
+pub fn get_shared_code_refcount(
+    state_update: &TrieUpdate,
+    code_hash: &CryptoHash,
+) -> Result<u64, StorageError> {
+    Ok(get(state_update, &TrieKey::SharedContractCodeRefcount { code_hash: *code_hash })?
+        .unwrap_or_default())
+}
+
+/// Adds a reference to the code shared between all accounts of the shard. The code itself is
+/// stored only when it is referenced for the first time.
+///
+/// The account is expected to refer to the code through `Account::code_hash`.
+pub fn set_shared_code(
+    state_update: &mut TrieUpdate,
+    code: &ContractCode,
+) -> Result<(), StorageError> {
+    let code_hash = *code.hash();
+    if get_shared_code_refcount(state_update, &code_hash)? == 0 {
+        state_update.set(TrieKey::SharedContractCode { code_hash }, code.code().to_vec());
+    }
+    increment_shared_code_refcount(state_update, &code_hash)
+}
+
+fn increment_shared_code_refcount(
+    state_update: &mut TrieUpdate,
+    code_hash: &CryptoHash,
+) -> Result<(), StorageError> {
+    let refcount =
+        get_shared_code_refcount(state_update, code_hash)?.checked_add(1).ok_or_else(|| {
+            StorageError::StorageInconsistentState(format!(
+                "Refcount integer overflow for shared contract code {}",
+                code_hash
+            ))
+        })?;
+    set(state_update, TrieKey::SharedContractCodeRefcount { code_hash: *code_hash }, &refcount);
+    Ok(())
+}
+
+/// Removes a reference to the shared code. The code is removed together with its last reference.
+pub fn remove_shared_code(
+    state_update: &mut TrieUpdate,
+    code_hash: &CryptoHash,
+) -> Result<(), StorageError> {
+    let code_hash = *code_hash;
+    match get_shared_code_refcount(state_update, &code_hash)? {
+        0 => {
+            return Err(StorageError::StorageInconsistentState(format!(
+                "Shared contract code {} is not referenced",
+                code_hash
+            )))
+        }
+        1 => {
+            state_update.remove(TrieKey::SharedContractCode { code_hash });
+            state_update.remove(TrieKey::SharedContractCodeRefcount { code_hash });
+        }
+        refcount => {
+            set(state_update, TrieKey::SharedContractCodeRefcount { code_hash }, &(refcount - 1));
+        }
+    }
+    Ok(())
+}
+
+/// Returns whether a code is deployed on the account, without reading the code.
+pub fn has_code(
+    state_update: &TrieUpdate,
+    account_id: &AccountId,
+    code_hash: &CryptoHash,
+) -> Result<bool, StorageError> {
+    if state_update.get_ref(&TrieKey::ContractCode { account_id: account_id.clone() })?.is_some() {
+        return Ok(true);
+    }
+    Ok(*code_hash != CryptoHash::default()
+        && state_update.get_ref(&TrieKey::SharedContractCode { code_hash: *code_hash })?.is_some())
+}
+
+/// Removes the code deployed on the account, whether it is stored under the account or shared.
+pub fn remove_code(
+    state_update: &mut TrieUpdate,
+    account_id: &AccountId,
+    code_hash: &CryptoHash,
+) -> Result<(), StorageError> {
+    let key = TrieKey::ContractCode { account_id: account_id.clone() };
+    if state_update.get_ref(&key)?.is_some() {
+        state_update.remove(key);
+        Ok(())
+    } else if *code_hash != CryptoHash::default() {
+        remove_shared_code(state_update, code_hash)
+    } else {
+        Ok(())
+    }
+}
+
+/// Number of accounts which codes are moved at once by `migrate_codes_to_shared_code`.
+const CODE_MIGRATION_BATCH_SIZE: usize = 1000;
+
+/// Moves the codes stored under accounts into the shared contract code of the shard.
+/// The changes of every batch are committed to `state_update` with the `Migration` cause.
+/// Returns the number of accounts which code was moved.
+pub fn migrate_codes_to_shared_code(state_update: &mut TrieUpdate) -> Result<u64, StorageError> {
+    let prefix = trie_key_parsers::get_raw_prefix_for_contract_code();
+    let mut num_migrated = 0;
+    let mut start = vec![];
+    loop {
+        // The accounts are read in batches, as the iterator borrows `state_update`.
+        let account_ids = TrieUpdateIterator::new(state_update, &prefix, &start, None)?
+            .take(CODE_MIGRATION_BATCH_SIZE)
+            .map(|raw_key| {
+                trie_key_parsers::parse_account_id_from_contract_code_key(&raw_key?).map_err(|_e| {
+                    StorageError::StorageInconsistentState(
+                        "Can't parse account id from raw key for ContractCode".to_string(),
+                    )
+                })
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        match account_ids.last() {
+            // Codes of the batch are removed below, so the next batch starts right after it.
+            Some(account_id) => start = account_id.as_ref().as_bytes().to_vec(),
+            None => return Ok(num_migrated),
+        }
+        for account_id in account_ids.iter() {
+            migrate_code_to_shared_code(state_update, account_id)?;
+        }
+        state_update.commit(StateChangeCause::Migration);
+        num_migrated += account_ids.len() as u64;
+    }
+}
+
+fn migrate_code_to_shared_code(
+    state_update: &mut TrieUpdate,
+    account_id: &AccountId,
+) -> Result<(), StorageError> {
+    let code_hash = get_account(state_update, account_id)?
+        .ok_or_else(|| {
+            StorageError::StorageInconsistentState(format!(
+                "Contract code is stored for non-existent account {}",
+                account_id
+            ))
+        })?
+        .code_hash();
+    let key = TrieKey::ContractCode { account_id: account_id.clone() };
+    // Only the first reference to the code requires reading it, so that the migration keeps
+    // in memory a single copy of every distinct code.
+    if get_shared_code_refcount(state_update, &code_hash)? == 0 {
+        let code = state_update.get(&key)?.ok_or_else(|| {
+            StorageError::StorageInconsistentState(format!(
+                "Contract code for account {} disappeared during migration",
+                account_id
+            ))
+        })?;
+        state_update.set(TrieKey::SharedContractCode { code_hash }, code);
+    }
+    increment_shared_code_refcount(state_update, &code_hash)?;
+    state_update.remove(key);
+    Ok(())
 }
 
 /// Removes account, code and all access keys associated to it.
@@ -524,9 +695,76 @@ impl CompiledContractCache for StoreCompiledContractCache {
 
 #[cfg(test)]
 mod tests {
+    use near_primitives::account::Account;
+    use near_primitives::contract::ContractCode;
+    use near_primitives::hash::CryptoHash;
+    use near_primitives::shard_layout::ShardUId;
+    use near_primitives::types::AccountId;
+
+    use crate::test_utils::create_tries;
+    use crate::{
+        get_code, get_shared_code_refcount, migrate_codes_to_shared_code, remove_code, set_account,
+        set_code, set_shared_code,
+    };
+
     #[test]
     fn test_no_cache_disabled() {
         #[cfg(feature = "no_cache")]
         panic!("no cache is enabled");
     }
+
+    #[test]
+    fn test_shared_code_refcount() {
+        let tries = create_tries();
+        let mut state_update =
+            tries.new_trie_update(ShardUId::single_shard(), CryptoHash::default());
+        let alice: AccountId = "alice".parse().unwrap();
+        let bob: AccountId = "bob".parse().unwrap();
+        let code = ContractCode::new(vec![1, 2, 3], None);
+        let code_hash = *code.hash();
+
+        set_shared_code(&mut state_update, &code).unwrap();
+        set_shared_code(&mut state_update, &code).unwrap();
+        assert_eq!(get_shared_code_refcount(&state_update, &code_hash).unwrap(), 2);
+        for account_id in [&alice, &bob] {
+            let stored = get_code(&state_update, account_id, Some(code_hash)).unwrap().unwrap();
+            assert_eq!(stored.code(), code.code());
+        }
+        assert!(get_code(&state_update, &alice, Some(CryptoHash::default())).unwrap().is_none());
+
+        remove_code(&mut state_update, &alice, &code_hash).unwrap();
+        assert_eq!(get_shared_code_refcount(&state_update, &code_hash).unwrap(), 1);
+        assert!(get_code(&state_update, &bob, Some(code_hash)).unwrap().is_some());
+        remove_code(&mut state_update, &bob, &code_hash).unwrap();
+        assert_eq!(get_shared_code_refcount(&state_update, &code_hash).unwrap(), 0);
+        assert!(get_code(&state_update, &bob, Some(code_hash)).unwrap().is_none());
+        assert!(remove_code(&mut state_update, &bob, &code_hash).is_err());
+    }
+
+    #[test]
+    fn test_migrate_codes_to_shared_code() {
+        let tries = create_tries();
+        let mut state_update =
+            tries.new_trie_update(ShardUId::single_shard(), CryptoHash::default());
+        let code = ContractCode::new(vec![1, 2, 3], None);
+        let other_code = ContractCode::new(vec![4, 5, 6], None);
+        let accounts = [("alice", &code), ("bob", &code), ("carol", &other_code)];
+        for (account_id, code) in accounts.iter() {
+            let account_id: AccountId = account_id.parse().unwrap();
+            let account = Account::new(0, 0, *code.hash(), 0);
+            set_account(&mut state_update, account_id.clone(), &account);
+            set_code(&mut state_update, account_id, code);
+        }
+
+        assert_eq!(migrate_codes_to_shared_code(&mut state_update).unwrap(), 3);
+        assert_eq!(get_shared_code_refcount(&state_update, code.hash()).unwrap(), 2);
+        assert_eq!(get_shared_code_refcount(&state_update, other_code.hash()).unwrap(), 1);
+        for (account_id, code) in accounts.iter() {
+            let account_id: AccountId = account_id.parse().unwrap();
+            let stored = get_code(&state_update, &account_id, Some(*code.hash())).unwrap().unwrap();
+            assert_eq!(stored.code(), code.code());
+        }
+        // There is nothing left to migrate.
+        assert_eq!(migrate_codes_to_shared_code(&mut state_update).unwrap(), 0);
+    }
 }
@@ -837,3 +837,20 @@ pub fn migrate_29_to_30(path: &Path) {
 
     set_store_version(&store, 30);
 }
+
+/// `KnownPeerState` got a peer score, appended to the end of the struct. The score of the known
+/// peers starts as the default one: a zero `value` and a zero `last_updated`.
+pub fn migrate_31_to_32(path: &Path) {
+    const DEFAULT_PEER_SCORE: [u8; 12] = [0; 12];
+    let store = create_store(path);
+    let peers: Vec<_> = store.iter(DBCol::ColPeers).collect();
+    let mut store_update = store.store_update();
+    for (key, value) in peers {
+        let mut new_value = value.to_vec();
+        new_value.extend(DEFAULT_PEER_SCORE);
+        store_update.set(DBCol::ColPeers, &key, &new_value);
+    }
+    store_update.commit().unwrap();
+
+    set_store_version(&store, 32);
+}
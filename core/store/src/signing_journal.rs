@@ -0,0 +1,287 @@
+//! Journal of the messages signed by a validator. Before a signed block, chunk or approval is
+//! released, the highest height signed for its kind is checked and updated, so that a validator
+//! never releases two conflicting signatures for the same height, e.g. when a backup node is
+//! running with the same key.
+//!
+//! The journal guards the release of signatures by the client rather than the signing itself,
+//! so it also covers a remote signer, but nodes failing over to each other must see the same
+//! journal: only `SharedFile` and `LocalSocket` modes keep it outside of the node's database.
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs::OpenOptions;
+use std::io::{self, Read, Seek, SeekFrom, Write};
+#[cfg(unix)]
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use borsh::{BorshDeserialize, BorshSerialize};
+use fs2::FileExt;
+
+use near_primitives::hash::CryptoHash;
+use near_primitives::types::{BlockHeight, ShardId};
+
+use crate::{DBCol, Store};
+
+const SIGNING_JOURNAL_KEY_PREFIX: &[u8] = b"SIGNING_JOURNAL:";
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SignedMessageKind {
+    Block,
+    Chunk(ShardId),
+    Approval,
+}
+
+impl SignedMessageKind {
+    fn key(&self) -> String {
+        match self {
+            SignedMessageKind::Block => "block".to_string(),
+            SignedMessageKind::Chunk(shard_id) => format!("chunk:{}", shard_id),
+            SignedMessageKind::Approval => "approval".to_string(),
+        }
+    }
+}
+
+impl fmt::Display for SignedMessageKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.key())
+    }
+}
+
+/// The highest height signed for a message kind and the hash of what was signed at it. For
+/// approvals the height is the target height.
+#[derive(BorshSerialize, BorshDeserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SignedRecord {
+    pub height: BlockHeight,
+    pub hash: CryptoHash,
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum SigningJournalError {
+    #[error("Refusing to sign {kind} {} at height {}, already signed {} at height {}", .record.hash, .record.height, .signed.hash, .signed.height)]
+    Conflict { kind: SignedMessageKind, record: SignedRecord, signed: SignedRecord },
+    #[error("Signing lock {0:?} is held by another process")]
+    LockHeld(PathBuf),
+    #[error("Signing journal IO error: {0}")]
+    IOError(#[from] io::Error),
+}
+
+/// Where the journal is kept and how concurrent signers are excluded.
+#[derive(Clone, Debug)]
+pub enum SigningJournalMode {
+    /// In the node's own database. Protects a single node across restarts.
+    Local,
+    /// In a file shared by a hot-standby pair, e.g. on a common volume. The file is exclusively
+    /// locked while a message is checked and recorded, so each node sees what the other signed.
+    SharedFile(PathBuf),
+    /// In a file next to the Unix socket at the given path, as with `SharedFile`, but only while
+    /// this process holds the socket. Of the nodes on a host using the same path, only the first
+    /// one signs, and the one taking over sees what it signed. Only supported on Unix.
+    LocalSocket(PathBuf),
+}
+
+pub struct SigningJournal {
+    store: Store,
+    mode: SigningJournalMode,
+    /// Serializes checks within the process and keeps the socket lock, once acquired.
+    #[cfg(unix)]
+    socket_lock: Mutex<Option<UnixListener>>,
+    #[cfg(not(unix))]
+    socket_lock: Mutex<()>,
+}
+
+impl SigningJournal {
+    pub fn new(store: Store, mode: SigningJournalMode) -> Self {
+        Self { store, mode, socket_lock: Mutex::default() }
+    }
+
+    /// Records that a message of the given kind was signed at `height` with the given hash.
+    /// Fails, and records nothing, if something else was already signed at this or a greater
+    /// height. Signing the same message again is allowed.
+    pub fn check_and_record(
+        &self,
+        kind: SignedMessageKind,
+        height: BlockHeight,
+        hash: CryptoHash,
+    ) -> Result<(), SigningJournalError> {
+        #[allow(unused_mut, unused_variables)]
+        let mut socket_lock = self.socket_lock.lock().unwrap();
+        let record = SignedRecord { height, hash };
+        match &self.mode {
+            SigningJournalMode::Local => self.check_and_record_in_store(kind, record),
+            SigningJournalMode::SharedFile(path) => check_and_record_in_file(path, kind, record),
+            #[cfg(unix)]
+            SigningJournalMode::LocalSocket(path) => {
+                if socket_lock.is_none() {
+                    *socket_lock = Some(acquire_socket_lock(path)?);
+                }
+                check_and_record_in_file(&socket_journal_path(path), kind, record)
+            }
+            #[cfg(not(unix))]
+            SigningJournalMode::LocalSocket(_) => Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "local socket signing lock is only supported on unix",
+            )
+            .into()),
+        }
+    }
+
+    fn check_and_record_in_store(
+        &self,
+        kind: SignedMessageKind,
+        record: SignedRecord,
+    ) -> Result<(), SigningJournalError> {
+        let key = [SIGNING_JOURNAL_KEY_PREFIX, kind.key().as_bytes()].concat();
+        let signed = self.store.get_ser::<SignedRecord>(DBCol::ColBlockMisc, &key)?;
+        if check_record(kind, signed, record)? {
+            let mut store_update = self.store.store_update();
+            store_update.set_ser(DBCol::ColBlockMisc, &key, &record)?;
+            store_update.commit()?;
+        }
+        Ok(())
+    }
+}
+
+/// Returns whether `record` has to be written, or an error if it conflicts with `signed`.
+fn check_record(
+    kind: SignedMessageKind,
+    signed: Option<SignedRecord>,
+    record: SignedRecord,
+) -> Result<bool, SigningJournalError> {
+    match signed {
+        Some(signed) if signed == record => Ok(false),
+        Some(signed) if signed.height >= record.height => {
+            Err(SigningJournalError::Conflict { kind, record, signed })
+        }
+        _ => Ok(true),
+    }
+}
+
+fn check_and_record_in_file(
+    path: &Path,
+    kind: SignedMessageKind,
+    record: SignedRecord,
+) -> Result<(), SigningJournalError> {
+    let mut file = OpenOptions::new().read(true).write(true).create(true).open(path)?;
+    file.lock_exclusive()?;
+    let result = (|| -> Result<(), SigningJournalError> {
+        let mut content = vec![];
+        file.read_to_end(&mut content)?;
+        let mut records = if content.is_empty() {
+            BTreeMap::new()
+        } else {
+            BTreeMap::<String, SignedRecord>::try_from_slice(&content)?
+        };
+        if check_record(kind, records.get(&kind.key()).cloned(), record)? {
+            records.insert(kind.key(), record);
+            let content = records.try_to_vec()?;
+            file.seek(SeekFrom::Start(0))?;
+            file.set_len(0)?;
+            file.write_all(&content)?;
+            file.sync_all()?;
+        }
+        Ok(())
+    })();
+    file.unlock()?;
+    result
+}
+
+/// Path of the journal kept for the nodes locking the Unix socket at `path`.
+#[cfg(unix)]
+fn socket_journal_path(path: &Path) -> PathBuf {
+    let mut journal_path = path.as_os_str().to_owned();
+    journal_path.push(".journal");
+    journal_path.into()
+}
+
+/// Binds the Unix socket at `path`, which is held until the process exits. A socket file left
+/// behind by a process which is gone is replaced.
+#[cfg(unix)]
+fn acquire_socket_lock(path: &Path) -> Result<UnixListener, SigningJournalError> {
+    match UnixListener::bind(path) {
+        Ok(listener) => Ok(listener),
+        Err(err) if err.kind() == io::ErrorKind::AddrInUse => {
+            if UnixStream::connect(path).is_ok() {
+                return Err(SigningJournalError::LockHeld(path.to_path_buf()));
+            }
+            std::fs::remove_file(path)?;
+            Ok(UnixListener::bind(path)?)
+        }
+        Err(err) => Err(err.into()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use near_primitives::hash::hash;
+
+    use super::*;
+    use crate::test_utils::create_test_store;
+
+    #[test]
+    fn test_local_journal() {
+        let journal = SigningJournal::new(create_test_store(), SigningJournalMode::Local);
+        journal.check_and_record(SignedMessageKind::Block, 10, hash(b"a")).unwrap();
+        // The same block can be released again.
+        journal.check_and_record(SignedMessageKind::Block, 10, hash(b"a")).unwrap();
+        assert!(matches!(
+            journal.check_and_record(SignedMessageKind::Block, 10, hash(b"b")),
+            Err(SigningJournalError::Conflict { .. })
+        ));
+        assert!(matches!(
+            journal.check_and_record(SignedMessageKind::Block, 9, hash(b"c")),
+            Err(SigningJournalError::Conflict { .. })
+        ));
+        journal.check_and_record(SignedMessageKind::Block, 11, hash(b"b")).unwrap();
+        // Kinds and shards are tracked separately.
+        journal.check_and_record(SignedMessageKind::Chunk(0), 10, hash(b"b")).unwrap();
+        journal.check_and_record(SignedMessageKind::Chunk(1), 10, hash(b"c")).unwrap();
+        journal.check_and_record(SignedMessageKind::Approval, 10, hash(b"d")).unwrap();
+    }
+
+    #[test]
+    fn test_shared_file_journal() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("signing_journal");
+        let primary =
+            SigningJournal::new(create_test_store(), SigningJournalMode::SharedFile(path.clone()));
+        let standby =
+            SigningJournal::new(create_test_store(), SigningJournalMode::SharedFile(path));
+        primary.check_and_record(SignedMessageKind::Approval, 5, hash(b"a")).unwrap();
+        assert!(matches!(
+            standby.check_and_record(SignedMessageKind::Approval, 5, hash(b"b")),
+            Err(SigningJournalError::Conflict { .. })
+        ));
+        standby.check_and_record(SignedMessageKind::Approval, 6, hash(b"b")).unwrap();
+        assert!(matches!(
+            primary.check_and_record(SignedMessageKind::Approval, 6, hash(b"c")),
+            Err(SigningJournalError::Conflict { .. })
+        ));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_local_socket_lock() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("signing.sock");
+        let primary =
+            SigningJournal::new(create_test_store(), SigningJournalMode::LocalSocket(path.clone()));
+        let standby =
+            SigningJournal::new(create_test_store(), SigningJournalMode::LocalSocket(path.clone()));
+        primary.check_and_record(SignedMessageKind::Block, 1, hash(b"a")).unwrap();
+        assert!(matches!(
+            standby.check_and_record(SignedMessageKind::Block, 1, hash(b"a")),
+            Err(SigningJournalError::LockHeld(_))
+        ));
+        drop(primary);
+        // The socket file is left behind, but nobody listens on it anymore.
+        assert!(path.exists());
+        // The journal is shared, so the standby doesn't sign over what the primary signed.
+        assert!(matches!(
+            standby.check_and_record(SignedMessageKind::Block, 1, hash(b"b")),
+            Err(SigningJournalError::Conflict { .. })
+        ));
+        standby.check_and_record(SignedMessageKind::Block, 2, hash(b"b")).unwrap();
+    }
+}
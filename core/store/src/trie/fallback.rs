@@ -0,0 +1,28 @@
+use near_primitives::trie_key::TrieKey;
+use near_primitives::types::StateRoot;
+
+use crate::StorageError;
+
+/// Source of values for keys which are missing from the local trie.
+///
+/// Sandbox nodes use this to lazily fork the state of another network: reads which miss the
+/// local trie are answered by the fallback, and `TrieUpdate::finalize` writes every value obtained
+/// this way into the local trie, so later reads no longer reach the fallback.
+///
+/// The fallback only serves the state roots it covers, which are the roots of the forked state
+/// and the roots derived from them by `TrieUpdate::finalize`. Iterating over the trie only sees
+/// keys which are present locally.
+pub trait TrieFallback: Send + Sync {
+    /// Whether reads of the state at `root` which miss the local trie go to the fallback.
+    fn covers(&self, root: &StateRoot) -> bool;
+
+    /// Called when a state update on top of a covered root produces `root`.
+    fn add_root(&self, root: StateRoot);
+
+    /// Returns the value of `key`, or `None` if the key doesn't exist in the fallback state.
+    fn get(&self, key: &TrieKey) -> Result<Option<Vec<u8>>, StorageError>;
+
+    /// Called when `key` is deleted from the local trie, so that the fallback no longer
+    /// returns a value for it.
+    fn mark_deleted(&self, key: &TrieKey);
+}
@@ -14,6 +14,8 @@ use near_primitives::hash::{hash, CryptoHash};
 pub use near_primitives::shard_layout::ShardUId;
 use near_primitives::types::{StateRoot, StateRootNode};
 
+#[cfg(feature = "sandbox")]
+pub use crate::trie::fallback::TrieFallback;
 use crate::trie::insert_delete::NodesStorage;
 use crate::trie::iterator::TrieIterator;
 use crate::trie::nibble_slice::NibbleSlice;
@@ -24,6 +26,8 @@ use crate::trie::trie_storage::{
 pub(crate) use crate::trie::trie_storage::{TrieCache, TrieCachingStorage};
 use crate::StorageError;
 
+#[cfg(feature = "sandbox")]
+mod fallback;
 mod insert_delete;
 pub mod iterator;
 mod nibble_slice;
@@ -409,6 +413,9 @@ impl RawTrieNodeWithSize {
 pub struct Trie {
     pub(crate) storage: Box<dyn TrieStorage>,
     pub counter: TouchedNodesCounter,
+    /// Source of values for keys missing from this trie, used by sandbox forks.
+    #[cfg(feature = "sandbox")]
+    pub(crate) fallback: Option<Arc<dyn TrieFallback>>,
 }
 
 /// Stores reference count change for some key-value pair in DB.
@@ -472,7 +479,12 @@ pub struct ApplyStatePartResult {
 
 impl Trie {
     pub fn new(store: Box<dyn TrieStorage>, _shard_uid: ShardUId) -> Self {
-        Trie { storage: store, counter: TouchedNodesCounter::default() }
+        Trie {
+            storage: store,
+            counter: TouchedNodesCounter::default(),
+            #[cfg(feature = "sandbox")]
+            fallback: None,
+        }
     }
 
     pub fn recording_reads(&self) -> Self {
@@ -483,7 +495,12 @@ impl Trie {
             shard_uid: storage.shard_uid,
             recorded: RefCell::new(Default::default()),
         };
-        Trie { storage: Box::new(storage), counter: TouchedNodesCounter::default() }
+        Trie {
+            storage: Box::new(storage),
+            counter: TouchedNodesCounter::default(),
+            #[cfg(feature = "sandbox")]
+            fallback: None,
+        }
     }
 
     pub fn empty_root() -> StateRoot {
@@ -507,6 +524,8 @@ impl Trie {
                 visited_nodes: Default::default(),
             }),
             counter: TouchedNodesCounter::default(),
+            #[cfg(feature = "sandbox")]
+            fallback: None,
         }
     }
 
@@ -1,8 +1,9 @@
 use std::rc::Rc;
 use std::sync::{Arc, RwLock};
 
-use borsh::BorshSerialize;
-use near_primitives::borsh::maybestd::collections::HashMap;
+use borsh::{BorshDeserialize, BorshSerialize};
+use near_primitives::account::Account;
+use near_primitives::borsh::maybestd::collections::{HashMap, HashSet};
 use near_primitives::hash::CryptoHash;
 use near_primitives::shard_layout;
 use near_primitives::shard_layout::{ShardUId, ShardVersion};
@@ -13,6 +14,8 @@ use near_primitives::types::{
 
 use crate::db::{DBCol, DBOp, DBTransaction};
 use crate::trie::trie_storage::{TrieCache, TrieCachingStorage};
+#[cfg(feature = "sandbox")]
+use crate::trie::TrieFallback;
 use crate::trie::{TrieRefcountChange, POISONED_LOCK_ERR};
 use crate::{StorageError, Store, StoreUpdate, Trie, TrieChanges, TrieUpdate};
 
@@ -22,6 +25,9 @@ struct ShardTriesInner {
     caches: RwLock<HashMap<ShardUId, TrieCache>>,
     /// Cache for readers.
     view_caches: RwLock<HashMap<ShardUId, TrieCache>>,
+    /// Source of values for keys missing from the local state, see `TrieFallback`.
+    #[cfg(feature = "sandbox")]
+    fallback: RwLock<Option<Arc<dyn TrieFallback>>>,
 }
 
 #[derive(Clone)]
@@ -41,6 +47,8 @@ impl ShardTries {
             store,
             caches: RwLock::new(Self::get_new_cache(&shards)),
             view_caches: RwLock::new(Self::get_new_cache(&shards)),
+            #[cfg(feature = "sandbox")]
+            fallback: RwLock::new(None),
         }))
     }
 
@@ -63,7 +71,20 @@ impl ShardTries {
             caches.entry(shard_uid).or_insert_with(TrieCache::new).clone()
         };
         let store = Box::new(TrieCachingStorage::new(self.0.store.clone(), cache, shard_uid));
-        Trie::new(store, shard_uid)
+        #[allow(unused_mut)]
+        let mut trie = Trie::new(store, shard_uid);
+        #[cfg(feature = "sandbox")]
+        {
+            trie.fallback = self.0.fallback.read().expect(POISONED_LOCK_ERR).clone();
+        }
+        trie
+    }
+
+    /// Sets the source of values for keys missing from the local state. Affects tries
+    /// created after this call.
+    #[cfg(feature = "sandbox")]
+    pub fn set_fallback(&self, fallback: Arc<dyn TrieFallback>) {
+        *self.0.fallback.write().expect(POISONED_LOCK_ERR) = Some(fallback);
     }
 
     pub fn get_trie_for_shard(&self, shard_uid: ShardUId) -> Trie {
@@ -286,8 +307,12 @@ impl WrappedTrieChanges {
     /// Save state changes into Store.
     ///
     /// NOTE: the changes are drained from `self`.
-    pub fn state_changes_into(&mut self, store_update: &mut StoreUpdate) {
-        for change_with_trie_key in self.state_changes.drain(..) {
+    pub fn state_changes_into(
+        &mut self,
+        store_update: &mut StoreUpdate,
+    ) -> Result<(), StorageError> {
+        let code_changes = self.shared_contract_code_changes()?;
+        for mut change_with_trie_key in self.state_changes.drain(..).chain(code_changes) {
             assert!(
                 !change_with_trie_key.changes.iter().any(|RawStateChange { cause, .. }| matches!(
                     cause,
@@ -309,9 +334,18 @@ impl WrappedTrieChanges {
             // when the node tracks multiple shards. See #2563.
             match &change_with_trie_key.trie_key {
                 TrieKey::Account { .. }
-                | TrieKey::ContractCode { .. }
                 | TrieKey::AccessKey { .. }
                 | TrieKey::ContractData { .. } => {}
+                TrieKey::ContractCode { .. } => {
+                    // Moving the codes into the shared contract code doesn't change the code
+                    // deployed on accounts.
+                    change_with_trie_key.changes.retain(|RawStateChange { cause, .. }| {
+                        !matches!(cause, StateChangeCause::Migration)
+                    });
+                    if change_with_trie_key.changes.is_empty() {
+                        continue;
+                    }
+                }
                 _ => continue,
             };
             let storage_key = KeyForStateChanges::new_from_trie_key(
@@ -324,6 +358,111 @@ impl WrappedTrieChanges {
                 &change_with_trie_key.try_to_vec().expect("Borsh serialize cannot fail"),
             );
         }
+        Ok(())
+    }
+
+    /// Returns the changes of the codes deployed on accounts which refer to the shared contract
+    /// code, keyed by `TrieKey::ContractCode` as if the codes were stored under the accounts.
+    ///
+    /// Such codes change together with `Account::code_hash`, so redeploying the same code is not
+    /// reported.
+    fn shared_contract_code_changes(
+        &self,
+    ) -> Result<Vec<RawStateChangesWithTrieKey>, StorageError> {
+        // A shared code can't be deployed or removed without changing its refcount.
+        if !self.state_changes.iter().any(|change_with_trie_key| {
+            matches!(change_with_trie_key.trie_key, TrieKey::SharedContractCodeRefcount { .. })
+        }) {
+            return Ok(vec![]);
+        }
+        let mut codes = HashMap::new();
+        let mut accounts_with_own_code = HashSet::new();
+        for change_with_trie_key in self.state_changes.iter() {
+            match &change_with_trie_key.trie_key {
+                TrieKey::SharedContractCode { code_hash } => {
+                    if let Some(code) =
+                        change_with_trie_key.changes.iter().find_map(|change| change.data.as_ref())
+                    {
+                        codes.insert(*code_hash, code.clone());
+                    }
+                }
+                TrieKey::ContractCode { account_id }
+                    if change_with_trie_key.changes.iter().any(
+                        |RawStateChange { cause, .. }| {
+                            !matches!(cause, StateChangeCause::Migration)
+                        },
+                    ) =>
+                {
+                    accounts_with_own_code.insert(account_id.clone());
+                }
+                _ => {}
+            }
+        }
+
+        let trie = self.tries.get_trie_for_shard(self.shard_uid);
+        let get_code = |code_hash: CryptoHash| -> Result<Vec<u8>, StorageError> {
+            if let Some(code) = codes.get(&code_hash) {
+                return Ok(code.clone());
+            }
+            // A code referenced during the block is either still referenced at the end of it, or
+            // was referenced before it.
+            let key = TrieKey::SharedContractCode { code_hash }.to_vec();
+            match trie.get(&self.trie_changes.new_root, &key)? {
+                Some(code) => Ok(code),
+                None => trie.get(&self.trie_changes.old_root, &key)?.ok_or_else(|| {
+                    StorageError::StorageInconsistentState(format!(
+                        "Shared contract code {} is missing",
+                        code_hash
+                    ))
+                }),
+            }
+        };
+        let get_code_hash = |value: Option<&Vec<u8>>| -> Result<CryptoHash, StorageError> {
+            match value {
+                Some(value) => Account::try_from_slice(value)
+                    .map(|account| account.code_hash())
+                    .map_err(|_| {
+                        StorageError::StorageInconsistentState(
+                            "Failed to parse account".to_string(),
+                        )
+                    }),
+                None => Ok(CryptoHash::default()),
+            }
+        };
+
+        let mut code_changes = vec![];
+        for change_with_trie_key in self.state_changes.iter() {
+            let account_id = match &change_with_trie_key.trie_key {
+                TrieKey::Account { account_id } if !accounts_with_own_code.contains(account_id) => {
+                    account_id
+                }
+                _ => continue,
+            };
+            let prev_account =
+                trie.get(&self.trie_changes.old_root, &change_with_trie_key.trie_key.to_vec())?;
+            let mut code_hash = get_code_hash(prev_account.as_ref())?;
+            let mut changes = vec![];
+            for RawStateChange { cause, data } in change_with_trie_key.changes.iter() {
+                let new_code_hash = get_code_hash(data.as_ref())?;
+                if new_code_hash == code_hash {
+                    continue;
+                }
+                let data = if new_code_hash == CryptoHash::default() {
+                    None
+                } else {
+                    Some(get_code(new_code_hash)?)
+                };
+                changes.push(RawStateChange { cause: cause.clone(), data });
+                code_hash = new_code_hash;
+            }
+            if !changes.is_empty() {
+                code_changes.push(RawStateChangesWithTrieKey {
+                    trie_key: TrieKey::ContractCode { account_id: account_id.clone() },
+                    changes,
+                });
+            }
+        }
+        Ok(code_changes)
     }
 
     pub fn wrapped_into(
@@ -331,7 +470,7 @@ impl WrappedTrieChanges {
         store_update: &mut StoreUpdate,
     ) -> Result<(), Box<dyn std::error::Error>> {
         self.insertions_into(store_update)?;
-        self.state_changes_into(store_update);
+        self.state_changes_into(store_update)?;
         store_update.set_ser(
             DBCol::ColTrieChanges,
             &shard_layout::get_block_shard_uid(&self.block_hash, &self.shard_uid),
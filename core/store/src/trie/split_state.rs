@@ -1,18 +1,25 @@
 use crate::trie::iterator::TrieItem;
 use crate::{
-    get, get_delayed_receipt_indices, set, ShardTries, StoreUpdate, Trie, TrieChanges, TrieUpdate,
+    get, get_account, get_delayed_receipt_indices, set, ShardTries, StoreUpdate, Trie, TrieChanges,
+    TrieUpdate,
 };
-use borsh::BorshDeserialize;
+use borsh::{BorshDeserialize, BorshSerialize};
 use bytesize::ByteSize;
 use near_primitives::account::id::AccountId;
+use near_primitives::checked_feature;
 use near_primitives::errors::StorageError;
+use near_primitives::hash::CryptoHash;
 use near_primitives::receipt::Receipt;
 use near_primitives::shard_layout::ShardUId;
-use near_primitives::trie_key::trie_key_parsers::parse_account_id_from_raw_key;
+use near_primitives::trie_key::trie_key_parsers::{
+    is_account_key, is_contract_code_key, is_shared_contract_code_key,
+    is_shared_contract_code_refcount_key, parse_account_id_from_raw_key,
+};
 use near_primitives::trie_key::TrieKey;
 use near_primitives::types::{
     ConsolidatedStateChange, StateChangeCause, StateChangesForSplitStates, StateRoot,
 };
+use near_primitives::version::ProtocolVersion;
 use std::collections::HashMap;
 
 impl Trie {
@@ -46,12 +53,19 @@ impl ShardTries {
     /// This function is used for applying updates to split states when processing blocks
     /// `add_values_to_split_states` are used to generate the initial states for shards split
     /// from the original parent shard.
+    /// `protocol_version` is the protocol version of the epoch `changes` belong to.
     pub fn apply_state_changes_to_split_states<'a>(
         &self,
         state_roots: &HashMap<ShardUId, StateRoot>,
         changes: StateChangesForSplitStates,
         account_id_to_shard_id: &(dyn Fn(&AccountId) -> ShardUId + 'a),
+        protocol_version: ProtocolVersion,
     ) -> Result<HashMap<ShardUId, TrieChanges>, StorageError> {
+        let deduplicate_code = checked_feature!(
+            "protocol_feature_contract_code_deduplication",
+            ContractCodeDeduplication,
+            protocol_version
+        );
         let mut trie_updates: HashMap<_, _> = self.get_trie_updates(state_roots);
         let mut insert_receipts = Vec::new();
         for ConsolidatedStateChange { trie_key, value } in changes.changes {
@@ -70,6 +84,36 @@ impl ShardTries {
                     }
                     None => {}
                 },
+                // Shared contract code is not tied to an account, so it is copied to all split
+                // states, and an account moved to a split state always finds its code there.
+                TrieKey::SharedContractCode { .. } => {
+                    for trie_update in trie_updates.values_mut() {
+                        match &value {
+                            Some(value) => trie_update.set(trie_key.clone(), value.clone()),
+                            None => trie_update.remove(trie_key.clone()),
+                        }
+                    }
+                }
+                // Refcounts only count the accounts of a split state, they are updated together
+                // with the accounts below.
+                TrieKey::SharedContractCodeRefcount { .. } => {}
+                TrieKey::Account { account_id } | TrieKey::ContractCode { account_id }
+                    if deduplicate_code =>
+                {
+                    let new_shard_uid = account_id_to_shard_id(account_id);
+                    let trie_update = trie_updates.get_mut(&new_shard_uid).unwrap();
+                    let prev_code_hash = get_shared_code_hash(trie_update, account_id)?;
+                    let account_id = account_id.clone();
+                    match value {
+                        Some(value) => trie_update.set(trie_key, value),
+                        None => trie_update.remove(trie_key),
+                    }
+                    let code_hash = get_shared_code_hash(trie_update, &account_id)?;
+                    if code_hash != prev_code_hash {
+                        update_shared_code_refcount(trie_update, prev_code_hash, -1)?;
+                        update_shared_code_refcount(trie_update, code_hash, 1)?;
+                    }
+                }
                 TrieKey::Account { account_id }
                 | TrieKey::ContractCode { account_id }
                 | TrieKey::AccessKey { account_id, .. }
@@ -117,14 +161,21 @@ impl ShardTries {
     /// The caller must guarantee that `state_roots` contains all shard_ids
     /// that `key_to_shard_id` that may return
     /// Ignore changes on DelayedReceipts or DelayedReceiptsIndices
+    /// `protocol_version` is the protocol version of the epoch the values belong to.
     /// Returns `store_update` and the new state_roots for split states
     pub fn add_values_to_split_states<'a>(
         &self,
         state_roots: &HashMap<ShardUId, StateRoot>,
         values: Vec<(Vec<u8>, Option<Vec<u8>>)>,
         account_id_to_shard_id: &(dyn Fn(&AccountId) -> ShardUId + 'a),
+        protocol_version: ProtocolVersion,
     ) -> Result<(StoreUpdate, HashMap<ShardUId, StateRoot>), StorageError> {
-        self.add_values_to_split_states_impl(state_roots, values, &|raw_key| {
+        let deduplicate_code = checked_feature!(
+            "protocol_feature_contract_code_deduplication",
+            ContractCodeDeduplication,
+            protocol_version
+        );
+        self.add_values_to_split_states_impl(state_roots, values, deduplicate_code, &|raw_key| {
             // Here changes on DelayedReceipts or DelayedReceiptsIndices will be excluded
             // This is because we cannot migrate delayed receipts part by part. They have to be
             // reconstructed in the new states after all DelayedReceipts are ready in the original
@@ -141,18 +192,90 @@ impl ShardTries {
         })
     }
 
+    /// `deduplicate_code` tells whether accounts may refer to shared contract code, whose
+    /// refcounts are then maintained in the split states.
     fn add_values_to_split_states_impl<'a>(
         &self,
         state_roots: &HashMap<ShardUId, StateRoot>,
         values: Vec<(Vec<u8>, Option<Vec<u8>>)>,
+        deduplicate_code: bool,
         key_to_shard_id: &(dyn Fn(&[u8]) -> Result<Option<ShardUId>, StorageError> + 'a),
     ) -> Result<(StoreUpdate, HashMap<ShardUId, StateRoot>), StorageError> {
         let mut changes_by_shard: HashMap<_, Vec<_>> = HashMap::new();
+        // The accounts and codes of `values` are also applied to these, so that whether an
+        // account refers to shared code can be checked before and after every value.
+        let mut trie_updates: HashMap<_, _> = self.get_trie_updates(state_roots);
+        let mut code_references: HashMap<_, i64> = HashMap::new();
         for (raw_key, value) in values.into_iter() {
+            if is_shared_contract_code_refcount_key(&raw_key) {
+                // Refcounts are computed from the accounts moved to every split state.
+                continue;
+            }
+            if is_shared_contract_code_key(&raw_key) {
+                // See `apply_state_changes_to_split_states`.
+                for shard_uid in state_roots.keys() {
+                    changes_by_shard
+                        .entry(*shard_uid)
+                        .or_default()
+                        .push((raw_key.clone(), value.clone()));
+                }
+                continue;
+            }
             if let Some(new_shard_uid) = key_to_shard_id(&raw_key)? {
+                if deduplicate_code && (is_account_key(&raw_key) || is_contract_code_key(&raw_key))
+                {
+                    let account_id = parse_account_id_from_raw_key(&raw_key)
+                        .ok()
+                        .flatten()
+                        .ok_or_else(|| {
+                            StorageError::StorageInconsistentState(format!(
+                                "Failed to parse account id from trie key {:?}",
+                                raw_key
+                            ))
+                        })?;
+                    let trie_key = if is_account_key(&raw_key) {
+                        TrieKey::Account { account_id: account_id.clone() }
+                    } else {
+                        TrieKey::ContractCode { account_id: account_id.clone() }
+                    };
+                    let trie_update = trie_updates.get_mut(&new_shard_uid).unwrap();
+                    if let Some(code_hash) = get_shared_code_hash(trie_update, &account_id)? {
+                        *code_references.entry((new_shard_uid, code_hash)).or_default() -= 1;
+                    }
+                    match &value {
+                        Some(value) => trie_update.set(trie_key, value.clone()),
+                        None => trie_update.remove(trie_key),
+                    }
+                    if let Some(code_hash) = get_shared_code_hash(trie_update, &account_id)? {
+                        *code_references.entry((new_shard_uid, code_hash)).or_default() += 1;
+                    }
+                }
                 changes_by_shard.entry(new_shard_uid).or_default().push((raw_key, value));
             }
         }
+        for ((shard_uid, code_hash), references) in code_references {
+            if references == 0 {
+                continue;
+            }
+            let key = TrieKey::SharedContractCodeRefcount { code_hash };
+            let refcount: u64 = get(&trie_updates[&shard_uid], &key)?.unwrap_or_default();
+            let refcount = if references < 0 {
+                refcount.checked_sub(references.unsigned_abs())
+            } else {
+                refcount.checked_add(references as u64)
+            }
+            .ok_or_else(|| {
+                StorageError::StorageInconsistentState(format!(
+                    "Invalid refcount of shared contract code {}",
+                    code_hash
+                ))
+            })?;
+            let value = match refcount {
+                0 => None,
+                refcount => Some(refcount.try_to_vec().expect("Borsh serialize cannot fail")),
+            };
+            changes_by_shard.entry(shard_uid).or_default().push((key.to_vec(), value));
+        }
         let mut new_state_roots = state_roots.clone();
         let mut store_update = StoreUpdate::new_with_tries(self.clone());
         for (shard_uid, changes) in changes_by_shard {
@@ -287,6 +410,57 @@ fn apply_delayed_receipts_to_split_states_impl<'a>(
     Ok(())
 }
 
+/// Returns the hash of the shared contract code the account refers to, if any. Accounts without
+/// code, or whose code is still stored under `TrieKey::ContractCode`, don't refer to shared code.
+fn get_shared_code_hash(
+    trie_update: &TrieUpdate,
+    account_id: &AccountId,
+) -> Result<Option<CryptoHash>, StorageError> {
+    let code_hash = match get_account(trie_update, account_id)? {
+        Some(account) => account.code_hash(),
+        None => return Ok(None),
+    };
+    if code_hash == CryptoHash::default()
+        || trie_update.get(&TrieKey::ContractCode { account_id: account_id.clone() })?.is_some()
+    {
+        return Ok(None);
+    }
+    Ok(Some(code_hash))
+}
+
+/// Adds `delta` references to the shared contract code in a split state. Unlike
+/// `remove_shared_code`, the code is kept when its last reference is removed, as the code of the
+/// split states follows the code of the parent shard.
+fn update_shared_code_refcount(
+    trie_update: &mut TrieUpdate,
+    code_hash: Option<CryptoHash>,
+    delta: i64,
+) -> Result<(), StorageError> {
+    let code_hash = match code_hash {
+        Some(code_hash) => code_hash,
+        None => return Ok(()),
+    };
+    let key = TrieKey::SharedContractCodeRefcount { code_hash };
+    let refcount: u64 = get(trie_update, &key)?.unwrap_or_default();
+    let refcount = if delta < 0 {
+        refcount.checked_sub(delta.unsigned_abs())
+    } else {
+        refcount.checked_add(delta as u64)
+    }
+    .ok_or_else(|| {
+        StorageError::StorageInconsistentState(format!(
+            "Invalid refcount of shared contract code {}",
+            code_hash
+        ))
+    })?;
+    if refcount == 0 {
+        trie_update.remove(key);
+    } else {
+        set(trie_update, key, &refcount);
+    }
+    Ok(())
+}
+
 /// Retrieve delayed receipts starting with `start_index` until `memory_limit` is hit
 /// return None if there is no delayed receipts with index >= start_index
 pub fn get_delayed_receipts(
@@ -344,6 +518,7 @@ mod tests {
     use near_primitives::types::{
         NumShards, StateChangeCause, StateChangesForSplitStates, StateRoot,
     };
+    use near_primitives::version::PROTOCOL_VERSION;
     use rand::seq::SliceRandom;
     use rand::Rng;
     use std::collections::HashMap;
@@ -485,7 +660,7 @@ mod tests {
                 );
 
                 let (store_update, new_state_roots) = tries
-                    .add_values_to_split_states_impl(&state_roots, changes, &|raw_key| {
+                    .add_values_to_split_states_impl(&state_roots, changes, false, &|raw_key| {
                         Ok(Some(ShardUId {
                             version: 1,
                             shard_id: (hash(raw_key).0[0] as NumShards % num_shards) as u32,
@@ -508,6 +683,146 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_add_values_to_split_states_shared_code_refcount() {
+        let tries = create_tries();
+        let code_hash = hash(b"code");
+        let account_ids: Vec<AccountId> =
+            vec!["alice".parse().unwrap(), "bob".parse().unwrap(), "carol".parse().unwrap()];
+        let mut values: Vec<_> = account_ids
+            .iter()
+            .map(|account_id| {
+                let account = Account::new(0, 0, code_hash, 0);
+                (
+                    TrieKey::Account { account_id: account_id.clone() }.to_vec(),
+                    Some(account.try_to_vec().unwrap()),
+                )
+            })
+            .collect();
+        // "dave" still has its code stored under the account, so it doesn't refer to the shared
+        // code.
+        let dave: AccountId = "dave".parse().unwrap();
+        values.push((
+            TrieKey::ContractCode { account_id: dave.clone() }.to_vec(),
+            Some(b"code".to_vec()),
+        ));
+        values.push((
+            TrieKey::Account { account_id: dave }.to_vec(),
+            Some(Account::new(0, 0, code_hash, 0).try_to_vec().unwrap()),
+        ));
+        values.push((TrieKey::SharedContractCode { code_hash }.to_vec(), Some(b"code".to_vec())));
+        values.push((
+            TrieKey::SharedContractCodeRefcount { code_hash }.to_vec(),
+            Some(3u64.try_to_vec().unwrap()),
+        ));
+        let state_roots: HashMap<_, _> =
+            (0..2).map(|x| (ShardUId { version: 1, shard_id: x }, CryptoHash::default())).collect();
+        let (store_update, state_roots) = tries
+            .add_values_to_split_states_impl(&state_roots, values, true, &|raw_key| {
+                // "carol" goes to the second shard, the other accounts to the first one.
+                let account_id = parse_account_id_from_raw_key(raw_key).unwrap().unwrap();
+                let shard_id = if account_id.as_ref() == "carol" { 1 } else { 0 };
+                Ok(Some(ShardUId { version: 1, shard_id }))
+            })
+            .unwrap();
+        store_update.commit().unwrap();
+
+        for (shard_id, expected_refcount) in [(0, 2u64), (1, 1u64)] {
+            let shard_uid = ShardUId { version: 1, shard_id };
+            let trie = tries.get_trie_for_shard(shard_uid);
+            let state_root = &state_roots[&shard_uid];
+            let code_key = TrieKey::SharedContractCode { code_hash }.to_vec();
+            assert_eq!(trie.get(state_root, &code_key).unwrap(), Some(b"code".to_vec()));
+            let refcount_key = TrieKey::SharedContractCodeRefcount { code_hash }.to_vec();
+            assert_eq!(
+                trie.get(state_root, &refcount_key).unwrap(),
+                Some(expected_refcount.try_to_vec().unwrap())
+            );
+        }
+    }
+
+    #[test]
+    fn test_add_values_to_split_states_without_code_deduplication() {
+        let tries = create_tries();
+        let code_hash = hash(b"code");
+        let values = vec![(
+            TrieKey::Account { account_id: "alice".parse().unwrap() }.to_vec(),
+            Some(Account::new(0, 0, code_hash, 0).try_to_vec().unwrap()),
+        )];
+        let shard_uid = ShardUId { version: 1, shard_id: 0 };
+        let state_roots: HashMap<_, _> =
+            vec![(shard_uid, CryptoHash::default())].into_iter().collect();
+        let (store_update, state_roots) = tries
+            .add_values_to_split_states_impl(&state_roots, values, false, &|_| Ok(Some(shard_uid)))
+            .unwrap();
+        store_update.commit().unwrap();
+        let refcount_key = TrieKey::SharedContractCodeRefcount { code_hash }.to_vec();
+        let trie = tries.get_trie_for_shard(shard_uid);
+        assert_eq!(trie.get(&state_roots[&shard_uid], &refcount_key).unwrap(), None);
+    }
+
+    /// Moving the code of an account to the shared contract code, as the migration to
+    /// `ContractCodeDeduplication` does, adds a reference to the shared code in the split state.
+    #[cfg(feature = "protocol_feature_contract_code_deduplication")]
+    #[test]
+    fn test_apply_state_changes_to_split_states_shared_code_refcount() {
+        use near_primitives::types::ConsolidatedStateChange;
+
+        let tries = create_tries();
+        let shard_uid = ShardUId { version: 1, shard_id: 0 };
+        let alice: AccountId = "alice".parse().unwrap();
+        let code = b"code".to_vec();
+        let code_hash = hash(&code);
+        let refcount_key = TrieKey::SharedContractCodeRefcount { code_hash }.to_vec();
+        let apply = |state_root: StateRoot, changes: Vec<(TrieKey, Option<Vec<u8>>)>| {
+            let state_roots: HashMap<_, _> = vec![(shard_uid, state_root)].into_iter().collect();
+            let changes = StateChangesForSplitStates {
+                changes: changes
+                    .into_iter()
+                    .map(|(trie_key, value)| ConsolidatedStateChange { trie_key, value })
+                    .collect(),
+                processed_delayed_receipts: vec![],
+            };
+            let trie_changes = tries
+                .apply_state_changes_to_split_states(
+                    &state_roots,
+                    changes,
+                    &|_| shard_uid,
+                    PROTOCOL_VERSION,
+                )
+                .unwrap();
+            let (store_update, state_root) =
+                tries.apply_all(&trie_changes[&shard_uid], shard_uid).unwrap();
+            store_update.commit().unwrap();
+            state_root
+        };
+
+        let state_root = apply(
+            CryptoHash::default(),
+            vec![
+                (
+                    TrieKey::Account { account_id: alice.clone() },
+                    Some(Account::new(0, 0, code_hash, 0).try_to_vec().unwrap()),
+                ),
+                (TrieKey::ContractCode { account_id: alice.clone() }, Some(code.clone())),
+            ],
+        );
+        let trie = tries.get_trie_for_shard(shard_uid);
+        assert_eq!(trie.get(&state_root, &refcount_key).unwrap(), None);
+
+        let state_root = apply(
+            state_root,
+            vec![
+                (TrieKey::ContractCode { account_id: alice.clone() }, None),
+                (TrieKey::SharedContractCode { code_hash }, Some(code)),
+            ],
+        );
+        assert_eq!(trie.get(&state_root, &refcount_key).unwrap(), Some(1u64.try_to_vec().unwrap()));
+
+        let state_root = apply(state_root, vec![(TrieKey::Account { account_id: alice }, None)]);
+        assert_eq!(trie.get(&state_root, &refcount_key).unwrap(), None);
+    }
+
     #[test]
     fn test_get_delayed_receipts() {
         let mut rng = rand::thread_rng();
@@ -692,6 +1007,7 @@ mod tests {
                     &split_state_roots,
                     trie_items.into_iter().map(|(key, value)| (key, Some(value))).collect(),
                     account_id_to_shard_id,
+                    PROTOCOL_VERSION,
                 )
                 .unwrap();
             store_update.commit().unwrap();
@@ -781,6 +1097,7 @@ mod tests {
                         removed_receipts,
                     ),
                     account_id_to_shard_id,
+                    PROTOCOL_VERSION,
                 )
                 .unwrap();
             split_state_roots = trie_changes
@@ -1,3 +1,5 @@
+#[cfg(feature = "sandbox")]
+use std::cell::RefCell;
 use std::collections::BTreeMap;
 use std::iter::Peekable;
 
@@ -28,11 +30,17 @@ pub struct TrieUpdate {
     root: CryptoHash,
     committed: RawStateChanges,
     prospective: TrieUpdates,
+    /// Values which were missing from the trie and were obtained from its fallback.
+    /// They are written into the trie on `finalize`.
+    #[cfg(feature = "sandbox")]
+    fetched: RefCell<BTreeMap<Vec<u8>, (TrieKey, Vec<u8>)>>,
 }
 
 pub enum TrieUpdateValuePtr<'a> {
     HashAndSize(&'a Trie, u32, CryptoHash),
     MemoryRef(&'a Vec<u8>),
+    #[cfg(feature = "sandbox")]
+    Owned(Vec<u8>),
 }
 
 impl<'a> TrieUpdateValuePtr<'a> {
@@ -40,6 +48,8 @@ impl<'a> TrieUpdateValuePtr<'a> {
         match self {
             TrieUpdateValuePtr::MemoryRef(value) => value.len() as u32,
             TrieUpdateValuePtr::HashAndSize(_, length, _) => *length,
+            #[cfg(feature = "sandbox")]
+            TrieUpdateValuePtr::Owned(value) => value.len() as u32,
         }
     }
 
@@ -49,21 +59,30 @@ impl<'a> TrieUpdateValuePtr<'a> {
             TrieUpdateValuePtr::HashAndSize(trie, _, hash) => {
                 trie.retrieve_raw_bytes(hash).map(|bytes| bytes.to_vec())
             }
+            #[cfg(feature = "sandbox")]
+            TrieUpdateValuePtr::Owned(value) => Ok(value.clone()),
         }
     }
 }
 
 impl TrieUpdate {
     pub fn new(trie: Rc<Trie>, root: CryptoHash) -> Self {
-        TrieUpdate { trie, root, committed: Default::default(), prospective: Default::default() }
+        TrieUpdate {
+            trie,
+            root,
+            committed: Default::default(),
+            prospective: Default::default(),
+            #[cfg(feature = "sandbox")]
+            fetched: Default::default(),
+        }
     }
 
     pub fn trie(&self) -> &Trie {
         self.trie.as_ref()
     }
 
-    pub fn get(&self, key: &TrieKey) -> Result<Option<Vec<u8>>, StorageError> {
-        let key = key.to_vec();
+    pub fn get(&self, trie_key: &TrieKey) -> Result<Option<Vec<u8>>, StorageError> {
+        let key = trie_key.to_vec();
         if let Some(key_value) = self.prospective.get(&key) {
             return Ok(key_value.value.as_ref().map(<Vec<u8>>::clone));
         } else if let Some(changes_with_trie_key) = self.committed.get(&key) {
@@ -72,11 +91,19 @@ impl TrieUpdate {
             }
         }
 
-        self.trie.get(&self.root, &key)
+        let value = self.trie.get(&self.root, &key)?;
+        #[cfg(feature = "sandbox")]
+        if value.is_none() {
+            return self.get_from_fallback(trie_key, key);
+        }
+        Ok(value)
     }
 
-    pub fn get_ref(&self, key: &TrieKey) -> Result<Option<TrieUpdateValuePtr<'_>>, StorageError> {
-        let key = key.to_vec();
+    pub fn get_ref(
+        &self,
+        trie_key: &TrieKey,
+    ) -> Result<Option<TrieUpdateValuePtr<'_>>, StorageError> {
+        let key = trie_key.to_vec();
         if let Some(key_value) = self.prospective.get(&key) {
             return Ok(key_value.value.as_ref().map(TrieUpdateValuePtr::MemoryRef));
         } else if let Some(changes_with_trie_key) = self.committed.get(&key) {
@@ -84,9 +111,63 @@ impl TrieUpdate {
                 return Ok(data.as_ref().map(TrieUpdateValuePtr::MemoryRef));
             }
         }
-        self.trie.get_ref(&self.root, &key).map(|option| {
-            option.map(|(length, hash)| TrieUpdateValuePtr::HashAndSize(&self.trie, length, hash))
-        })
+        let value_ref = self.trie.get_ref(&self.root, &key)?;
+        #[cfg(feature = "sandbox")]
+        if value_ref.is_none() {
+            return Ok(self.get_from_fallback(trie_key, key)?.map(TrieUpdateValuePtr::Owned));
+        }
+        Ok(value_ref
+            .map(|(length, hash)| TrieUpdateValuePtr::HashAndSize(&self.trie, length, hash)))
+    }
+
+    /// Looks up a key which is missing from the trie in the trie fallback, if there is one.
+    #[cfg(feature = "sandbox")]
+    fn get_from_fallback(
+        &self,
+        trie_key: &TrieKey,
+        key: Vec<u8>,
+    ) -> Result<Option<Vec<u8>>, StorageError> {
+        let fallback = match &self.trie.fallback {
+            Some(fallback) if fallback.covers(&self.root) => fallback,
+            _ => return Ok(None),
+        };
+        if let Some((_, value)) = self.fetched.borrow().get(&key) {
+            return Ok(Some(value.clone()));
+        }
+        let value = fallback.get(trie_key)?;
+        if let Some(value) = &value {
+            self.fetched.borrow_mut().insert(key, (trie_key.clone(), value.clone()));
+        }
+        Ok(value)
+    }
+
+    /// Adds the values obtained from the trie fallback to the committed changes, so that they
+    /// get persisted in the trie, and tells the fallback about deleted keys.
+    #[cfg(feature = "sandbox")]
+    fn merge_fetched_values(
+        trie: &Trie,
+        root: &CryptoHash,
+        fetched: BTreeMap<Vec<u8>, (TrieKey, Vec<u8>)>,
+        committed: &mut RawStateChanges,
+    ) {
+        let fallback = match &trie.fallback {
+            Some(fallback) if fallback.covers(root) => fallback,
+            _ => return,
+        };
+        for changes_with_trie_key in committed.values() {
+            if let Some(RawStateChange { data: None, .. }) = changes_with_trie_key.changes.last() {
+                fallback.mark_deleted(&changes_with_trie_key.trie_key);
+            }
+        }
+        for (key, (trie_key, value)) in fetched {
+            committed.entry(key).or_insert_with(|| RawStateChangesWithTrieKey {
+                trie_key,
+                changes: vec![RawStateChange {
+                    cause: StateChangeCause::InitialState,
+                    data: Some(value),
+                }],
+            });
+        }
     }
 
     pub fn set(&mut self, trie_key: TrieKey, value: Vec<u8>) {
@@ -118,7 +199,10 @@ impl TrieUpdate {
 
     pub fn finalize(self) -> Result<(TrieChanges, Vec<RawStateChangesWithTrieKey>), StorageError> {
         assert!(self.prospective.is_empty(), "Finalize cannot be called with uncommitted changes.");
-        let TrieUpdate { trie, root, committed, .. } = self;
+        #[allow(unused_mut)]
+        let TrieUpdate { trie, root, mut committed, .. } = self;
+        #[cfg(feature = "sandbox")]
+        Self::merge_fetched_values(&trie, &root, self.fetched.into_inner(), &mut committed);
         let mut state_changes = Vec::with_capacity(committed.len());
         let trie_changes = trie.update(
             &root,
@@ -133,6 +217,10 @@ impl TrieUpdate {
                 (k, data)
             }),
         )?;
+        #[cfg(feature = "sandbox")]
+        if let Some(fallback) = trie.fallback.as_ref().filter(|fallback| fallback.covers(&root)) {
+            fallback.add_root(trie_changes.new_root);
+        }
         Ok((trie_changes, state_changes))
     }
 
@@ -510,4 +598,82 @@ mod tests {
             ]
         );
     }
+
+    #[cfg(feature = "sandbox")]
+    #[test]
+    fn trie_fallback() {
+        use crate::TrieFallback;
+        use near_primitives::types::StateRoot;
+        use std::collections::{HashMap, HashSet};
+        use std::sync::{Arc, Mutex};
+
+        #[derive(Default)]
+        struct MapFallback(Mutex<HashMap<Vec<u8>, Vec<u8>>>, Mutex<HashSet<StateRoot>>);
+
+        impl TrieFallback for MapFallback {
+            fn covers(&self, root: &StateRoot) -> bool {
+                self.1.lock().unwrap().contains(root)
+            }
+
+            fn add_root(&self, root: StateRoot) {
+                self.1.lock().unwrap().insert(root);
+            }
+
+            fn get(&self, key: &TrieKey) -> Result<Option<Vec<u8>>, StorageError> {
+                Ok(self.0.lock().unwrap().get(&key.to_vec()).cloned())
+            }
+
+            fn mark_deleted(&self, key: &TrieKey) {
+                self.0.lock().unwrap().remove(&key.to_vec());
+            }
+        }
+
+        let tries = create_tries();
+        // A state which is not derived from the forked one.
+        let mut trie_update =
+            tries.new_trie_update(ShardUId::single_shard(), CryptoHash::default());
+        trie_update.set(test_key(b"fox".to_vec()), b"kit".to_vec());
+        trie_update
+            .commit(StateChangeCause::TransactionProcessing { tx_hash: CryptoHash::default() });
+        let (trie_changes, _) = trie_update.finalize().unwrap();
+        let (store_update, other_root) =
+            tries.apply_all(&trie_changes, ShardUId::single_shard()).unwrap();
+        store_update.commit().unwrap();
+
+        let fallback = Arc::new(MapFallback::default());
+        fallback.0.lock().unwrap().insert(test_key(b"cat".to_vec()).to_vec(), b"kitten".to_vec());
+        fallback.0.lock().unwrap().insert(test_key(b"dog".to_vec()).to_vec(), b"puppy".to_vec());
+        fallback.add_root(CryptoHash::default());
+        tries.set_fallback(fallback.clone());
+
+        // Only the forked state and the states derived from it read from the fallback.
+        let trie_update = tries.new_trie_update(ShardUId::single_shard(), other_root);
+        assert_eq!(trie_update.get(&test_key(b"cat".to_vec())), Ok(None));
+
+        // Values read from the fallback are written into the trie on finalize.
+        let mut trie_update =
+            tries.new_trie_update(ShardUId::single_shard(), CryptoHash::default());
+        assert_eq!(trie_update.get(&test_key(b"cat".to_vec())), Ok(Some(b"kitten".to_vec())));
+        let value_ref = trie_update.get_ref(&test_key(b"dog".to_vec())).unwrap().unwrap();
+        assert_eq!(value_ref.deref_value(), Ok(b"puppy".to_vec()));
+        assert_eq!(trie_update.get(&test_key(b"eel".to_vec())), Ok(None));
+        trie_update.remove(test_key(b"dog".to_vec()));
+        trie_update
+            .commit(StateChangeCause::TransactionProcessing { tx_hash: CryptoHash::default() });
+        let (trie_changes, state_changes) = trie_update.finalize().unwrap();
+        assert_eq!(state_changes.len(), 2);
+        let (store_update, new_root) =
+            tries.apply_all(&trie_changes, ShardUId::single_shard()).unwrap();
+        store_update.commit().unwrap();
+        assert!(fallback.covers(&new_root));
+
+        // Deleted values are not resurrected by the fallback.
+        assert_eq!(fallback.get(&test_key(b"dog".to_vec())), Ok(None));
+        fallback.0.lock().unwrap().clear();
+        let trie_update = tries.new_trie_update(ShardUId::single_shard(), new_root);
+        assert_eq!(trie_update.get(&test_key(b"cat".to_vec())), Ok(Some(b"kitten".to_vec())));
+        assert_eq!(trie_update.get(&test_key(b"dog".to_vec())), Ok(None));
+        let values: Result<Vec<Vec<u8>>, _> = trie_update.iter(&[]).unwrap().collect();
+        assert_eq!(values.unwrap(), vec![test_key(b"cat".to_vec()).to_vec()]);
+    }
 }
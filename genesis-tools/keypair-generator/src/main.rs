@@ -3,12 +3,37 @@ use std::path::{Path, PathBuf};
 
 use clap::{App, AppSettings, Arg, SubCommand};
 
-use near_crypto::{InMemorySigner, KeyType, SecretKey, Signer};
+use near_crypto::{KeyFile, KeyType, SecretKey};
 use nearcore::get_default_home;
 
-fn generate_key_to_file(account_id: &str, key: SecretKey, path: &PathBuf) -> std::io::Result<()> {
-    let signer = InMemorySigner::from_secret_key(account_id.parse().unwrap(), key);
-    signer.write_to_file(path.as_path())
+fn generate_key_to_file(
+    account_id: &str,
+    key: SecretKey,
+    path: &PathBuf,
+    passphrase: Option<&str>,
+) -> std::io::Result<()> {
+    let key_file = KeyFile {
+        account_id: account_id.parse().unwrap(),
+        public_key: key.public_key(),
+        secret_key: key,
+    };
+    match passphrase {
+        Some(passphrase) => key_file.write_encrypted_to_file(path.as_path(), passphrase),
+        None => key_file.write_to_file(path.as_path()),
+    }
+}
+
+/// Passphrase from the environment, or else prompted for.
+fn read_passphrase() -> String {
+    if let Some(passphrase) = near_crypto::key_passphrase().expect("Failed to read passphrase") {
+        return passphrase;
+    }
+    let passphrase =
+        rpassword::prompt_password_stderr("New passphrase: ").expect("Failed to read passphrase");
+    let repeated = rpassword::prompt_password_stderr("Repeat passphrase: ")
+        .expect("Failed to read passphrase");
+    assert_eq!(passphrase, repeated, "Passphrases don't match");
+    passphrase
 }
 
 fn main() {
@@ -34,6 +59,12 @@ fn main() {
                 .help("Whether to generate a config file when generating keys. Requires account-id to be specified.")
                 .takes_value(false),
         )
+        .arg(
+            Arg::with_name("encrypt")
+                .long("encrypt")
+                .help("Encrypt the generated key files with a passphrase, taken from NEAR_KEY_PASSPHRASE or NEAR_KEY_PASSPHRASE_FILE if set.")
+                .takes_value(false),
+        )
         .subcommand(
             SubCommand::with_name("signer-keys").about("Generate signer keys.").arg(
                 Arg::with_name("num-keys")
@@ -52,6 +83,12 @@ fn main() {
     fs::create_dir_all(home_dir).expect("Failed to create directory");
     let account_id = matches.value_of("account-id");
     let generate_config = matches.is_present("generate-config");
+    let passphrase = if generate_config && matches.is_present("encrypt") {
+        Some(read_passphrase())
+    } else {
+        None
+    };
+    let passphrase = passphrase.as_deref();
 
     match matches.subcommand() {
         ("signer-keys", Some(args)) => {
@@ -72,7 +109,8 @@ fn main() {
                     let key_file_name = format!("signer{}_key.json", i);
                     let mut path = home_dir.to_path_buf();
                     path.push(&key_file_name);
-                    if let Err(e) = generate_key_to_file(account_id, key.clone(), &path) {
+                    if let Err(e) = generate_key_to_file(account_id, key.clone(), &path, passphrase)
+                    {
                         eprintln!("Error writing key to {}: {}", path.display(), e);
                         return;
                     }
@@ -92,7 +130,7 @@ fn main() {
                     account_id.expect("Account id must be specified if --generate-config is used");
                 let mut path = home_dir.to_path_buf();
                 path.push(nearcore::config::VALIDATOR_KEY_FILE);
-                if let Err(e) = generate_key_to_file(account_id, key, &path) {
+                if let Err(e) = generate_key_to_file(account_id, key, &path, passphrase) {
                     eprintln!("Error writing key to {}: {}", path.display(), e);
                     return;
                 }
@@ -104,7 +142,7 @@ fn main() {
             if generate_config {
                 let mut path = home_dir.to_path_buf();
                 path.push(nearcore::config::NODE_KEY_FILE);
-                if let Err(e) = generate_key_to_file("node", key, &path) {
+                if let Err(e) = generate_key_to_file("node", key, &path, passphrase) {
                     eprintln!("Error writing key to {}: {}", path.display(), e);
                     return;
                 }
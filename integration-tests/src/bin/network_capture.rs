@@ -0,0 +1,146 @@
+extern crate integration_tests;
+
+use actix::clock::sleep;
+use actix::System;
+use clap::{AppSettings, Clap};
+use std::path::PathBuf;
+use std::time::Duration;
+
+use integration_tests::mock_network::replay::{setup_replay, IsReplayFinished};
+use near_actix_test_utils::run_actix;
+use near_chain_configs::GenesisValidationMode;
+use near_client::GetBlock;
+use near_logger_utils::init_integration_logger;
+use near_network::recorder::{CaptureReader, MessageDirection, RecordedMessage};
+use near_primitives::utils::from_timestamp;
+
+/// Inspects and replays the messages recorded by a node with `network.message_recorder`
+/// enabled in its config.
+#[derive(Clap)]
+#[clap(setting = AppSettings::SubcommandRequiredElseHelp)]
+struct Cli {
+    /// Capture directory, or a single capture file.
+    #[clap(long, parse(from_os_str))]
+    capture: PathBuf,
+    #[clap(subcommand)]
+    subcmd: SubCommand,
+}
+
+#[derive(Clap)]
+enum SubCommand {
+    /// Print the recorded messages.
+    Print(PrintCmd),
+    /// Replay the received messages into a client and a view client started on a node's home
+    /// dir. Nothing is sent back to the network.
+    Replay(ReplayCmd),
+}
+
+#[derive(Clap)]
+struct PrintCmd {
+    /// Only print messages of this variant, e.g. `Block` or `PartialEncodedChunkResponse`.
+    /// Can be repeated.
+    #[clap(long = "variant")]
+    variants: Vec<String>,
+    /// Only print messages exchanged with this peer.
+    #[clap(long)]
+    peer: Option<String>,
+    /// Only print messages sent in this direction, `in` or `out`.
+    #[clap(long)]
+    direction: Option<String>,
+    /// Print the whole message instead of its variant only.
+    #[clap(long)]
+    verbose: bool,
+}
+
+impl PrintCmd {
+    fn run(self, records: impl Iterator<Item = RecordedMessage>) {
+        let direction = self.direction.as_deref().map(|direction| match direction {
+            "in" => MessageDirection::Inbound,
+            "out" => MessageDirection::Outbound,
+            _ => panic!("Unknown direction {}, expected `in` or `out`", direction),
+        });
+        for record in records {
+            if direction.map_or(false, |direction| direction != record.direction) {
+                continue;
+            }
+            let peer_id = record.peer_id.as_ref().map_or("unknown".to_string(), |p| p.to_string());
+            if self.peer.as_ref().map_or(false, |peer| peer != &peer_id) {
+                continue;
+            }
+            let time = from_timestamp(record.timestamp).to_rfc3339();
+            let msg = match record.peer_message() {
+                Ok(msg) => msg,
+                Err(err) => {
+                    println!("{} {} {} failed to decode: {}", time, record.direction, peer_id, err);
+                    continue;
+                }
+            };
+            let variant = msg.msg_variant();
+            if !self.variants.is_empty() && !self.variants.iter().any(|v| v == variant) {
+                continue;
+            }
+            if self.verbose {
+                println!("{} {} {} {:?}", time, record.direction, peer_id, msg);
+            } else {
+                println!("{} {} {} {}", time, record.direction, peer_id, variant);
+            }
+        }
+    }
+}
+
+#[derive(Clap)]
+struct ReplayCmd {
+    /// Home dir of the node to replay the messages into, e.g. a copy of the home dir of the
+    /// node the capture was recorded on.
+    #[clap(long, parse(from_os_str))]
+    home: PathBuf,
+    /// Replay this many times faster than recorded.
+    #[clap(long, default_value = "1.0")]
+    speed: f64,
+    /// How long to let the client work after the last message before stopping, in seconds.
+    #[clap(long, default_value = "10")]
+    linger: u64,
+}
+
+impl ReplayCmd {
+    fn run(self, records: Vec<RecordedMessage>) {
+        let near_config = nearcore::config::load_config(&self.home, GenesisValidationMode::Full)
+            .unwrap_or_else(|e| panic!("Error loading config: {:#}", e));
+        let linger = Duration::from_secs(self.linger);
+        run_actix(async move {
+            let (replay_actor, _client, view_client) =
+                setup_replay(&self.home, &near_config, records, self.speed);
+            actix::spawn(async move {
+                while !replay_actor.send(IsReplayFinished).await.unwrap() {
+                    sleep(Duration::from_secs(1)).await;
+                }
+                sleep(linger).await;
+                if let Ok(Ok(block)) = view_client.send(GetBlock::latest()).await {
+                    println!("Head after replay: #{} {}", block.header.height, block.header.hash);
+                }
+                System::current().stop();
+            });
+        })
+    }
+}
+
+fn main() {
+    init_integration_logger();
+    let args = Cli::parse();
+    let reader = if args.capture.is_dir() {
+        CaptureReader::open_dir(&args.capture).expect("Failed to read the capture directory")
+    } else {
+        CaptureReader::open_file(&args.capture)
+    };
+    let records = reader.filter_map(|record| match record {
+        Ok(record) => Some(record),
+        Err(err) => {
+            eprintln!("Failed to read the capture, skipping the rest of the file: {}", err);
+            None
+        }
+    });
+    match args.subcmd {
+        SubCommand::Print(cmd) => cmd.run(records),
+        SubCommand::Replay(cmd) => cmd.run(records.collect()),
+    }
+}
@@ -1,5 +1,6 @@
 pub mod genesis_helpers;
 pub mod mock_network;
+pub mod network_simulator;
 pub mod node;
 pub mod runtime_utils;
 pub mod test_helpers;
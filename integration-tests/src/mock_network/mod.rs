@@ -16,6 +16,7 @@ use near_primitives::types::{BlockHeight, ShardId};
 use std::collections::HashMap;
 use std::time::Duration;
 
+pub mod replay;
 pub mod setup;
 
 /// MockPeerManagerActor mocks PeerManagerActor and responds to messages from ClientActor.
@@ -157,9 +158,10 @@ impl Handler<PeerManagerMessageRequest> for MockPeerManagerActor {
                             .chain_history_access
                             .retrieve_partial_encoded_chunk(&request)
                             .unwrap();
-                        let _response = act
-                            .client_addr
-                            .do_send(NetworkClientMessages::PartialEncodedChunkResponse(response));
+                        let peer_id = act.network_info.connected_peers[0].peer_info.id.clone();
+                        let _response = act.client_addr.do_send(
+                            NetworkClientMessages::PartialEncodedChunkResponse(response, peer_id),
+                        );
                     });
                 }
                 NetworkRequests::Block { .. } => {}
@@ -0,0 +1,299 @@
+//! Replays the messages recorded by `near_network::recorder::MessageRecorder` into a
+//! ClientActor and a ViewClientActor, to reproduce networking issues such as sync stalls
+//! locally.
+use actix::{Actor, Addr, Arbiter, Context, Handler, Recipient};
+use near_chain::ChainGenesis;
+#[cfg(feature = "test_features")]
+use near_client::AdversarialControls;
+use near_client::{start_client, start_view_client, ClientActor, ViewClientActor};
+use near_network::recorder::{MessageDirection, RecordedMessage};
+use near_network::test_utils::NetworkRecipient;
+use near_network::types::{
+    FullPeerInfo, NetworkClientMessages, NetworkInfo, NetworkResponses, PeerManagerMessageRequest,
+    PeerManagerMessageResponse, PeerMessage,
+};
+use near_network_primitives::types::{
+    NetworkViewClientMessages, PartialEdgeInfo, PeerChainInfoV2, PeerInfo,
+};
+use near_performance_metrics::actix::run_later;
+use near_primitives::block::GenesisId;
+use near_primitives::network::PeerId;
+use near_primitives::types::{NumShards, ShardId};
+use near_store::create_store;
+use near_telemetry::TelemetryActor;
+use nearcore::{get_store_path, NearConfig, NightshadeRuntime};
+use std::collections::{HashMap, VecDeque};
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Duration;
+use tracing::{debug, info, warn};
+
+/// How often the peers seen in the capture are pushed to the client.
+const PUSH_NETWORK_INFO_PERIOD: Duration = Duration::from_millis(100);
+
+/// A recorded message converted to what the peer actor would have sent to the client or the
+/// view client.
+pub enum ReplayedMessage {
+    Client(NetworkClientMessages),
+    ViewClient(NetworkViewClientMessages),
+}
+
+/// Converts a message received from `peer_id` the same way `PeerActor` does. Returns `None`
+/// for messages handled by the network layer itself, e.g. handshakes and routing table sync.
+pub fn to_replayed_message(peer_id: PeerId, msg: PeerMessage) -> Option<ReplayedMessage> {
+    if msg.is_view_client_message() {
+        msg.into_view_client_message().map(ReplayedMessage::ViewClient)
+    } else {
+        // We don't know if blocks were requested, assume they were so that they are processed
+        // even if they are far ahead of the head.
+        msg.into_client_message(peer_id, true).map(ReplayedMessage::Client)
+    }
+}
+
+/// Plays the role of the peer manager: sends the recorded inbound messages to the client and
+/// the view client with the recorded timing, and swallows everything they send to the network.
+pub struct ReplayActor {
+    client_addr: Recipient<NetworkClientMessages>,
+    view_client_addr: Recipient<NetworkViewClientMessages>,
+    /// Inbound messages left to replay.
+    records: VecDeque<RecordedMessage>,
+    /// Time is sped up by this factor, e.g. `2.0` replays twice as fast as recorded.
+    speed: f64,
+    /// Peers seen in the capture, reported to the client as connected.
+    peers: HashMap<PeerId, FullPeerInfo>,
+    genesis_id: GenesisId,
+    num_shards: NumShards,
+    num_replayed: usize,
+}
+
+impl ReplayActor {
+    pub fn new(
+        client_addr: Recipient<NetworkClientMessages>,
+        view_client_addr: Recipient<NetworkViewClientMessages>,
+        records: Vec<RecordedMessage>,
+        speed: f64,
+        genesis_id: GenesisId,
+        num_shards: NumShards,
+    ) -> Self {
+        assert!(speed > 0.0, "replay speed must be positive");
+        let records = records
+            .into_iter()
+            .filter(|record| {
+                record.direction == MessageDirection::Inbound && record.peer_id.is_some()
+            })
+            .collect();
+        Self {
+            client_addr,
+            view_client_addr,
+            records,
+            speed,
+            peers: HashMap::new(),
+            genesis_id,
+            num_shards,
+            num_replayed: 0,
+        }
+    }
+
+    /// Keeps track of the peers and their heights, so that the client has someone to sync from.
+    fn update_peer(&mut self, peer_id: &PeerId, msg: &PeerMessage) {
+        let genesis_id = &self.genesis_id;
+        let num_shards = self.num_shards;
+        let peer = self.peers.entry(peer_id.clone()).or_insert_with(|| FullPeerInfo {
+            peer_info: PeerInfo { id: peer_id.clone(), addr: None, account_id: None },
+            chain_info: PeerChainInfoV2 {
+                genesis_id: genesis_id.clone(),
+                height: 0,
+                tracked_shards: (0..num_shards).collect::<Vec<ShardId>>(),
+                archival: false,
+            },
+            partial_edge_info: PartialEdgeInfo::default(),
+        });
+        let height = match msg {
+            PeerMessage::Block(block) => block.header().height(),
+            PeerMessage::BlockHeaders(headers) => {
+                headers.last().map_or(0, |header| header.height())
+            }
+            _ => 0,
+        };
+        peer.chain_info.height = peer.chain_info.height.max(height);
+    }
+
+    fn push_network_info_trigger(&self, ctx: &mut Context<Self>, every: Duration) {
+        let connected_peers: Vec<FullPeerInfo> = self.peers.values().cloned().collect();
+        let _ = self.client_addr.do_send(NetworkClientMessages::NetworkInfo(NetworkInfo {
+            num_connected_peers: connected_peers.len(),
+            peer_max_count: connected_peers.len() as u32,
+            highest_height_peers: connected_peers.clone(),
+            connected_peers,
+            sent_bytes_per_sec: 0,
+            received_bytes_per_sec: 0,
+            known_producers: vec![],
+            peer_counter: 0,
+        }));
+        run_later(ctx, every, move |act, ctx| act.push_network_info_trigger(ctx, every));
+    }
+
+    fn replay_next(&mut self, ctx: &mut Context<Self>) {
+        let record = match self.records.pop_front() {
+            Some(record) => record,
+            None => {
+                info!(target: "replay", num_replayed = self.num_replayed, "Replay finished");
+                return;
+            }
+        };
+        let peer_id = record.peer_id.clone().unwrap();
+        match record.peer_message() {
+            Ok(msg) => {
+                self.update_peer(&peer_id, &msg);
+                debug!(target: "replay", %peer_id, variant = msg.msg_variant(), "Replaying");
+                match to_replayed_message(peer_id, msg) {
+                    Some(ReplayedMessage::Client(msg)) => {
+                        let _ = self.client_addr.do_send(msg);
+                    }
+                    Some(ReplayedMessage::ViewClient(msg)) => {
+                        let _ = self.view_client_addr.do_send(msg);
+                    }
+                    None => {}
+                }
+                self.num_replayed += 1;
+            }
+            Err(err) => warn!(target: "replay", ?err, "Failed to decode recorded message"),
+        }
+        let delay = match self.records.front() {
+            Some(next) => {
+                let gap = Duration::from_nanos(next.timestamp.saturating_sub(record.timestamp));
+                gap.div_f64(self.speed)
+            }
+            None => Duration::from_secs(0),
+        };
+        run_later(ctx, delay, move |act, ctx| act.replay_next(ctx));
+    }
+}
+
+impl Actor for ReplayActor {
+    type Context = Context<Self>;
+
+    fn started(&mut self, ctx: &mut Self::Context) {
+        info!(target: "replay", num_messages = self.records.len(), "Starting replay");
+        self.push_network_info_trigger(ctx, PUSH_NETWORK_INFO_PERIOD);
+        self.replay_next(ctx);
+    }
+}
+
+impl Handler<PeerManagerMessageRequest> for ReplayActor {
+    type Result = PeerManagerMessageResponse;
+
+    fn handle(&mut self, msg: PeerManagerMessageRequest, _ctx: &mut Self::Context) -> Self::Result {
+        // Nothing is sent out, but the requests of the client help to understand what it was
+        // waiting for.
+        debug!(target: "replay", ?msg, "Dropping request to the network");
+        PeerManagerMessageResponse::NetworkResponses(NetworkResponses::NoResponse)
+    }
+}
+
+#[derive(actix::Message, Debug)]
+#[rtype(result = "bool")]
+pub struct IsReplayFinished;
+
+impl Handler<IsReplayFinished> for ReplayActor {
+    type Result = bool;
+
+    fn handle(&mut self, _msg: IsReplayFinished, _ctx: &mut Self::Context) -> Self::Result {
+        self.records.is_empty()
+    }
+}
+
+/// Starts a ClientActor and a ViewClientActor on the node in `home_dir` and replays `records`
+/// into them.
+/// `speed`: factor by which the recorded time is sped up
+pub fn setup_replay(
+    home_dir: &Path,
+    config: &NearConfig,
+    records: Vec<RecordedMessage>,
+    speed: f64,
+) -> (Addr<ReplayActor>, Addr<ClientActor>, Addr<ViewClientActor>) {
+    let store = create_store(&get_store_path(home_dir));
+    let runtime = Arc::new(NightshadeRuntime::with_config(
+        home_dir,
+        store,
+        config,
+        config.client_config.trie_viewer_state_size_limit,
+        config.client_config.max_gas_burnt_view,
+    ));
+
+    let telemetry = TelemetryActor::new(config.telemetry_config.clone()).start();
+    let chain_genesis = ChainGenesis::from(&config.genesis);
+    let node_id = PeerId::new(config.network_config.public_key.clone().into());
+    let network_adapter = Arc::new(NetworkRecipient::default());
+    #[cfg(feature = "test_features")]
+    let adv = Arc::new(std::sync::RwLock::new(AdversarialControls::default()));
+
+    let (client_actor, _) = start_client(
+        config.client_config.clone(),
+        chain_genesis.clone(),
+        runtime.clone(),
+        node_id,
+        network_adapter.clone(),
+        config.validator_signer.clone(),
+        telemetry,
+        None,
+        #[cfg(feature = "test_features")]
+        adv.clone(),
+    );
+    let view_client = start_view_client(
+        None,
+        chain_genesis.clone(),
+        runtime.clone(),
+        network_adapter.clone(),
+        config.client_config.clone(),
+        #[cfg(feature = "test_features")]
+        adv.clone(),
+    );
+
+    let genesis_hash = *near_chain::Chain::new_for_view_client(
+        runtime,
+        &chain_genesis,
+        near_chain::DoomslugThresholdMode::NoApprovals,
+    )
+    .unwrap()
+    .genesis()
+    .hash();
+    let genesis_id =
+        GenesisId { chain_id: config.genesis.config.chain_id.clone(), hash: genesis_hash };
+    let num_shards = config.genesis.config.shard_layout.num_shards();
+
+    let arbiter = Arbiter::new();
+    let client_addr = client_actor.clone().recipient();
+    let view_client_addr = view_client.clone().recipient();
+    let replay_actor = ReplayActor::start_in_arbiter(&arbiter.handle(), move |_ctx| {
+        ReplayActor::new(client_addr, view_client_addr, records, speed, genesis_id, num_shards)
+    });
+    network_adapter.set_recipient(replay_actor.clone().recipient());
+    (replay_actor, client_actor, view_client)
+}
+
+#[cfg(test)]
+mod test {
+    use crate::mock_network::replay::{to_replayed_message, ReplayedMessage};
+    use near_network::types::{NetworkClientMessages, PeerMessage};
+    use near_network_primitives::types::{NetworkViewClientMessages, PeerInfo};
+    use near_primitives::hash::CryptoHash;
+
+    #[test]
+    fn test_to_replayed_message() {
+        let peer_id = PeerInfo::random().id;
+        let hash = CryptoHash::default();
+        assert!(matches!(
+            to_replayed_message(peer_id.clone(), PeerMessage::BlockRequest(hash)),
+            Some(ReplayedMessage::ViewClient(NetworkViewClientMessages::BlockRequest(h))) if h == hash
+        ));
+        assert!(matches!(
+            to_replayed_message(peer_id.clone(), PeerMessage::BlockHeaders(vec![])),
+            Some(ReplayedMessage::Client(NetworkClientMessages::BlockHeaders(headers, p)))
+                if headers.is_empty() && p == peer_id
+        ));
+        // Messages handled by the network layer are not replayed.
+        assert!(to_replayed_message(peer_id.clone(), PeerMessage::PeersRequest).is_none());
+        assert!(to_replayed_message(peer_id, PeerMessage::Disconnect).is_none());
+    }
+}
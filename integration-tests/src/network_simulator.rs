@@ -0,0 +1,765 @@
+//! Deterministic in-process network simulator for multi-node tests.
+//!
+//! `NetworkSimulator` runs a set of validator `Client`s on the current thread. Instead of a
+//! `PeerManagerActor`, every client is given a `SimulatedPeerManagerAdapter` which hands the
+//! requests over to a shared `SimulatedNetwork`. The network delivers them in virtual time,
+//! according to the latency, loss and bandwidth of each link and to the current partitions.
+//!
+//! All randomness comes from the configured seed, and the virtual time is also what the clients
+//! see through `Clock`, so a run with the same seed is reproduced exactly. Messages sent while
+//! processing a single event are ordered by their content before being scheduled, as the clients
+//! iterate over hash maps in places.
+//!
+//! The clients run with doomslug, so block production waits for the approvals of two thirds of
+//! the stake and heights are skipped when their block producer is unreachable. The simulator
+//! stands in for the parts of `ClientActor` the scenarios rely on:
+//! - the doomslug timer, block production checks, chunk request retries and head progress checks
+//!   run on a timer,
+//! - orphan blocks make the receiver request the missing parent from the sender.
+//!
+//! Header sync and state sync are not simulated.
+use std::cmp::{max, Ordering, Reverse};
+use std::collections::{BinaryHeap, HashMap};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use actix::MailboxError;
+use borsh::BorshSerialize;
+use chrono::TimeZone;
+use futures::future::BoxFuture;
+use futures::{future, FutureExt};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use tracing::{debug, trace};
+
+use near_chain::test_utils::KeyValueRuntime;
+use near_chain::types::AcceptedBlock;
+use near_chain::{ChainGenesis, ErrorKind, Provenance};
+use near_chain_configs::ClientConfig;
+use near_client::test_utils::run_catchup;
+use near_client::{Client, SyncStatus};
+use near_crypto::KeyType;
+use near_network::test_utils::peer_id_from_seed;
+use near_network::types::{
+    NetworkRequests, NetworkResponses, PeerManagerAdapter, PeerManagerMessageRequest,
+    PeerManagerMessageResponse,
+};
+use near_network_primitives::types::RoutedMessageBody;
+use near_primitives::block::{ApprovalType, Block};
+use near_primitives::hash::{hash, CryptoHash};
+use near_primitives::network::PeerId;
+use near_primitives::sharding::PartialEncodedChunk;
+use near_primitives::time::{Clock, Instant, MockClockGuard, Utc};
+use near_primitives::types::{AccountId, BlockHeight, BlockHeightDelta, NumSeats, NumShards};
+use near_primitives::utils::MaybeValidated;
+use near_primitives::validator_signer::{InMemoryValidatorSigner, ValidatorSigner};
+use near_store::test_utils::create_test_store;
+
+/// Unix timestamp at which every simulation starts, so that blocks don't depend on the time of
+/// the run.
+const GENESIS_TIMESTAMP: i64 = 1_600_000_000;
+/// A client rebroadcasts its head if it didn't change for this many block production intervals.
+const HEAD_STALL_INTERVALS: u32 = 3;
+
+/// Properties of the link from one node to another.
+#[derive(Clone, Debug)]
+pub struct LinkConfig {
+    /// The latency of every message is drawn uniformly from `[min_latency, max_latency]`.
+    pub min_latency: Duration,
+    pub max_latency: Duration,
+    /// Probability for every message to be lost, in `[0, 1]`.
+    pub loss_rate: f64,
+    /// Bytes per second, or `None` for unlimited. Messages queue up behind the previous ones
+    /// sent over the same link.
+    pub bandwidth: Option<u64>,
+}
+
+impl Default for LinkConfig {
+    fn default() -> Self {
+        Self {
+            min_latency: Duration::from_millis(10),
+            max_latency: Duration::from_millis(50),
+            loss_rate: 0.0,
+            bandwidth: None,
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct SimulatorConfig {
+    /// Every node is a block and chunk producer.
+    pub num_validators: usize,
+    pub num_shards: NumShards,
+    pub epoch_length: BlockHeightDelta,
+    pub seed: u64,
+    /// Link between any two nodes, unless overridden with `NetworkSimulator::set_link`.
+    pub link: LinkConfig,
+    /// Minimal delay between two blocks. Doomslug skips a height after twice that without a
+    /// block.
+    pub block_production_interval: Duration,
+    /// Period of the clients' timers: doomslug, block production checks, chunk request retries
+    /// and head progress checks.
+    pub timer_interval: Duration,
+}
+
+impl SimulatorConfig {
+    pub fn new(num_validators: usize, seed: u64) -> Self {
+        Self {
+            num_validators,
+            num_shards: 1,
+            epoch_length: 5,
+            seed,
+            link: LinkConfig::default(),
+            block_production_interval: Duration::from_secs(1),
+            timer_interval: Duration::from_millis(100),
+        }
+    }
+}
+
+/// Counters of the messages that went through the network.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct NetworkStats {
+    pub sent: u64,
+    pub bytes_sent: u64,
+    pub delivered: u64,
+    /// Dropped according to the loss rate of the link.
+    pub lost: u64,
+    /// Dropped because the nodes were in different partitions.
+    pub partitioned: u64,
+}
+
+#[derive(Clone, Debug)]
+enum SimulatedMessage {
+    Block(Block),
+    BlockRequest(CryptoHash),
+    BlockResponse(Block),
+    Routed { body: RoutedMessageBody, route_back: CryptoHash },
+}
+
+impl SimulatedMessage {
+    fn routed(body: RoutedMessageBody) -> Self {
+        Self::Routed { body, route_back: CryptoHash::default() }
+    }
+
+    /// Serialized message, used for its size and to order the messages deterministically.
+    fn encode(&self) -> Vec<u8> {
+        match self {
+            Self::Block(block) | Self::BlockResponse(block) => block.try_to_vec(),
+            Self::BlockRequest(hash) => hash.try_to_vec(),
+            Self::Routed { body, .. } => body.try_to_vec(),
+        }
+        .expect("Failed to serialize message")
+    }
+}
+
+struct Delivery {
+    time: Duration,
+    /// Breaks ties between messages delivered at the same time.
+    seq: u64,
+    from: usize,
+    to: usize,
+    message: SimulatedMessage,
+}
+
+impl PartialEq for Delivery {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Delivery {}
+
+impl PartialOrd for Delivery {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Delivery {
+    fn cmp(&self, other: &Self) -> Ordering {
+        (self.time, self.seq).cmp(&(other.time, other.seq))
+    }
+}
+
+/// Network state shared by the simulator and the adapters of all nodes.
+struct SimulatedNetwork {
+    /// Virtual time since the start of the simulation.
+    now: Duration,
+    rng: StdRng,
+    accounts: Vec<AccountId>,
+    peer_ids: Vec<PeerId>,
+    default_link: LinkConfig,
+    links: HashMap<(usize, usize), LinkConfig>,
+    /// Partition of every node, nodes can only reach the nodes in the same partition.
+    partitions: Vec<usize>,
+    /// Time until which every link with limited bandwidth is busy sending earlier messages.
+    link_busy_until: HashMap<(usize, usize), Duration>,
+    /// Requests sent by every node since the simulator last flushed them.
+    outboxes: Vec<Vec<NetworkRequests>>,
+    queue: BinaryHeap<Reverse<Delivery>>,
+    next_seq: u64,
+    /// Node to send the response to, for every chunk request in flight.
+    route_back: HashMap<CryptoHash, usize>,
+    stats: NetworkStats,
+}
+
+impl SimulatedNetwork {
+    fn new(config: &SimulatorConfig, accounts: Vec<AccountId>, peer_ids: Vec<PeerId>) -> Self {
+        let num_nodes = accounts.len();
+        Self {
+            now: Duration::default(),
+            rng: StdRng::seed_from_u64(config.seed),
+            accounts,
+            peer_ids,
+            default_link: config.link.clone(),
+            links: HashMap::new(),
+            partitions: vec![0; num_nodes],
+            link_busy_until: HashMap::new(),
+            outboxes: vec![vec![]; num_nodes],
+            queue: BinaryHeap::new(),
+            next_seq: 0,
+            route_back: HashMap::new(),
+            stats: NetworkStats::default(),
+        }
+    }
+
+    fn num_nodes(&self) -> usize {
+        self.accounts.len()
+    }
+
+    fn node_by_account(&self, account_id: &AccountId) -> Option<usize> {
+        self.accounts.iter().position(|account| account == account_id)
+    }
+
+    fn node_by_peer_id(&self, peer_id: &PeerId) -> Option<usize> {
+        self.peer_ids.iter().position(|peer| peer == peer_id)
+    }
+
+    /// Schedules the delivery of the requests sent by `from` since the last flush.
+    fn flush(&mut self, from: usize) {
+        let requests = std::mem::take(&mut self.outboxes[from]);
+        let mut messages = vec![];
+        for request in requests {
+            self.resolve(from, request, &mut messages);
+        }
+        let mut messages: Vec<_> = messages
+            .into_iter()
+            .map(|(to, message)| {
+                let encoded = message.encode();
+                (to, encoded, message)
+            })
+            .collect();
+        messages.sort_by(|a, b| (a.0, &a.1).cmp(&(b.0, &b.1)));
+        for (to, encoded, message) in messages {
+            self.send(from, to, message, encoded.len());
+        }
+    }
+
+    /// Converts a request of the client into the messages sent to other nodes, the way
+    /// `PeerManagerActor` would.
+    fn resolve(
+        &mut self,
+        from: usize,
+        request: NetworkRequests,
+        messages: &mut Vec<(usize, SimulatedMessage)>,
+    ) {
+        let (to, message) = match request {
+            NetworkRequests::Block { block } => {
+                for to in (0..self.num_nodes()).filter(|&to| to != from) {
+                    messages.push((to, SimulatedMessage::Block(block.clone())));
+                }
+                return;
+            }
+            NetworkRequests::BlockRequest { hash, peer_id } => {
+                (self.node_by_peer_id(&peer_id), SimulatedMessage::BlockRequest(hash))
+            }
+            NetworkRequests::Approval { approval_message } => (
+                self.node_by_account(&approval_message.target),
+                SimulatedMessage::routed(RoutedMessageBody::BlockApproval(
+                    approval_message.approval,
+                )),
+            ),
+            NetworkRequests::PartialEncodedChunkRequest { target, request } => {
+                // Without a target account any peer tracking the shard would do, and every node
+                // tracks all shards.
+                let to = match target.account_id {
+                    Some(account_id) => self.node_by_account(&account_id),
+                    None => Some((from + 1) % self.num_nodes()),
+                };
+                (
+                    to,
+                    SimulatedMessage::routed(RoutedMessageBody::PartialEncodedChunkRequest(
+                        request,
+                    )),
+                )
+            }
+            NetworkRequests::PartialEncodedChunkResponse { route_back, response } => (
+                self.route_back.remove(&route_back),
+                SimulatedMessage::routed(RoutedMessageBody::PartialEncodedChunkResponse(response)),
+            ),
+            NetworkRequests::PartialEncodedChunkMessage { account_id, partial_encoded_chunk } => (
+                self.node_by_account(&account_id),
+                SimulatedMessage::routed(partial_encoded_chunk.into()),
+            ),
+            NetworkRequests::PartialEncodedChunkForward { account_id, forward } => (
+                self.node_by_account(&account_id),
+                SimulatedMessage::routed(RoutedMessageBody::PartialEncodedChunkForward(forward)),
+            ),
+            NetworkRequests::ForwardTx(account_id, tx) => (
+                self.node_by_account(&account_id),
+                SimulatedMessage::routed(RoutedMessageBody::ForwardTx(tx)),
+            ),
+            request => {
+                trace!(target: "network", from, ?request, "Request not supported by the simulator");
+                return;
+            }
+        };
+        match to {
+            Some(to) => messages.push((to, message)),
+            None => debug!(target: "network", from, "No route for message {:?}", message),
+        }
+    }
+
+    fn send(&mut self, from: usize, to: usize, mut message: SimulatedMessage, size: usize) {
+        self.stats.sent += 1;
+        self.stats.bytes_sent += size as u64;
+        if self.partitions[from] != self.partitions[to] {
+            self.stats.partitioned += 1;
+            return;
+        }
+        let link = self.links.get(&(from, to)).unwrap_or(&self.default_link).clone();
+        if link.loss_rate > 0.0 && self.rng.gen_bool(link.loss_rate) {
+            self.stats.lost += 1;
+            return;
+        }
+        let min_latency = link.min_latency.as_nanos() as u64;
+        let max_latency = max(link.max_latency.as_nanos() as u64, min_latency);
+        let latency = Duration::from_nanos(self.rng.gen_range(min_latency, max_latency + 1));
+        let mut departure = self.now;
+        if let Some(bandwidth) = link.bandwidth {
+            let busy_until = self.link_busy_until.entry((from, to)).or_default();
+            let transmission = Duration::from_secs_f64(size as f64 / bandwidth as f64);
+            *busy_until = max(*busy_until, self.now) + transmission;
+            departure = *busy_until;
+        }
+        if let SimulatedMessage::Routed {
+            body: RoutedMessageBody::PartialEncodedChunkRequest(_),
+            route_back,
+        } = &mut message
+        {
+            *route_back = hash(&self.next_seq.to_le_bytes());
+            self.route_back.insert(*route_back, from);
+        }
+        self.queue.push(Reverse(Delivery {
+            time: departure + latency,
+            seq: self.next_seq,
+            from,
+            to,
+            message,
+        }));
+        self.next_seq += 1;
+    }
+}
+
+/// `PeerManagerAdapter` of a simulated node, queues the requests on the `SimulatedNetwork`.
+pub struct SimulatedPeerManagerAdapter {
+    node: usize,
+    network: Arc<Mutex<SimulatedNetwork>>,
+}
+
+impl PeerManagerAdapter for SimulatedPeerManagerAdapter {
+    fn send(
+        &self,
+        msg: PeerManagerMessageRequest,
+    ) -> BoxFuture<'static, Result<PeerManagerMessageResponse, MailboxError>> {
+        self.do_send(msg);
+        future::ok(PeerManagerMessageResponse::NetworkResponses(NetworkResponses::NoResponse))
+            .boxed()
+    }
+
+    fn do_send(&self, msg: PeerManagerMessageRequest) {
+        if let PeerManagerMessageRequest::NetworkRequests(request) = msg {
+            self.network.lock().unwrap().outboxes[self.node].push(request);
+        }
+    }
+}
+
+enum Event {
+    Delivery,
+    Timers,
+}
+
+/// Runs validator clients connected through a simulated network, in virtual time.
+///
+/// Mocks `Clock` for the current thread, so only one simulator can exist on a thread at a time.
+pub struct NetworkSimulator {
+    pub clients: Vec<Client>,
+    network: Arc<Mutex<SimulatedNetwork>>,
+    config: SimulatorConfig,
+    clock: MockClockGuard,
+    start_utc: chrono::DateTime<Utc>,
+    start_instant: Instant,
+    next_timers_at: Duration,
+}
+
+impl NetworkSimulator {
+    pub fn new(config: SimulatorConfig) -> Self {
+        let clock = MockClockGuard::default();
+        let start_utc = Utc.timestamp(GENESIS_TIMESTAMP, 0);
+        let start_instant = Instant::now();
+        clock.set_utc(start_utc);
+        clock.set_instant(start_instant);
+
+        let accounts: Vec<AccountId> =
+            (0..config.num_validators).map(|i| format!("test{}", i).parse().unwrap()).collect();
+        let peer_ids = accounts.iter().map(|account_id| peer_id_from_seed(account_id.as_ref()));
+        let network = Arc::new(Mutex::new(SimulatedNetwork::new(
+            &config,
+            accounts.clone(),
+            peer_ids.collect(),
+        )));
+        let mut chain_genesis = ChainGenesis::test();
+        chain_genesis.epoch_length = config.epoch_length;
+        let min_block_production_delay = config.block_production_interval.as_millis() as u64;
+        let mut client_config = ClientConfig::test(
+            true,
+            min_block_production_delay,
+            2 * min_block_production_delay,
+            config.num_validators as NumSeats,
+            false,
+            true,
+        );
+        client_config.epoch_length = config.epoch_length;
+        let clients = accounts
+            .iter()
+            .enumerate()
+            .map(|(node, account_id)| {
+                let runtime_adapter = Arc::new(KeyValueRuntime::new_with_validators(
+                    create_test_store(),
+                    vec![accounts.clone()],
+                    1,
+                    config.num_shards,
+                    config.epoch_length,
+                ));
+                let network_adapter =
+                    Arc::new(SimulatedPeerManagerAdapter { node, network: network.clone() });
+                let validator_signer = Arc::new(InMemoryValidatorSigner::from_seed(
+                    account_id.clone(),
+                    KeyType::ED25519,
+                    account_id.as_ref(),
+                )) as Arc<dyn ValidatorSigner>;
+                let mut client = Client::new(
+                    client_config.clone(),
+                    chain_genesis.clone(),
+                    runtime_adapter,
+                    network_adapter,
+                    Some(validator_signer),
+                    true,
+                    hash(format!("{}:{}", config.seed, node).as_bytes()).0,
+                )
+                .unwrap();
+                client.sync_status = SyncStatus::NoSync;
+                client
+            })
+            .collect();
+
+        Self {
+            clients,
+            network,
+            next_timers_at: config.timer_interval,
+            config,
+            clock,
+            start_utc,
+            start_instant,
+        }
+    }
+
+    /// Virtual time since the start of the simulation.
+    pub fn now(&self) -> Duration {
+        self.network.lock().unwrap().now
+    }
+
+    pub fn stats(&self) -> NetworkStats {
+        self.network.lock().unwrap().stats.clone()
+    }
+
+    pub fn account_id(&self, node: usize) -> AccountId {
+        self.network.lock().unwrap().accounts[node].clone()
+    }
+
+    pub fn head_height(&self, node: usize) -> BlockHeight {
+        self.clients[node].chain.head().unwrap().height
+    }
+
+    /// Overrides the config of the links between `node0` and `node1`, in both directions.
+    pub fn set_link(&mut self, node0: usize, node1: usize, link: LinkConfig) {
+        let mut network = self.network.lock().unwrap();
+        network.links.insert((node0, node1), link.clone());
+        network.links.insert((node1, node0), link);
+    }
+
+    /// Splits the network so that nodes can only reach the nodes of the same group. Nodes which
+    /// are not part of any group form an additional one.
+    pub fn partition(&mut self, groups: &[&[usize]]) {
+        let mut network = self.network.lock().unwrap();
+        let num_groups = groups.len();
+        network.partitions = vec![num_groups; network.num_nodes()];
+        for (group_id, group) in groups.iter().enumerate() {
+            for &node in group.iter() {
+                network.partitions[node] = group_id;
+            }
+        }
+    }
+
+    /// Reconnects all the nodes. Messages dropped during the partition are not resent.
+    pub fn heal(&mut self) {
+        let mut network = self.network.lock().unwrap();
+        network.partitions = vec![0; network.num_nodes()];
+    }
+
+    /// Runs the simulation for `duration` of virtual time.
+    pub fn run_for(&mut self, duration: Duration) {
+        let deadline = self.now() + duration;
+        while self.step(deadline) {}
+    }
+
+    /// Runs the simulation until `condition` holds, for at most `timeout` of virtual time.
+    /// Returns whether the condition was met.
+    pub fn run_until(
+        &mut self,
+        timeout: Duration,
+        mut condition: impl FnMut(&mut Self) -> bool,
+    ) -> bool {
+        let deadline = self.now() + timeout;
+        loop {
+            if condition(self) {
+                return true;
+            }
+            if !self.step(deadline) {
+                return condition(self);
+            }
+        }
+    }
+
+    /// Processes the next event, unless it's after `deadline`. In that case moves the clock to
+    /// `deadline` and returns false.
+    fn step(&mut self, deadline: Duration) -> bool {
+        let next_delivery =
+            self.network.lock().unwrap().queue.peek().map(|delivery| delivery.0.time);
+        // On ties messages are delivered first, then timers run.
+        let mut next = (self.next_timers_at, Event::Timers);
+        if let Some(time) = next_delivery.filter(|&time| time <= next.0) {
+            next = (time, Event::Delivery);
+        }
+        let (time, event) = next;
+        if time > deadline {
+            self.set_time(deadline);
+            return false;
+        }
+        self.set_time(time);
+        match event {
+            Event::Delivery => {
+                let delivery = self.network.lock().unwrap().queue.pop().unwrap().0;
+                self.deliver(delivery);
+            }
+            Event::Timers => {
+                for node in 0..self.clients.len() {
+                    self.run_timers(node);
+                }
+                self.next_timers_at += self.config.timer_interval;
+            }
+        }
+        true
+    }
+
+    fn set_time(&mut self, now: Duration) {
+        self.network.lock().unwrap().now = now;
+        self.clock.set_utc(self.start_utc + chrono::Duration::from_std(now).unwrap());
+        self.clock.set_instant(self.start_instant + now);
+    }
+
+    fn flush(&mut self, node: usize) {
+        self.network.lock().unwrap().flush(node);
+    }
+
+    fn deliver(&mut self, delivery: Delivery) {
+        let Delivery { from, to, message, .. } = delivery;
+        let peer_id = {
+            let mut network = self.network.lock().unwrap();
+            network.stats.delivered += 1;
+            network.peer_ids[from].clone()
+        };
+        let client = &mut self.clients[to];
+        let accepted_blocks = match message {
+            SimulatedMessage::Block(block) => {
+                self.process_block(to, block, Provenance::NONE, Some(from));
+                vec![]
+            }
+            SimulatedMessage::BlockResponse(block) => {
+                self.process_block(to, block, Provenance::SYNC, Some(from));
+                vec![]
+            }
+            SimulatedMessage::BlockRequest(hash) => {
+                if let Ok(block) = client.chain.get_block(&hash) {
+                    let message = SimulatedMessage::BlockResponse(block.clone());
+                    let size = message.encode().len();
+                    self.network.lock().unwrap().send(to, from, message, size);
+                }
+                vec![]
+            }
+            SimulatedMessage::Routed { body, route_back } => match body {
+                RoutedMessageBody::BlockApproval(approval) => {
+                    client.collect_block_approval(&approval, ApprovalType::PeerApproval(peer_id));
+                    vec![]
+                }
+                RoutedMessageBody::ForwardTx(tx) => {
+                    client.process_tx(tx, true, false);
+                    vec![]
+                }
+                RoutedMessageBody::PartialEncodedChunkRequest(request) => {
+                    client.shards_mgr.process_partial_encoded_chunk_request(
+                        request,
+                        route_back,
+                        client.chain.mut_store(),
+                    );
+                    vec![]
+                }
+                RoutedMessageBody::PartialEncodedChunkResponse(response) => client
+                    .process_partial_encoded_chunk_response(response, peer_id)
+                    .unwrap_or_default(),
+                RoutedMessageBody::PartialEncodedChunk(partial_encoded_chunk) => client
+                    .process_partial_encoded_chunk(MaybeValidated::from(PartialEncodedChunk::V1(
+                        partial_encoded_chunk,
+                    )))
+                    .unwrap_or_default(),
+                RoutedMessageBody::VersionedPartialEncodedChunk(partial_encoded_chunk) => client
+                    .process_partial_encoded_chunk(MaybeValidated::from(partial_encoded_chunk))
+                    .unwrap_or_default(),
+                RoutedMessageBody::PartialEncodedChunkForward(forward) => {
+                    client.process_partial_encoded_chunk_forward(forward).unwrap_or_default()
+                }
+                _ => vec![],
+            },
+        };
+        self.on_blocks_accepted(to, accepted_blocks);
+        self.flush(to);
+    }
+
+    /// Processes a block the way `ClientActor` does: broadcasts it if we produced it and asks
+    /// the sender for the parent of orphans.
+    fn process_block(
+        &mut self,
+        node: usize,
+        block: Block,
+        provenance: Provenance,
+        from: Option<usize>,
+    ) {
+        if provenance == Provenance::PRODUCED {
+            self.network.lock().unwrap().outboxes[node]
+                .push(NetworkRequests::Block { block: block.clone() });
+        }
+        let prev_hash = *block.header().prev_hash();
+        let client = &mut self.clients[node];
+        let (mut accepted_blocks, result) =
+            client.process_block(MaybeValidated::from(block), provenance);
+        if let Err(err) = result {
+            match err.kind() {
+                ErrorKind::Orphan => {
+                    if let Some(from) = from.filter(|_| !client.chain.is_orphan(&prev_hash)) {
+                        let peer_id = self.network.lock().unwrap().peer_ids[from].clone();
+                        self.network.lock().unwrap().outboxes[node]
+                            .push(NetworkRequests::BlockRequest { hash: prev_hash, peer_id });
+                    }
+                }
+                // Missing chunks were already requested by the client.
+                ErrorKind::ChunksMissing(_) => {}
+                _ => debug!(target: "client", node, "Block refused by chain: {}", err),
+            }
+        }
+        match run_catchup(&mut self.clients[node], &vec![]) {
+            Ok(more_accepted_blocks) => accepted_blocks.extend(more_accepted_blocks),
+            Err(err) => debug!(target: "client", node, "Catchup failed: {}", err),
+        }
+        self.on_blocks_accepted(node, accepted_blocks);
+    }
+
+    fn on_blocks_accepted(&mut self, node: usize, accepted_blocks: Vec<AcceptedBlock>) {
+        for accepted_block in accepted_blocks {
+            self.clients[node].on_block_accepted(
+                accepted_block.hash,
+                accepted_block.status,
+                accepted_block.provenance,
+            );
+        }
+    }
+
+    /// Sends the approvals due according to doomslug, like `ClientActor::try_doomslug_timer`.
+    fn run_doomslug_timer(&mut self, node: usize) {
+        let client = &mut self.clients[node];
+        let _ = client.check_and_update_doomslug_tip();
+        let approvals = client.doomslug.process_timer(Clock::instant());
+        let mut chain_store_update = client.chain.mut_store().store_update();
+        chain_store_update.save_largest_target_height(client.doomslug.get_largest_target_height());
+        chain_store_update.commit().unwrap();
+        let head = client.chain.head().unwrap();
+        if client.is_validator(&head.epoch_id, &head.last_block_hash)
+            || client.is_validator(&head.next_epoch_id, &head.last_block_hash)
+        {
+            let tip_hash = client.doomslug.get_tip().0;
+            for approval in approvals {
+                if let Err(err) = client.send_approval(&tip_hash, approval) {
+                    debug!(target: "client", node, "Failed to send approval: {}", err);
+                }
+            }
+        }
+    }
+
+    /// Produces the heights we are the block producer of once doomslug allows it, like
+    /// `ClientActor::handle_block_production`.
+    fn try_produce_blocks(&mut self, node: usize) {
+        let client = &mut self.clients[node];
+        let _ = client.check_and_update_doomslug_tip();
+        let head = client.chain.head().unwrap();
+        let latest_known = client.chain.mut_store().get_latest_known().unwrap();
+        let epoch_id =
+            client.runtime_adapter.get_epoch_id_from_prev_block(&head.last_block_hash).unwrap();
+        let last_height =
+            max(latest_known.height + 1, client.doomslug.get_largest_height_crossing_threshold());
+        for height in latest_known.height + 1..=last_height {
+            let client = &mut self.clients[node];
+            let block_producer = client.runtime_adapter.get_block_producer(&epoch_id, height);
+            if block_producer.ok().as_ref()
+                != client.validator_signer.as_ref().map(|signer| signer.validator_id())
+            {
+                continue;
+            }
+            let shards_ready = client.shards_mgr.shards_ready_for_block(&head.last_block_hash);
+            let num_shards = client.runtime_adapter.num_shards(&epoch_id).unwrap();
+            let have_all_chunks = head.height == 0 || shards_ready.len() as NumShards == num_shards;
+            if !client.doomslug.ready_to_produce_block(Clock::instant(), height, have_all_chunks) {
+                continue;
+            }
+            match client.produce_block(height) {
+                Ok(Some(block)) => self.process_block(node, block, Provenance::PRODUCED, None),
+                Ok(None) => {}
+                Err(err) => {
+                    debug!(target: "client", node, height, "Block production failed: {}", err)
+                }
+            }
+        }
+        self.flush(node);
+    }
+
+    fn run_timers(&mut self, node: usize) {
+        self.run_doomslug_timer(node);
+        self.try_produce_blocks(node);
+        let client = &mut self.clients[node];
+        if let Ok(header_head) = client.chain.header_head() {
+            client.shards_mgr.resend_chunk_requests(&header_head);
+        }
+        let _ = client.check_head_progress_stalled(
+            self.config.block_production_interval * HEAD_STALL_INTERVALS,
+        );
+        self.flush(node);
+    }
+}
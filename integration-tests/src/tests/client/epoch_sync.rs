@@ -0,0 +1,212 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use borsh::BorshSerialize;
+
+use crate::tests::client::process_blocks::create_nightshade_runtimes;
+use near_chain::chain::ApplyStatePartsRequest;
+use near_chain::{ChainGenesis, ChainStoreAccess, Provenance};
+use near_chain_configs::Genesis;
+use near_client::test_utils::TestEnv;
+use near_client::Client;
+use near_logger_utils::init_test_logger;
+use near_network::types::{FullPeerInfo, NetworkRequests};
+use near_network_primitives::types::{PartialEdgeInfo, PeerChainInfoV2, PeerInfo};
+use near_primitives::block::{Block, GenesisId};
+use near_primitives::network::PeerId;
+use near_primitives::syncing::{
+    get_num_state_parts, EpochSyncResponse, ShardStateSyncResponseHeader, StatePartKey,
+};
+use near_primitives::types::EpochId;
+use near_store::db::DBCol::ColStateParts;
+use nearcore::config::GenesisExt;
+
+/// Answers the epoch sync request the same way the view client does.
+fn epoch_sync_response(client: &mut Client, epoch_id: &EpochId) -> EpochSyncResponse {
+    match client.chain.mut_store().get_epoch_light_client_block(&epoch_id.0) {
+        Ok(light_client_block) => {
+            EpochSyncResponse::Advance { light_client_block_view: light_client_block.clone() }
+        }
+        Err(_) => {
+            assert!(client.runtime_adapter.epoch_exists(epoch_id));
+            EpochSyncResponse::UpToDate
+        }
+    }
+}
+
+fn produce_blocks(env: &mut TestEnv, num_blocks: u64) -> Vec<Block> {
+    (1..=num_blocks)
+        .map(|height| {
+            let block = env.clients[0].produce_block(height).unwrap().unwrap();
+            env.process_block(0, block.clone(), Provenance::PRODUCED);
+            block
+        })
+        .collect()
+}
+
+/// A fresh node verifies the light client blocks of all the finished epochs, finalizes epoch sync
+/// at the first block of the current epoch, continues with header sync and state syncs to it.
+#[test]
+fn test_epoch_sync_then_state_sync() {
+    init_test_logger();
+    let epoch_length = 5;
+    let mut genesis = Genesis::test(vec!["test0".parse().unwrap(), "test1".parse().unwrap()], 1);
+    genesis.config.epoch_length = epoch_length;
+    let mut env = TestEnv::builder(ChainGenesis::from(&genesis))
+        .clients_count(2)
+        .runtime_adapters(create_nightshade_runtimes(&genesis, 2))
+        .build();
+    let blocks = produce_blocks(&mut env, 5 * epoch_length + 2);
+    let head = env.clients[0].chain.head().unwrap();
+
+    let peer = FullPeerInfo {
+        peer_info: PeerInfo::random(),
+        chain_info: PeerChainInfoV2 {
+            genesis_id: GenesisId {
+                chain_id: genesis.config.chain_id.clone(),
+                hash: *env.clients[0].chain.genesis().hash(),
+            },
+            height: head.height,
+            tracked_shards: vec![0],
+            archival: false,
+        },
+        partial_edge_info: PartialEdgeInfo::default(),
+    };
+    let peer_id = peer.peer_info.id.clone();
+    let peers = vec![peer];
+
+    // Serve the requests of the second client until it finalizes epoch sync.
+    let mut num_epochs = 0;
+    loop {
+        // The same peer is not asked again until `EPOCH_SYNC_PEER_TIMEOUT` passes.
+        let request = loop {
+            let client = &mut env.clients[1];
+            let mut sync_status = client.sync_status.clone();
+            assert!(client
+                .epoch_sync
+                .run(&mut sync_status, &mut client.chain, head.height, &peers)
+                .unwrap());
+            if let Some(request) = env.network_adapters[1].pop() {
+                break request.as_network_requests();
+            }
+            std::thread::sleep(Duration::from_millis(1));
+        };
+        match request {
+            NetworkRequests::EpochSyncRequest { peer_id: to, epoch_id } => {
+                assert_eq!(to, peer_id);
+                let response = epoch_sync_response(&mut env.clients[0], &epoch_id);
+                if let EpochSyncResponse::Advance { .. } = response {
+                    num_epochs += 1;
+                }
+                env.clients[1].epoch_sync.on_response(peer_id.clone(), response).unwrap();
+            }
+            NetworkRequests::EpochSyncFinalizationRequest { peer_id: to, epoch_id } => {
+                assert_eq!(to, peer_id);
+                assert_eq!(epoch_id, head.epoch_id);
+                let response =
+                    env.clients[0].chain.get_epoch_sync_finalization_response(&epoch_id).unwrap();
+                let client = &mut env.clients[1];
+                client
+                    .epoch_sync
+                    .on_finalization_response(&mut client.chain, peer_id.clone(), response)
+                    .unwrap();
+                break;
+            }
+            request => panic!("unexpected request {:?}", request),
+        }
+    }
+    assert!(num_epochs >= 4);
+    assert!(env.clients[1].epoch_sync.done);
+
+    // Header sync continues from the first block of the current epoch.
+    let sync_hash = env.clients[1].epoch_sync.sync_hash;
+    let sync_header = env.clients[0].chain.get_block_header(&sync_hash).unwrap().clone();
+    assert_eq!(sync_header.epoch_id(), &head.epoch_id);
+    assert_eq!(env.clients[1].chain.header_head().unwrap().last_block_hash, sync_hash);
+    let headers = blocks
+        .iter()
+        .filter(|block| block.header().height() > sync_header.height())
+        .map(|block| block.header().clone())
+        .collect();
+    env.clients[1].chain.sync_block_headers(headers, &mut |_| {}).unwrap();
+    assert_eq!(env.clients[1].chain.header_head().unwrap().last_block_hash, head.last_block_hash);
+
+    // State sync to the first block of the epoch.
+    assert!(env.clients[0].chain.check_sync_hash_validity(&sync_hash).unwrap());
+    let state_sync_header = env.clients[0].chain.get_state_response_header(0, sync_hash).unwrap();
+    let state_root = match &state_sync_header {
+        ShardStateSyncResponseHeader::V1(header) => header.chunk.header.inner.prev_state_root,
+        ShardStateSyncResponseHeader::V2(header) => {
+            *header.chunk.cloned_header().take_inner().prev_state_root()
+        }
+    };
+    let state_root_node =
+        env.clients[0].runtime_adapter.get_state_root_node(0, &sync_hash, &state_root).unwrap();
+    let num_parts = get_num_state_parts(state_root_node.memory_usage);
+    let state_sync_parts = (0..num_parts)
+        .map(|i| env.clients[0].chain.get_state_response_part(0, i, sync_hash).unwrap())
+        .collect::<Vec<_>>();
+
+    env.clients[1].chain.set_state_header(0, sync_hash, state_sync_header).unwrap();
+    for i in 0..num_parts {
+        env.clients[1]
+            .chain
+            .set_state_part(0, sync_hash, i, num_parts, &state_sync_parts[i as usize])
+            .unwrap();
+    }
+    let rt = Arc::clone(&env.clients[1].runtime_adapter);
+    let f = move |msg: ApplyStatePartsRequest| {
+        let store = rt.get_store();
+        for part_id in 0..msg.num_parts {
+            let key = StatePartKey(msg.sync_hash, msg.shard_id, part_id).try_to_vec().unwrap();
+            let part = store.get(ColStateParts, &key).unwrap().unwrap();
+            rt.apply_state_part(
+                msg.shard_id,
+                &msg.state_root,
+                part_id,
+                msg.num_parts,
+                &part,
+                &msg.epoch_id,
+            )
+            .unwrap();
+        }
+    };
+    env.clients[1].chain.schedule_apply_state_parts(0, sync_hash, num_parts, &f).unwrap();
+    env.clients[1].chain.set_state_finalize(0, sync_hash, Ok(())).unwrap();
+
+    let prev_hash = *sync_header.prev_hash();
+    let shard_uid = env.clients[0].runtime_adapter.shard_id_to_uid(0, &head.epoch_id).unwrap();
+    let chunk_extra_after_sync =
+        env.clients[1].chain.get_chunk_extra(&prev_hash, &shard_uid).unwrap().clone();
+    let expected_chunk_extra =
+        env.clients[0].chain.get_chunk_extra(&prev_hash, &shard_uid).unwrap().clone();
+    assert_eq!(chunk_extra_after_sync, expected_chunk_extra);
+}
+
+/// Light client blocks which are not signed by the verified block producers are rejected.
+#[test]
+fn test_epoch_sync_rejects_invalid_light_client_block() {
+    init_test_logger();
+    let epoch_length = 5;
+    let mut genesis = Genesis::test(vec!["test0".parse().unwrap(), "test1".parse().unwrap()], 1);
+    genesis.config.epoch_length = epoch_length;
+    let mut env = TestEnv::builder(ChainGenesis::from(&genesis))
+        .clients_count(2)
+        .runtime_adapters(create_nightshade_runtimes(&genesis, 2))
+        .build();
+    produce_blocks(&mut env, 3 * epoch_length);
+
+    let next_epoch_id = env.clients[1].chain.genesis().next_epoch_id().clone();
+    let mut response = epoch_sync_response(&mut env.clients[0], &next_epoch_id);
+    match &mut response {
+        EpochSyncResponse::Advance { light_client_block_view } => {
+            light_client_block_view.inner_lite.outcome_root = Default::default();
+        }
+        EpochSyncResponse::UpToDate => panic!("the first epoch must be finished"),
+    }
+    let peer_id = PeerId::random();
+    assert!(env.clients[1].epoch_sync.on_response(peer_id.clone(), response).is_err());
+
+    let response = epoch_sync_response(&mut env.clients[0], &next_epoch_id);
+    env.clients[1].epoch_sync.on_response(peer_id, response).unwrap();
+}
@@ -0,0 +1,59 @@
+use std::collections::HashSet;
+
+use crate::tests::client::process_blocks::create_nightshade_runtimes;
+use near_chain::{ChainGenesis, ChainStoreAccess, Provenance};
+use near_chain_configs::Genesis;
+use near_client::test_utils::TestEnv;
+use near_logger_utils::init_test_logger;
+use near_primitives::types::EpochId;
+use nearcore::config::GenesisExt;
+
+/// A light node follows the chain by headers only and saves the same light client blocks as a
+/// full node, without storing any blocks.
+#[test]
+fn test_light_node_follows_headers() {
+    init_test_logger();
+    let epoch_length = 5;
+    let mut genesis = Genesis::test(vec!["test0".parse().unwrap(), "test1".parse().unwrap()], 1);
+    genesis.config.epoch_length = epoch_length;
+    let mut env = TestEnv::builder(ChainGenesis::from(&genesis))
+        .clients_count(2)
+        .runtime_adapters(create_nightshade_runtimes(&genesis, 2))
+        .build();
+    env.clients[1].config.light_node = true;
+    let genesis_hash = *env.clients[1].chain.genesis().hash();
+
+    let mut blocks = vec![];
+    for height in 1..=4 * epoch_length + 2 {
+        let block = env.clients[0].produce_block(height).unwrap().unwrap();
+        env.process_block(0, block.clone(), Provenance::PRODUCED);
+        // Same as a light node receiving a block from the network.
+        env.clients[1].sync_block_headers(vec![block.header().clone()]).unwrap();
+        blocks.push(block);
+    }
+
+    let head = env.clients[0].chain.head().unwrap();
+    assert_eq!(env.clients[1].chain.header_head().unwrap().last_block_hash, head.last_block_hash);
+    assert_eq!(env.clients[1].chain.head().unwrap().last_block_hash, genesis_hash);
+    assert!(blocks.iter().all(|block| env.clients[1].chain.get_block(block.hash()).is_err()));
+
+    let epoch_ids: HashSet<EpochId> =
+        blocks.iter().map(|block| block.header().epoch_id().clone()).collect();
+    let mut num_epochs = 0;
+    for epoch_id in epoch_ids {
+        let expected =
+            match env.clients[0].chain.mut_store().get_epoch_light_client_block(&epoch_id.0) {
+                Ok(light_client_block) => light_client_block.clone(),
+                Err(_) => continue,
+            };
+        let light_client_block = env.clients[1]
+            .chain
+            .mut_store()
+            .get_epoch_light_client_block(&epoch_id.0)
+            .unwrap()
+            .clone();
+        assert_eq!(light_client_block, expected);
+        num_epochs += 1;
+    }
+    assert!(num_epochs >= 3);
+}
@@ -1,5 +1,7 @@
 mod challenges;
 mod chunks_management;
+mod epoch_sync;
+mod light_node;
 mod process_blocks;
 mod runtimes;
 #[cfg(feature = "sandbox")]
@@ -59,7 +59,8 @@ use near_primitives::validator_signer::{InMemoryValidatorSigner, ValidatorSigner
 use near_primitives::version::ProtocolFeature;
 use near_primitives::version::PROTOCOL_VERSION;
 use near_primitives::views::{
-    BlockHeaderView, FinalExecutionStatus, QueryRequest, QueryResponseKind,
+    BlockHeaderView, BlockProductionStatusView, FinalExecutionStatus, QueryRequest,
+    QueryResponseKind,
 };
 use near_store::db::DBCol::ColStateParts;
 use near_store::get;
@@ -377,7 +378,8 @@ fn receive_network_block() {
                 &signer,
                 last_block.header.next_bp_hash,
                 block_merkle_tree.root(),
-            );
+            )
+            .unwrap();
             client.do_send(NetworkClientMessages::Block(block, PeerInfo::random().id, false));
             future::ready(())
         }));
@@ -453,7 +455,8 @@ fn produce_block_with_approvals() {
                 &signer1,
                 last_block.header.next_bp_hash,
                 block_merkle_tree.root(),
-            );
+            )
+            .unwrap();
             client.do_send(NetworkClientMessages::Block(
                 block.clone(),
                 PeerInfo::random().id,
@@ -474,7 +477,8 @@ fn produce_block_with_approvals() {
                     block.header().height(),
                     10, // the height at which "test1" is producing
                     &signer,
-                );
+                )
+                .unwrap();
                 client
                     .do_send(NetworkClientMessages::BlockApproval(approval, PeerInfo::random().id));
             }
@@ -642,7 +646,8 @@ fn invalid_blocks_common(is_requested: bool) {
                 &signer,
                 last_block.header.next_bp_hash,
                 block_merkle_tree.root(),
-            );
+            )
+            .unwrap();
             // Send block with invalid chunk mask
             let mut block = valid_block.clone();
             block.mut_header().get_mut().inner_rest.chunk_mask = vec![];
@@ -1112,7 +1117,8 @@ fn test_invalid_approvals() {
                     KeyType::ED25519,
                     account_id.as_ref(),
                 )
-                .sign_approval(&ApprovalInner::Endorsement(*genesis.hash()), 1),
+                .sign_approval(&ApprovalInner::Endorsement(*genesis.hash()), 1)
+                .unwrap(),
             )
         })
         .collect();
@@ -1136,6 +1142,25 @@ fn test_no_double_sign() {
     assert_eq!(env.clients[0].produce_block(1).unwrap(), None);
 }
 
+#[test]
+fn test_block_production_debug_info() {
+    let mut env = TestEnv::builder(ChainGenesis::test()).build();
+    let block = env.clients[0].produce_block(1).unwrap().unwrap();
+    let info = env.clients[0].get_block_production_debug_info().unwrap();
+    assert_eq!(info.heights.len(), 1);
+    assert_eq!(info.heights[0].height, 1);
+    assert_matches!(
+        info.heights[0].status,
+        Some(BlockProductionStatusView::Produced { block_hash, num_chunks: 0, .. })
+            if block_hash == *block.hash()
+    );
+
+    // Producing at the same height again is skipped.
+    assert_eq!(env.clients[0].produce_block(1).unwrap(), None);
+    let info = env.clients[0].get_block_production_debug_info().unwrap();
+    assert_matches!(info.heights[0].status, Some(BlockProductionStatusView::Skipped { .. }));
+}
+
 #[test]
 fn test_invalid_gas_price() {
     init_test_logger();
@@ -1197,6 +1222,23 @@ fn test_invalid_height_too_old() {
     assert!(matches!(res.unwrap_err().kind(), ErrorKind::InvalidBlockHeight(_)));
 }
 
+/// A client only produces blocks while its validator signer matches the staked key.
+#[test]
+fn test_set_validator_signer() {
+    let mut env = TestEnv::builder(ChainGenesis::test()).build();
+    env.produce_block(0, 1);
+
+    let signer =
+        InMemoryValidatorSigner::from_seed("test0".parse().unwrap(), KeyType::ED25519, "other");
+    env.clients[0].set_validator_signer(Some(Arc::new(signer)));
+    assert!(env.clients[0].produce_block(2).unwrap().is_none());
+
+    let signer =
+        InMemoryValidatorSigner::from_seed("test0".parse().unwrap(), KeyType::ED25519, "test0");
+    env.clients[0].set_validator_signer(Some(Arc::new(signer)));
+    assert!(env.clients[0].produce_block(2).unwrap().is_some());
+}
+
 #[test]
 fn test_bad_orphan() {
     let mut genesis = ChainGenesis::test();
@@ -3133,11 +3175,13 @@ fn test_header_version_downgrade() {
         match header {
             BlockHeader::BlockHeaderV1(ref mut header) => {
                 header.inner_rest.latest_protocol_version = PROTOCOL_VERSION;
-                let (hash, signature) = validator_signer.sign_block_header_parts(
-                    header.prev_hash,
-                    &header.inner_lite.try_to_vec().expect("Failed to serialize"),
-                    &header.inner_rest.try_to_vec().expect("Failed to serialize"),
-                );
+                let (hash, signature) = validator_signer
+                    .sign_block_header_parts(
+                        header.prev_hash,
+                        &header.inner_lite.try_to_vec().expect("Failed to serialize"),
+                        &header.inner_rest.try_to_vec().expect("Failed to serialize"),
+                    )
+                    .unwrap();
                 header.hash = hash;
                 header.signature = signature;
             }
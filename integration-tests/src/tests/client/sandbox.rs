@@ -8,17 +8,20 @@ use near_actix_test_utils::run_actix;
 use near_chain::{ChainGenesis, Provenance, RuntimeAdapter};
 use near_chain_configs::Genesis;
 use near_client::test_utils::{setup_mock, TestEnv};
-use near_crypto::{InMemorySigner, KeyType};
+use near_crypto::{InMemorySigner, KeyType, PublicKey, Signature};
 use near_logger_utils::init_test_logger;
 use near_network::types::{
-    NetworkClientMessages, NetworkRequests, NetworkResponses, PeerManagerMessageResponse,
+    NetworkClientMessages, NetworkClientResponses, NetworkRequests, NetworkResponses,
+    PeerManagerMessageResponse,
 };
 use near_network_primitives::types::NetworkSandboxMessage;
 use near_primitives::account::Account;
+use near_primitives::errors::InvalidTxError;
 use near_primitives::serialize::{from_base64, to_base64};
 use near_primitives::state_record::StateRecord;
 use near_primitives::transaction::{
-    Action, DeployContractAction, FunctionCallAction, SignedTransaction,
+    Action, DeployContractAction, FunctionCallAction, SignedTransaction, Transaction,
+    TransferAction,
 };
 use near_primitives::types::{AccountId, BlockHeight, Nonce};
 use near_store::test_utils::create_test_store;
@@ -152,3 +155,91 @@ fn test_fast_forward() {
         near_network::test_utils::wait_or_panic(5000);
     });
 }
+
+#[test]
+fn test_snapshot_and_revert() {
+    let (mut env, signer) = test_setup();
+    let state = env.query_state("test0".parse().unwrap());
+    let snapshot_head = env.clients[0].chain.head().unwrap();
+    let snapshot_id = env.clients[0].chain.sandbox_snapshot().unwrap();
+
+    env.clients[0].chain.patch_state(vec![StateRecord::Data {
+        account_id: "test0".parse().unwrap(),
+        data_key: from_base64(&state[0].key).unwrap(),
+        value: b"world".to_vec(),
+    }]);
+    do_blocks(&mut env, 9, 20);
+    let patched = env.query_state("test0".parse().unwrap());
+    assert_eq!(patched[0].value, to_base64(b"world"));
+    // A transaction validated against the abandoned fork is dropped by the revert.
+    send_tx(
+        &mut env,
+        3,
+        "test0".parse().unwrap(),
+        "test1".parse().unwrap(),
+        &signer,
+        vec![Action::Transfer(TransferAction { deposit: 1 })],
+    );
+    assert!(env.clients[0].shards_mgr.get_pool_iterator(0).is_some());
+
+    env.clients[0].sandbox_revert(snapshot_id).unwrap();
+    assert_eq!(env.clients[0].chain.head().unwrap(), snapshot_head);
+    assert_eq!(env.clients[0].chain.header_head().unwrap(), snapshot_head);
+    assert!(env.clients[0].shards_mgr.get_pool_iterator(0).is_none());
+    assert_eq!(env.query_state("test0".parse().unwrap()), state);
+
+    // New blocks are built on top of the snapshot at heights above the abandoned fork.
+    do_blocks(&mut env, 20, 25);
+    let head = env.clients[0].chain.head().unwrap();
+    assert_eq!(head.height, 24);
+    assert_eq!(env.query_state("test0".parse().unwrap()), state);
+
+    assert!(env.clients[0].sandbox_revert(snapshot_id + 1).is_err());
+
+    // A deleted snapshot can't be reverted to, and its id is not reused.
+    env.clients[0].chain.sandbox_delete_snapshot(snapshot_id).unwrap();
+    assert!(env.clients[0].chain.sandbox_delete_snapshot(snapshot_id).is_err());
+    assert!(env.clients[0].sandbox_revert(snapshot_id).is_err());
+    assert_eq!(env.clients[0].chain.sandbox_snapshot().unwrap(), snapshot_id + 1);
+}
+
+#[test]
+fn test_impersonate() {
+    let (mut env, _signer) = test_setup();
+    let test1_before = env.query_account("test1".parse().unwrap());
+    let test0_before = env.query_account("test0".parse().unwrap());
+
+    // Nobody holds a key for the empty public key used by impersonated transactions.
+    let (_, response) = env.clients[0]
+        .sandbox_impersonate(
+            "test1".parse().unwrap(),
+            "test0".parse().unwrap(),
+            vec![Action::Transfer(TransferAction { deposit: 1_000 })],
+        )
+        .unwrap();
+    assert_eq!(response, NetworkClientResponses::ValidTx);
+    do_blocks(&mut env, 9, 15);
+
+    let test1_after = env.query_account("test1".parse().unwrap());
+    let test0_after = env.query_account("test0".parse().unwrap());
+    assert_eq!(test0_after.amount, test0_before.amount + 1_000);
+    assert!(test1_after.amount < test1_before.amount - 1_000);
+}
+
+#[test]
+fn test_empty_key_transaction_is_rejected() {
+    let (mut env, _signer) = test_setup();
+    let head = env.clients[0].chain.head().unwrap();
+    // Same as an impersonated transaction, but submitted like any other transaction.
+    let transaction = Transaction {
+        signer_id: "test1".parse().unwrap(),
+        public_key: PublicKey::empty(KeyType::ED25519),
+        nonce: 1,
+        receiver_id: "test0".parse().unwrap(),
+        block_hash: head.last_block_hash,
+        actions: vec![Action::Transfer(TransferAction { deposit: 1_000 })],
+    };
+    let tx = SignedTransaction::new(Signature::empty(KeyType::ED25519), transaction);
+    let response = env.clients[0].process_tx(tx, false, false);
+    assert_eq!(response, NetworkClientResponses::InvalidTx(InvalidTxError::InvalidSignature));
+}
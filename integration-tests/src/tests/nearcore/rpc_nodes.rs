@@ -8,21 +8,20 @@ use futures::{future, FutureExt, TryFutureExt};
 
 use crate::genesis_helpers::genesis_block;
 use near_actix_test_utils::spawn_interruptible;
-use near_client::{GetBlock, GetExecutionOutcome, GetValidatorInfo};
+use near_client::{GetBlock, GetExecutionOutcome, GetStateChanges, GetValidatorInfo};
 use near_crypto::{InMemorySigner, KeyType};
 use near_jsonrpc::client::new_client;
 use near_logger_utils::init_integration_logger;
 use near_network::test_utils::WaitOrTimeoutActor;
-use near_primitives::hash::{hash, CryptoHash};
 use near_primitives::merkle::{compute_root_from_path_and_item, verify_path};
 use near_primitives::runtime::config_store::RuntimeConfigStore;
-use near_primitives::serialize::{from_base64, to_base64};
-use near_primitives::transaction::{PartialExecutionStatus, SignedTransaction};
+use near_primitives::serialize::to_base64;
+use near_primitives::transaction::{Action, DeployContractAction, SignedTransaction};
 use near_primitives::types::{
     BlockId, BlockReference, EpochId, EpochReference, Finality, TransactionOrReceiptId,
 };
 use near_primitives::version::ProtocolVersion;
-use near_primitives::views::{ExecutionOutcomeView, ExecutionStatusView};
+use near_primitives::views::{StateChangeValueView, StateChangesRequestView};
 
 use crate::tests::nearcore::node_cluster::NodeCluster;
 
@@ -68,32 +67,6 @@ fn test_get_validator_info_rpc() {
     });
 }
 
-fn outcome_view_to_hashes(outcome: &ExecutionOutcomeView) -> Vec<CryptoHash> {
-    let status = match &outcome.status {
-        ExecutionStatusView::Unknown => PartialExecutionStatus::Unknown,
-        ExecutionStatusView::SuccessValue(s) => {
-            PartialExecutionStatus::SuccessValue(from_base64(s).unwrap())
-        }
-        ExecutionStatusView::Failure(_) => PartialExecutionStatus::Failure,
-        ExecutionStatusView::SuccessReceiptId(id) => PartialExecutionStatus::SuccessReceiptId(*id),
-    };
-    let mut result = vec![hash(
-        &(
-            outcome.receipt_ids.clone(),
-            outcome.gas_burnt,
-            outcome.tokens_burnt,
-            outcome.executor_id.clone(),
-            status,
-        )
-            .try_to_vec()
-            .expect("Failed to serialize"),
-    )];
-    for log in outcome.logs.iter() {
-        result.push(hash(log.as_bytes()));
-    }
-    result
-}
-
 fn test_get_execution_outcome(is_tx_successful: bool) {
     init_integration_logger();
 
@@ -167,16 +140,11 @@ fn test_get_execution_outcome(is_tx_successful: bool) {
                                             ))))
                                             .then(move |res| {
                                                 let res = res.unwrap().unwrap();
-                                                let mut outcome_with_id_to_hash = vec![
-                                                    execution_outcome_response.outcome_proof.id,
-                                                ];
-                                                outcome_with_id_to_hash.extend(
-                                                    outcome_view_to_hashes(
-                                                        &execution_outcome_response
-                                                            .outcome_proof
-                                                            .outcome,
-                                                    ),
-                                                );
+                                                let outcome_with_id_to_hash =
+                                                    execution_outcome_response
+                                                        .outcome_proof
+                                                        .to_hashes()
+                                                        .unwrap();
                                                 let chunk_outcome_root =
                                                     compute_root_from_path_and_item(
                                                         &execution_outcome_response
@@ -456,6 +424,72 @@ fn test_send_tx_sync_returns_transaction_hash() {
     });
 }
 
+#[test]
+#[cfg_attr(not(feature = "expensive_tests"), ignore)]
+fn test_contract_code_changes() {
+    init_integration_logger();
+
+    let cluster = NodeCluster::new(1, |index| format!("contract_code_changes{}", index))
+        .set_num_shards(1)
+        .set_num_validator_seats(1)
+        .set_num_lightclients(0)
+        .set_epoch_length(10)
+        .set_genesis_height(0);
+
+    cluster.exec_until_stop(|genesis, rpc_addrs, clients| async move {
+        let view_client = clients[0].1.clone();
+
+        let genesis_hash = *genesis_block(&genesis).hash();
+        let signer =
+            InMemorySigner::from_seed("near.0".parse().unwrap(), KeyType::ED25519, "near.0");
+        let code = near_test_contracts::rs_contract().to_vec();
+        let transaction = SignedTransaction::from_actions(
+            1,
+            "near.0".parse().unwrap(),
+            "near.0".parse().unwrap(),
+            &signer,
+            vec![Action::DeployContract(DeployContractAction { code: code.clone() })],
+            genesis_hash,
+        );
+
+        let client = new_client(&format!("http://{}", rpc_addrs[0]));
+        let bytes = transaction.try_to_vec().unwrap();
+
+        spawn_interruptible(async move {
+            loop {
+                let res = view_client.send(GetBlock::latest()).await;
+                if let Ok(Ok(block)) = res {
+                    if block.header.height > 10 {
+                        let outcome = client.broadcast_tx_commit(to_base64(&bytes)).await.unwrap();
+                        // The code is deployed in the block where the receipt is applied, and the
+                        // change must be reported whether the code is stored under the account or
+                        // shared between accounts.
+                        let block_hash = outcome.receipts_outcome[0].block_hash;
+                        let changes = view_client
+                            .send(GetStateChanges {
+                                block_hash,
+                                state_changes_request: StateChangesRequestView::ContractCodeChanges {
+                                    account_ids: vec!["near.0".parse().unwrap()],
+                                },
+                            })
+                            .await
+                            .unwrap()
+                            .unwrap();
+                        assert!(changes.iter().any(|change| matches!(
+                            &change.value,
+                            StateChangeValueView::ContractCodeUpdate { account_id, code: changed_code }
+                                if account_id.as_ref() == "near.0" && *changed_code == code
+                        )));
+                        System::current().stop();
+                        break;
+                    }
+                }
+                sleep(std::time::Duration::from_millis(500)).await;
+            }
+        });
+    });
+}
+
 #[test]
 #[cfg_attr(not(feature = "expensive_tests"), ignore)]
 fn test_send_tx_sync_to_lightclient_must_be_routed() {
@@ -5,4 +5,5 @@ mod infinite_loop;
 mod peer_handshake;
 mod routing;
 mod runner;
+mod simulator;
 mod stress_network;
@@ -0,0 +1,68 @@
+use std::time::Duration;
+
+use near_primitives::hash::CryptoHash;
+use near_primitives::types::BlockHeight;
+
+use crate::network_simulator::{LinkConfig, NetworkSimulator, NetworkStats, SimulatorConfig};
+
+fn block_hash_at(simulator: &mut NetworkSimulator, node: usize, height: BlockHeight) -> CryptoHash {
+    *simulator.clients[node].chain.get_header_by_height(height).unwrap().hash()
+}
+
+#[test]
+fn validators_agree_on_chain() {
+    let mut simulator = NetworkSimulator::new(SimulatorConfig::new(4, 0));
+
+    assert!(simulator.run_until(Duration::from_secs(60), |simulator| {
+        (0..4).all(|node| simulator.head_height(node) >= 20)
+    }));
+    let expected = block_hash_at(&mut simulator, 0, 20);
+    for node in 1..4 {
+        assert_eq!(block_hash_at(&mut simulator, node, 20), expected);
+    }
+    assert_eq!(simulator.stats().lost, 0);
+}
+
+fn run_lossy_network(seed: u64) -> (Vec<CryptoHash>, NetworkStats) {
+    let mut config = SimulatorConfig::new(4, seed);
+    config.link = LinkConfig {
+        min_latency: Duration::from_millis(20),
+        max_latency: Duration::from_millis(300),
+        loss_rate: 0.1,
+        bandwidth: Some(1_000_000),
+    };
+    let mut simulator = NetworkSimulator::new(config);
+    simulator.run_for(Duration::from_secs(30));
+    let heads = simulator.clients.iter().map(|client| client.chain.head().unwrap().last_block_hash);
+    (heads.collect(), simulator.stats())
+}
+
+#[test]
+fn same_seed_same_run() {
+    let (heads, stats) = run_lossy_network(7);
+    assert!(stats.lost > 0);
+    assert_eq!(run_lossy_network(7), (heads, stats));
+}
+
+#[test]
+fn partitioned_node_catches_up() {
+    let mut simulator = NetworkSimulator::new(SimulatorConfig::new(4, 1));
+    simulator.run_for(Duration::from_secs(5));
+
+    simulator.partition(&[&[0, 1, 2]]);
+    simulator.run_for(Duration::from_secs(2));
+    let partitioned_height = simulator.head_height(3);
+    simulator.run_for(Duration::from_secs(13));
+    // Without the approvals of the other nodes, node 3 can't produce blocks on its own.
+    assert_eq!(simulator.head_height(3), partitioned_height);
+    assert!(simulator.head_height(0) > partitioned_height + 5);
+    assert!(simulator.stats().partitioned > 0);
+
+    simulator.heal();
+    assert!(simulator.run_until(Duration::from_secs(30), |simulator| {
+        let height = simulator.head_height(0);
+        simulator.head_height(3) == height
+            && block_hash_at(simulator, 3, height) == block_hash_at(simulator, 0, height)
+    }));
+    assert!(simulator.head_height(3) > partitioned_height);
+}
@@ -1,6 +1,7 @@
 use std::fs;
 use std::fs::File;
-use std::io::{Read, Write};
+use std::io::Write;
+use std::net::{SocketAddr, ToSocketAddrs};
 use std::path::{Path, PathBuf};
 use std::str::FromStr;
 use std::sync::Arc;
@@ -19,14 +20,14 @@ use tracing::{error, info};
 
 use near_chain_configs::{
     get_initial_supply, ClientConfig, Genesis, GenesisConfig, GenesisValidationMode,
-    LogSummaryStyle,
+    LogSummaryStyle, SigningJournalConfig, StatePartsConfig,
 };
 use near_crypto::{InMemorySigner, KeyFile, KeyType, PublicKey, Signer};
 #[cfg(feature = "json_rpc")]
 use near_jsonrpc::RpcConfig;
 use near_network::test_utils::open_port;
 use near_network_primitives::types::blacklist_from_iter;
-use near_network_primitives::types::{NetworkConfig, ROUTED_MESSAGE_TTL};
+use near_network_primitives::types::{MessageRecorderConfig, NetworkConfig, ROUTED_MESSAGE_TTL};
 use near_primitives::account::{AccessKey, Account};
 use near_primitives::hash::CryptoHash;
 #[cfg(test)]
@@ -40,6 +41,7 @@ use near_primitives::types::{
 use near_primitives::utils::{generate_random_string, get_num_seats_per_shard};
 use near_primitives::validator_signer::{InMemoryValidatorSigner, ValidatorSigner};
 use near_primitives::version::PROTOCOL_VERSION;
+use near_remote_signer::{RemoteSignerConfig, RemoteValidatorSigner};
 #[cfg(feature = "rosetta_rpc")]
 use near_rosetta_rpc::RosettaRpcConfig;
 use near_telemetry::TelemetryConfig;
@@ -163,6 +165,10 @@ pub const PROTOCOL_UPGRADE_STAKE_THRESHOLD: Rational = Rational::new_raw(4, 5);
 fn default_max_num_peers() -> u32 {
     40
 }
+/// Maximum number of direct connections to other validators, on top of `max_num_peers`.
+fn default_max_num_validator_peers() -> u32 {
+    50
+}
 /// Minimum outbound connections a peer should have to avoid eclipse attacks.
 fn default_minimum_outbound_connections() -> u32 {
     5
@@ -198,18 +204,43 @@ fn default_peer_stats_period() -> Duration {
     Duration::from_secs(5)
 }
 
+fn default_message_recorder_max_file_size() -> u64 {
+    256 * 1024 * 1024
+}
+
+fn default_message_recorder_max_files() -> usize {
+    8
+}
+
+/// Records all messages exchanged with peers, for debugging.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct MessageRecorder {
+    /// Directory to write the capture files to, relative to the home dir.
+    pub dir: PathBuf,
+    /// Size in bytes after which a new capture file is started.
+    #[serde(default = "default_message_recorder_max_file_size")]
+    pub max_file_size: u64,
+    /// Number of capture files to keep.
+    #[serde(default = "default_message_recorder_max_files")]
+    pub max_files: usize,
+}
+
 #[derive(Serialize, Deserialize, Clone, Debug)]
 pub struct Network {
     /// Address to listen for incoming connections.
     pub addr: String,
-    /// Address to advertise to peers for them to connect.
-    /// If empty, will use the same port as the addr, and will introspect on the listener.
+    /// Address to advertise to other validators for them to connect directly.
+    /// If empty, no address is advertised.
     pub external_address: String,
     /// Comma separated list of nodes to connect to.
     pub boot_nodes: String,
     /// Maximum number of active peers. Hard limit.
     #[serde(default = "default_max_num_peers")]
     pub max_num_peers: u32,
+    /// Maximum number of direct connections to the other block and chunk producers of the current
+    /// and next epoch, in addition to `max_num_peers`.
+    #[serde(default = "default_max_num_validator_peers")]
+    pub max_num_validator_peers: u32,
     /// Minimum outbound connections a peer should have to avoid eclipse attacks.
     #[serde(default = "default_minimum_outbound_connections")]
     pub minimum_outbound_peers: u32,
@@ -242,6 +273,17 @@ pub struct Network {
     /// It can be IP:Port or IP (to blacklist all connections coming from this address).
     #[serde(default)]
     pub blacklist: Vec<String>,
+    /// Comma separated list of peers to always stay connected with, in the same format as
+    /// `boot_nodes`. Addresses of these peers are never shared with other peers.
+    #[serde(default)]
+    pub trusted_peers: String,
+    /// Only connect to and accept connections from `trusted_peers`. Used to run a validator
+    /// behind sentry nodes.
+    #[serde(default)]
+    pub whitelist_only: bool,
+    /// Record all messages exchanged with peers into rotating capture files. Disabled if unset.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub message_recorder: Option<MessageRecorder>,
     /// Time to persist Accounts Id in the router without removing them in seconds.
     #[serde(default = "default_ttl_account_id_router")]
     pub ttl_account_id_router: Duration,
@@ -250,6 +292,23 @@ pub struct Network {
     pub peer_stats_period: Duration,
 }
 
+impl Network {
+    /// Resolves `external_address`, which may be either an IP or a hostname with a port.
+    pub fn public_addr(&self) -> anyhow::Result<Option<SocketAddr>> {
+        if self.external_address.is_empty() {
+            return Ok(None);
+        }
+        let mut addrs = self
+            .external_address
+            .to_socket_addrs()
+            .with_context(|| format!("Invalid external_address {:?}", self.external_address))?;
+        match addrs.next() {
+            Some(addr) => Ok(Some(addr)),
+            None => bail!("external_address {:?} doesn't resolve to any IP", self.external_address),
+        }
+    }
+}
+
 impl Default for Network {
     fn default() -> Self {
         Network {
@@ -257,6 +316,7 @@ impl Default for Network {
             external_address: "".to_string(),
             boot_nodes: "".to_string(),
             max_num_peers: default_max_num_peers(),
+            max_num_validator_peers: default_max_num_validator_peers(),
             minimum_outbound_peers: default_minimum_outbound_connections(),
             ideal_connections_lo: default_ideal_connections_lo(),
             ideal_connections_hi: default_ideal_connections_hi(),
@@ -268,6 +328,9 @@ impl Default for Network {
             skip_sync_wait: false,
             ban_window: Duration::from_secs(3 * 60 * 60),
             blacklist: vec![],
+            trusted_peers: "".to_string(),
+            whitelist_only: false,
+            message_recorder: None,
             ttl_account_id_router: default_ttl_account_id_router(),
             peer_stats_period: default_peer_stats_period(),
         }
@@ -431,6 +494,17 @@ pub struct Config {
     pub tracked_accounts: Vec<AccountId>,
     pub tracked_shards: Vec<ShardId>,
     pub archive: bool,
+    /// Only sync block headers and serve `next_light_client_block`, but not `light_client_proof`.
+    pub light_node: bool,
+    /// Journal of the messages signed by the validator, used to refuse signing conflicting ones.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub signing_journal: Option<SigningJournalConfig>,
+    /// Sign with a separate signing process instead of the key in `validator_key_file`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub remote_signer: Option<RemoteSignerConfig>,
+    /// Dump state parts for state sync to, and fetch them from, a directory or an object store.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub state_parts: Option<StatePartsConfig>,
     pub log_summary_style: LogSummaryStyle,
     #[serde(default = "default_gc_blocks_limit")]
     pub gc_blocks_limit: NumBlocks,
@@ -453,6 +527,10 @@ pub struct Config {
     /// For example, setting "use_db_migration_snapshot" to "/tmp/" will create a directory "/tmp/db_migration_snapshot" and populate it with the database files.
     #[serde(skip_serializing_if = "Option::is_none")]
     pub db_migration_snapshot_path: Option<PathBuf>,
+    /// Lazily fork the state of another network. Only available in sandbox mode.
+    #[cfg(feature = "sandbox")]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sandbox_fork: Option<crate::sandbox_fork::SandboxForkConfig>,
 }
 
 impl Default for Config {
@@ -472,6 +550,10 @@ impl Default for Config {
             tracked_accounts: vec![],
             tracked_shards: vec![],
             archive: false,
+            light_node: false,
+            signing_journal: None,
+            remote_signer: None,
+            state_parts: None,
             log_summary_style: LogSummaryStyle::Colored,
             gc_blocks_limit: default_gc_blocks_limit(),
             epoch_sync_enabled: true,
@@ -481,6 +563,8 @@ impl Default for Config {
             max_gas_burnt_view: None,
             db_migration_snapshot_path: None,
             use_db_migration_snapshot: true,
+            #[cfg(feature = "sandbox")]
+            sandbox_fork: None,
         }
     }
 }
@@ -636,8 +720,8 @@ impl NearConfig {
         genesis: Genesis,
         network_key_pair: KeyFile,
         validator_signer: Option<Arc<dyn ValidatorSigner>>,
-    ) -> Self {
-        NearConfig {
+    ) -> anyhow::Result<Self> {
+        Ok(NearConfig {
             config: config.clone(),
             client_config: ClientConfig {
                 version: Default::default(),
@@ -659,6 +743,7 @@ impl NearConfig {
                     .consensus
                     .header_sync_expected_height_per_second,
                 state_sync_timeout: config.consensus.state_sync_timeout,
+                state_parts: config.state_parts.clone().unwrap_or_default(),
                 min_num_peers: config.consensus.min_num_peers,
                 log_summary_period: Duration::from_secs(10),
                 produce_empty_blocks: config.consensus.produce_empty_blocks,
@@ -676,6 +761,9 @@ impl NearConfig {
                 tracked_accounts: config.tracked_accounts,
                 tracked_shards: config.tracked_shards,
                 archive: config.archive,
+                light_node: config.light_node,
+                validator_key_file: None,
+                signing_journal: config.signing_journal.clone(),
                 log_summary_style: config.log_summary_style,
                 gc_blocks_limit: config.gc_blocks_limit,
                 view_client_threads: config.view_client_threads,
@@ -726,6 +814,26 @@ impl NearConfig {
                 push_info_period: Duration::from_millis(100),
                 blacklist: blacklist_from_iter(config.network.blacklist),
                 outbound_disabled: false,
+                trusted_peers: if config.network.trusted_peers.is_empty() {
+                    vec![]
+                } else {
+                    config
+                        .network
+                        .trusted_peers
+                        .split(',')
+                        .map(|chunk| chunk.try_into().expect("Failed to parse PeerInfo"))
+                        .collect()
+                },
+                whitelist_only: config.network.whitelist_only,
+                public_addr: config.network.public_addr()?,
+                max_num_validator_peers: config.network.max_num_validator_peers,
+                message_recorder: config.network.message_recorder.map(|recorder| {
+                    MessageRecorderConfig {
+                        dir: recorder.dir,
+                        max_file_size: recorder.max_file_size,
+                        max_files: recorder.max_files,
+                    }
+                }),
                 archive: config.archive,
             },
             telemetry_config: config.telemetry,
@@ -735,7 +843,7 @@ impl NearConfig {
             rosetta_rpc_config: config.rosetta_rpc,
             genesis,
             validator_signer,
-        }
+        })
     }
 
     pub fn rpc_addr(&self) -> Option<&str> {
@@ -1599,9 +1707,7 @@ struct NodeKeyFile {
 
 impl NodeKeyFile {
     fn from_file(path: &Path) -> Self {
-        let mut file = File::open(path).expect("Could not open key file.");
-        let mut content = String::new();
-        file.read_to_string(&mut content).expect("Could not read from key file.");
+        let content = near_crypto::read_key_file(path).expect("Could not read key file.");
         serde_json::from_str(&content).expect("Failed to deserialize KeyFile")
     }
 }
@@ -1622,10 +1728,18 @@ impl From<NodeKeyFile> for KeyFile {
     }
 }
 
-pub fn load_config(dir: &Path, genesis_validation: GenesisValidationMode) -> NearConfig {
-    let config = Config::from_file(&dir.join(CONFIG_FILENAME)).unwrap();
+pub fn load_config(
+    dir: &Path,
+    genesis_validation: GenesisValidationMode,
+) -> anyhow::Result<NearConfig> {
+    let config = Config::from_file(&dir.join(CONFIG_FILENAME))?;
     let genesis_file = dir.join(&config.genesis_file);
-    let validator_signer = if dir.join(&config.validator_key_file).exists() {
+    let validator_signer = if let Some(remote_signer) = &config.remote_signer {
+        let signer = RemoteValidatorSigner::from_config(remote_signer, dir).with_context(|| {
+            anyhow!("Failed to connect to remote signer {}", remote_signer.addr)
+        })?;
+        Some(Arc::new(signer) as Arc<dyn ValidatorSigner>)
+    } else if dir.join(&config.validator_key_file).exists() {
         let signer =
             Arc::new(InMemoryValidatorSigner::from_file(&dir.join(&config.validator_key_file)))
                 as Arc<dyn ValidatorSigner>;
@@ -1635,8 +1749,14 @@ pub fn load_config(dir: &Path, genesis_validation: GenesisValidationMode) -> Nea
     };
     let network_signer = NodeKeyFile::from_file(&dir.join(&config.node_key_file));
 
+    // With a remote signer there is no key file to reload.
+    let validator_key_file = if config.remote_signer.is_none() {
+        Some(dir.join(&config.validator_key_file))
+    } else {
+        None
+    };
     let genesis_records_file = config.genesis_records_file.clone();
-    NearConfig::new(
+    let mut near_config = NearConfig::new(
         config,
         match genesis_records_file {
             Some(genesis_records_file) => Genesis::from_files(
@@ -1648,7 +1768,12 @@ pub fn load_config(dir: &Path, genesis_validation: GenesisValidationMode) -> Nea
         },
         network_signer.into(),
         validator_signer,
-    )
+    )?;
+    near_config.client_config.validator_key_file = validator_key_file;
+    if let Some(recorder) = &mut near_config.network_config.message_recorder {
+        recorder.dir = dir.join(&recorder.dir);
+    }
+    Ok(near_config)
 }
 
 pub fn load_test_config(seed: &str, port: u16, genesis: Genesis) -> NearConfig {
@@ -1673,7 +1798,7 @@ pub fn load_test_config(seed: &str, port: u16, genesis: Genesis) -> NearConfig {
         )) as Arc<dyn ValidatorSigner>;
         (signer, Some(validator_signer))
     };
-    NearConfig::new(config, genesis, signer.into(), validator_signer)
+    NearConfig::new(config, genesis, signer.into(), validator_signer).unwrap()
 }
 
 #[test]
@@ -1722,3 +1847,15 @@ fn test_init_config_localnet() {
         2
     );
 }
+
+#[test]
+fn test_network_public_addr() {
+    let mut network = Config::default().network;
+    assert_eq!(network.public_addr().unwrap(), None);
+    network.external_address = "1.2.3.4:24567".to_string();
+    assert_eq!(network.public_addr().unwrap(), Some("1.2.3.4:24567".parse().unwrap()));
+    network.external_address = "localhost:24567".to_string();
+    assert_eq!(network.public_addr().unwrap().unwrap().port(), 24567);
+    network.external_address = "1.2.3.4".to_string();
+    assert!(network.public_addr().is_err());
+}
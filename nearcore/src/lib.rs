@@ -26,7 +26,8 @@ use near_store::migrations::{
     fill_col_outcomes_by_hash, fill_col_transaction_refcount, get_store_version, migrate_10_to_11,
     migrate_11_to_12, migrate_13_to_14, migrate_14_to_15, migrate_17_to_18, migrate_20_to_21,
     migrate_21_to_22, migrate_25_to_26, migrate_26_to_27, migrate_28_to_29, migrate_29_to_30,
-    migrate_6_to_7, migrate_7_to_8, migrate_8_to_9, migrate_9_to_10, set_store_version,
+    migrate_31_to_32, migrate_6_to_7, migrate_7_to_8, migrate_8_to_9, migrate_9_to_10,
+    set_store_version,
 };
 use near_store::{create_store, Store};
 use near_telemetry::TelemetryActor;
@@ -40,6 +41,8 @@ pub mod append_only_map;
 pub mod config;
 pub mod migrations;
 mod runtime;
+#[cfg(feature = "sandbox")]
+pub mod sandbox_fork;
 mod shard_tracker;
 
 const STORE_PATH: &str = "data";
@@ -325,6 +328,11 @@ pub fn apply_store_migrations(path: &Path, near_config: &NearConfig) {
         info!(target: "near", "Migrate DB from version 30 to 31");
         migrate_30_to_31(path, &near_config);
     }
+    if db_version <= 31 {
+        // version 31 => 32: add a score to known peers
+        info!(target: "near", "Migrate DB from version 31 to 32");
+        migrate_31_to_32(path);
+    }
 
     #[cfg(feature = "nightly_protocol")]
     {
@@ -401,6 +409,20 @@ pub fn start_with_config_and_synchronization(
         config.client_config.max_gas_burnt_view,
     ));
 
+    #[cfg(feature = "sandbox")]
+    if let Some(fork_config) = &config.config.sandbox_fork {
+        info!(
+            target: "near",
+            "Forking state of {} at height {}",
+            fork_config.rpc_url,
+            fork_config.block_height
+        );
+        let (_, genesis_state_roots) = near_chain::RuntimeAdapter::genesis_state(runtime.as_ref());
+        let roots = sandbox_fork::local_state_roots(&store, &genesis_state_roots);
+        near_chain::RuntimeAdapter::get_tries(runtime.as_ref())
+            .set_fallback(Arc::new(sandbox_fork::RpcStateFallback::new(fork_config, roots)));
+    }
+
     let telemetry = TelemetryActor::new(config.telemetry_config.clone()).start();
     let chain_genesis = ChainGenesis::from(&config.genesis);
 
@@ -460,6 +482,8 @@ pub fn start_with_config_and_synchronization(
             config.genesis.config.clone(),
             client_actor.clone(),
             view_client.clone(),
+            Some(network_actor.clone().recipient()),
+            Some(network_actor.clone().recipient()),
             #[cfg(feature = "test_features")]
             network_actor.clone(),
             #[cfg(feature = "test_features")]
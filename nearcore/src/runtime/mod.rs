@@ -39,20 +39,20 @@ use near_primitives::types::{
 use near_primitives::version::ProtocolVersion;
 use near_primitives::views::{
     AccessKeyInfoView, CallResult, EpochValidatorInfo, QueryRequest, QueryResponse,
-    QueryResponseKind, ViewApplyState, ViewStateResult,
+    QueryResponseKind, ValidatorEpochPerformanceView, ViewApplyState, ViewStateResult,
 };
 use near_vm_runner::precompile_contract;
 
 use near_store::{
     get_genesis_hash, get_genesis_state_roots, set_genesis_hash, set_genesis_state_roots,
     ApplyStatePartResult, ColState, PartialStorage, ShardTries, Store, StoreCompiledContractCache,
-    StoreUpdate, Trie, WrappedTrieChanges,
+    StoreUpdate, Trie, TrieUpdate, WrappedTrieChanges,
 };
 use node_runtime::adapter::ViewRuntimeAdapter;
 use node_runtime::state_viewer::TrieViewer;
 use node_runtime::{
     validate_transaction, verify_and_charge_transaction, ApplyState, Runtime,
-    ValidatorAccountsUpdate,
+    ValidatorAccountsUpdate, VerificationResult,
 };
 
 use crate::shard_tracker::{ShardTracker, TrackedConfig};
@@ -60,12 +60,15 @@ use crate::shard_tracker::{ShardTracker, TrackedConfig};
 use crate::migrations::load_migration_data;
 use crate::NearConfig;
 use errors::FromStateViewerErrors;
+use near_primitives::runtime::config::RuntimeConfig;
 use near_primitives::runtime::config_store::{RuntimeConfigStore, INITIAL_TESTNET_CONFIG};
 use near_primitives::runtime::migration_data::{MigrationData, MigrationFlags};
 use near_primitives::shard_layout::{
     account_id_to_shard_id, account_id_to_shard_uid, ShardLayout, ShardUId,
 };
-use near_primitives::syncing::{get_num_state_parts, STATE_PART_MEMORY_LIMIT};
+use near_primitives::syncing::{
+    get_epoch_sync_data_hash, get_num_state_parts, STATE_PART_MEMORY_LIMIT,
+};
 use near_store::split_state::get_delayed_receipts;
 use node_runtime::near_primitives::shard_layout::ShardLayoutError;
 use rayon::iter::{IntoParallelRefIterator, ParallelIterator};
@@ -625,6 +628,40 @@ impl NightshadeRuntime {
             });
         Ok(())
     }
+
+    /// Verifies and charges the transaction, letting it act on behalf of its signer if it was
+    /// submitted through the sandbox impersonation RPC.
+    fn verify_and_charge_transaction(
+        &self,
+        runtime_config: &RuntimeConfig,
+        state_update: &mut TrieUpdate,
+        gas_price: Balance,
+        transaction: &SignedTransaction,
+        verify_signature: bool,
+        block_height: Option<BlockHeight>,
+        current_protocol_version: ProtocolVersion,
+    ) -> Result<VerificationResult, RuntimeError> {
+        #[cfg(feature = "sandbox")]
+        if self.runtime.is_sandbox_impersonated(transaction) {
+            return node_runtime::verify_and_charge_impersonated_transaction(
+                runtime_config,
+                state_update,
+                gas_price,
+                transaction,
+                block_height,
+                current_protocol_version,
+            );
+        }
+        verify_and_charge_transaction(
+            runtime_config,
+            state_update,
+            gas_price,
+            transaction,
+            verify_signature,
+            block_height,
+            current_protocol_version,
+        )
+    }
 }
 
 fn apply_delayed_receipts<'a>(
@@ -722,7 +759,7 @@ impl RuntimeAdapter for NightshadeRuntime {
                 self.account_id_to_shard_uid(&transaction.transaction.signer_id, epoch_id)?;
             let mut state_update = self.tries.new_trie_update(shard_uid, state_root);
 
-            match verify_and_charge_transaction(
+            match self.verify_and_charge_transaction(
                 runtime_config,
                 &mut state_update,
                 gas_price,
@@ -749,7 +786,7 @@ impl RuntimeAdapter for NightshadeRuntime {
                 runtime_config,
                 gas_price,
                 transaction,
-                verify_signature,
+                verify_signature && !self.runtime.is_sandbox_impersonated(transaction),
                 current_protocol_version,
             ) {
                 Ok(_) => Ok(None),
@@ -765,6 +802,11 @@ impl RuntimeAdapter for NightshadeRuntime {
         }
     }
 
+    #[cfg(feature = "sandbox")]
+    fn sandbox_impersonate_transaction(&self, tx_hash: CryptoHash) {
+        self.runtime.sandbox_impersonate_transaction(tx_hash);
+    }
+
     fn prepare_transactions(
         &self,
         gas_price: Balance,
@@ -796,7 +838,7 @@ impl RuntimeAdapter for NightshadeRuntime {
                     // Verifying the transaction is on the same chain and hasn't expired yet.
                     if chain_validate(&tx) {
                         // Verifying the validity of the transaction based on the current state.
-                        match verify_and_charge_transaction(
+                        match self.verify_and_charge_transaction(
                             runtime_config,
                             &mut state_update,
                             gas_price,
@@ -1221,7 +1263,6 @@ impl RuntimeAdapter for NightshadeRuntime {
         Ok(epoch_manager.get_epoch_info(epoch_id)?.minted_amount())
     }
 
-    // TODO #3488 this likely to be updated
     fn get_epoch_sync_data_hash(
         &self,
         prev_epoch_last_block_hash: &CryptoHash,
@@ -1236,16 +1277,16 @@ impl RuntimeAdapter for NightshadeRuntime {
             cur_epoch_info,
             next_epoch_info,
         ) = self.get_epoch_sync_data(prev_epoch_last_block_hash, epoch_id, next_epoch_id)?;
-        let mut data = prev_epoch_first_block_info.try_to_vec().unwrap();
-        data.extend(prev_epoch_prev_last_block_info.try_to_vec().unwrap());
-        data.extend(prev_epoch_last_block_info.try_to_vec().unwrap());
-        data.extend(prev_epoch_info.try_to_vec().unwrap());
-        data.extend(cur_epoch_info.try_to_vec().unwrap());
-        data.extend(next_epoch_info.try_to_vec().unwrap());
-        Ok(hash(data.as_slice()))
+        Ok(get_epoch_sync_data_hash(
+            &prev_epoch_first_block_info,
+            &prev_epoch_prev_last_block_info,
+            &prev_epoch_last_block_info,
+            &prev_epoch_info,
+            &cur_epoch_info,
+            &next_epoch_info,
+        ))
     }
 
-    // TODO #3488 this likely to be updated
     fn get_epoch_sync_data(
         &self,
         prev_epoch_last_block_hash: &CryptoHash,
@@ -1281,22 +1322,19 @@ impl RuntimeAdapter for NightshadeRuntime {
         epoch_info: EpochInfo,
         next_epoch_id: &EpochId,
         next_epoch_info: EpochInfo,
-    ) -> Result<(), Error> {
+    ) -> Result<StoreUpdate, Error> {
         let mut epoch_manager = self.epoch_manager.as_ref().write().expect(POISONED_LOCK_ERR);
-        epoch_manager
-            .init_after_epoch_sync(
-                prev_epoch_first_block_info,
-                prev_epoch_prev_last_block_info,
-                prev_epoch_last_block_info,
-                prev_epoch_id,
-                prev_epoch_info,
-                epoch_id,
-                epoch_info,
-                next_epoch_id,
-                next_epoch_info,
-            )?
-            .commit()
-            .map_err(|err| err.into())
+        Ok(epoch_manager.init_after_epoch_sync(
+            prev_epoch_first_block_info,
+            prev_epoch_prev_last_block_info,
+            prev_epoch_last_block_info,
+            prev_epoch_id,
+            prev_epoch_info,
+            epoch_id,
+            epoch_info,
+            next_epoch_id,
+            next_epoch_info,
+        )?)
     }
 
     fn add_validator_proposals(
@@ -1567,6 +1605,19 @@ impl RuntimeAdapter for NightshadeRuntime {
         epoch_manager.get_validator_info(epoch_id).map_err(|e| e.into())
     }
 
+    fn get_validator_performance(
+        &self,
+        account_id: &AccountId,
+        block_hash: &CryptoHash,
+        from_epoch_height: EpochHeight,
+        to_epoch_height: EpochHeight,
+    ) -> Result<Vec<ValidatorEpochPerformanceView>, Error> {
+        let mut epoch_manager = self.epoch_manager.as_ref().write().expect(POISONED_LOCK_ERR);
+        epoch_manager
+            .get_validator_performance(account_id, block_hash, from_epoch_height, to_epoch_height)
+            .map_err(|e| e.into())
+    }
+
     /// Returns StorageError when storage is inconsistent.
     /// This is possible with the used isolation level + running ViewClient in a separate thread
     /// `block_hash` is a block whose `prev_state_root` is `state_root`
@@ -1625,11 +1676,13 @@ impl RuntimeAdapter for NightshadeRuntime {
         state_roots: HashMap<ShardUId, StateRoot>,
         next_epoch_shard_layout: &ShardLayout,
         state_changes: StateChangesForSplitStates,
+        protocol_version: ProtocolVersion,
     ) -> Result<Vec<ApplySplitStateResult>, Error> {
         let trie_changes = self.tries.apply_state_changes_to_split_states(
             &state_roots,
             state_changes,
             &|account_id| account_id_to_shard_uid(account_id, next_epoch_shard_layout),
+            protocol_version,
         )?;
 
         Ok(trie_changes
@@ -1653,6 +1706,7 @@ impl RuntimeAdapter for NightshadeRuntime {
         shard_uid: ShardUId,
         state_root: &StateRoot,
         next_epoch_shard_layout: &ShardLayout,
+        protocol_version: ProtocolVersion,
     ) -> Result<HashMap<ShardUId, StateRoot>, Error> {
         let trie = self.tries.get_view_trie_for_shard(shard_uid);
         let shard_id = shard_uid.shard_id();
@@ -1685,6 +1739,7 @@ impl RuntimeAdapter for NightshadeRuntime {
                 &state_roots,
                 trie_items.into_iter().map(|(key, value)| (key, Some(value))).collect(),
                 &checked_account_id_to_shard_id,
+                protocol_version,
             )?;
             state_roots = new_state_roots;
             store_update.commit()?;
@@ -2019,7 +2074,7 @@ mod test {
                 .unwrap();
             let mut store_update = self.store.store_update();
             result.trie_changes.insertions_into(&mut store_update).unwrap();
-            result.trie_changes.state_changes_into(&mut store_update);
+            result.trie_changes.state_changes_into(&mut store_update).unwrap();
             store_update.commit().unwrap();
             (result.new_root, result.validator_proposals, result.outgoing_receipts)
         }
@@ -0,0 +1,357 @@
+//! Lazy forking of the state of another network for sandbox nodes.
+//!
+//! When `sandbox_fork` is set in the config, state reads which miss the local trie are served by
+//! querying the JSON-RPC of the upstream network at a pinned block. Fetched values are cached in
+//! memory and are written into the local trie by the first chunk which reads them, after which
+//! they behave like any other local state.
+//!
+//! Only accounts, access keys, contract code and contract data are fetched. Contract data can
+//! only be queried by prefix, so all the upstream values under the requested key are cached at
+//! once, and a key whose contract state under it is too large for the upstream node can't be
+//! read. Iterating over the state (e.g. `view_state` with a prefix or listing access keys) only
+//! sees keys which are already present locally.
+//!
+//! Only the state roots of the local chain read from the upstream node, so that tries of
+//! unrelated states, e.g. the ones built during state sync, are not mixed with the forked state.
+use std::collections::{HashMap, HashSet};
+use std::sync::{mpsc, Mutex};
+use std::time::Duration;
+
+use borsh::{BorshDeserialize, BorshSerialize};
+use serde::{Deserialize, Serialize};
+use tracing::{debug, error, warn};
+
+use near_jsonrpc_client::{new_client, JsonRpcClient};
+use near_jsonrpc_primitives::errors::{RpcError, RpcErrorKind};
+use near_jsonrpc_primitives::types::query::{QueryResponseKind, RpcQueryError, RpcQueryRequest};
+use near_primitives::account::{AccessKey, Account};
+use near_primitives::errors::StorageError;
+use near_primitives::serialize::from_base64;
+use near_primitives::trie_key::TrieKey;
+use near_primitives::types::chunk_extra::ChunkExtra;
+use near_primitives::types::{BlockHeight, BlockId, BlockReference, StateRoot};
+use near_primitives::views::QueryRequest;
+use near_store::{ColChunkExtra, Store, TrieFallback};
+
+/// Configuration of a sandbox node which lazily forks the state of another network.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct SandboxForkConfig {
+    /// JSON-RPC endpoint of the network to fork, e.g. `https://rpc.testnet.near.org`.
+    pub rpc_url: String,
+    /// Height of the upstream block at which the state is read.
+    pub block_height: BlockHeight,
+}
+
+/// Number of times a request to the upstream node is sent before giving up.
+const FETCH_ATTEMPTS: u32 = 3;
+/// Delay before retrying a failed request, multiplied by the number of failed attempts.
+const FETCH_RETRY_DELAY: Duration = Duration::from_millis(500);
+
+/// Upstream values learned from a single request, including the requested key.
+type FetchResult = Result<Vec<(TrieKey, Option<Vec<u8>>)>, String>;
+
+/// `TrieFallback` which reads missing state from an upstream JSON-RPC node.
+pub struct RpcStateFallback {
+    requests: tokio::sync::mpsc::UnboundedSender<(TrieKey, mpsc::SyncSender<FetchResult>)>,
+    /// Upstream values by raw trie key. `None` means the key doesn't exist upstream or was
+    /// deleted locally.
+    cache: Mutex<HashMap<Vec<u8>, Option<Vec<u8>>>>,
+    /// State roots of the local chain, which are derived from the forked state.
+    roots: Mutex<HashSet<StateRoot>>,
+}
+
+impl RpcStateFallback {
+    /// Starts a thread which sends requests to the upstream node. The thread stops when the
+    /// fallback is dropped.
+    ///
+    /// `roots` are the state roots the node already knows, i.e. the genesis state roots and the
+    /// roots of the chunks applied before a restart.
+    pub fn new(config: &SandboxForkConfig, roots: impl IntoIterator<Item = StateRoot>) -> Self {
+        let (requests, mut receiver) =
+            tokio::sync::mpsc::unbounded_channel::<(TrieKey, mpsc::SyncSender<FetchResult>)>();
+        let rpc_url = config.rpc_url.clone();
+        let block_reference = BlockReference::BlockId(BlockId::Height(config.block_height));
+        std::thread::Builder::new()
+            .name("sandbox_fork".to_string())
+            .spawn(move || {
+                actix::System::new().block_on(async move {
+                    let client = new_client(&rpc_url);
+                    while let Some((key, response)) = receiver.recv().await {
+                        let mut attempt = 1;
+                        let result = loop {
+                            match fetch(&client, block_reference.clone(), &key).await {
+                                Err(err) if attempt < FETCH_ATTEMPTS => {
+                                    warn!(target: "sandbox", "Failed to fetch {:?} from upstream, retrying: {}", key, err);
+                                    actix::clock::sleep(FETCH_RETRY_DELAY * attempt).await;
+                                    attempt += 1;
+                                }
+                                result => break result,
+                            }
+                        };
+                        let _ = response.send(result);
+                    }
+                })
+            })
+            .expect("Failed to start sandbox fork thread");
+        Self {
+            requests,
+            cache: Mutex::new(HashMap::new()),
+            roots: Mutex::new(roots.into_iter().collect()),
+        }
+    }
+}
+
+impl TrieFallback for RpcStateFallback {
+    fn covers(&self, root: &StateRoot) -> bool {
+        self.roots.lock().unwrap().contains(root)
+    }
+
+    fn add_root(&self, root: StateRoot) {
+        self.roots.lock().unwrap().insert(root);
+    }
+
+    fn get(&self, key: &TrieKey) -> Result<Option<Vec<u8>>, StorageError> {
+        let raw_key = key.to_vec();
+        if let Some(value) = self.cache.lock().unwrap().get(&raw_key) {
+            return Ok(value.clone());
+        }
+        let (sender, receiver) = mpsc::sync_channel(1);
+        let result = self
+            .requests
+            .send((key.clone(), sender))
+            .map_err(|_| "sandbox fork thread has stopped".to_string())
+            .and_then(|()| {
+                receiver.recv().map_err(|_| "sandbox fork thread has stopped".to_string())?
+            });
+        match result {
+            Ok(values) => {
+                let mut cache = self.cache.lock().unwrap();
+                for (fetched_key, value) in values {
+                    // Keep the entry if the key got deleted while the request was in flight.
+                    cache.entry(fetched_key.to_vec()).or_insert(value);
+                }
+                let value = cache.entry(raw_key).or_insert(None).clone();
+                debug!(target: "sandbox", "Fetched {:?} from upstream: {}", key, value.is_some());
+                Ok(value)
+            }
+            Err(err) => {
+                // The chunk fails to apply and is retried later, like after a database error.
+                error!(target: "sandbox", "Failed to fetch {:?} from upstream: {}", key, err);
+                Err(StorageError::StorageInternalError)
+            }
+        }
+    }
+
+    fn mark_deleted(&self, key: &TrieKey) {
+        self.cache.lock().unwrap().insert(key.to_vec(), None);
+    }
+}
+
+/// Returns the genesis state roots and the state roots of all the chunks applied so far, which
+/// are the roots of the local chain when the node starts.
+pub fn local_state_roots(store: &Store, genesis_state_roots: &[StateRoot]) -> Vec<StateRoot> {
+    let mut roots = genesis_state_roots.to_vec();
+    for (_, value) in store.iter(ColChunkExtra) {
+        match ChunkExtra::try_from_slice(&value) {
+            Ok(chunk_extra) => roots.push(*chunk_extra.state_root()),
+            Err(err) => error!(target: "sandbox", "Failed to read chunk extra: {}", err),
+        }
+    }
+    roots
+}
+
+/// Reads the value of `key` from the upstream node, along with the other values returned by the
+/// same query. Keys which can't be queried over JSON-RPC, such as receipts and their indices,
+/// are treated as missing.
+async fn fetch(
+    client: &JsonRpcClient,
+    block_reference: BlockReference,
+    key: &TrieKey,
+) -> FetchResult {
+    let request = match key {
+        TrieKey::Account { account_id } => {
+            QueryRequest::ViewAccount { account_id: account_id.clone() }
+        }
+        TrieKey::ContractCode { account_id } => {
+            QueryRequest::ViewCode { account_id: account_id.clone() }
+        }
+        TrieKey::AccessKey { account_id, public_key } => QueryRequest::ViewAccessKey {
+            account_id: account_id.clone(),
+            public_key: public_key.clone(),
+        },
+        TrieKey::ContractData { account_id, key } => {
+            QueryRequest::ViewState { account_id: account_id.clone(), prefix: key.clone().into() }
+        }
+        _ => return Ok(vec![]),
+    };
+    let response = match client.query(RpcQueryRequest { block_reference, request }).await {
+        Ok(response) => response,
+        Err(err) if is_missing_value_error(&err) => return Ok(vec![]),
+        Err(err) => return Err(err.to_string()),
+    };
+    let value = match (response.kind, key) {
+        (QueryResponseKind::ViewAccount(view), TrieKey::Account { .. }) => {
+            Account::from(view).try_to_vec().map_err(|err| err.to_string())?
+        }
+        (QueryResponseKind::ViewCode(view), TrieKey::ContractCode { .. }) => view.code,
+        (QueryResponseKind::AccessKey(view), TrieKey::AccessKey { .. }) => {
+            AccessKey::from(view).try_to_vec().map_err(|err| err.to_string())?
+        }
+        (QueryResponseKind::ViewState(result), TrieKey::ContractData { account_id, .. }) => {
+            // The upstream returns all the keys starting with the requested one.
+            return result
+                .values
+                .into_iter()
+                .map(|item| {
+                    let key = from_base64(&item.key).map_err(|err| err.to_string())?;
+                    let value = from_base64(&item.value).map_err(|err| err.to_string())?;
+                    Ok((TrieKey::ContractData { account_id: account_id.clone(), key }, Some(value)))
+                })
+                .collect();
+        }
+        (kind, _) => return Err(format!("unexpected query response {:?}", kind)),
+    };
+    Ok(vec![(key.clone(), Some(value))])
+}
+
+/// Whether the error means that the requested value doesn't exist upstream.
+fn is_missing_value_error(err: &RpcError) -> bool {
+    let value = match &err.error_struct {
+        Some(RpcErrorKind::HandlerError(value)) => value,
+        _ => return false,
+    };
+    matches!(
+        serde_json::from_value(value.clone()),
+        Ok(RpcQueryError::UnknownAccount { .. }
+            | RpcQueryError::NoContractCode { .. }
+            | RpcQueryError::UnknownAccessKey { .. })
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use actix_web::{web, App, HttpServer};
+    use serde_json::Value;
+
+    use near_jsonrpc_primitives::message::Message;
+    use near_jsonrpc_primitives::types::query::RpcQueryResponse;
+    use near_primitives::hash::CryptoHash;
+    use near_primitives::serialize::to_base64;
+    use near_primitives::views::{AccountView, StateItem, ViewStateResult};
+
+    use super::*;
+
+    static QUERIES: AtomicUsize = AtomicUsize::new(0);
+
+    /// Stand-in for the upstream node, which knows about a single account `alice.near`.
+    async fn query(request: web::Json<Value>) -> web::Json<Message> {
+        QUERIES.fetch_add(1, Ordering::SeqCst);
+        let params = &request["params"];
+        let kind = match (params["request_type"].as_str(), params["account_id"].as_str()) {
+            (Some("view_account"), Some("alice.near")) => {
+                QueryResponseKind::ViewAccount(AccountView {
+                    amount: 100,
+                    locked: 0,
+                    code_hash: CryptoHash::default(),
+                    storage_usage: 182,
+                    storage_paid_at: 0,
+                })
+            }
+            (Some("view_state"), Some("alice.near")) => {
+                let item = |key: &[u8], value: &[u8]| StateItem {
+                    key: to_base64(key),
+                    value: to_base64(value),
+                    proof: vec![],
+                };
+                QueryResponseKind::ViewState(ViewStateResult {
+                    values: vec![item(b"counter", b"42"), item(b"counter2", b"43")],
+                    proof: vec![],
+                })
+            }
+            _ => {
+                let error = RpcQueryError::UnknownAccount {
+                    requested_account_id: params["account_id"].as_str().unwrap().parse().unwrap(),
+                    block_height: 1,
+                    block_hash: CryptoHash::default(),
+                };
+                return web::Json(Message::response(request["id"].clone(), Err(error.into())));
+            }
+        };
+        let response =
+            RpcQueryResponse { kind, block_height: 1, block_hash: CryptoHash::default() };
+        let result = serde_json::to_value(response).unwrap();
+        web::Json(Message::response(request["id"].clone(), Ok(result)))
+    }
+
+    fn start_upstream() -> String {
+        let (sender, receiver) = mpsc::channel();
+        std::thread::spawn(move || {
+            actix::System::new().block_on(async move {
+                let server = HttpServer::new(|| App::new().route("/", web::post().to(query)))
+                    .bind("127.0.0.1:0")
+                    .unwrap();
+                sender.send(server.addrs()[0]).unwrap();
+                server.run().await.unwrap();
+            })
+        });
+        format!("http://{}", receiver.recv().unwrap())
+    }
+
+    #[test]
+    fn test_fetch_from_upstream() {
+        let rpc_url = start_upstream();
+        let fallback = RpcStateFallback::new(
+            &SandboxForkConfig { rpc_url, block_height: 1 },
+            vec![CryptoHash::default()],
+        );
+        assert!(fallback.covers(&CryptoHash::default()));
+        let alice = TrieKey::Account { account_id: "alice.near".parse().unwrap() };
+
+        let account = fallback.get(&alice).unwrap().unwrap();
+        assert_eq!(Account::try_from_slice(&account).unwrap().amount(), 100);
+        assert_eq!(
+            fallback.get(&TrieKey::Account { account_id: "bob.near".parse().unwrap() }),
+            Ok(None)
+        );
+        assert_eq!(
+            fallback.get(&TrieKey::ContractData {
+                account_id: "alice.near".parse().unwrap(),
+                key: b"counter".to_vec(),
+            }),
+            Ok(Some(b"42".to_vec()))
+        );
+        // Keys which can't be queried upstream are not requested.
+        assert_eq!(fallback.get(&TrieKey::DelayedReceiptIndices), Ok(None));
+        assert_eq!(QUERIES.load(Ordering::SeqCst), 3);
+
+        // Values are cached, including missing ones and the ones returned for another key.
+        assert!(fallback.get(&alice).unwrap().is_some());
+        assert_eq!(
+            fallback.get(&TrieKey::ContractData {
+                account_id: "alice.near".parse().unwrap(),
+                key: b"counter2".to_vec(),
+            }),
+            Ok(Some(b"43".to_vec()))
+        );
+        assert_eq!(
+            fallback.get(&TrieKey::Account { account_id: "bob.near".parse().unwrap() }),
+            Ok(None)
+        );
+        assert_eq!(QUERIES.load(Ordering::SeqCst), 3);
+
+        fallback.mark_deleted(&alice);
+        assert_eq!(fallback.get(&alice), Ok(None));
+    }
+
+    #[test]
+    fn test_upstream_unavailable() {
+        // Nothing listens on the port, so every attempt fails.
+        let rpc_url = "http://127.0.0.1:1".to_string();
+        let fallback = RpcStateFallback::new(&SandboxForkConfig { rpc_url, block_height: 1 }, []);
+        let alice = TrieKey::Account { account_id: "alice.near".parse().unwrap() };
+        assert_eq!(fallback.get(&alice), Err(StorageError::StorageInternalError));
+        assert!(!fallback.covers(&CryptoHash::default()));
+    }
+}
@@ -69,6 +69,7 @@ impl NeardCmd {
             NeardSubCommand::StateViewer(cmd) => {
                 cmd.run(&home_dir, genesis_validation);
             }
+            NeardSubCommand::Keys(cmd) => cmd.run(&home_dir),
         }
     }
 }
@@ -132,6 +133,9 @@ pub(super) enum NeardSubCommand {
     /// View DB state.
     #[clap(name = "view_state")]
     StateViewer(StateViewerSubCommand),
+    /// Encrypt, decrypt or generate key files.
+    #[clap(name = "keys")]
+    Keys(KeysCmd),
 }
 
 #[derive(Clap)]
@@ -247,6 +251,11 @@ pub(super) struct RunCmd {
     /// Keep old blocks in the storage (default false).
     #[clap(long)]
     archive: bool,
+    /// Only sync block headers and serve the `next_light_client_block` RPC method,
+    /// without downloading blocks, chunks or state (default false). The
+    /// `light_client_proof` method needs a full node.
+    #[clap(long)]
+    light_node: bool,
     /// Set the boot nodes to bootstrap network from.
     #[clap(long)]
     boot_nodes: Option<String>,
@@ -283,12 +292,23 @@ pub(super) struct RunCmd {
     /// configuration will be taken.
     #[clap(long)]
     max_gas_burnt_view: Option<Gas>,
+    /// Start the contract debugger listening on the given address.  Contracts
+    /// will be run on Wasmtime and can be paused at breakpoints set over this
+    /// socket.
+    #[cfg(feature = "sandbox")]
+    #[clap(long)]
+    sandbox_debugger_addr: Option<SocketAddr>,
 }
 
 impl RunCmd {
     pub(super) fn run(self, home_dir: &Path, genesis_validation: GenesisValidationMode) {
+        unlock_key_files(home_dir);
         // Load configs from home.
-        let mut near_config = nearcore::config::load_config(home_dir, genesis_validation);
+        let mut near_config = nearcore::config::load_config(home_dir, genesis_validation)
+            .unwrap_or_else(|err| {
+                eprintln!("Error loading config: {:#}", err);
+                std::process::exit(1);
+            });
 
         check_release_build(&near_config.client_config.chain_id);
 
@@ -332,6 +352,21 @@ impl RunCmd {
         if self.archive {
             near_config.client_config.archive = true;
         }
+        if self.light_node {
+            near_config.client_config.light_node = true;
+        }
+        if near_config.client_config.light_node {
+            if near_config.client_config.archive {
+                eprintln!("Light node can't be an archival node");
+                std::process::exit(1);
+            }
+            if near_config.validator_signer.is_some() {
+                eprintln!("Light node can't be a validator, remove the validator key");
+                std::process::exit(1);
+            }
+            near_config.client_config.tracked_accounts.clear();
+            near_config.client_config.tracked_shards.clear();
+        }
         if self.max_gas_burnt_view.is_some() {
             near_config.client_config.max_gas_burnt_view = self.max_gas_burnt_view;
         }
@@ -347,12 +382,18 @@ impl RunCmd {
                 );
                 std::process::exit(1);
             }
+            if let Some(addr) = self.sandbox_debugger_addr {
+                if let Err(err) = near_vm_runner::sandbox_debugger::start(addr) {
+                    eprintln!("Failed to start sandbox debugger on {}: {}", addr, err);
+                    std::process::exit(1);
+                }
+            }
         }
 
         let (tx, rx) = oneshot::channel::<()>();
         let sys = actix::System::new();
         sys.block_on(async move {
-            let nearcore::NearNode { rpc_servers, .. } =
+            let nearcore::NearNode { rpc_servers, client, .. } =
                 nearcore::start_with_config_and_synchronization(home_dir, near_config, Some(tx))
                     .expect("start_with_config");
 
@@ -360,10 +401,22 @@ impl RunCmd {
                 use tokio::signal::unix::{signal, SignalKind};
                 let mut sigint = signal(SignalKind::interrupt()).unwrap();
                 let mut sigterm = signal(SignalKind::terminate()).unwrap();
-                futures::select! {
-                    _ = sigint .recv().fuse() => "SIGINT",
-                    _ = sigterm.recv().fuse() => "SIGTERM",
-                    _ = rx.fuse() => "ClentActor died",
+                let mut sighup = signal(SignalKind::hangup()).unwrap();
+                let mut rx = rx.fuse();
+                loop {
+                    futures::select! {
+                        _ = sigint .recv().fuse() => break "SIGINT",
+                        _ = sigterm.recv().fuse() => break "SIGTERM",
+                        _ = sighup.recv().fuse() => {
+                            info!(target: "neard", "Got 'SIGHUP', reloading validator key");
+                            match client.send(near_client::ReloadValidatorKey { force: false }).await {
+                                Ok(Ok(response)) => info!(target: "neard", "Switched to validator key {} of {}", response.public_key, response.account_id),
+                                Ok(Err(err)) => warn!(target: "neard", "Failed to reload validator key: {}", err),
+                                Err(err) => warn!(target: "neard", "Failed to reload validator key: {}", err),
+                            }
+                        }
+                        _ = rx => break "ClentActor died",
+                    }
                 }
             } else {
                 // TODO(#6372): Support graceful shutdown on windows.
@@ -413,6 +466,178 @@ impl LocalnetCmd {
     }
 }
 
+/// Prompts for the passphrase of the encrypted key files in the home directory, unless it's
+/// already given in the environment.
+fn unlock_key_files(home_dir: &Path) {
+    let config = match nearcore::config::Config::from_file(
+        &home_dir.join(nearcore::config::CONFIG_FILENAME),
+    ) {
+        Ok(config) => config,
+        // Reported when loading the config.
+        Err(_) => return,
+    };
+    let encrypted = [&config.validator_key_file, &config.node_key_file]
+        .iter()
+        .any(|file| near_crypto::is_key_file_encrypted(&home_dir.join(file)).unwrap_or(false));
+    if !encrypted || matches!(near_crypto::key_passphrase(), Ok(Some(_))) {
+        return;
+    }
+    match rpassword::prompt_password_stderr("Key file passphrase: ") {
+        Ok(passphrase) => near_crypto::set_key_passphrase(passphrase),
+        Err(err) => {
+            eprintln!(
+                "Key files are encrypted, set {} or {} or run neard in a terminal: {}",
+                near_crypto::KEY_PASSPHRASE_ENV,
+                near_crypto::KEY_PASSPHRASE_FILE_ENV,
+                err
+            );
+            std::process::exit(1);
+        }
+    }
+}
+
+#[derive(Clap)]
+pub(super) struct KeysCmd {
+    #[clap(subcommand)]
+    subcmd: KeysSubCommand,
+}
+
+#[derive(Clap)]
+enum KeysSubCommand {
+    /// Encrypts a key file with a passphrase.
+    #[clap(name = "encrypt")]
+    Encrypt(EncryptKeyCmd),
+    /// Decrypts an encrypted key file.
+    #[clap(name = "decrypt")]
+    Decrypt(DecryptKeyCmd),
+    /// Generates a new key file, encrypted unless `--plaintext` is given.
+    #[clap(name = "generate")]
+    Generate(GenerateKeyCmd),
+}
+
+impl KeysCmd {
+    pub(super) fn run(self, home_dir: &Path) {
+        let result = match self.subcmd {
+            KeysSubCommand::Encrypt(cmd) => cmd.run(home_dir),
+            KeysSubCommand::Decrypt(cmd) => cmd.run(home_dir),
+            KeysSubCommand::Generate(cmd) => cmd.run(home_dir),
+        };
+        if let Err(err) = result {
+            eprintln!("{}", err);
+            std::process::exit(1);
+        }
+    }
+}
+
+#[derive(Clap)]
+struct EncryptKeyCmd {
+    /// Key file, relative to the home directory, e.g. `validator_key.json`.
+    #[clap(parse(from_os_str))]
+    file: PathBuf,
+    /// Where to write the encrypted key file. By default the key file is replaced.
+    #[clap(long, parse(from_os_str))]
+    output: Option<PathBuf>,
+}
+
+impl EncryptKeyCmd {
+    fn run(self, home_dir: &Path) -> io::Result<()> {
+        let path = home_dir.join(&self.file);
+        if near_crypto::is_key_file_encrypted(&path)? {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "key file is already encrypted",
+            ));
+        }
+        let content = fs::read_to_string(&path)?;
+        let key: serde_json::Value = serde_json::from_str(&content)?;
+        let public_key =
+            key["public_key"].as_str().and_then(|public_key| public_key.parse().ok()).ok_or_else(
+                || io::Error::new(io::ErrorKind::InvalidData, "key file has no public key"),
+            )?;
+        let passphrase = new_passphrase()?;
+        near_crypto::EncryptedKeyFile::encrypt(
+            content.as_bytes(),
+            public_key,
+            &passphrase,
+            Default::default(),
+        )?
+        .write_to_file(&self.output.map_or(path, |output| home_dir.join(output)))
+    }
+}
+
+#[derive(Clap)]
+struct DecryptKeyCmd {
+    /// Encrypted key file, relative to the home directory, e.g. `validator_key.json`.
+    #[clap(parse(from_os_str))]
+    file: PathBuf,
+    /// Where to write the plaintext key file. By default the key file is replaced.
+    #[clap(long, parse(from_os_str))]
+    output: Option<PathBuf>,
+}
+
+impl DecryptKeyCmd {
+    fn run(self, home_dir: &Path) -> io::Result<()> {
+        let path = home_dir.join(&self.file);
+        if !near_crypto::is_key_file_encrypted(&path)? {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "key file isn't encrypted"));
+        }
+        if near_crypto::key_passphrase()?.is_none() {
+            near_crypto::set_key_passphrase(rpassword::prompt_password_stderr("Passphrase: ")?);
+        }
+        let content = near_crypto::read_key_file(&path)?;
+        let output = self.output.map_or(path, |output| home_dir.join(output));
+        near_crypto::replace_key_file(&output, content.as_bytes())
+    }
+}
+
+#[derive(Clap)]
+struct GenerateKeyCmd {
+    /// Account ID stored in the key file.
+    #[clap(long)]
+    account_id: String,
+    /// Where to write the key file, relative to the home directory.
+    #[clap(long, parse(from_os_str))]
+    output: PathBuf,
+    /// Write the key file without encrypting it.
+    #[clap(long)]
+    plaintext: bool,
+}
+
+impl GenerateKeyCmd {
+    fn run(self, home_dir: &Path) -> io::Result<()> {
+        let account_id = self
+            .account_id
+            .parse::<near_primitives::types::AccountId>()
+            .map_err(|err| io::Error::new(io::ErrorKind::InvalidInput, err))?;
+        let secret_key = near_crypto::SecretKey::from_random(near_crypto::KeyType::ED25519);
+        let key_file =
+            near_crypto::KeyFile { account_id, public_key: secret_key.public_key(), secret_key };
+        let path = home_dir.join(&self.output);
+        if self.plaintext {
+            key_file.write_to_file(&path)?;
+        } else {
+            key_file.write_encrypted_to_file(&path, &new_passphrase()?)?;
+        }
+        println!("{}", key_file.public_key);
+        Ok(())
+    }
+}
+
+/// Returns the passphrase to encrypt a key file with, from the environment or prompting for it.
+fn new_passphrase() -> io::Result<String> {
+    if let Some(passphrase) = near_crypto::key_passphrase()? {
+        return Ok(passphrase);
+    }
+    let passphrase = rpassword::prompt_password_stderr("New passphrase: ")?;
+    if passphrase.is_empty() {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "passphrase is empty"));
+    }
+    if rpassword::prompt_password_stderr("Repeat passphrase: ")? != passphrase {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "passphrases don't match"));
+    }
+    Ok(passphrase)
+}
+
 fn init_logging(verbose: Option<&str>) {
     const DEFAULT_RUST_LOG: &'static str =
         "tokio_reactor=info,near=info,stats=info,telemetry=info,\
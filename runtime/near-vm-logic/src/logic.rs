@@ -2401,6 +2401,12 @@ impl<'a> VMLogic<'a> {
         Ok(res? as u64)
     }
 
+    /// Returns the contents of all registers, for inspection by the sandbox debugger.
+    #[cfg(feature = "sandbox")]
+    pub fn sandbox_registers(&self) -> &HashMap<u64, Vec<u8>> {
+        &self.registers
+    }
+
     /// Debug print given utf-8 string to node log. It's only available in Sandbox node
     ///
     /// # Errors
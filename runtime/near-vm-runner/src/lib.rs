@@ -9,6 +9,8 @@ mod memory;
 mod preload;
 pub mod prepare;
 mod runner;
+#[cfg(all(feature = "sandbox", feature = "wasmtime_vm"))]
+pub mod sandbox_debugger;
 #[cfg(test)]
 mod tests;
 mod vm_kind;
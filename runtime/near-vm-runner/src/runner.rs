@@ -35,6 +35,9 @@ pub fn run(
     cache: Option<&dyn CompiledContractCache>,
 ) -> (Option<VMOutcome>, Option<VMError>) {
     let vm_kind = VMKind::for_protocol_version(current_protocol_version);
+    // The sandbox debugger only instruments contracts run on Wasmtime.
+    #[cfg(all(feature = "sandbox", feature = "wasmtime_vm"))]
+    let vm_kind = if crate::sandbox_debugger::is_enabled() { VMKind::Wasmtime } else { vm_kind };
     if let Some(runtime) = vm_kind.runtime(wasm_config.clone()) {
         runtime.run(
             code,
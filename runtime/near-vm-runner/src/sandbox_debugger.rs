@@ -0,0 +1,567 @@
+//! Wasm-level debugger for sandbox nodes.
+//!
+//! When started with [`start`], contracts are executed on the Wasmtime backend, and every function
+//! whose name is listed in the breakpoints gets instrumented to pause at its entry. While paused, a client connected to the debugger socket can inspect the locals
+//! of the function, the linear memory and the `VMLogic` registers, and then resume execution.
+//!
+//! The protocol is line-delimited JSON over TCP. Every request is an object with a `command`
+//! field and gets exactly one response line back:
+//!
+//! - `{"command": "set_breakpoints", "functions": ["name", ...]}` replaces the breakpoints,
+//! - `{"command": "locals"}` returns the locals of the paused function,
+//! - `{"command": "memory", "offset": 0, "length": 32}` returns a hex-encoded memory slice,
+//! - `{"command": "registers"}` returns the hex-encoded contents of the registers,
+//! - `{"command": "continue"}` resumes the paused execution.
+//!
+//! Whenever execution hits a breakpoint, a `{"event": "stopped", ...}` line is sent to the
+//! client. Breakpoints are ignored while no client is connected, and disconnecting resumes any
+//! paused execution, so a forgotten debugger can't stall the node forever.
+//!
+//! Breakpoints can be set on function names from the `name` custom section or on export names.
+//! The names are resolved against the original contract code, so they aren't affected by the
+//! functions added during preparation.
+
+use once_cell::sync::{Lazy, OnceCell};
+use pwasm_utils::parity_wasm::elements::{
+    self, External, FunctionType, ImportCountType, ImportEntry, Instruction, Internal, Section,
+    Type, ValueType,
+};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::cell::RefCell;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::ffi::c_void;
+use std::io::{self, BufRead, BufReader, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::sync::{mpsc, Mutex};
+
+/// Module name of the host functions called by the instrumented code.
+const DEBUG_MODULE: &str = "near_debug";
+
+/// Maximum number of bytes returned by a single `memory` request.
+const MAX_MEMORY_READ: u64 = 1 << 20;
+
+#[derive(Deserialize)]
+#[serde(tag = "command", rename_all = "snake_case")]
+enum Request {
+    SetBreakpoints { functions: Vec<String> },
+    Locals,
+    Memory { offset: u64, length: u64 },
+    Registers,
+    Continue,
+}
+
+type PausedSender = mpsc::Sender<(Request, mpsc::Sender<Value>)>;
+
+#[derive(Default)]
+struct Debugger {
+    breakpoints: HashSet<String>,
+    /// Connection of the current client, used to send `stopped` events.
+    client: Option<TcpStream>,
+    /// Channel to the execution thread paused at a breakpoint, if any.
+    paused: Option<PausedSender>,
+}
+
+static DEBUGGER: OnceCell<Mutex<Debugger>> = OnceCell::new();
+
+/// Only one execution can be paused at a time; other threads hitting a breakpoint wait here.
+static PAUSE_LOCK: Lazy<Mutex<()>> = Lazy::new(|| Mutex::new(()));
+
+thread_local! {
+    static PENDING_LOCALS: RefCell<Vec<(u32, i64)>> = RefCell::new(Vec::new());
+}
+
+/// Starts the debugger server listening on `addr` and returns the address it is bound to.
+///
+/// Can only be called once per process.
+pub fn start(addr: SocketAddr) -> io::Result<SocketAddr> {
+    let listener = TcpListener::bind(addr)?;
+    let local_addr = listener.local_addr()?;
+    DEBUGGER
+        .set(Mutex::new(Debugger::default()))
+        .map_err(|_| io::Error::new(io::ErrorKind::AlreadyExists, "debugger already started"))?;
+    std::thread::Builder::new().name("sandbox-debugger".to_string()).spawn(move || {
+        for stream in listener.incoming() {
+            let stream = match stream {
+                Ok(stream) => stream,
+                Err(err) => {
+                    tracing::warn!(target: "sandbox", ?err, "debugger failed to accept connection");
+                    continue;
+                }
+            };
+            debugger().lock().unwrap().client = stream.try_clone().ok();
+            if let Err(err) = serve(stream) {
+                tracing::debug!(target: "sandbox", ?err, "debugger client disconnected");
+            }
+            let mut debugger = debugger().lock().unwrap();
+            debugger.client = None;
+            // Dropping the sender resumes the paused execution.
+            debugger.paused = None;
+        }
+    })?;
+    tracing::info!(target: "sandbox", local_addr = %local_addr, "sandbox debugger listening");
+    Ok(local_addr)
+}
+
+pub(crate) fn is_enabled() -> bool {
+    DEBUGGER.get().is_some()
+}
+
+fn debugger() -> &'static Mutex<Debugger> {
+    DEBUGGER.get().expect("debugger is started")
+}
+
+fn serve(stream: TcpStream) -> io::Result<()> {
+    let mut writer = stream.try_clone()?;
+    for line in BufReader::new(stream).lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let response = match serde_json::from_str::<Request>(&line) {
+            Ok(Request::SetBreakpoints { functions }) => {
+                debugger().lock().unwrap().breakpoints = functions.into_iter().collect();
+                json!({ "success": true })
+            }
+            Ok(request) => {
+                let paused = debugger().lock().unwrap().paused.clone();
+                let (reply_tx, reply_rx) = mpsc::channel();
+                match paused {
+                    Some(paused) if paused.send((request, reply_tx)).is_ok() => reply_rx
+                        .recv()
+                        .unwrap_or_else(|_| json!({ "success": false, "error": "not stopped" })),
+                    _ => json!({ "success": false, "error": "not stopped" }),
+                }
+            }
+            Err(err) => json!({ "success": false, "error": err.to_string() }),
+        };
+        write_line(&mut writer, &response)?;
+    }
+    Ok(())
+}
+
+fn write_line(writer: &mut TcpStream, value: &Value) -> io::Result<()> {
+    writer.write_all(format!("{}\n", value).as_bytes())
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+/// A function instrumented to pause at its entry.
+pub(crate) struct BreakpointFunction {
+    name: String,
+    /// Index of the function in the original contract code.
+    index: u32,
+    params: Vec<ValueType>,
+    /// Non-parameter locals, which are always zero at the function entry.
+    locals: Vec<ValueType>,
+}
+
+/// Instruments the `prepared_code` so that functions matching the current breakpoints call into
+/// the debugger at their entry.
+///
+/// Returns the code unchanged if the debugger is not started, there are no matching functions,
+/// or the instrumentation fails.
+pub(crate) fn instrument(
+    original_code: &[u8],
+    prepared_code: Vec<u8>,
+) -> (Vec<u8>, Vec<BreakpointFunction>) {
+    let breakpoints = match DEBUGGER.get() {
+        Some(debugger) => debugger.lock().unwrap().breakpoints.clone(),
+        None => return (prepared_code, Vec::new()),
+    };
+    if breakpoints.is_empty() {
+        return (prepared_code, Vec::new());
+    }
+    match instrument_breakpoints(original_code, &prepared_code, &breakpoints) {
+        Ok(Some((code, functions))) => (code, functions),
+        Ok(None) => (prepared_code, Vec::new()),
+        Err(err) => {
+            tracing::warn!(target: "sandbox", ?err, "failed to instrument contract for debugging");
+            (prepared_code, Vec::new())
+        }
+    }
+}
+
+fn instrument_breakpoints(
+    original_code: &[u8],
+    prepared_code: &[u8],
+    breakpoints: &HashSet<String>,
+) -> Result<Option<(Vec<u8>, Vec<BreakpointFunction>)>, elements::Error> {
+    let original = elements::deserialize_buffer::<elements::Module>(original_code)?;
+    let original = original.parse_names().unwrap_or_else(|(_, module)| module);
+    let original_imports = original.import_count(ImportCountType::Function) as u32;
+
+    let mut targets = BTreeMap::new();
+    if let Some(names) = original.names_section().and_then(|section| section.functions()) {
+        for (index, name) in names.names().iter() {
+            if breakpoints.contains(name) {
+                targets.insert(index, name.clone());
+            }
+        }
+    }
+    if let Some(exports) = original.export_section() {
+        for export in exports.entries() {
+            if let Internal::Function(index) = export.internal() {
+                if breakpoints.contains(export.field()) {
+                    targets.entry(*index).or_insert_with(|| export.field().to_string());
+                }
+            }
+        }
+    }
+    targets.retain(|index, _| *index >= original_imports);
+    if targets.is_empty() {
+        return Ok(None);
+    }
+
+    let mut module = elements::deserialize_buffer::<elements::Module>(prepared_code)?;
+    let prepared_imports = module.import_count(ImportCountType::Function) as u32;
+    // Preparation only appends imports (e.g. for gas metering) and functions (e.g. stack limiter
+    // thunks), so the function bodies of the original code keep their positions.
+    let invalid = || elements::Error::Other("malformed prepared module");
+
+    let mut functions = Vec::with_capacity(targets.len());
+    for (index, name) in targets {
+        let body_index = (index - original_imports) as usize;
+        let type_ref = module
+            .function_section()
+            .and_then(|section| section.entries().get(body_index))
+            .ok_or_else(invalid)?
+            .type_ref();
+        let Type::Function(func_type) = module
+            .type_section()
+            .and_then(|section| section.types().get(type_ref as usize))
+            .ok_or_else(invalid)?;
+        let body = module
+            .code_section()
+            .and_then(|section| section.bodies().get(body_index))
+            .ok_or_else(invalid)?;
+        let locals = body
+            .locals()
+            .iter()
+            .flat_map(|local| (0..local.count()).map(move |_| local.value_type()))
+            .collect();
+        functions.push(BreakpointFunction {
+            name,
+            index,
+            params: func_type.params().to_vec(),
+            locals,
+        });
+    }
+
+    let types = module.type_section_mut().ok_or_else(invalid)?.types_mut();
+    let local_type = types.len() as u32;
+    types.push(Type::Function(FunctionType::new(vec![ValueType::I32, ValueType::I64], vec![])));
+    types.push(Type::Function(FunctionType::new(vec![ValueType::I32], vec![])));
+    let imports = module.import_section_mut().ok_or_else(invalid)?.entries_mut();
+    for (field, type_ref) in [("local", local_type), ("enter", local_type + 1)] {
+        imports.push(ImportEntry::new(
+            DEBUG_MODULE.to_string(),
+            field.to_string(),
+            External::Function(type_ref),
+        ));
+    }
+    let local_func = prepared_imports;
+    let enter_func = prepared_imports + 1;
+    let remap = |index: &mut u32| {
+        if *index >= prepared_imports {
+            *index += 2;
+        }
+    };
+
+    if let Some(code) = module.code_section_mut() {
+        for body in code.bodies_mut() {
+            for instruction in body.code_mut().elements_mut() {
+                if let Instruction::Call(index) = instruction {
+                    remap(index);
+                }
+            }
+        }
+    }
+    if let Some(exports) = module.export_section_mut() {
+        for export in exports.entries_mut() {
+            if let Internal::Function(index) = export.internal_mut() {
+                remap(index);
+            }
+        }
+    }
+    if let Some(elements) = module.elements_section_mut() {
+        for segment in elements.entries_mut() {
+            segment.members_mut().iter_mut().for_each(remap);
+        }
+    }
+    if let Some(mut start) = module.start_section() {
+        remap(&mut start);
+        module.set_start_section(start);
+    }
+    // The function indices in the name section are stale after preparation anyway.
+    module.sections_mut().retain(|section| match section {
+        Section::Name(_) => false,
+        Section::Custom(custom) => custom.name() != "name",
+        _ => true,
+    });
+
+    let bodies = module.code_section_mut().ok_or_else(invalid)?.bodies_mut();
+    for (id, function) in functions.iter().enumerate() {
+        let mut prologue = Vec::new();
+        for (local, value_type) in function.params.iter().enumerate() {
+            prologue.push(Instruction::I32Const(local as i32));
+            prologue.push(Instruction::GetLocal(local as u32));
+            if *value_type == ValueType::I32 {
+                prologue.push(Instruction::I64ExtendUI32);
+            } else if *value_type == ValueType::F32 {
+                prologue.push(Instruction::I32ReinterpretF32);
+                prologue.push(Instruction::I64ExtendUI32);
+            } else if *value_type == ValueType::F64 {
+                prologue.push(Instruction::I64ReinterpretF64);
+            }
+            prologue.push(Instruction::Call(local_func));
+        }
+        prologue.push(Instruction::I32Const(id as i32));
+        prologue.push(Instruction::Call(enter_func));
+        let body_index = (function.index - original_imports) as usize;
+        let instructions =
+            bodies.get_mut(body_index).ok_or_else(invalid)?.code_mut().elements_mut();
+        prologue.append(instructions);
+        *instructions = prologue;
+    }
+
+    Ok(Some((elements::serialize(module)?, functions)))
+}
+
+/// Links the host functions called by the code instrumented with [`instrument`].
+pub(crate) fn link(
+    linker: &mut wasmtime::Linker<()>,
+    memory: wasmtime::Memory,
+    raw_logic: *mut c_void,
+    functions: Vec<BreakpointFunction>,
+) {
+    if functions.is_empty() {
+        return;
+    }
+    // Raw pointers are not `Send`, which host functions must be, so smuggle it as an integer.
+    let raw_logic = raw_logic as usize;
+    linker
+        .func_wrap(DEBUG_MODULE, "local", |index: i32, value: i64| {
+            PENDING_LOCALS.with(|locals| locals.borrow_mut().push((index as u32, value)));
+        })
+        .expect("cannot link debugger");
+    linker
+        .func_wrap(DEBUG_MODULE, "enter", move |caller: wasmtime::Caller<'_, ()>, id: i32| {
+            let params = PENDING_LOCALS.with(|locals| std::mem::take(&mut *locals.borrow_mut()));
+            let function = &functions[id as usize];
+            // SAFETY: `raw_logic` points to the `VMLogic` of the `run` call that instantiated
+            // this module, which outlives the instance, see `imports::wasmtime::link`. Host
+            // functions run on the thread executing the contract, one at a time, so no mutable
+            // reference to the logic is alive while we are in here, and the shared reference
+            // doesn't escape this call.
+            let logic: &near_vm_logic::VMLogic<'_> =
+                unsafe { &*(raw_logic as *const near_vm_logic::VMLogic<'_>) };
+            pause(function, &params, |request| match request {
+                Request::Locals => json!({ "success": true, "locals": locals(function, &params) }),
+                Request::Memory { offset, length } => {
+                    let data = memory.data(&caller);
+                    let start = offset.min(data.len() as u64) as usize;
+                    let end = offset.saturating_add(length.min(MAX_MEMORY_READ));
+                    let end = end.min(data.len() as u64) as usize;
+                    json!({ "success": true, "offset": offset, "data": to_hex(&data[start..end]) })
+                }
+                Request::Registers => {
+                    let registers: HashMap<String, String> = logic
+                        .sandbox_registers()
+                        .iter()
+                        .map(|(id, data)| (id.to_string(), to_hex(data)))
+                        .collect();
+                    json!({ "success": true, "registers": registers })
+                }
+                Request::SetBreakpoints { .. } | Request::Continue => unreachable!(),
+            });
+        })
+        .expect("cannot link debugger");
+}
+
+fn locals(function: &BreakpointFunction, params: &[(u32, i64)]) -> Vec<Value> {
+    let values: HashMap<u32, i64> = params.iter().copied().collect();
+    function
+        .params
+        .iter()
+        .chain(function.locals.iter())
+        .enumerate()
+        .map(|(index, value_type)| {
+            let bits = values.get(&(index as u32)).copied().unwrap_or(0);
+            let value = if *value_type == ValueType::F32 {
+                json!(f32::from_bits(bits as u32))
+            } else if *value_type == ValueType::F64 {
+                json!(f64::from_bits(bits as u64))
+            } else if *value_type == ValueType::I32 {
+                json!(bits as i32)
+            } else {
+                json!(bits)
+            };
+            json!({ "index": index, "type": value_type.to_string(), "value": value })
+        })
+        .collect()
+}
+
+/// Blocks the calling thread until the client resumes it, answering inspection requests.
+fn pause(
+    function: &BreakpointFunction,
+    params: &[(u32, i64)],
+    mut handle: impl FnMut(Request) -> Value,
+) {
+    let _guard = PAUSE_LOCK.lock().unwrap_or_else(|err| err.into_inner());
+    let (paused_tx, paused_rx) = mpsc::channel();
+    {
+        let mut debugger = debugger().lock().unwrap();
+        let client = match debugger.client.as_mut() {
+            Some(client) => client,
+            None => return,
+        };
+        let event = json!({
+            "event": "stopped",
+            "function": function.name,
+            "function_index": function.index,
+            "num_params": params.len(),
+        });
+        if write_line(client, &event).is_err() {
+            return;
+        }
+        debugger.paused = Some(paused_tx);
+    }
+    tracing::debug!(target: "sandbox", function = %function.name, "paused at breakpoint");
+    while let Ok((request, reply)) = paused_rx.recv() {
+        if let Request::Continue = request {
+            debugger().lock().unwrap().paused = None;
+            let _ = reply.send(json!({ "success": true }));
+            break;
+        }
+        let _ = reply.send(handle(request));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_instrument_breakpoints() {
+        let original = wat::parse_str(
+            r#"
+            (module
+              (import "env" "input" (func $input (param i64)))
+              (func $add (param i32 f64) (result i32)
+                (local i64)
+                (local.get 0))
+              (func (export "main")
+                (drop (call $add (i32.const 1) (f64.const 2)))
+                (call $input (i64.const 0))))
+            "#,
+        )
+        .unwrap();
+        let config = near_vm_logic::VMConfig::test();
+        let prepared = crate::prepare::prepare_contract(&original, &config).unwrap();
+        let breakpoints = ["add".to_string(), "main".to_string()].into_iter().collect();
+        let (code, functions) =
+            instrument_breakpoints(&original, &prepared, &breakpoints).unwrap().unwrap();
+
+        assert_eq!(functions.len(), 2);
+        assert_eq!(functions[0].name, "add");
+        assert_eq!(functions[0].params, vec![ValueType::I32, ValueType::F64]);
+        assert_eq!(functions[0].locals, vec![ValueType::I64]);
+        assert_eq!(functions[1].name, "main");
+        wasmparser::Validator::new()
+            .wasm_features(crate::prepare::WASM_FEATURES)
+            .validate_all(&code)
+            .unwrap();
+
+        let module = elements::deserialize_buffer::<elements::Module>(&code).unwrap();
+        let imports = module.import_section().unwrap().entries();
+        assert!(imports.iter().any(|import| import.module() == DEBUG_MODULE));
+    }
+
+    fn read_json(lines: &mut io::Lines<BufReader<TcpStream>>) -> Value {
+        serde_json::from_str(&lines.next().unwrap().unwrap()).unwrap()
+    }
+
+    fn request(
+        client: &mut TcpStream,
+        lines: &mut io::Lines<BufReader<TcpStream>>,
+        request: Value,
+    ) -> Value {
+        write_line(client, &request).unwrap();
+        read_json(lines)
+    }
+
+    /// Only this test may start the debugger, as it stays enabled for the rest of the process.
+    #[test]
+    fn test_pause_at_breakpoint() {
+        use crate::vm_kind::VMKind;
+        use near_primitives::contract::ContractCode;
+        use near_primitives::runtime::fees::RuntimeFeesConfig;
+        use near_primitives::version::PROTOCOL_VERSION;
+        use near_vm_logic::mocks::mock_external::MockedExternal;
+        use std::time::Duration;
+
+        let addr = start("127.0.0.1:0".parse().unwrap()).unwrap();
+        let mut client = TcpStream::connect(addr).unwrap();
+        client.set_read_timeout(Some(Duration::from_secs(30))).unwrap();
+        let mut lines = BufReader::new(client.try_clone().unwrap()).lines();
+        let breakpoints = json!({ "command": "set_breakpoints", "functions": ["target"] });
+        assert_eq!(request(&mut client, &mut lines, breakpoints)["success"], true);
+
+        let code = wat::parse_str(
+            r#"
+            (module
+              (import "env" "input" (func $input (param i64)))
+              (func $target (param i32)
+                (local i64))
+              (func (export "main")
+                (call $input (i64.const 0))
+                (call $target (i32.const 7))))
+            "#,
+        )
+        .unwrap();
+        let execution = std::thread::spawn(move || {
+            let code = ContractCode::new(code, None);
+            let context = crate::tests::create_context(b"hi".to_vec());
+            let runtime = VMKind::Wasmtime.runtime(near_vm_logic::VMConfig::test()).unwrap();
+            runtime.run(
+                &code,
+                "main",
+                &mut MockedExternal::new(),
+                context,
+                &RuntimeFeesConfig::test(),
+                &[],
+                PROTOCOL_VERSION,
+                None,
+            )
+        });
+
+        let stopped = read_json(&mut lines);
+        assert_eq!(stopped["event"], "stopped");
+        assert_eq!(stopped["function"], "target");
+        let locals = request(&mut client, &mut lines, json!({ "command": "locals" }));
+        assert_eq!(locals["locals"][0]["value"], 7);
+        assert_eq!(locals["locals"][1]["value"], 0);
+        let registers = request(&mut client, &mut lines, json!({ "command": "registers" }));
+        assert_eq!(registers["registers"]["0"], to_hex(b"hi"));
+        let resumed = request(&mut client, &mut lines, json!({ "command": "continue" }));
+        assert_eq!(resumed["success"], true);
+
+        let (outcome, err) = execution.join().unwrap();
+        assert!(err.is_none(), "{:?}", err);
+        assert!(outcome.is_some());
+        let resumed = request(&mut client, &mut lines, json!({ "command": "continue" }));
+        assert_eq!(resumed["error"], "not stopped");
+    }
+
+    #[test]
+    fn test_no_matching_breakpoints() {
+        let original = wat::parse_str(r#"(module (func (export "main")))"#).unwrap();
+        let config = near_vm_logic::VMConfig::test();
+        let prepared = crate::prepare::prepare_contract(&original, &config).unwrap();
+        let breakpoints = ["other".to_string()].into_iter().collect();
+        assert!(instrument_breakpoints(&original, &prepared, &breakpoints).unwrap().is_none());
+    }
+}
@@ -34,7 +34,7 @@ fn with_vm_variants(runner: fn(VMKind) -> ()) {
     runner(VMKind::Wasmer2);
 }
 
-fn create_context(input: Vec<u8>) -> VMContext {
+pub(crate) fn create_context(input: Vec<u8>) -> VMContext {
     VMContext {
         current_account_id: CURRENT_ACCOUNT_ID.parse().unwrap(),
         signer_account_id: SIGNER_ACCOUNT_ID.parse().unwrap(),
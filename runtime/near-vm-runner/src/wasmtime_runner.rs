@@ -208,6 +208,9 @@ impl crate::runner::VM for WasmtimeVM {
             Ok(code) => code,
             Err(err) => return (None, Some(VMError::from(err))),
         };
+        #[cfg(feature = "sandbox")]
+        let (prepared_code, debug_functions) =
+            crate::sandbox_debugger::instrument(code.code(), prepared_code);
         let module = match Module::new(&engine, prepared_code) {
             Ok(module) => module,
             Err(err) => return (None, Some(err.into_vm_error())),
@@ -238,6 +241,8 @@ impl crate::runner::VM for WasmtimeVM {
         // lifetimes of the logic instance and pass raw pointers here.
         let raw_logic = &mut logic as *mut _ as *mut c_void;
         imports::wasmtime::link(&mut linker, memory_copy, raw_logic, current_protocol_version);
+        #[cfg(feature = "sandbox")]
+        crate::sandbox_debugger::link(&mut linker, memory_copy, raw_logic, debug_functions);
         if method_name.is_empty() {
             return (
                 None,
@@ -132,7 +132,8 @@ fn main() -> anyhow::Result<()> {
         )
         .expect("failed to init config");
 
-        let near_config = load_config(&state_dump_path, GenesisValidationMode::Full);
+        let near_config = load_config(&state_dump_path, GenesisValidationMode::Full)
+            .unwrap_or_else(|e| panic!("Error loading config: {:#}", e));
         let store = create_store(&get_store_path(&state_dump_path));
         GenesisBuilder::from_config_and_store(
             &state_dump_path,
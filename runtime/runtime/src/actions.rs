@@ -21,8 +21,8 @@ use near_primitives::version::{
     DELETE_KEY_STORAGE_USAGE_PROTOCOL_VERSION,
 };
 use near_store::{
-    get_access_key, get_code, remove_access_key, remove_account, set_access_key, set_code,
-    StorageError, TrieUpdate,
+    get_access_key, get_code, has_code, remove_access_key, remove_account, remove_code,
+    set_access_key, set_code, set_shared_code, StorageError, TrieUpdate,
 };
 use near_vm_errors::{
     AnyError, CacheError, CompilationError, FunctionCallError, InconsistentStateError, VMError,
@@ -439,7 +439,8 @@ pub(crate) fn action_deploy_contract(
     let _span = tracing::debug_span!(target: "runtime", "action_deploy_contract").entered();
     let code = ContractCode::new(deploy_contract.code.clone(), None);
     let prev_code = get_code(state_update, account_id, Some(account.code_hash()))?;
-    let prev_code_length = prev_code.map(|code| code.code().len() as u64).unwrap_or_default();
+    let prev_code_length =
+        prev_code.as_ref().map(|code| code.code().len() as u64).unwrap_or_default();
     account.set_storage_usage(account.storage_usage().saturating_sub(prev_code_length));
     account.set_storage_usage(
         account.storage_usage().checked_add(code.code().len() as u64).ok_or_else(|| {
@@ -449,8 +450,19 @@ pub(crate) fn action_deploy_contract(
             ))
         })?,
     );
+    if checked_feature!(
+        "protocol_feature_contract_code_deduplication",
+        ContractCodeDeduplication,
+        current_protocol_version
+    ) {
+        if prev_code.is_some() {
+            remove_code(state_update, account_id, &account.code_hash())?;
+        }
+        set_shared_code(state_update, &code)?;
+    } else {
+        set_code(state_update, account_id.clone(), &code);
+    }
     account.set_code_hash(*code.hash());
-    set_code(state_update, account_id.clone(), &code);
     // Precompile the contract and store result (compiled code or error) in the database.
     // Note, that contract compilation costs are already accounted in deploy cost using
     // special logic in estimator (see get_runtime_config() function).
@@ -499,6 +511,16 @@ pub(crate) fn action_delete_account(
             .new_receipts
             .push(Receipt::new_balance_refund(&delete_account.beneficiary_id, account_balance));
     }
+    if checked_feature!(
+        "protocol_feature_contract_code_deduplication",
+        ContractCodeDeduplication,
+        current_protocol_version
+    ) {
+        let code_hash = account.as_ref().unwrap().code_hash();
+        if has_code(state_update, account_id, &code_hash)? {
+            remove_code(state_update, account_id, &code_hash)?;
+        }
+    }
     remove_account(state_update, account_id)?;
     *actor_id = receipt.predecessor_id.clone();
     *account = None;
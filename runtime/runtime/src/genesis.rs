@@ -4,6 +4,7 @@ use borsh::BorshSerialize;
 
 use near_chain_configs::Genesis;
 use near_crypto::PublicKey;
+use near_primitives::checked_feature;
 use near_primitives::runtime::fees::StorageUsageConfig;
 use near_primitives::shard_layout::ShardUId;
 use near_primitives::{
@@ -16,7 +17,7 @@ use near_primitives::{
 };
 use near_store::{
     get_account, get_received_data, set, set_access_key, set_account, set_code,
-    set_postponed_receipt, set_received_data, ShardTries, TrieUpdate,
+    set_postponed_receipt, set_received_data, set_shared_code, ShardTries, TrieUpdate,
 };
 
 use crate::config::RuntimeConfig;
@@ -133,7 +134,15 @@ impl GenesisStateApplier {
                     let acc = get_account(&state_update, &account_id).expect("Failed to read state").expect("Code state record should be preceded by the corresponding account record");
                     // Recompute contract code hash.
                     let code = ContractCode::new(code, None);
-                    set_code(&mut state_update, account_id, &code);
+                    if checked_feature!(
+                        "protocol_feature_contract_code_deduplication",
+                        ContractCodeDeduplication,
+                        genesis.config.protocol_version
+                    ) {
+                        set_shared_code(&mut state_update, &code).expect("Failed to write state");
+                    } else {
+                        set_code(&mut state_update, account_id, &code);
+                    }
                     assert_eq!(*code.hash(), acc.code_hash());
                 }
                 StateRecord::AccessKey { account_id, public_key, access_key } => {
@@ -61,6 +61,8 @@ use crate::config::{
 };
 use crate::genesis::{GenesisStateApplier, StorageComputer};
 use crate::verifier::validate_receipt;
+#[cfg(feature = "sandbox")]
+pub use crate::verifier::verify_and_charge_impersonated_transaction;
 pub use crate::verifier::{validate_transaction, verify_and_charge_transaction};
 
 mod actions;
@@ -185,11 +187,71 @@ impl Default for ActionResult {
     }
 }
 
-pub struct Runtime {}
+pub struct Runtime {
+    /// Hashes of the transactions submitted through the sandbox impersonation RPC which are not
+    /// included in a chunk yet.
+    #[cfg(feature = "sandbox")]
+    sandbox_impersonated_transactions: std::sync::Mutex<HashSet<CryptoHash>>,
+}
 
 impl Runtime {
     pub fn new() -> Self {
-        Self {}
+        Self {
+            #[cfg(feature = "sandbox")]
+            sandbox_impersonated_transactions: Default::default(),
+        }
+    }
+
+    /// Lets the transaction with the given hash act on behalf of its signer without a signature,
+    /// an access key or a nonce, until it is included in a chunk.
+    #[cfg(feature = "sandbox")]
+    pub fn sandbox_impersonate_transaction(&self, tx_hash: CryptoHash) {
+        self.sandbox_impersonated_transactions.lock().unwrap().insert(tx_hash);
+    }
+
+    /// Returns whether the transaction was registered by `sandbox_impersonate_transaction` and
+    /// not included in a chunk yet.
+    #[cfg(feature = "sandbox")]
+    pub fn is_sandbox_impersonated(&self, signed_transaction: &SignedTransaction) -> bool {
+        self.sandbox_impersonated_transactions
+            .lock()
+            .unwrap()
+            .contains(&signed_transaction.get_hash())
+    }
+
+    #[cfg(not(feature = "sandbox"))]
+    pub fn is_sandbox_impersonated(&self, _signed_transaction: &SignedTransaction) -> bool {
+        false
+    }
+
+    /// Verifies and charges the transaction, skipping the checks that an impersonated
+    /// transaction doesn't pass.
+    fn verify_and_charge(
+        &self,
+        state_update: &mut TrieUpdate,
+        apply_state: &ApplyState,
+        signed_transaction: &SignedTransaction,
+    ) -> Result<VerificationResult, RuntimeError> {
+        #[cfg(feature = "sandbox")]
+        if self.is_sandbox_impersonated(signed_transaction) {
+            return verify_and_charge_impersonated_transaction(
+                &apply_state.config,
+                state_update,
+                apply_state.gas_price,
+                signed_transaction,
+                Some(apply_state.block_index),
+                apply_state.current_protocol_version,
+            );
+        }
+        verify_and_charge_transaction(
+            &apply_state.config,
+            state_update,
+            apply_state.gas_price,
+            signed_transaction,
+            true,
+            Some(apply_state.block_index),
+            apply_state.current_protocol_version,
+        )
     }
 
     fn print_log(log: &[LogEntry]) {
@@ -226,15 +288,7 @@ impl Runtime {
             tracing::debug_span!(target: "runtime", "Runtime::process_transaction").entered();
         metrics::TRANSACTION_PROCESSED_TOTAL.inc();
 
-        match verify_and_charge_transaction(
-            &apply_state.config,
-            state_update,
-            apply_state.gas_price,
-            signed_transaction,
-            true,
-            Some(apply_state.block_index),
-            apply_state.current_protocol_version,
-        ) {
+        match self.verify_and_charge(state_update, apply_state, signed_transaction) {
             Ok(verification_result) => {
                 metrics::TRANSACTION_PROCESSED_SUCCESSFULLY_TOTAL.inc();
                 state_update.commit(StateChangeCause::TransactionProcessing {
@@ -1138,6 +1192,20 @@ impl Runtime {
             state_update.commit(StateChangeCause::Migration);
         }
 
+        // Move contract codes stored under accounts into the shared contract code of the shard.
+        // The migration is not charged, similarly to the restored receipts below.
+        #[cfg(feature = "protocol_feature_contract_code_deduplication")]
+        if ProtocolFeature::ContractCodeDeduplication.protocol_version() == protocol_version
+            && migration_flags.is_first_block_with_chunk_of_version
+        {
+            let num_migrated = near_store::migrate_codes_to_shared_code(state_update)?;
+            tracing::debug!(
+                target: "runtime",
+                "Moved contract codes of {} accounts to shared contract code",
+                num_migrated
+            );
+        }
+
         // Re-introduce receipts lost because of a bug in apply_chunks.
         // We take the first block with existing chunk in the first epoch in which protocol feature
         // RestoreReceiptsAfterFixApplyChunks was enabled, and put the restored receipts there.
@@ -1249,6 +1317,12 @@ impl Runtime {
                 signed_transaction,
                 &mut stats,
             )?;
+            // The transaction is included, so the same transaction can't be impersonated again.
+            #[cfg(feature = "sandbox")]
+            self.sandbox_impersonated_transactions
+                .lock()
+                .unwrap()
+                .remove(&signed_transaction.get_hash());
             if receipt.receiver_id == signed_transaction.transaction.signer_id {
                 local_receipts.push(receipt);
             } else {
@@ -1,7 +1,9 @@
 use near_crypto::key_conversion::is_valid_staking_key;
+#[cfg(feature = "sandbox")]
+use near_crypto::{KeyType, PublicKey};
 use near_primitives::runtime::get_insufficient_storage_stake;
 use near_primitives::{
-    account::AccessKeyPermission,
+    account::{AccessKey, AccessKeyPermission},
     config::VMLimitConfig,
     errors::{
         ActionsValidationError, InvalidAccessKeyError, InvalidTxError, ReceiptValidationError,
@@ -78,6 +80,56 @@ pub fn verify_and_charge_transaction(
     gas_price: Balance,
     signed_transaction: &SignedTransaction,
     verify_signature: bool,
+    block_height: Option<BlockHeight>,
+    current_protocol_version: ProtocolVersion,
+) -> Result<VerificationResult, RuntimeError> {
+    verify_and_charge_transaction_impl(
+        config,
+        state_update,
+        gas_price,
+        signed_transaction,
+        verify_signature,
+        false,
+        block_height,
+        current_protocol_version,
+    )
+}
+
+/// Same as `verify_and_charge_transaction`, but the transaction acts on behalf of its signer
+/// without a signature, an access key or a nonce.
+///
+/// Only used for the transactions submitted through the sandbox impersonation RPC, which use the
+/// empty ED25519 public key. Transactions with any other key are verified as usual.
+#[cfg(feature = "sandbox")]
+pub fn verify_and_charge_impersonated_transaction(
+    config: &RuntimeConfig,
+    state_update: &mut TrieUpdate,
+    gas_price: Balance,
+    signed_transaction: &SignedTransaction,
+    block_height: Option<BlockHeight>,
+    current_protocol_version: ProtocolVersion,
+) -> Result<VerificationResult, RuntimeError> {
+    let impersonated =
+        signed_transaction.transaction.public_key == PublicKey::empty(KeyType::ED25519);
+    verify_and_charge_transaction_impl(
+        config,
+        state_update,
+        gas_price,
+        signed_transaction,
+        !impersonated,
+        impersonated,
+        block_height,
+        current_protocol_version,
+    )
+}
+
+fn verify_and_charge_transaction_impl(
+    config: &RuntimeConfig,
+    state_update: &mut TrieUpdate,
+    gas_price: Balance,
+    signed_transaction: &SignedTransaction,
+    verify_signature: bool,
+    impersonated: bool,
     #[allow(unused)] block_height: Option<BlockHeight>,
     current_protocol_version: ProtocolVersion,
 ) -> Result<VerificationResult, RuntimeError> {
@@ -98,39 +150,45 @@ pub fn verify_and_charge_transaction(
             return Err(InvalidTxError::SignerDoesNotExist { signer_id: signer_id.clone() }.into());
         }
     };
-    let mut access_key = match get_access_key(state_update, signer_id, &transaction.public_key)? {
-        Some(access_key) => access_key,
-        None => {
-            return Err(InvalidTxError::InvalidAccessKeyError(
-                InvalidAccessKeyError::AccessKeyNotFound {
-                    account_id: signer_id.clone(),
-                    public_key: transaction.public_key.clone(),
-                },
-            )
-            .into());
+    let mut access_key = if impersonated {
+        AccessKey::full_access()
+    } else {
+        match get_access_key(state_update, signer_id, &transaction.public_key)? {
+            Some(access_key) => access_key,
+            None => {
+                return Err(InvalidTxError::InvalidAccessKeyError(
+                    InvalidAccessKeyError::AccessKeyNotFound {
+                        account_id: signer_id.clone(),
+                        public_key: transaction.public_key.clone(),
+                    },
+                )
+                .into());
+            }
         }
     };
 
-    if transaction.nonce <= access_key.nonce {
-        return Err(InvalidTxError::InvalidNonce {
-            tx_nonce: transaction.nonce,
-            ak_nonce: access_key.nonce,
+    if !impersonated {
+        if transaction.nonce <= access_key.nonce {
+            return Err(InvalidTxError::InvalidNonce {
+                tx_nonce: transaction.nonce,
+                ak_nonce: access_key.nonce,
+            }
+            .into());
         }
-        .into());
-    }
-    if checked_feature!("stable", AccessKeyNonceRange, current_protocol_version) {
-        if let Some(height) = block_height {
-            let upper_bound =
-                height * near_primitives::account::AccessKey::ACCESS_KEY_NONCE_RANGE_MULTIPLIER;
-            if transaction.nonce >= upper_bound {
-                return Err(InvalidTxError::NonceTooLarge {
-                    tx_nonce: transaction.nonce,
-                    upper_bound,
+        if checked_feature!("stable", AccessKeyNonceRange, current_protocol_version) {
+            if let Some(height) = block_height {
+                let upper_bound =
+                    height * near_primitives::account::AccessKey::ACCESS_KEY_NONCE_RANGE_MULTIPLIER;
+                if transaction.nonce >= upper_bound {
+                    return Err(InvalidTxError::NonceTooLarge {
+                        tx_nonce: transaction.nonce,
+                        upper_bound,
+                    }
+                    .into());
                 }
-                .into());
             }
-        }
-    };
+        };
+    }
 
     access_key.nonce = transaction.nonce;
 
@@ -215,7 +273,14 @@ pub fn verify_and_charge_transaction(
         }
     };
 
-    set_access_key(state_update, signer_id.clone(), transaction.public_key.clone(), &access_key);
+    if !impersonated {
+        set_access_key(
+            state_update,
+            signer_id.clone(),
+            transaction.public_key.clone(),
+            &access_key,
+        );
+    }
     set_account(state_update, signer_id.clone(), &signer);
 
     Ok(VerificationResult { gas_burnt, gas_remaining, receipt_gas_price, burnt_amount })
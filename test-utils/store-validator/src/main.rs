@@ -28,7 +28,8 @@ fn main() {
         .get_matches();
 
     let home_dir = matches.value_of("home").map(Path::new).unwrap();
-    let near_config = load_config(home_dir, GenesisValidationMode::Full);
+    let near_config = load_config(home_dir, GenesisValidationMode::Full)
+        .unwrap_or_else(|e| panic!("Error loading config: {:#}", e));
 
     let store = create_store(&get_store_path(home_dir));
 
@@ -0,0 +1,311 @@
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+use tracing::warn;
+
+use near_crypto::{InMemorySigner, PublicKey, SecretKey, Signature};
+use near_primitives::block::ApprovalInner;
+use near_primitives::challenge::ChallengeBody;
+use near_primitives::hash::CryptoHash;
+use near_primitives::network::PeerId;
+use near_primitives::sharding::ChunkHash;
+use near_primitives::telemetry::TelemetryInfo;
+use near_primitives::types::{AccountId, BlockHeight, EpochId};
+use near_primitives::validator_signer::{SigningError, ValidatorSigner};
+
+use crate::protocol::{
+    connect, noise_handshake, read_handshake_message, write_handshake_message, x25519_public_key,
+    HandshakeResult, SecureStream, SignerAddr, SignerRequest, SignerResponse,
+};
+use crate::RemoteSignerError;
+
+const RECONNECT_MIN_DELAY: Duration = Duration::from_millis(100);
+const RECONNECT_MAX_DELAY: Duration = Duration::from_secs(5);
+
+fn default_timeout() -> Duration {
+    Duration::from_millis(500)
+}
+
+/// Node side configuration of a remote signer.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct RemoteSignerConfig {
+    /// Address of the signer, either `unix:<path>` or `tcp:<host>:<port>`.
+    pub addr: String,
+    /// Key file with the key the node authenticates with, relative to the home dir.
+    pub auth_key_file: String,
+    /// Key the signer has to authenticate with.
+    pub signer_public_key: PublicKey,
+    /// Limit on connecting to the signer and on each read and write.
+    #[serde(default = "default_timeout")]
+    pub timeout: Duration,
+}
+
+/// Validator signer which forwards everything to a separate signing process, so that the
+/// validator key never has to be on the node.
+///
+/// Each request which fails returns a `SigningError`, so that whatever was being signed isn't
+/// released. Requests are sent on the caller's thread, so each read and write is limited by
+/// `timeout`, which has to be well below the block time. After a failure the connection is
+/// reopened on a background thread, and until then requests fail right away, so that a signer
+/// which is down doesn't stall the caller.
+pub struct RemoteValidatorSigner {
+    account_id: AccountId,
+    public_key: PublicKey,
+    pub(crate) connection: Arc<Connection>,
+}
+
+/// Connection to the signer, shared with the thread reopening it.
+pub(crate) struct Connection {
+    addr: SignerAddr,
+    auth_key: SecretKey,
+    signer_public_key: PublicKey,
+    timeout: Duration,
+    pub(crate) stream: Mutex<Option<SecureStream>>,
+    reconnecting: AtomicBool,
+}
+
+impl RemoteValidatorSigner {
+    /// Connects to the signer and fetches the validator account and key from it.
+    pub fn connect(
+        addr: SignerAddr,
+        auth_key: SecretKey,
+        signer_public_key: PublicKey,
+        timeout: Duration,
+    ) -> Result<Self, RemoteSignerError> {
+        let mut stream = open_connection(&addr, &auth_key, &signer_public_key, timeout)?;
+        stream.send(&SignerRequest::ValidatorInfo)?;
+        let (account_id, public_key) = match stream.recv()? {
+            SignerResponse::ValidatorInfo { account_id, public_key } => (account_id, public_key),
+            SignerResponse::Error(err) => return Err(RemoteSignerError::Refused(err)),
+            _ => return Err(RemoteSignerError::UnexpectedResponse),
+        };
+        Ok(Self {
+            account_id,
+            public_key,
+            connection: Arc::new(Connection {
+                addr,
+                auth_key,
+                signer_public_key,
+                timeout,
+                stream: Mutex::new(Some(stream)),
+                reconnecting: AtomicBool::new(false),
+            }),
+        })
+    }
+
+    pub fn from_config(
+        config: &RemoteSignerConfig,
+        home_dir: &Path,
+    ) -> Result<Self, RemoteSignerError> {
+        let addr = config.addr.parse().map_err(RemoteSignerError::InvalidAddr)?;
+        let auth_signer = InMemorySigner::try_from_file(&home_dir.join(&config.auth_key_file))?;
+        Self::connect(
+            addr,
+            auth_signer.secret_key,
+            config.signer_public_key.clone(),
+            config.timeout,
+        )
+    }
+
+    fn request(&self, request: &SignerRequest) -> Result<SignerResponse, RemoteSignerError> {
+        let mut connection = self.connection.stream.lock().unwrap();
+        let mut stream = match connection.take() {
+            Some(stream) => stream,
+            None => {
+                drop(connection);
+                Connection::reconnect(&self.connection);
+                return Err(RemoteSignerError::NotConnected);
+            }
+        };
+        match stream.send(request).and_then(|()| stream.recv()) {
+            Ok(SignerResponse::Error(err)) => {
+                *connection = Some(stream);
+                Err(RemoteSignerError::Refused(err))
+            }
+            Ok(response) => {
+                *connection = Some(stream);
+                Ok(response)
+            }
+            Err(err) => {
+                // The signer may have been restarted, so the connection is reopened.
+                drop(connection);
+                Connection::reconnect(&self.connection);
+                Err(err.into())
+            }
+        }
+    }
+
+    /// Sends the request and extracts the result from the response.
+    fn sign<T>(
+        &self,
+        request: SignerRequest,
+        extract: impl FnOnce(SignerResponse) -> Option<T>,
+    ) -> Result<T, SigningError> {
+        self.request(&request)
+            .and_then(|response| extract(response).ok_or(RemoteSignerError::UnexpectedResponse))
+            .map_err(|err| {
+                warn!(target: "remote_signer", "Remote signer {} failed to serve {:?}: {}", self.connection.addr, request, err);
+                SigningError(err.to_string())
+            })
+    }
+
+    fn sign_with_signature(&self, request: SignerRequest) -> Result<Signature, SigningError> {
+        self.sign(request, |response| match response {
+            SignerResponse::Signature(signature) => Some(signature),
+            _ => None,
+        })
+    }
+
+    fn sign_with_hash(
+        &self,
+        request: SignerRequest,
+    ) -> Result<(CryptoHash, Signature), SigningError> {
+        self.sign(request, |response| match response {
+            SignerResponse::HashAndSignature(hash, signature) => Some((hash, signature)),
+            _ => None,
+        })
+    }
+}
+
+impl Connection {
+    /// Reopens the connection on a background thread, unless that is already happening.
+    fn reconnect(this: &Arc<Self>) {
+        if this.reconnecting.swap(true, Ordering::SeqCst) {
+            return;
+        }
+        let this = this.clone();
+        std::thread::spawn(move || {
+            let mut delay = RECONNECT_MIN_DELAY;
+            loop {
+                match open_connection(
+                    &this.addr,
+                    &this.auth_key,
+                    &this.signer_public_key,
+                    this.timeout,
+                ) {
+                    Ok(stream) => {
+                        *this.stream.lock().unwrap() = Some(stream);
+                        break;
+                    }
+                    Err(err) => {
+                        warn!(target: "remote_signer", "Failed to reconnect to remote signer {}: {}", this.addr, err);
+                        std::thread::sleep(delay);
+                        delay = std::cmp::min(delay * 2, RECONNECT_MAX_DELAY);
+                    }
+                }
+            }
+            this.reconnecting.store(false, Ordering::SeqCst);
+        });
+    }
+}
+
+/// Connects to the signer and authenticates both sides.
+fn open_connection(
+    addr: &SignerAddr,
+    auth_key: &SecretKey,
+    signer_public_key: &PublicKey,
+    timeout: Duration,
+) -> Result<SecureStream, RemoteSignerError> {
+    let expected_rs = x25519_public_key(signer_public_key)
+        .ok_or(RemoteSignerError::InvalidAuthKey("only ED25519 signer keys are supported"))?;
+    let mut stream = connect(addr, timeout)?;
+    let mut state = noise_handshake(true, auth_key)?;
+    write_handshake_message(&mut *stream, &mut state)?;
+    read_handshake_message(&mut *stream, &mut state)?;
+    // The signer's static key is known from its message, and is checked before authenticating.
+    if state.get_remote_static() != Some(&expected_rs[..]) {
+        return Err(RemoteSignerError::UnexpectedSigner { expected: signer_public_key.clone() });
+    }
+    write_handshake_message(&mut *stream, &mut state)?;
+    let mut stream = SecureStream::new(stream, state)?;
+    match stream.recv()? {
+        HandshakeResult::Accepted => Ok(stream),
+        HandshakeResult::Rejected(err) => Err(RemoteSignerError::Refused(err)),
+    }
+}
+
+impl ValidatorSigner for RemoteValidatorSigner {
+    fn validator_id(&self) -> &AccountId {
+        &self.account_id
+    }
+
+    fn public_key(&self) -> PublicKey {
+        self.public_key.clone()
+    }
+
+    fn sign_telemetry(&self, info: &TelemetryInfo) -> Result<serde_json::Value, SigningError> {
+        let info = serde_json::to_string(info).expect("Telemetry must serialize to JSON");
+        self.sign(SignerRequest::SignTelemetry(info), |response| match response {
+            SignerResponse::Telemetry(value) => serde_json::from_str(&value).ok(),
+            _ => None,
+        })
+    }
+
+    fn sign_block_header_parts(
+        &self,
+        prev_hash: CryptoHash,
+        inner_lite: &[u8],
+        inner_rest: &[u8],
+    ) -> Result<(CryptoHash, Signature), SigningError> {
+        self.sign_with_hash(SignerRequest::SignBlockHeaderParts {
+            prev_hash,
+            inner_lite: inner_lite.to_vec(),
+            inner_rest: inner_rest.to_vec(),
+        })
+    }
+
+    fn sign_chunk_hash(&self, chunk_hash: &ChunkHash) -> Result<Signature, SigningError> {
+        self.sign_with_signature(SignerRequest::SignChunkHash(chunk_hash.clone()))
+    }
+
+    fn sign_approval(
+        &self,
+        inner: &ApprovalInner,
+        target_height: BlockHeight,
+    ) -> Result<Signature, SigningError> {
+        self.sign_with_signature(SignerRequest::SignApproval {
+            inner: inner.clone(),
+            target_height,
+        })
+    }
+
+    fn sign_challenge(
+        &self,
+        challenge_body: &ChallengeBody,
+    ) -> Result<(CryptoHash, Signature), SigningError> {
+        self.sign_with_hash(SignerRequest::SignChallenge(challenge_body.clone()))
+    }
+
+    fn sign_account_announce(
+        &self,
+        account_id: &AccountId,
+        peer_id: &PeerId,
+        epoch_id: &EpochId,
+    ) -> Result<Signature, SigningError> {
+        self.sign_with_signature(SignerRequest::SignAccountAnnounce {
+            account_id: account_id.clone(),
+            peer_id: peer_id.clone(),
+            epoch_id: epoch_id.clone(),
+        })
+    }
+
+    fn compute_vrf_with_proof(
+        &self,
+        data: &[u8],
+    ) -> Result<(near_crypto::vrf::Value, near_crypto::vrf::Proof), SigningError> {
+        self.sign(SignerRequest::ComputeVrfWithProof(data.to_vec()), |response| match response {
+            SignerResponse::Vrf(value, proof) => Some((value, proof)),
+            _ => None,
+        })
+    }
+
+    fn write_to_file(&self, _path: &Path) -> std::io::Result<()> {
+        Err(std::io::Error::new(
+            std::io::ErrorKind::Unsupported,
+            "the key of a remote signer can't be written to a file",
+        ))
+    }
+}
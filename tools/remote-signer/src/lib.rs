@@ -0,0 +1,220 @@
+//! Remote signer support: a `ValidatorSigner` which forwards sign requests to a separate
+//! signing process over a Unix socket or TCP, and the server side of that process. The
+//! connection is mutually authenticated and encrypted with Noise, see `protocol`.
+
+use std::io;
+
+use near_crypto::PublicKey;
+
+pub use crate::client::{RemoteSignerConfig, RemoteValidatorSigner};
+pub use crate::protocol::SignerAddr;
+pub use crate::server::{SignerListener, SignerServer};
+
+mod client;
+mod protocol;
+mod server;
+
+#[derive(thiserror::Error, Debug)]
+pub enum RemoteSignerError {
+    #[error("{0}")]
+    InvalidAddr(String),
+    #[error("Remote signer IO error: {0}")]
+    IOError(#[from] io::Error),
+    #[error("Remote signer authenticated with an unexpected key, expected {expected}")]
+    UnexpectedSigner { expected: PublicKey },
+    #[error("Invalid auth key: {0}")]
+    InvalidAuthKey(&'static str),
+    #[error("Remote signer handshake failed: {0}")]
+    Noise(#[from] snow::Error),
+    #[error("Remote signer refused the request: {0}")]
+    Refused(String),
+    #[error("Unexpected response from remote signer")]
+    UnexpectedResponse,
+    #[error("Not connected to remote signer, reconnecting")]
+    NotConnected,
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    use near_crypto::{InMemorySigner, KeyType, Signer};
+    use near_primitives::block::{Approval, ApprovalInner};
+    use near_primitives::challenge::{BlockDoubleSign, ChallengeBody};
+    use near_primitives::hash::hash;
+    use near_primitives::network::PeerId;
+    use near_primitives::sharding::ChunkHash;
+    use near_primitives::types::EpochId;
+    use near_primitives::validator_signer::{InMemoryValidatorSigner, ValidatorSigner};
+
+    use super::*;
+
+    const TIMEOUT: Duration = Duration::from_secs(5);
+
+    fn start_server(
+        addr: &SignerAddr,
+        validator_signer: Arc<dyn ValidatorSigner>,
+        server_auth: &InMemorySigner,
+        allowed_clients: Vec<PublicKey>,
+    ) -> SignerAddr {
+        let listener = SignerListener::bind(addr).unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server = Arc::new(SignerServer::new(
+            validator_signer,
+            server_auth.secret_key.clone(),
+            allowed_clients,
+            TIMEOUT,
+        ));
+        std::thread::spawn(move || server.serve(listener));
+        addr
+    }
+
+    fn auth_signer(seed: &str) -> InMemorySigner {
+        InMemorySigner::from_seed(seed.parse().unwrap(), KeyType::ED25519, seed)
+    }
+
+    #[test]
+    fn test_remote_signer_matches_in_memory() {
+        let validator_signer = Arc::new(InMemoryValidatorSigner::from_seed(
+            "test0".parse().unwrap(),
+            KeyType::ED25519,
+            "test0",
+        ));
+        let server_auth = auth_signer("server");
+        let client_auth = auth_signer("client");
+        let addr = start_server(
+            &"tcp:127.0.0.1:0".parse().unwrap(),
+            validator_signer.clone(),
+            &server_auth,
+            vec![client_auth.public_key()],
+        );
+        let remote = RemoteValidatorSigner::connect(
+            addr,
+            client_auth.secret_key.clone(),
+            server_auth.public_key(),
+            TIMEOUT,
+        )
+        .unwrap();
+
+        assert_eq!(remote.validator_id(), validator_signer.validator_id());
+        assert_eq!(ValidatorSigner::public_key(&remote), validator_signer.public_key());
+
+        let prev_hash = hash(b"prev");
+        assert_eq!(
+            remote.sign_block_header_parts(prev_hash, b"lite", b"rest"),
+            validator_signer.sign_block_header_parts(prev_hash, b"lite", b"rest")
+        );
+        // Frames larger than a Noise message are sealed in several chunks.
+        let large = vec![7u8; 200_000];
+        assert_eq!(
+            remote.sign_block_header_parts(prev_hash, b"lite", &large),
+            validator_signer.sign_block_header_parts(prev_hash, b"lite", &large)
+        );
+        let chunk_hash = ChunkHash(hash(b"chunk"));
+        assert_eq!(
+            remote.sign_chunk_hash(&chunk_hash),
+            validator_signer.sign_chunk_hash(&chunk_hash)
+        );
+        let inner = ApprovalInner::Endorsement(prev_hash);
+        let signature = remote.sign_approval(&inner, 10).unwrap();
+        assert_eq!(signature, validator_signer.sign_approval(&inner, 10).unwrap());
+        assert!(signature
+            .verify(&Approval::get_data_for_sig(&inner, 10), &validator_signer.public_key()));
+        let challenge_body = ChallengeBody::BlockDoubleSign(BlockDoubleSign {
+            left_block_header: vec![1],
+            right_block_header: vec![2],
+        });
+        assert_eq!(
+            remote.sign_challenge(&challenge_body),
+            validator_signer.sign_challenge(&challenge_body)
+        );
+        let peer_id = PeerId::new(client_auth.public_key());
+        let epoch_id = EpochId::default();
+        assert_eq!(
+            remote.sign_account_announce(validator_signer.validator_id(), &peer_id, &epoch_id),
+            validator_signer.sign_account_announce(
+                validator_signer.validator_id(),
+                &peer_id,
+                &epoch_id
+            )
+        );
+        let (value, proof) = remote.compute_vrf_with_proof(b"data").unwrap();
+        assert_eq!(value, validator_signer.compute_vrf_with_proof(b"data").unwrap().0);
+        let vrf_public_key = near_crypto::key_conversion::convert_public_key(
+            validator_signer.public_key().unwrap_as_ed25519(),
+        )
+        .unwrap();
+        assert!(vrf_public_key.is_vrf_valid(&b"data".as_ref(), &value, &proof));
+    }
+
+    #[test]
+    fn test_remote_signer_reconnects() {
+        let dir = tempfile::tempdir().unwrap();
+        let addr = SignerAddr::Unix(dir.path().join("signer.sock"));
+        let validator_signer = Arc::new(InMemoryValidatorSigner::from_seed(
+            "test0".parse().unwrap(),
+            KeyType::ED25519,
+            "test0",
+        ));
+        let server_auth = auth_signer("server");
+        let client_auth = auth_signer("client");
+        start_server(&addr, validator_signer.clone(), &server_auth, vec![client_auth.public_key()]);
+        let remote = RemoteValidatorSigner::connect(
+            addr,
+            client_auth.secret_key.clone(),
+            server_auth.public_key(),
+            TIMEOUT,
+        )
+        .unwrap();
+        // Drop the connection, as if the signer was restarted.
+        *remote.connection.stream.lock().unwrap() = None;
+        let chunk_hash = ChunkHash(hash(b"chunk"));
+        // The request fails right away, and the connection is reopened in the background.
+        assert!(remote.sign_chunk_hash(&chunk_hash).is_err());
+        while remote.connection.stream.lock().unwrap().is_none() {
+            std::thread::sleep(Duration::from_millis(10));
+        }
+        assert_eq!(
+            remote.sign_chunk_hash(&chunk_hash).unwrap(),
+            validator_signer.sign_chunk_hash(&chunk_hash).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_remote_signer_auth() {
+        let validator_signer = Arc::new(InMemoryValidatorSigner::from_seed(
+            "test0".parse().unwrap(),
+            KeyType::ED25519,
+            "test0",
+        ));
+        let server_auth = auth_signer("server");
+        let client_auth = auth_signer("client");
+        let addr = start_server(
+            &"tcp:127.0.0.1:0".parse().unwrap(),
+            validator_signer,
+            &server_auth,
+            vec![client_auth.public_key()],
+        );
+        // Unknown client.
+        assert!(matches!(
+            RemoteValidatorSigner::connect(
+                addr.clone(),
+                auth_signer("other").secret_key,
+                server_auth.public_key(),
+                TIMEOUT
+            ),
+            Err(RemoteSignerError::Refused(_))
+        ));
+        // Signer authenticates with an unexpected key.
+        assert!(matches!(
+            RemoteValidatorSigner::connect(
+                addr,
+                client_auth.secret_key,
+                auth_signer("other").public_key(),
+                TIMEOUT
+            ),
+            Err(RemoteSignerError::UnexpectedSigner { .. })
+        ));
+    }
+}
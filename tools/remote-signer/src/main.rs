@@ -0,0 +1,96 @@
+//! Reference signing process: keeps the validator key and signs for the nodes it serves.
+
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::Duration;
+
+use clap::{AppSettings, Clap};
+use tracing::info;
+
+use near_crypto::{InMemorySigner, KeyType, PublicKey, SecretKey, Signer};
+use near_logger_utils::init_integration_logger;
+use near_primitives::validator_signer::InMemoryValidatorSigner;
+use near_remote_signer::{SignerAddr, SignerListener, SignerServer};
+
+#[derive(Clap)]
+#[clap(setting = AppSettings::SubcommandRequiredElseHelp)]
+struct RemoteSignerCmd {
+    #[clap(subcommand)]
+    subcmd: RemoteSignerSubCommand,
+}
+
+#[derive(Clap)]
+enum RemoteSignerSubCommand {
+    /// Serve sign requests.
+    Run(RunCmd),
+    /// Generate a key used to authenticate the signer or a node.
+    GenerateAuthKey(GenerateAuthKeyCmd),
+}
+
+#[derive(Clap)]
+struct RunCmd {
+    /// Validator key file.
+    #[clap(long, parse(from_os_str))]
+    key_file: PathBuf,
+    /// Key file with the key the signer authenticates with.
+    #[clap(long, parse(from_os_str))]
+    auth_key_file: PathBuf,
+    /// Address to listen on, either `unix:<path>` or `tcp:<host>:<port>`.
+    #[clap(long)]
+    listen: String,
+    /// Auth public key of a node allowed to use the signer. Can be repeated.
+    #[clap(long = "allowed-client", parse(try_from_str = PublicKey::from_str))]
+    allowed_clients: Vec<PublicKey>,
+    /// Limit in milliseconds on the handshake and on sending each response.
+    #[clap(long, default_value = "5000")]
+    timeout_ms: u64,
+}
+
+impl RunCmd {
+    fn run(self) {
+        let signer = InMemoryValidatorSigner::from_file(&self.key_file);
+        let auth_signer = InMemorySigner::from_file(&self.auth_key_file);
+        let addr = SignerAddr::from_str(&self.listen).unwrap_or_else(|err| panic!("{}", err));
+        let listener = SignerListener::bind(&addr)
+            .unwrap_or_else(|err| panic!("Failed to listen on {}: {}", addr, err));
+        info!(target: "remote_signer", "Listening on {}, auth key {}", addr, auth_signer.public_key());
+        let server = Arc::new(SignerServer::new(
+            Arc::new(signer),
+            auth_signer.secret_key,
+            self.allowed_clients,
+            Duration::from_millis(self.timeout_ms),
+        ));
+        if let Err(err) = server.serve(listener) {
+            panic!("Failed to accept connection: {}", err);
+        }
+    }
+}
+
+#[derive(Clap)]
+struct GenerateAuthKeyCmd {
+    /// Name stored in the key file.
+    #[clap(long)]
+    account_id: String,
+    /// Where to write the key file.
+    #[clap(long, parse(from_os_str))]
+    output: PathBuf,
+}
+
+impl GenerateAuthKeyCmd {
+    fn run(self) {
+        let account_id = self.account_id.parse().unwrap_or_else(|err| panic!("{}", err));
+        let signer =
+            InMemorySigner::from_secret_key(account_id, SecretKey::from_random(KeyType::ED25519));
+        signer.write_to_file(&self.output).expect("Failed to write key file");
+        println!("{}", signer.public_key());
+    }
+}
+
+fn main() {
+    init_integration_logger();
+    match RemoteSignerCmd::parse().subcmd {
+        RemoteSignerSubCommand::Run(cmd) => cmd.run(),
+        RemoteSignerSubCommand::GenerateAuthKey(cmd) => cmd.run(),
+    }
+}
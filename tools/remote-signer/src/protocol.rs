@@ -0,0 +1,311 @@
+//! Wire protocol between a node and its remote signer.
+//!
+//! Every message is a frame prefixed with its length as a little-endian u32.
+//! A connection starts with a Noise XX handshake (`Noise_XX_25519_ChaChaPoly_SHA256`,
+//! implemented by `snow`, like the encryption of the connections between peers), in which the
+//! static keys are the auth keys of both sides converted to X25519. Its three messages are sent
+//! as frames:
+//!     node -> signer: e
+//!     signer -> node: e, ee, s, es
+//!     node -> signer: s, se
+//! The node checks the signer's static key before answering, and the signer checks the node's
+//! one against its allowed clients and answers with a `HandshakeResult`.
+//!
+//! After that the node sends `SignerRequest`s, each of which is answered with a `SignerResponse`.
+//! Every frame is sealed with ChaCha20-Poly1305 under a per-direction key and a counter nonce, so
+//! frames can't be read, altered, replayed, reordered or dropped unnoticed on a TCP connection.
+
+use std::fmt;
+use std::io::{self, Read, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+#[cfg(unix)]
+use std::os::unix::net::UnixStream;
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::time::Duration;
+
+use borsh::{BorshDeserialize, BorshSerialize};
+
+use near_crypto::key_conversion::{convert_public_key_to_x25519, convert_secret_key_to_x25519};
+use near_crypto::{PublicKey, SecretKey};
+use near_primitives::block::ApprovalInner;
+use near_primitives::challenge::ChallengeBody;
+use near_primitives::hash::CryptoHash;
+use near_primitives::network::PeerId;
+use near_primitives::sharding::ChunkHash;
+use near_primitives::types::{AccountId, BlockHeight, EpochId};
+
+use crate::RemoteSignerError;
+
+/// Frames larger than this are rejected, block headers are the largest messages to sign.
+const MAX_FRAME_SIZE: usize = 64 * 1024 * 1024;
+
+const NOISE_PARAMS: &str = "Noise_XX_25519_ChaChaPoly_SHA256";
+const NOISE_PROLOGUE: &[u8] = b"near-remote-signer";
+/// Noise messages are limited to 64 KiB, so larger frames are sealed in several chunks. This is
+/// also the limit on the frames of the handshake.
+const MAX_MESSAGE_LEN: usize = 65535;
+const TAG_LEN: usize = 16;
+const MAX_CHUNK_LEN: usize = MAX_MESSAGE_LEN - TAG_LEN;
+/// Length of the sealed length that starts every frame.
+const LEN_SEALED_LEN: usize = 8 + TAG_LEN;
+
+/// First message of the signer after the handshake.
+#[derive(BorshSerialize, BorshDeserialize, Debug)]
+pub enum HandshakeResult {
+    Accepted,
+    Rejected(String),
+}
+
+/// X25519 key corresponding to an auth key. Only ED25519 auth keys can be converted.
+pub fn x25519_public_key(key: &PublicKey) -> Option<[u8; 32]> {
+    match key {
+        PublicKey::ED25519(key) => convert_public_key_to_x25519(key),
+        PublicKey::SECP256K1(_) => None,
+    }
+}
+
+/// Side of the Noise handshake, with its auth key as the static key.
+pub fn noise_handshake(
+    initiator: bool,
+    auth_key: &SecretKey,
+) -> Result<snow::HandshakeState, RemoteSignerError> {
+    let s = match auth_key {
+        SecretKey::ED25519(key) => convert_secret_key_to_x25519(key),
+        SecretKey::SECP256K1(_) => {
+            return Err(RemoteSignerError::InvalidAuthKey("only ED25519 auth keys are supported"))
+        }
+    };
+    let builder = snow::Builder::new(NOISE_PARAMS.parse().expect("Valid Noise parameters"))
+        .local_private_key(&s)
+        .prologue(NOISE_PROLOGUE);
+    Ok(if initiator { builder.build_initiator() } else { builder.build_responder() }?)
+}
+
+/// Writes the next message of the handshake as a frame.
+pub fn write_handshake_message(
+    stream: &mut dyn Stream,
+    state: &mut snow::HandshakeState,
+) -> Result<(), RemoteSignerError> {
+    let mut message = vec![0u8; MAX_MESSAGE_LEN];
+    let len = state.write_message(&[], &mut message)?;
+    write_frame_bytes(stream, &message[..len])?;
+    Ok(())
+}
+
+/// Reads the next message of the handshake from a frame.
+pub fn read_handshake_message(
+    stream: &mut dyn Stream,
+    state: &mut snow::HandshakeState,
+) -> Result<(), RemoteSignerError> {
+    let message = read_frame_bytes(stream, MAX_MESSAGE_LEN)?;
+    let mut payload = vec![0u8; MAX_MESSAGE_LEN];
+    if state.read_message(&message, &mut payload)? != 0 {
+        return Err(
+            io::Error::new(io::ErrorKind::InvalidData, "unexpected handshake payload").into()
+        );
+    }
+    Ok(())
+}
+
+/// Connection after the handshake.
+pub struct SecureStream {
+    stream: Box<dyn Stream>,
+    transport: snow::TransportState,
+}
+
+impl SecureStream {
+    /// Finishes the handshake, failing if it isn't finished yet.
+    pub fn new(
+        stream: Box<dyn Stream>,
+        state: snow::HandshakeState,
+    ) -> Result<Self, RemoteSignerError> {
+        Ok(Self { stream, transport: state.into_transport_mode()? })
+    }
+
+    pub fn set_timeouts(&self, read: Option<Duration>, write: Option<Duration>) -> io::Result<()> {
+        self.stream.set_timeouts(read, write)
+    }
+
+    /// Seals a frame: its length, followed by the message in chunks of at most `MAX_CHUNK_LEN`
+    /// bytes. The sealed length lets the receiver detect truncated frames.
+    pub fn send<T: BorshSerialize>(&mut self, message: &T) -> io::Result<()> {
+        let message = message.try_to_vec()?;
+        let num_chunks = (message.len() + MAX_CHUNK_LEN - 1) / MAX_CHUNK_LEN;
+        let mut sealed = vec![0u8; LEN_SEALED_LEN + message.len() + num_chunks * TAG_LEN];
+        let mut offset = self
+            .transport
+            .write_message(&(message.len() as u64).to_le_bytes(), &mut sealed)
+            .map_err(noise_error)?;
+        for chunk in message.chunks(MAX_CHUNK_LEN) {
+            offset +=
+                self.transport.write_message(chunk, &mut sealed[offset..]).map_err(noise_error)?;
+        }
+        write_frame_bytes(&mut *self.stream, &sealed)
+    }
+
+    /// Opens a frame sealed by `send`.
+    pub fn recv<T: BorshDeserialize>(&mut self) -> io::Result<T> {
+        let sealed = read_frame_bytes(&mut *self.stream, MAX_FRAME_SIZE)?;
+        if sealed.len() < LEN_SEALED_LEN {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "frame is too short"));
+        }
+        let (sealed_len, chunks) = sealed.split_at(LEN_SEALED_LEN);
+        let mut len = [0u8; 8];
+        self.transport.read_message(sealed_len, &mut len).map_err(noise_error)?;
+        let len = u64::from_le_bytes(len);
+        let num_chunks = (chunks.len() + MAX_MESSAGE_LEN - 1) / MAX_MESSAGE_LEN;
+        if chunks.len() as u64 != len + (num_chunks * TAG_LEN) as u64 {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "invalid frame length"));
+        }
+        let mut message = vec![0u8; chunks.len()];
+        let mut offset = 0;
+        for chunk in chunks.chunks(MAX_MESSAGE_LEN) {
+            offset +=
+                self.transport.read_message(chunk, &mut message[offset..]).map_err(noise_error)?;
+        }
+        message.truncate(offset);
+        T::try_from_slice(&message)
+    }
+}
+
+fn noise_error(err: snow::Error) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, err)
+}
+
+#[derive(BorshSerialize, BorshDeserialize, Debug)]
+pub enum SignerRequest {
+    ValidatorInfo,
+    /// Telemetry info serialized to JSON.
+    SignTelemetry(String),
+    SignBlockHeaderParts {
+        prev_hash: CryptoHash,
+        inner_lite: Vec<u8>,
+        inner_rest: Vec<u8>,
+    },
+    SignChunkHash(ChunkHash),
+    SignApproval {
+        inner: ApprovalInner,
+        target_height: BlockHeight,
+    },
+    SignChallenge(ChallengeBody),
+    SignAccountAnnounce {
+        account_id: AccountId,
+        peer_id: PeerId,
+        epoch_id: EpochId,
+    },
+    ComputeVrfWithProof(Vec<u8>),
+}
+
+#[derive(BorshSerialize, BorshDeserialize, Debug)]
+pub enum SignerResponse {
+    ValidatorInfo {
+        account_id: AccountId,
+        public_key: PublicKey,
+    },
+    /// Signed telemetry serialized to JSON.
+    Telemetry(String),
+    Signature(Signature),
+    HashAndSignature(CryptoHash, Signature),
+    Vrf(near_crypto::vrf::Value, near_crypto::vrf::Proof),
+    Error(String),
+}
+
+/// Address of a remote signer, either `unix:<path>` or `tcp:<host>:<port>`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum SignerAddr {
+    Unix(PathBuf),
+    Tcp(String),
+}
+
+impl FromStr for SignerAddr {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if let Some(path) = s.strip_prefix("unix:") {
+            Ok(SignerAddr::Unix(PathBuf::from(path)))
+        } else if let Some(addr) = s.strip_prefix("tcp:") {
+            Ok(SignerAddr::Tcp(addr.to_string()))
+        } else {
+            Err(format!(
+                "invalid signer address {:?}, expected unix:<path> or tcp:<host>:<port>",
+                s
+            ))
+        }
+    }
+}
+
+impl fmt::Display for SignerAddr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SignerAddr::Unix(path) => write!(f, "unix:{}", path.display()),
+            SignerAddr::Tcp(addr) => write!(f, "tcp:{}", addr),
+        }
+    }
+}
+
+pub trait Stream: Read + Write + Send {
+    fn set_timeouts(&self, read: Option<Duration>, write: Option<Duration>) -> io::Result<()>;
+}
+
+impl Stream for TcpStream {
+    fn set_timeouts(&self, read: Option<Duration>, write: Option<Duration>) -> io::Result<()> {
+        self.set_read_timeout(read)?;
+        self.set_write_timeout(write)
+    }
+}
+
+#[cfg(unix)]
+impl Stream for UnixStream {
+    fn set_timeouts(&self, read: Option<Duration>, write: Option<Duration>) -> io::Result<()> {
+        self.set_read_timeout(read)?;
+        self.set_write_timeout(write)
+    }
+}
+
+/// Connects to the signer, with every read and write on the connection limited by `timeout`.
+pub fn connect(addr: &SignerAddr, timeout: Duration) -> io::Result<Box<dyn Stream>> {
+    match addr {
+        #[cfg(unix)]
+        SignerAddr::Unix(path) => {
+            let stream = UnixStream::connect(path)?;
+            stream.set_timeouts(Some(timeout), Some(timeout))?;
+            Ok(Box::new(stream))
+        }
+        #[cfg(not(unix))]
+        SignerAddr::Unix(_) => Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "unix sockets are only supported on unix",
+        )),
+        SignerAddr::Tcp(addr) => {
+            let socket_addr = addr.to_socket_addrs()?.next().ok_or_else(|| {
+                io::Error::new(io::ErrorKind::InvalidInput, format!("can't resolve {}", addr))
+            })?;
+            let stream = TcpStream::connect_timeout(&socket_addr, timeout)?;
+            stream.set_timeouts(Some(timeout), Some(timeout))?;
+            stream.set_nodelay(true)?;
+            Ok(Box::new(stream))
+        }
+    }
+}
+
+fn write_frame_bytes(stream: &mut dyn Stream, data: &[u8]) -> io::Result<()> {
+    if data.len() > MAX_FRAME_SIZE {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "frame is too large"));
+    }
+    stream.write_all(&(data.len() as u32).to_le_bytes())?;
+    stream.write_all(data)?;
+    stream.flush()
+}
+
+fn read_frame_bytes(stream: &mut dyn Stream, max_len: usize) -> io::Result<Vec<u8>> {
+    let mut len = [0u8; 4];
+    stream.read_exact(&mut len)?;
+    let len = u32::from_le_bytes(len) as usize;
+    if len > max_len {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "frame is too large"));
+    }
+    let mut data = vec![0u8; len];
+    stream.read_exact(&mut data)?;
+    Ok(data)
+}
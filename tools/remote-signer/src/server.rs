@@ -0,0 +1,187 @@
+use std::io;
+use std::net::TcpListener;
+#[cfg(unix)]
+use std::os::unix::net::UnixListener;
+use std::sync::Arc;
+use std::time::Duration;
+
+use tracing::{info, warn};
+
+use near_crypto::{PublicKey, SecretKey};
+use near_primitives::telemetry::TelemetryInfo;
+use near_primitives::validator_signer::ValidatorSigner;
+
+use crate::protocol::{
+    noise_handshake, read_handshake_message, write_handshake_message, x25519_public_key,
+    HandshakeResult, SecureStream, SignerAddr, SignerRequest, SignerResponse, Stream,
+};
+use crate::RemoteSignerError;
+
+/// Listening socket of a signer.
+pub enum SignerListener {
+    #[cfg(unix)]
+    Unix(UnixListener),
+    Tcp(TcpListener),
+}
+
+impl SignerListener {
+    pub fn bind(addr: &SignerAddr) -> io::Result<Self> {
+        match addr {
+            #[cfg(unix)]
+            SignerAddr::Unix(path) => Ok(SignerListener::Unix(UnixListener::bind(path)?)),
+            #[cfg(not(unix))]
+            SignerAddr::Unix(_) => Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "unix sockets are only supported on unix",
+            )),
+            SignerAddr::Tcp(addr) => Ok(SignerListener::Tcp(TcpListener::bind(addr)?)),
+        }
+    }
+
+    /// Address the listener is bound to, e.g. to find out the port when binding to port 0.
+    pub fn local_addr(&self) -> io::Result<SignerAddr> {
+        match self {
+            #[cfg(unix)]
+            SignerListener::Unix(listener) => {
+                let addr = listener.local_addr()?;
+                let path = addr.as_pathname().ok_or_else(|| {
+                    io::Error::new(io::ErrorKind::InvalidInput, "unnamed unix socket")
+                })?;
+                Ok(SignerAddr::Unix(path.to_path_buf()))
+            }
+            SignerListener::Tcp(listener) => {
+                Ok(SignerAddr::Tcp(listener.local_addr()?.to_string()))
+            }
+        }
+    }
+
+    fn accept(&self, timeout: Duration) -> io::Result<Box<dyn Stream>> {
+        match self {
+            #[cfg(unix)]
+            SignerListener::Unix(listener) => {
+                let (stream, _) = listener.accept()?;
+                stream.set_timeouts(Some(timeout), Some(timeout))?;
+                Ok(Box::new(stream))
+            }
+            SignerListener::Tcp(listener) => {
+                let (stream, _) = listener.accept()?;
+                stream.set_timeouts(Some(timeout), Some(timeout))?;
+                stream.set_nodelay(true)?;
+                Ok(Box::new(stream))
+            }
+        }
+    }
+}
+
+/// Signing process serving the nodes whose auth keys are in `allowed_clients`.
+pub struct SignerServer {
+    signer: Arc<dyn ValidatorSigner>,
+    auth_key: SecretKey,
+    allowed_clients: Vec<PublicKey>,
+    /// Limit on the handshake and on each write. Authenticated connections may stay idle.
+    timeout: Duration,
+}
+
+impl SignerServer {
+    pub fn new(
+        signer: Arc<dyn ValidatorSigner>,
+        auth_key: SecretKey,
+        allowed_clients: Vec<PublicKey>,
+        timeout: Duration,
+    ) -> Self {
+        Self { signer, auth_key, allowed_clients, timeout }
+    }
+
+    /// Serves connections from the listener, each on its own thread, until accepting fails.
+    pub fn serve(self: Arc<Self>, listener: SignerListener) -> io::Result<()> {
+        loop {
+            let stream = listener.accept(self.timeout)?;
+            let server = self.clone();
+            std::thread::spawn(move || {
+                if let Err(err) = server.handle_connection(stream) {
+                    warn!(target: "remote_signer", "Closing connection: {}", err);
+                }
+            });
+        }
+    }
+
+    fn handle_connection(&self, mut stream: Box<dyn Stream>) -> Result<(), RemoteSignerError> {
+        let mut state = noise_handshake(false, &self.auth_key)?;
+        read_handshake_message(&mut *stream, &mut state)?;
+        write_handshake_message(&mut *stream, &mut state)?;
+        read_handshake_message(&mut *stream, &mut state)?;
+        let client = state.get_remote_static().and_then(|rs| {
+            self.allowed_clients
+                .iter()
+                .find(|key| x25519_public_key(key).map_or(false, |key| &key[..] == rs))
+                .cloned()
+        });
+        let mut stream = SecureStream::new(stream, state)?;
+        let client = match client {
+            Some(client) => client,
+            None => {
+                let err = "client is not allowed".to_string();
+                stream.send(&HandshakeResult::Rejected(err.clone()))?;
+                return Err(RemoteSignerError::Refused(err));
+            }
+        };
+        stream.send(&HandshakeResult::Accepted)?;
+        info!(target: "remote_signer", "Client {} connected", client);
+        // The client keeps its connection open between requests.
+        stream.set_timeouts(None, Some(self.timeout))?;
+
+        loop {
+            let request: SignerRequest = match stream.recv() {
+                Ok(request) => request,
+                Err(err) if err.kind() == io::ErrorKind::UnexpectedEof => return Ok(()),
+                Err(err) => return Err(err.into()),
+            };
+            stream.send(&self.handle_request(request))?;
+        }
+    }
+
+    fn handle_request(&self, request: SignerRequest) -> SignerResponse {
+        let response = match request {
+            SignerRequest::ValidatorInfo => Ok(SignerResponse::ValidatorInfo {
+                account_id: self.signer.validator_id().clone(),
+                public_key: self.signer.public_key(),
+            }),
+            SignerRequest::SignTelemetry(info) => {
+                // Only well-formed telemetry is signed, so that this can't be used to sign
+                // arbitrary data.
+                match serde_json::from_str::<TelemetryInfo>(&info) {
+                    Ok(info) => self
+                        .signer
+                        .sign_telemetry(&info)
+                        .map(|value| SignerResponse::Telemetry(value.to_string())),
+                    Err(err) => {
+                        return SignerResponse::Error(format!("invalid telemetry: {}", err))
+                    }
+                }
+            }
+            SignerRequest::SignBlockHeaderParts { prev_hash, inner_lite, inner_rest } => self
+                .signer
+                .sign_block_header_parts(prev_hash, &inner_lite, &inner_rest)
+                .map(|(hash, signature)| SignerResponse::HashAndSignature(hash, signature)),
+            SignerRequest::SignChunkHash(chunk_hash) => {
+                self.signer.sign_chunk_hash(&chunk_hash).map(SignerResponse::Signature)
+            }
+            SignerRequest::SignApproval { inner, target_height } => {
+                self.signer.sign_approval(&inner, target_height).map(SignerResponse::Signature)
+            }
+            SignerRequest::SignChallenge(challenge_body) => self
+                .signer
+                .sign_challenge(&challenge_body)
+                .map(|(hash, signature)| SignerResponse::HashAndSignature(hash, signature)),
+            SignerRequest::SignAccountAnnounce { account_id, peer_id, epoch_id } => self
+                .signer
+                .sign_account_announce(&account_id, &peer_id, &epoch_id)
+                .map(SignerResponse::Signature),
+            SignerRequest::ComputeVrfWithProof(data) => self
+                .signer
+                .compute_vrf_with_proof(&data)
+                .map(|(value, proof)| SignerResponse::Vrf(value, proof)),
+        };
+        response.unwrap_or_else(|err| SignerResponse::Error(err.to_string()))
+    }
+}
@@ -7,7 +7,7 @@ use near_logger_utils::init_integration_logger;
 use near_primitives::account::id::AccountId;
 use near_primitives::hash::CryptoHash;
 use near_primitives::sharding::ChunkHash;
-use near_primitives::types::{BlockHeight, ShardId};
+use near_primitives::types::{Balance, BlockHeight, EpochHeight, ShardId};
 use near_primitives::version::{DB_VERSION, PROTOCOL_VERSION};
 use near_store::{create_store, Store};
 use nearcore::{get_default_home, get_store_path, load_config, NearConfig};
@@ -104,11 +104,18 @@ pub enum StateViewerSubCommand {
     Chunks(ChunksCmd),
     #[clap(name = "partial_chunks")]
     PartialChunks(PartialChunksCmd),
+    /// Print per-epoch block and chunk production, kickouts and rewards of a validator.
+    #[clap(name = "validator_performance")]
+    ValidatorPerformance(ValidatorPerformanceCmd),
+    /// Predict the validator selection at the end of the current epoch given hypothetical stakes.
+    #[clap(name = "simulate_validators")]
+    SimulateValidators(SimulateValidatorsCmd),
 }
 
 impl StateViewerSubCommand {
     pub fn run(self, home_dir: &Path, genesis_validation: GenesisValidationMode) {
-        let near_config = load_config(home_dir, genesis_validation);
+        let near_config = load_config(home_dir, genesis_validation)
+            .unwrap_or_else(|e| panic!("Error loading config: {:#}", e));
         let store = create_store(&get_store_path(home_dir));
         match self {
             StateViewerSubCommand::Peers => peers(store),
@@ -127,6 +134,8 @@ impl StateViewerSubCommand {
             StateViewerSubCommand::Receipts(cmd) => cmd.run(near_config, store),
             StateViewerSubCommand::Chunks(cmd) => cmd.run(near_config, store),
             StateViewerSubCommand::PartialChunks(cmd) => cmd.run(near_config, store),
+            StateViewerSubCommand::ValidatorPerformance(cmd) => cmd.run(near_config, store),
+            StateViewerSubCommand::SimulateValidators(cmd) => cmd.run(near_config, store),
         }
     }
 }
@@ -358,3 +367,49 @@ impl PartialChunksCmd {
         get_partial_chunk(partial_chunk_hash, near_config, store)
     }
 }
+
+#[derive(Clap)]
+pub struct ValidatorPerformanceCmd {
+    #[clap(long)]
+    account_id: String,
+    /// First epoch height to report, inclusive.
+    #[clap(long, default_value = "0")]
+    from_epoch_height: EpochHeight,
+    /// Last epoch height to report, inclusive. Defaults to the last finished epoch.
+    #[clap(long)]
+    to_epoch_height: Option<EpochHeight>,
+}
+
+impl ValidatorPerformanceCmd {
+    pub fn run(self, near_config: NearConfig, store: Store) {
+        print_validator_performance(
+            AccountId::from_str(&self.account_id).unwrap(),
+            self.from_epoch_height,
+            self.to_epoch_height.unwrap_or(EpochHeight::MAX),
+            near_config,
+            store,
+        );
+    }
+}
+
+#[derive(Clap)]
+pub struct SimulateValidatorsCmd {
+    /// Hypothetical stake of an account given as `<account_id>=<amount in yoctoNEAR>`, replacing
+    /// its current proposal. Can be repeated.
+    #[clap(long = "stake", parse(try_from_str = parse_stake))]
+    stakes: Vec<(AccountId, Balance)>,
+}
+
+fn parse_stake(s: &str) -> Result<(AccountId, Balance), String> {
+    let (account_id, amount) =
+        s.split_once('=').ok_or_else(|| format!("expected <account_id>=<amount>, got {}", s))?;
+    let account_id = AccountId::from_str(account_id).map_err(|err| err.to_string())?;
+    let amount = amount.parse().map_err(|err: std::num::ParseIntError| err.to_string())?;
+    Ok((account_id, amount))
+}
+
+impl SimulateValidatorsCmd {
+    pub fn run(self, near_config: NearConfig, store: Store) {
+        simulate_validators(self.stakes.into_iter().collect(), near_config, store);
+    }
+}
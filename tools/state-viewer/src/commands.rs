@@ -17,12 +17,12 @@ use near_primitives::sharding::ChunkHash;
 use near_primitives::state_record::StateRecord;
 use near_primitives::trie_key::TrieKey;
 use near_primitives::types::chunk_extra::ChunkExtra;
-use near_primitives::types::{BlockHeight, ShardId, StateRoot};
+use near_primitives::types::{Balance, BlockHeight, EpochHeight, ShardId, StateRoot};
 use near_store::test_utils::create_test_store;
 use near_store::{Store, TrieIterator};
 use nearcore::{NearConfig, NightshadeRuntime};
 use node_runtime::adapter::ViewRuntimeAdapter;
-use std::collections::HashMap;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
 use std::fs::{self, File};
 use std::io::Write;
 use std::path::{Path, PathBuf};
@@ -550,6 +550,87 @@ pub(crate) fn print_epoch_info(
     );
 }
 
+pub(crate) fn print_validator_performance(
+    account_id: AccountId,
+    from_epoch_height: EpochHeight,
+    to_epoch_height: EpochHeight,
+    near_config: NearConfig,
+    store: Store,
+) {
+    let chain_store = ChainStore::new(store.clone(), near_config.genesis.config.genesis_height);
+    let mut epoch_manager =
+        EpochManager::new_from_genesis_config(store, &near_config.genesis.config)
+            .expect("Failed to start Epoch Manager");
+    let head = chain_store.head().unwrap();
+    match epoch_manager.get_validator_performance(
+        &account_id,
+        &head.last_block_hash,
+        from_epoch_height,
+        to_epoch_height,
+    ) {
+        Ok(performance) => println!("{}", serde_json::to_string_pretty(&performance).unwrap()),
+        Err(err) => {
+            println!("Failed to get the performance of {}: {}", account_id, err);
+            std::process::exit(1);
+        }
+    }
+}
+
+pub(crate) fn simulate_validators(
+    stake_changes: HashMap<AccountId, Balance>,
+    near_config: NearConfig,
+    store: Store,
+) {
+    let mut chain_store = ChainStore::new(store.clone(), near_config.genesis.config.genesis_height);
+    let mut epoch_manager =
+        EpochManager::new_from_genesis_config(store, &near_config.genesis.config)
+            .expect("Failed to start Epoch Manager");
+    let head = chain_store.head().unwrap();
+    let rng_seed = chain_store.get_block_header(&head.last_block_hash).unwrap().random_value().0;
+    let epoch_info = epoch_manager
+        .simulate_next_next_epoch_info(&head.last_block_hash, &stake_changes, rng_seed)
+        .unwrap();
+
+    println!(
+        "Simulated epoch {} at head #{} {}",
+        epoch_info.epoch_height(),
+        head.height,
+        head.last_block_hash
+    );
+    println!("Seat price: {}", epoch_info.seat_price());
+    println!("Block producers:");
+    let block_producers: BTreeSet<_> = epoch_info.block_producers_settlement().iter().collect();
+    for validator_id in block_producers {
+        let validator = epoch_info.get_validator(*validator_id);
+        let num_seats =
+            epoch_info.block_producers_settlement().iter().filter(|id| *id == validator_id).count();
+        println!("  {} stake: {} seats: {}", validator.account_id(), validator.stake(), num_seats);
+    }
+    for (shard_id, chunk_producers) in epoch_info.chunk_producers_settlement().iter().enumerate() {
+        let accounts: Vec<_> = chunk_producers
+            .iter()
+            .map(|id| epoch_info.get_validator(*id).account_id().to_string())
+            .collect();
+        println!("Chunk producers of shard {}: {}", shard_id, accounts.join(", "));
+    }
+    let fishermen: Vec<_> =
+        epoch_info.fishermen_iter().map(|v| v.account_id().to_string()).collect();
+    println!("Fishermen: {}", fishermen.join(", "));
+    println!("Kickouts:");
+    let kickouts: BTreeMap<_, _> = epoch_info.validator_kickout().iter().collect();
+    for (account_id, reason) in kickouts {
+        println!("  {}: {:?}", account_id, reason);
+    }
+    for (account_id, stake) in stake_changes.iter() {
+        let status = if epoch_info.get_validator_by_account(account_id).is_some() {
+            "gets a seat"
+        } else {
+            "does not get a seat"
+        };
+        println!("{} with stake {} {}", account_id, stake, status);
+    }
+}
+
 pub(crate) fn get_receipt(receipt_id: CryptoHash, near_config: NearConfig, store: Store) {
     let mut chain_store = ChainStore::new(store.clone(), near_config.genesis.config.genesis_height);
     let receipt = chain_store.get_receipt(&receipt_id);
@@ -2,10 +2,13 @@ use near_chain::RuntimeAdapter;
 use near_chain_configs::Genesis;
 use near_crypto::PublicKey;
 use near_primitives::account::id::AccountId;
+use near_primitives::account::Account;
 use near_primitives::block::BlockHeader;
+use near_primitives::hash::CryptoHash;
 use near_primitives::state_record::StateRecord;
+use near_primitives::trie_key::TrieKey;
 use near_primitives::types::{AccountInfo, Balance, StateRoot};
-use near_store::TrieIterator;
+use near_store::{Trie, TrieIterator};
 use nearcore::config::NearConfig;
 use nearcore::NightshadeRuntime;
 use serde::ser::{SerializeSeq, Serializer};
@@ -126,10 +129,10 @@ fn iterate_over_records(
     for (shard_id, state_root) in state_roots.iter().enumerate() {
         let trie =
             runtime.get_trie_for_shard(shard_id as u64, last_block_header.prev_hash()).unwrap();
-        let trie = TrieIterator::new(&trie, state_root).unwrap();
-        for item in trie {
+        for item in TrieIterator::new(&trie, state_root).unwrap() {
             let (key, value) = item.unwrap();
             if let Some(mut sr) = StateRecord::from_raw_key_value(key, value) {
+                let mut shared_code_record = None;
                 if let StateRecord::Account { account_id, account } = &mut sr {
                     total_supply += account.amount() + account.locked();
                     if account.locked() > 0 {
@@ -137,14 +140,41 @@ fn iterate_over_records(
                         account.set_amount(account.amount() + account.locked() - stake);
                         account.set_locked(stake);
                     }
+                    shared_code_record =
+                        get_shared_code_record(&trie, state_root, account_id, account);
                 }
                 callback(sr);
+                // Contract record has to follow the corresponding account record.
+                if let Some(sr) = shared_code_record {
+                    callback(sr);
+                }
             }
         }
     }
     total_supply
 }
 
+/// Returns the `Contract` record for an account which refers to a shared contract code instead of
+/// storing its own copy of the code.
+fn get_shared_code_record(
+    trie: &Trie,
+    state_root: &StateRoot,
+    account_id: &AccountId,
+    account: &Account,
+) -> Option<StateRecord> {
+    if account.code_hash() == CryptoHash::default() {
+        return None;
+    }
+    let key = TrieKey::ContractCode { account_id: account_id.clone() };
+    if trie.get(state_root, &key.to_vec()).unwrap().is_some() {
+        return None;
+    }
+    let key = TrieKey::SharedContractCode { code_hash: account.code_hash() };
+    trie.get(state_root, &key.to_vec())
+        .unwrap()
+        .map(|code| StateRecord::Contract { account_id: account_id.clone(), code })
+}
+
 #[cfg(test)]
 mod test {
     use std::collections::HashSet;
@@ -206,7 +236,8 @@ mod test {
                 "test".parse().unwrap(),
                 KeyType::ED25519,
             ))),
-        );
+        )
+        .unwrap();
 
         (store, genesis, env, near_config)
     }
@@ -463,7 +494,8 @@ mod test {
                 "test".parse().unwrap(),
                 KeyType::ED25519,
             ))),
-        );
+        )
+        .unwrap();
 
         let last_block = blocks.pop().unwrap();
         let state_roots =
@@ -522,7 +554,8 @@ mod test {
                 "test".parse().unwrap(),
                 KeyType::ED25519,
             ))),
-        );
+        )
+        .unwrap();
         let head = env.clients[0].chain.head().unwrap();
         let last_block_hash = head.last_block_hash;
         let cur_epoch_id = head.epoch_id;
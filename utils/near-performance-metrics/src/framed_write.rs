@@ -216,12 +216,14 @@ use tokio::io::{AsyncWrite, AsyncWriteExt};
 use tokio_util::codec::Encoder;
 
 // This file was copied from actix-0.11.0-beta.1/src/io.rs
-// The only change was to add EncoderCallBack
+// The only changes were to add EncoderCallBack, `buffer_len` and `WriteHandler::drained`
 
 bitflags! {
     struct Flags: u8 {
         const CLOSING = 0b0000_0001;
         const CLOSED = 0b0000_0010;
+        /// The whole buffer was written since `WriteHandler::drained` was last called.
+        const DRAINED = 0b0000_0100;
     }
 }
 
@@ -248,6 +250,11 @@ where
     fn finished(&mut self, ctx: &mut Self::Context) {
         ctx.stop()
     }
+
+    /// Called when the writer wrote the whole buffer to the transport.
+    ///
+    /// Items written from this method are written before the transport is flushed.
+    fn drained(&mut self, ctx: &mut Self::Context) {}
 }
 
 struct InnerWriter<E: From<io::Error>, K: EncoderCallBack> {
@@ -380,6 +387,11 @@ impl<I, T: AsyncWrite + Unpin, U: Encoder<I>, K: 'static + EncoderCallBack>
         success
     }
 
+    /// Returns the number of bytes waiting to be written to the sink.
+    pub fn buffer_len(&self) -> usize {
+        self.inner.0.borrow().buffer.len()
+    }
+
     /// Returns the `SpawnHandle` for this writer.
     pub fn handle(&self) -> SpawnHandle {
         self.inner.0.borrow().handle
@@ -426,57 +438,80 @@ where
         task: &mut Context<'_>,
     ) -> Poll<Self::Output> {
         let this = self.get_mut();
-        let mut inner = this.inner.0.borrow_mut();
-        if let Some(err) = inner.error.take() {
-            if act.error(err, ctx) == Running::Stop {
-                act.finished(ctx);
-                return Poll::Ready(());
-            }
-        }
-
-        let mut io = this.inner.1.borrow_mut();
-        inner.task = None;
-        while !inner.buffer.is_empty() {
-            match Pin::new(io.deref_mut()).poll_write(task, &inner.buffer) {
-                Poll::Ready(Ok(n)) => {
-                    if n == 0
-                        && act.error(
-                            io::Error::new(
-                                io::ErrorKind::WriteZero,
-                                "failed to write frame to transport",
-                            )
-                            .into(),
-                            ctx,
-                        ) == Running::Stop
-                    {
+        loop {
+            {
+                let mut inner = this.inner.0.borrow_mut();
+                if let Some(err) = inner.error.take() {
+                    if act.error(err, ctx) == Running::Stop {
                         act.finished(ctx);
                         return Poll::Ready(());
                     }
-                    let _ = inner.buffer.split_to(n);
-                    let len = inner.buffer.len();
-                    let capacity = inner.buffer.capacity();
-                    inner.callback.drained(n, len, capacity);
-                    // Fix memory leak see (#6173)
-                    if inner.buffer.is_empty() && inner.buffer.capacity() > 0 {
-                        inner.buffer = BytesMut::new()
-                    }
-                }
-                Poll::Ready(Err(ref e)) if e.kind() == io::ErrorKind::WouldBlock => {
-                    if inner.buffer.len() > inner.high {
-                        ctx.wait(WriterDrain { inner: this.inner.clone(), act: PhantomData });
-                    }
-                    return Poll::Pending;
                 }
-                Poll::Ready(Err(e)) => {
-                    if act.error(e.into(), ctx) == Running::Stop {
-                        act.finished(ctx);
-                        return Poll::Ready(());
+
+                let mut io = this.inner.1.borrow_mut();
+                inner.task = None;
+                while !inner.buffer.is_empty() {
+                    match Pin::new(io.deref_mut()).poll_write(task, &inner.buffer) {
+                        Poll::Ready(Ok(n)) => {
+                            if n == 0
+                                && act.error(
+                                    io::Error::new(
+                                        io::ErrorKind::WriteZero,
+                                        "failed to write frame to transport",
+                                    )
+                                    .into(),
+                                    ctx,
+                                ) == Running::Stop
+                            {
+                                act.finished(ctx);
+                                return Poll::Ready(());
+                            }
+                            let _ = inner.buffer.split_to(n);
+                            let len = inner.buffer.len();
+                            let capacity = inner.buffer.capacity();
+                            inner.callback.drained(n, len, capacity);
+                            if inner.buffer.is_empty() {
+                                inner.flags.insert(Flags::DRAINED);
+                            }
+                            // Fix memory leak see (#6173)
+                            if inner.buffer.is_empty() && inner.buffer.capacity() > 0 {
+                                inner.buffer = BytesMut::new()
+                            }
+                        }
+                        Poll::Ready(Err(ref e)) if e.kind() == io::ErrorKind::WouldBlock => {
+                            if inner.buffer.len() > inner.high {
+                                ctx.wait(WriterDrain {
+                                    inner: this.inner.clone(),
+                                    act: PhantomData,
+                                });
+                            }
+                            return Poll::Pending;
+                        }
+                        Poll::Ready(Err(e)) => {
+                            if act.error(e.into(), ctx) == Running::Stop {
+                                act.finished(ctx);
+                                return Poll::Ready(());
+                            }
+                        }
+                        Poll::Pending => return Poll::Pending,
                     }
                 }
-                Poll::Pending => return Poll::Pending,
             }
+            let drained = {
+                let mut inner = this.inner.0.borrow_mut();
+                let drained = inner.flags.contains(Flags::DRAINED);
+                inner.flags.remove(Flags::DRAINED);
+                drained
+            };
+            if !drained {
+                break;
+            }
+            // The writer mustn't be borrowed here, as the actor may write more items.
+            act.drained(ctx);
         }
 
+        let mut inner = this.inner.0.borrow_mut();
+        let mut io = this.inner.1.borrow_mut();
         // Try flushing the underlying IO
         match Pin::new(io.deref_mut()).poll_flush(task) {
             Poll::Ready(Ok(_)) => (),
@@ -556,6 +591,9 @@ where
                     let len = inner.buffer.len();
                     let capacity = inner.buffer.capacity();
                     inner.callback.drained(n, len, capacity);
+                    if inner.buffer.is_empty() {
+                        inner.flags.insert(Flags::DRAINED);
+                    }
                     // Fix memory leak see (#6173)
                     if inner.buffer.is_empty() && inner.buffer.capacity() > 0 {
                         inner.buffer = BytesMut::new()